@@ -1,34 +1,282 @@
 pub mod paths {
-    use std::{env, path::Path, collections::HashMap};
-    use glob::glob;
+    use std::{env, fs, io::{self, Read}, path::Path, collections::HashMap};
     use inquire::Text;
     use regex::Regex;
-    use crate::{configs::config::{self, Artefacts}, ops::{get_files, file_ops::{self, log_msg}}};
+    use crate::{configs::config::{self, Artefacts, Severity}, ops::{get_files, file_ops::{self, log_msg}}};
 
-    pub fn check_art(artefacts: Vec<Artefacts>, data_source: &String, silent: bool, main_args: &config::MainArgs) -> HashMap<String, String> {
+    /// a hash of the artefact names and their paths
+    type ArtPaths = HashMap<String, String>;
+
+    /// the file in the output folder of the artefacts that were not found, to re-collect
+    pub const MISSING_FILE: &str = "missing_artefacts.json";
+
+    /// An artefact not found in the data source, with where it was expected and the
+    /// commands that needed it, for the collection team to re-collect it
+    #[derive(Debug, serde::Serialize)]
+    struct MissingArt {
+        name: String,
+        severity: Severity,
+        paths: Vec<String>,
+        needed_by: Vec<String>,
+    }
+
+    /// write the artefacts that were not found and that a selected command reads, to the
+    /// missing artefacts file in the output folder, as a worklist to re-collect them
+    ///
+    /// Args:
+    /// * `missing` - the artefacts not found, with the paths they were expected at
+    /// * `config` - the config, for the commands that read each artefact
+    /// * `main_args` - the main args from main.rs, i.e. the out_path
+    fn write_missing(missing: Vec<(String, Vec<String>)>, config: &config::Config, main_args: &config::MainArgs) {
+        let missing: Vec<MissingArt> = missing.into_iter()
+            .map(|(name, paths)| MissingArt {
+                needed_by: config.stages().iter()
+                    .flat_map(|(_, func)| func.iter())
+                    .filter(|w| w.selected(main_args) && [&w.input, &w.input_other, &w.run_if_artefact].contains(&&name))
+                    .map(|w| w.name.to_string())
+                    .collect(),
+                severity: config.severity(&name),
+                name,
+                paths,
+            })
+            .filter(|m| !m.needed_by.is_empty())
+            .collect();
+        let missing_path = Path::new(&main_args.out_path).join(MISSING_FILE);
+        let result = serde_json::to_string_pretty(&missing).map_err(|e| e.to_string())
+            .and_then(|m| fs::write(&missing_path, m).map_err(|e| e.to_string()));
+        match result {
+            Ok(_) if missing.is_empty() => (),
+            Ok(_) => log_msg(&main_args.out_log, format!("[-] {} artefacts needed by the commands are missing, listed to re-collect in: {}", missing.len(), missing_path.display())),
+            Err(e) => log_msg(&main_args.out_log, format!("[!] Unable to write the missing artefacts to {}. Error: {}", missing_path.display(), e)),
+        }
+    }
+
+    /// find the folder under the data source that has the Windows folder, as
+    /// collections can store the drive under a subfolder, i.e. uploads/auto/C%3A
+    /// 
+    /// Return: the relative path of the folder, empty if it is the data source
+    fn find_source_prefix(data_source: &str) -> Option<String> {
+        walkdir::WalkDir::new(data_source)
+            .min_depth(1)
+            .max_depth(4)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .find(|e| e.file_type().is_dir() && e.file_name().to_string_lossy().eq_ignore_ascii_case("windows"))
+            .and_then(|e| e.path().parent().map(|p| p.to_path_buf()))
+            .and_then(|p| p.strip_prefix(data_source).ok().map(|p| p.to_string_lossy().to_string()))
+    }
+
+    /// get the root that replaces `{root}` in the artefact paths, mapping the
+    /// logical root of the config onto where the files are on disk, with the
+    /// --source-prefix of either a subfolder, `from=to` to replace a subfolder, or `auto`
+    /// 
+    /// Return: the (root, from) where `from` is the start of the artefact path to replace
+    /// 
+    /// Args:
+    /// * `data_source` - the file path to the data source
+    /// * `source_prefix` - the mapping given to --source-prefix, empty for none
+    /// * `main_args` - the main args from main.rs, to log the mapping applied
+    fn source_root(data_source: &String, source_prefix: &str, main_args: &config::MainArgs) -> (String, String) {
+        let (from, to) = match source_prefix.split_once('=') {
+            Some((from, to)) => (from.trim_matches(['/', '\\']).to_string(), to.to_string()),
+            None if source_prefix == "auto" => match find_source_prefix(data_source) {
+                Some(to) => (String::new(), to),
+                None => {
+                    log_msg(&main_args.out_log, format!("[-] No source prefix found under {}, using it as the root", data_source));
+                    (String::new(), String::new())
+                }
+            },
+            None => (String::new(), source_prefix.to_string()),
+        };
+        let to = to.trim_matches(['/', '\\']);
+        if source_prefix.is_empty() {
+            return (data_source.to_string(), from);
+        }
+        let root = match to.is_empty() {
+            true => data_source.to_string(),
+            false => format!("{}/{}", data_source, to),
+        };
+        let logical = match from.is_empty() {
+            true => "{root}".to_string(),
+            false => format!("{{root}}/{}", from),
+        };
+        log_msg(&main_args.out_log, format!("[ ] Source prefix mapping {} to {}", logical, root));
+        (root, from)
+    }
+
+    /// replace the `{root}` of the artefact path with the mapped root. If the mapping
+    /// replaces the start of the path, only the paths that start with it are mapped
+    fn map_root(path: &str, data_source: &str, root: &str, from: &str) -> String {
+        if from.is_empty() {
+            return path.replace("{root}", root);
+        }
+        for sep in ["/", "\\"] {
+            let prefix = format!("{{root}}{}{}", sep, from);
+            if path.len() >= prefix.len() && path[..prefix.len()].eq_ignore_ascii_case(&prefix) {
+                return format!("{}{}", root, &path[prefix.len()..]);
+            }
+        }
+        path.replace("{root}", data_source)
+    }
+
+    /// split an artefact path into its literal parts and its named capture groups, i.e.
+    /// `{root}/Users/(?P<user>[^/]+)/NTUSER.DAT`, as (name, pattern) where the name is
+    /// empty for a literal part
+    fn capture_parts(path: &str) -> Vec<(String, String)> {
+        let mut parts = Vec::new();
+        let mut rest = path;
+        while let Some(start) = rest.find("(?P<").or_else(|| rest.find("(?<")) {
+            let open = if rest[start..].starts_with("(?P<") { 4 } else { 3 };
+            let Some(name_len) = rest[start + open..].find('>') else { break };
+            // find the closing bracket of the group, skipping escapes and nested groups
+            let mut depth = 0;
+            let mut escaped = false;
+            let mut end = None;
+            for (i, c) in rest[start..].char_indices() {
+                match (escaped, c) {
+                    (true, _) => escaped = false,
+                    (false, '\\') => escaped = true,
+                    (false, '(') => depth += 1,
+                    (false, ')') => {
+                        depth -= 1;
+                        if depth == 0 {
+                            end = Some(start + i);
+                            break;
+                        }
+                    },
+                    _ => (),
+                }
+            }
+            let Some(end) = end else { break };
+            parts.push((String::new(), rest[..start].to_string()));
+            let name = &rest[start + open..start + open + name_len];
+            parts.push((name.to_string(), rest[start + open + name_len + 1..end].to_string()));
+            rest = &rest[end + 1..];
+        }
+        parts.push((String::new(), rest.to_string()));
+        parts
+    }
+
+    /// the names of the capture groups in an artefact path, for `${name}` in the args
+    pub fn capture_names(path: &str) -> Vec<String> {
+        capture_parts(path).into_iter().map(|(n, _)| n).filter(|n| !n.is_empty()).collect()
+    }
+
+    /// the glob of an artefact path, with each capture group matching any name
+    fn capture_glob(path: &str) -> String {
+        capture_parts(path).into_iter()
+            .map(|(name, part)| if name.is_empty() { part } else { "*".to_string() })
+            .collect()
+    }
+
+    /// the regex of an artefact path with capture groups, to get their values from the
+    /// paths found by its glob. The separators match either slash, as does a wildcard
+    fn capture_regex(path: &str) -> Option<Regex> {
+        let literal = |part: String| part.chars().map(|c| match c {
+            '/' | '\\' => r"[/\\]".to_string(),
+            '*' => r"[^/\\]*".to_string(),
+            '?' => r"[^/\\]".to_string(),
+            c => regex::escape(&c.to_string()),
+        }).collect::<String>();
+        let re = capture_parts(path).into_iter()
+            .map(|(name, part)| match name.is_empty() {
+                true => literal(part),
+                false => format!("(?P<{}>{})", name, part),
+            })
+            .collect::<String>();
+        Regex::new(&format!("(?i)^{}$", re)).ok()
+    }
+
+    /// find each instance of an artefact path with capture groups, with the values the
+    /// groups captured from its path, i.e. the user of each NTUSER.DAT
+    ///
+    /// Return: a vector of the path of each instance and its variables
+    pub fn art_instances(path: &str, ignore: &config::Ignore) -> Vec<(String, HashMap<String, String>)> {
+        let Some(re) = capture_regex(path) else {
+            return Vec::new();
+        };
+        let names = capture_names(path);
+        file_ops::walk_glob(&capture_glob(path)).0.into_iter()
+            .filter(|p| !ignore.is_ignored(p))
+            .filter_map(|p| {
+                let caps = re.captures(&p)?;
+                let vars = names.iter()
+                    .filter_map(|n| caps.name(n).map(|v| (n.to_string(), v.as_str().to_string())))
+                    .collect();
+                Some((p, vars))
+            })
+            .collect()
+    }
+
+    /// replace the `${name}` variables in a field of a command with the values captured
+    /// from the path of the artefact instance
+    pub fn replace_vars(field: &str, vars: &HashMap<String, String>) -> String {
+        vars.iter().fold(field.to_string(), |f, (name, value)| f.replace(&format!("${{{}}}", name), value))
+    }
+
+    /// check the file paths of the artefacts exist in the data source, asking the user
+    /// for the path of those not found unless silent. A missing artefact is logged by
+    /// its severity in the config, and those of severity error stop the run, unless the
+    /// user gives a path to them that exists. A path with named capture groups is found
+    /// by its glob, and its pattern is kept to find each instance of it, i.e. each user
+    ///
+    /// Return: a hash of the artefact name and its path, with a hash of the artefact name
+    /// and its pattern of those with capture groups, or the names of the missing artefacts
+    /// of severity error
+    ///
+    /// Args:
+    /// * `artefacts` - the artefacts of the config, with their paths
+    /// * `data_source` - the file path to the data source
+    /// * `silent` - don't ask the user for the paths not found
+    /// * `config` - the config, for the severity of each missing artefact
+    /// * `main_args` - the main args from main.rs, i.e. the source prefix
+    pub fn check_art(artefacts: Vec<Artefacts>, data_source: &String, silent: bool, config: &config::Config, main_args: &config::MainArgs) -> Result<(ArtPaths, ArtPaths), String> {
         let mut art_paths = HashMap::new();
+        let mut art_patterns = HashMap::new();
+        let mut required = Vec::new();
+        let mut missing = Vec::new();
+        let mut ignored = 0;
+        let mut incomplete = Vec::new();
+        let data_source = &file_ops::trim_separators(data_source);
+        let (root, from) = source_root(data_source, &main_args.source_prefix, main_args);
         // TODO: Loop through all artefact paths and check if file/folder exists, if not check alt and legacy path
         for art in artefacts {
             let art_name = format!("{}", art.name);
-            let path_str = &art.path.replace(
-                "{root}", 
-                data_source
-            );
+            let pattern = map_root(&art.path, data_source, &root, &from);
+            let path_str = &capture_glob(&pattern);
+            if !capture_names(&pattern).is_empty() {
+                art_patterns.insert(art_name.to_string(), pattern);
+            }
             // resolve the path_str into a path, and add it to art_path hash
-            get_path(path_str, &mut art_paths, &art_name);
+            get_path(path_str, &mut art_paths, &art_name, &main_args.out_log);
             if art_paths.get(&art.name).is_none() {
                 // TODO: check urlencoded filename
-                get_enc_path(path_str, &mut art_paths, &art_name);
+                get_enc_path(path_str, &mut art_paths, &art_name, &main_args.out_log);
                 if art.legacy != "" {
                     // check legacy path
-                    let path_str_leg = &art.legacy.replace(
-                        "{root}", 
-                        data_source
-                    );
-                    get_path(&path_str_leg, &mut art_paths, &art_name);
+                    let pattern_leg = map_root(&art.legacy, data_source, &root, &from);
+                    let path_str_leg = &capture_glob(&pattern_leg);
+                    get_path(&path_str_leg, &mut art_paths, &art_name, &main_args.out_log);
+                    if art_paths.get(&art_name) == Some(path_str_leg) && !capture_names(&pattern_leg).is_empty() {
+                        art_patterns.insert(art_name.to_string(), pattern_leg);
+                    }
                 }
                 if art_paths.get(&art.name).is_none() && art.name != "none" {
-                    file_ops::log_msg(&main_args.out_log, format!("[-] Path for {} not found at {}", art.name, path_str));
+                    let severity = config.severity(&art.name);
+                    let level = match severity {
+                        Severity::Info => "[ ]",
+                        Severity::Warn => "[-]",
+                        Severity::Error => "[!]",
+                    };
+                    file_ops::log_msg(&main_args.out_log, format!("{} Path for {} not found at {}", level, art.name, path_str));
+                    if severity == Severity::Error {
+                        required.push(art.name.to_string());
+                    }
+                    let mut expected = vec![path_str.to_string()];
+                    if !art.legacy.is_empty() {
+                        expected.push(capture_glob(&map_root(&art.legacy, data_source, &root, &from)));
+                    }
+                    missing.push((art.name.to_string(), expected));
                     if silent {
                         // path not found, set as empty to skip processing
                         art_paths.insert(
@@ -37,7 +285,7 @@ pub mod paths {
                         );
                     } else {
                         // ask the user for the path
-                        get_users_path(&art_name, &mut art_paths);
+                        get_users_path(&art_name, &mut art_paths, &main_args.out_log);
                     }
                 } else if art.name == "none" {
                     // if art name has been given none in the config, ignore and add
@@ -45,14 +293,119 @@ pub mod paths {
                         art.name,
                         art.path.to_string()
                     );
+                    continue;
+                }
+            }
+            // skip the artefacts excluded by the ignore file, where a glob is excluded if all it matches are
+            if let Some(path) = art_paths.get(&art_name).filter(|p| *p != "wiskess_none" && art_name != "none") {
+                let excluded = match Path::new(path).exists() {
+                    true => main_args.ignore.is_ignored(path),
+                    false => {
+                        let matched = file_ops::walk_glob(path).0;
+                        !matched.is_empty() && matched.iter().all(|p| main_args.ignore.is_ignored(p))
+                    },
+                };
+                if excluded {
+                    log_msg(&main_args.out_log, format!("[-] Skipping the artefact {} at {}, excluded by the ignore file", art_name, path));
+                    art_paths.insert(art_name, "wiskess_none".to_string());
+                    ignored += 1;
+                    continue;
+                }
+            }
+            // report the artefacts that match fewer paths than expected, as the collection may be incomplete
+            if let Some(path) = art_paths.get(&art_name).filter(|p| *p != "wiskess_none" && art.min_matches > 0) {
+                let count = file_ops::walk_glob(path).0.iter().filter(|p| !main_args.ignore.is_ignored(p)).count();
+                if count < art.min_matches {
+                    file_ops::log_msg(&main_args.out_log, format!(
+                        "[!] Artefact {} matches {} paths at {}, fewer than the {} expected by min_matches, the collection may be incomplete",
+                        art_name, count, path, art.min_matches
+                    ));
+                    incomplete.push(format!("{} ({} of {})", art_name, count, art.min_matches));
+                }
+            }
+            // mark the artefacts that can't be read, unless check_copy_art copies them from the drive
+            let copied = main_args.raw_copy && env::consts::OS == "windows" && file_ops::is_local_drive(data_source);
+            if let Some(path) = art_paths.get(&art_name).filter(|p| *p != "wiskess_none" && !copied) {
+                if let Some(e) = locked_error(path) {
+                    file_ops::log_msg(&main_args.out_log, format!("[!] Artefact {} at {} is locked or permission denied: {}", art_name, path, e));
+                    art_paths.insert(art_name, "wiskess_locked".to_string());
                 }
             }
         }
+        if !main_args.ignore.patterns.is_empty() {
+            log_msg(&main_args.out_log, format!("[ ] The ignore file excluded {} artefact paths", ignored));
+        }
+        // those the user gave the path of are found
+        missing.retain(|(name, _)| art_paths.get(name).is_none_or(|p| p == "wiskess_none"));
+        write_missing(missing, config, main_args);
+        // those of severity error stop the run unless the user gave a path that exists
+        required.retain(|name| art_paths.get(name).is_none_or(|p| {
+            p == "wiskess_none" || (!Path::new(p).exists() && file_ops::walk_glob(p).0.is_empty())
+        }));
+        if !required.is_empty() {
+            return Err(format!("the artefacts of severity error were not found: {}", required.join(", ")));
+        }
+        if main_args.strict_matches && !incomplete.is_empty() {
+            return Err(format!("the artefacts matched fewer paths than their min_matches: {}", incomplete.join(", ")));
+        }
+        // only keep the patterns of the artefacts found by them
+        art_patterns.retain(|name, pattern| art_paths.get(name).is_some_and(|p| *p == capture_glob(pattern)));
+        check_instances(&art_patterns, config, main_args)?;
         // Return a hashmap of artefact paths
-        art_paths
+        Ok((art_paths, art_patterns))
+    }
+
+    /// check the instances of each artefact with capture groups, that a command is run for
+    /// each of, are within --max-artefacts, and those of all of them within --max-artefacts-total,
+    /// so a pattern that is too broad does not start thousands of processes. Only the artefacts
+    /// that are the input of a command selected to run are counted
+    ///
+    /// Return: the reason, naming the pattern and the paths it matched, if over a limit
+    fn check_instances(art_patterns: &ArtPaths, config: &config::Config, main_args: &config::MainArgs) -> Result<(), String> {
+        if main_args.max_artefacts == 0 && main_args.max_artefacts_total == 0 {
+            return Ok(());
+        }
+        let inputs: Vec<&String> = config.stages().iter()
+            .flat_map(|(_, func)| func.iter())
+            .filter(|w| w.selected(main_args))
+            .map(|w| &w.input)
+            .collect();
+        let mut total = 0;
+        for (name, pattern) in art_patterns.iter().filter(|(name, _)| inputs.contains(name)) {
+            let count = art_instances(pattern, &main_args.ignore).len();
+            if main_args.max_artefacts > 0 && count > main_args.max_artefacts {
+                return Err(format!(
+                    "the artefact {} matches {} paths by its pattern {}, over --max-artefacts {}",
+                    name, count, pattern, main_args.max_artefacts
+                ));
+            }
+            total += count;
+        }
+        if main_args.max_artefacts_total > 0 && total > main_args.max_artefacts_total {
+            return Err(format!(
+                "the artefacts with capture groups match {} paths, over --max-artefacts-total {}",
+                total, main_args.max_artefacts_total
+            ));
+        }
+        Ok(())
+    }
+
+    /// try to read the artefact, or list it if a folder, to find if it is locked by
+    /// another process or the permissions deny reading it, i.e. a live registry hive
+    /// 
+    /// Return: the error if it is locked or permission denied, None if readable or a glob
+    fn locked_error(path: &str) -> Option<io::Error> {
+        let path = Path::new(path);
+        let read = match path.is_dir() {
+            true => fs::read_dir(path).map(|_| ()),
+            false if path.is_file() => fs::File::open(path).and_then(|mut f| f.read(&mut [0u8; 1]).map(|_| ())),
+            false => Ok(()),
+        };
+        // the raw errors are the sharing and lock violations of Windows
+        read.err().filter(|e| e.kind() == io::ErrorKind::PermissionDenied || matches!(e.raw_os_error(), Some(32) | Some(33)))
     }
 
-    fn get_enc_path(path_str: &String, art_paths: &mut HashMap<String, String>, art_name: &String) {
+    fn get_enc_path(path_str: &String, art_paths: &mut HashMap<String, String>, art_name: &String, out_log: &String) {
         let path = Path::new(path_str);
         let filename = path.file_name();
         if filename != None {
@@ -63,14 +416,15 @@ pub mod paths {
                 parent.to_str().unwrap(), 
                 filename_str);
             // get the path that has url encoding
-            get_path(&enc_path, art_paths, art_name);
+            get_path(&enc_path, art_paths, art_name, out_log);
         }
     }
 
     /// check the mounted drive artefact is readable, and if not copy to the 
     /// "out_path/Artefacts" folder. This works on windows-only, as linux has no
     /// issue with permissions of mounted artefacts. It will check if the base
-    /// path is a drive letter, and return early if not, as that's typically a collection.
+    /// path is a drive letter, and return early if not, as that's typically a collection,
+    /// or if --locked-files skip.
     /// 
     /// Return: the updated datapaths with any copied files and the original paths
     /// 
@@ -79,14 +433,15 @@ pub mod paths {
     /// * `main_args` - a vector of the main args from main.rs, including the output path
     pub fn check_copy_art(data_paths: HashMap<String, String>, main_args: &config::MainArgs) -> HashMap<String, String> {
         let mut data_paths_clone = data_paths.to_owned();
-        let base_path = Path::new(&data_paths_clone["base"]);
-        match base_path.parent() {
-            Some(_) => {
-                println!("[DEBUG] This is likely a collection, not collecting.");
-                return data_paths_clone;
-            },
-            None => (),
-        };
+        // the locked artefacts were marked by check_art to be skipped instead
+        if !main_args.raw_copy {
+            return data_paths_clone;
+        }
+        // only a local disk is read raw, not a collection, a UNC share or a mapped network drive
+        if !file_ops::is_local_drive(&data_paths_clone["base"]) {
+            file_ops::log_msg(&main_args.out_log, "[-] The data source is likely a collection or a network share, so the locked files are not copied raw".to_string());
+            return data_paths_clone;
+        }
         if env::consts::OS == "windows" {
             // set the path where artefacts are copied to
             let dest_path = Path::new(&main_args.out_path).join("Artefacts");
@@ -159,12 +514,13 @@ pub mod paths {
     /// Args:
     /// * art_name - the name of the artefact, i.e. pagefile
     /// * art_paths - the file path to the artefact, i.e. c:/pagefile.sys
-    fn get_users_path(art_name: &String, art_paths: &mut HashMap<String, String>) {
+    /// * out_log - the log, of the symlink loops skipped in the path
+    fn get_users_path(art_name: &String, art_paths: &mut HashMap<String, String>, out_log: &String) {
         let msg = format!("What is the file path of {}?", &art_name);
         let path_ask = Text::new(&msg).prompt();
         match path_ask {
             Ok(path_ask) => {
-                get_path(&path_ask, art_paths, &art_name);
+                get_path(&path_ask, art_paths, &art_name, out_log);
                 if art_paths.get(art_name).is_none() {
                     // user's path not found, adding to hash anyway
                     art_paths.insert(
@@ -178,7 +534,7 @@ pub mod paths {
         }
     }
 
-    fn get_path(path_str: &String, art_paths: &mut HashMap<String, String>, art_name: &String) {
+    fn get_path(path_str: &String, art_paths: &mut HashMap<String, String>, art_name: &String, out_log: &String) {
         let path_arg = Path::new(path_str);
         if path_arg.exists() {
             // add path to hash
@@ -187,7 +543,7 @@ pub mod paths {
                 path_arg.display().to_string()
             );
         } else {
-            if is_glob_path(path_str) {
+            if is_glob_path(path_str, out_log) {
                 // add path to hash
                 art_paths.insert(
                     art_name.to_string(),
@@ -197,19 +553,69 @@ pub mod paths {
         }
     }
 
-    fn is_glob_path(path_str: &String) -> bool {
-        // Get path from glob based path  
-        for entry in glob(path_str).expect("Unable to read glob pattern") {
-            match entry {
-                Ok(_) => {
-                    return true;
-                },
-                Err(e) => {
-                    println!("{:?}", e);
-                    return false;
-                }
+    /// check a glob matches a path, logging the symlink loops in the data source that were
+    /// skipped, which would otherwise hang the walk, i.e. a recursive link in a mounted image
+    fn is_glob_path(path_str: &String, out_log: &String) -> bool {
+        let (paths, loops) = file_ops::walk_glob(path_str);
+        for path in loops {
+            log_msg(out_log, format!("[-] Skipping the symlink loop at {} while finding {}", path, path_str));
+        }
+        !paths.is_empty()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const NTUSER: &str = "{root}/Users/(?P<user>[^/]+)/NTUSER.DAT";
+
+        #[test]
+        fn capture_parts_splits_the_groups_from_the_literals() {
+            assert_eq!(capture_parts(NTUSER), vec![
+                (String::new(), "{root}/Users/".to_string()),
+                ("user".to_string(), "[^/]+".to_string()),
+                (String::new(), "/NTUSER.DAT".to_string()),
+            ]);
+            // a nested or escaped bracket is part of the group
+            assert_eq!(capture_parts(r"a/(?<ver>(v|V)\)[0-9])/b")[1], ("ver".to_string(), r"(v|V)\)[0-9]".to_string()));
+            assert_eq!(capture_parts("a/*.evtx"), vec![(String::new(), "a/*.evtx".to_string())]);
+            assert_eq!(capture_glob(NTUSER), "{root}/Users/*/NTUSER.DAT");
+        }
+
+        #[test]
+        fn capture_regex_gets_the_values_of_a_matching_path() {
+            let re = capture_regex("/data/Users/(?P<user>[^/\\\\]+)/NTUSER.DAT").unwrap();
+            let caps = re.captures(r"/data\Users\alice\ntuser.dat").unwrap();
+            assert_eq!(&caps["user"], "alice");
+            assert!(re.captures("/data/Users/alice/AppData/NTUSER.DAT").is_none());
+            assert!(re.captures("/data/Users/alice/NTUSER.DAT.LOG1").is_none());
+            assert!(capture_regex("/data/(?P<bad>[)/x").is_none());
+        }
+
+        #[test]
+        fn replace_vars_replaces_the_captured_names() {
+            let vars = HashMap::from([("user".to_string(), "alice".to_string())]);
+            assert_eq!(
+                replace_vars("-f {input} --csvf ${user}_ntuser.csv ${other}", &vars),
+                "-f {input} --csvf alice_ntuser.csv ${other}"
+            );
+        }
+
+        #[test]
+        fn art_instances_finds_each_match_with_its_values() {
+            let dir = tempfile::tempdir().unwrap();
+            for user in ["alice", "bob"] {
+                fs::create_dir_all(dir.path().join("Users").join(user)).unwrap();
+                fs::write(dir.path().join("Users").join(user).join("NTUSER.DAT"), b"regf").unwrap();
             }
+            fs::create_dir_all(dir.path().join("Users/Public")).unwrap();
+            let path = format!("{}/Users/(?P<user>[^/]+)/NTUSER.DAT", dir.path().display());
+            let ignore = config::Ignore::new(&dir.path().display().to_string(), &[]).unwrap();
+            let mut instances = art_instances(&path, &ignore);
+            instances.sort_by(|a, b| a.0.cmp(&b.0));
+            let users: Vec<&str> = instances.iter().map(|(_, vars)| vars["user"].as_str()).collect();
+            assert_eq!(users, vec!["alice", "bob"]);
+            assert!(instances[0].0.ends_with("NTUSER.DAT"));
         }
-        return false
     }
 }
@@ -1,13 +1,226 @@
 pub mod config {
+  use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
   use indicatif::MultiProgress;
+  use crate::ops::summary_ops::CmdResult;
+use crate::ops::secret_ops::Secret;
+  use crate::ops::file_ops;
 use serde::{Deserialize, Serialize};
+  use schemars::JsonSchema;
 
   /// Top level structure of config file
-  #[derive(Debug, Serialize, Deserialize, Clone)]
+  #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
   pub struct Config {
       pub wiskers: Vec<Wiskers>,
       pub enrichers: Vec<Wiskers>,
       pub reporters: Vec<Wiskers>,
+      #[serde(default)]
+      pub dedupe: Vec<Dedupe>,
+      #[serde(default)]
+      pub shared_outfolders: bool,
+      #[serde(default)]
+      pub artefacts_file: String,
+      #[serde(default)]
+      pub artefacts: Vec<Artefacts>,
+      #[serde(default)]
+      pub missing_severity: std::collections::HashMap<String, Severity>,
+      #[serde(default)]
+      pub ioc_severity: std::collections::HashMap<String, IocSeverity>,
+      #[serde(default)]
+      pub cleanup: Cleanup,
+  }
+
+  impl Config {
+    /// the stages in the order they are run, with the name of each
+    pub fn stages(&self) -> [(&'static str, &Vec<Wiskers>); 3] {
+      [
+        ("wiskers", &self.wiskers),
+        ("enrichers", &self.enrichers),
+        ("reporters", &self.reporters),
+      ]
+    }
+
+    /// the names in the `depends_on` of the commands that are not a command in the config,
+    /// each as `stage/name: depends_on 'x' is not in the config`
+    pub fn unknown_depends(&self) -> Vec<String> {
+      self.stages().iter()
+        .flat_map(|(stage, func)| func.iter().map(move |w| (stage, w)))
+        .flat_map(|(stage, w)| w.depends_on.iter()
+          .filter(|dep| self.find(dep).is_none())
+          .map(move |dep| format!("{}/{}: depends_on '{}' is not in the config", stage, w.name, dep)))
+        .collect()
+    }
+
+    /// find a command by name in any of the stages
+    pub fn find(&self, name: &str) -> Option<&Wiskers> {
+      self.wiskers.iter()
+        .chain(self.enrichers.iter())
+        .chain(self.reporters.iter())
+        .find(|w| w.name == name)
+    }
+
+    /// the severity of an artefact not being found, by its name, else the longest
+    /// pattern that matches it, i.e. `evtx_*`, else `default`, which is warn if not set
+    pub fn severity(&self, art_name: &str) -> Severity {
+      if let Some(severity) = self.missing_severity.get(art_name) {
+        return severity.clone();
+      }
+      self.missing_severity.iter()
+        .filter(|(k, _)| glob::Pattern::new(k).is_ok_and(|p| p.matches(art_name)))
+        .max_by_key(|(k, _)| k.len())
+        .map(|(_, s)| s.clone())
+        .or_else(|| self.missing_severity.get("default").cloned())
+        .unwrap_or(Severity::Warn)
+    }
+
+    /// the output folder of each command by its name, relative to the out_path
+    pub fn out_dirs(&self) -> std::collections::HashMap<String, String> {
+      self.stages().iter()
+        .flat_map(|(_, func)| func.iter())
+        .map(|w| (w.name.to_string(), w.out_dir()))
+        .collect()
+    }
+
+    /// the outputs of the commands marked `intermediate`, with the commands that depend on each
+    pub fn intermediates(&self, out_path: &str) -> Vec<Intermediate> {
+      let commands: Vec<&Wiskers> = self.stages().iter().flat_map(|(_, func)| func.iter()).collect();
+      commands.iter()
+        .filter(|w| w.intermediate)
+        .map(|w| Intermediate {
+          name: w.name.to_string(),
+          out_file: w.out_file(out_path),
+          dependents: commands.iter().filter(|d| d.depends_on.contains(&w.name)).map(|d| d.name.to_string()).collect(),
+        })
+        .collect()
+    }
+
+    /// add to the `depends_on` of each command those whose outputs match its `inputs`, so the
+    /// dependencies are inferred from the paths rather than written by hand. An input that the
+    /// outputs of more than one command match is ambiguous, and the dependencies, written and
+    /// inferred, must not be circular. Nothing is inferred or checked unless a command has `inputs`
+    ///
+    /// returns a note of each dependency inferred, else the ambiguous inputs and the cycles
+    pub fn infer_depends(&mut self) -> Result<Vec<String>, Vec<String>> {
+      let commands: Vec<(String, Vec<String>, Vec<String>)> = self.stages().iter()
+        .flat_map(|(_, func)| func.iter())
+        .map(|w| (w.name.to_string(), w.inputs.clone(), w.output_paths()))
+        .collect();
+      if commands.iter().all(|(_, inputs, _)| inputs.is_empty()) {
+        return Ok(Vec::new());
+      }
+      let mut inferred = Vec::new();
+      let mut problems = Vec::new();
+      let mut depends: std::collections::HashMap<&str, Vec<&str>> = std::collections::HashMap::new();
+      for (name, inputs, _) in &commands {
+        for input in inputs {
+          let producers: Vec<&str> = commands.iter()
+            .filter(|(other, _, outputs)| other != name && outputs.iter().any(|o| paths_match(input, o)))
+            .map(|(other, _, _)| other.as_str())
+            .collect();
+          match producers.as_slice() {
+            [] => {},
+            [producer] => {
+              depends.entry(name).or_default().push(producer);
+              inferred.push(format!("{} depends on {}, by its input {}", name, producer, input));
+            },
+            _ => problems.push(format!("{}: the input '{}' matches the outputs of {}, so which it depends on is ambiguous", name, input, producers.join(", "))),
+          }
+        }
+      }
+      let mut stages = [&mut self.wiskers, &mut self.enrichers, &mut self.reporters];
+      for wisker in stages.iter_mut().flat_map(|func| func.iter_mut()) {
+        for dep in depends.get(wisker.name.as_str()).into_iter().flatten() {
+          if !wisker.depends_on.iter().any(|d| d == dep) {
+            wisker.depends_on.push(dep.to_string());
+          }
+        }
+      }
+      problems.extend(self.circular_depends());
+      match problems.is_empty() {
+        true => Ok(inferred),
+        false => Err(problems),
+      }
+    }
+
+    /// the cycles in the `depends_on` of the commands, each as the chain of names back to where it started
+    fn circular_depends(&self) -> Vec<String> {
+      fn visit<'a>(name: &'a str, config: &'a Config, path: &mut Vec<&'a str>, seen: &mut std::collections::HashSet<&'a str>, cycles: &mut Vec<String>) {
+        if let Some(i) = path.iter().position(|p| *p == name) {
+          cycles.push(format!("{}: depends_on is circular, {} -> {}", name, path[i..].join(" -> "), name));
+          return;
+        }
+        if !seen.insert(name) {
+          return;
+        }
+        path.push(name);
+        for dep in config.find(name).map(|w| w.depends_on.as_slice()).unwrap_or_default() {
+          visit(dep, config, path, seen, cycles);
+        }
+        path.pop();
+      }
+      let mut cycles = Vec::new();
+      let mut seen = std::collections::HashSet::new();
+      for (_, func) in self.stages() {
+        for wisker in func {
+          visit(&wisker.name, self, &mut Vec::new(), &mut seen, &mut cycles);
+        }
+      }
+      cycles
+    }
+
+    /// set every command to share its outfolder, if the config opts out of each
+    /// command having its own output folder, i.e. its reporters read fixed paths
+    pub fn share_outfolders(mut self) -> Self {
+      if self.shared_outfolders {
+        for wisker in self.wiskers.iter_mut().chain(self.enrichers.iter_mut()).chain(self.reporters.iter_mut()) {
+          wisker.shared_outfolder = true;
+        }
+      }
+      self
+    }
+  }
+
+  /// check an input path matches an output path, relative to the output folder, where either
+  /// can be a glob of the other, ignoring the case and the separators
+  fn paths_match(input: &str, output: &str) -> bool {
+    let normal = |p: &str| p.replace('\\', "/").trim_start_matches("./").trim_start_matches('/').to_lowercase();
+    let (input, output) = (normal(input), normal(output));
+    input == output
+      || glob::Pattern::new(&input).is_ok_and(|p| p.matches(&output))
+      || glob::Pattern::new(&output).is_ok_and(|p| p.matches(&input))
+  }
+
+  /// How a missing artefact is reported, where an error stops the run
+  #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+  #[serde(rename_all = "lowercase")]
+  pub enum Severity {
+    Info,
+    Warn,
+    Error,
+  }
+
+  /// The severity of an indicator, to rank the IOC hits so the highest are reviewed first
+  #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, JsonSchema)]
+  #[serde(rename_all = "lowercase")]
+  pub enum IocSeverity {
+    Low,
+    Medium,
+    High,
+    Critical,
+  }
+
+  /// Which outputs are removed at the end of a successful run, to reclaim the space
+  /// of the files only needed by the commands that read them
+  #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default, JsonSchema)]
+  #[serde(rename_all = "lowercase")]
+  pub enum Cleanup {
+    /// keep all the outputs
+    #[default]
+    None,
+    /// remove the outputs of the commands marked `intermediate: true`
+    Intermediate,
+    /// remove the outputs of the wiskers and enrichers that no reporter depends on
+    Aggressive,
   }
 
   /// Top level structure of artefacts config file
@@ -23,11 +236,35 @@ use serde::{Deserialize, Serialize};
     false
   }
 
+  /// The binary of a command, either the same on every OS or keyed by the OS,
+  /// i.e. `windows` and `linux`
+  #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+  #[serde(untagged)]
+  pub enum Binary {
+    Plain(String),
+    PerOs(std::collections::HashMap<String, String>),
+  }
+
+  impl Binary {
+    /// the binary for the OS wiskess is running on, none if it is keyed by OS without this one
+    pub fn for_os(&self) -> Option<&str> {
+      match self {
+        Binary::Plain(binary) => Some(binary),
+        Binary::PerOs(binaries) => binaries.get(std::env::consts::OS).map(|b| b.as_str()),
+      }
+    }
+
+    /// the binary for this OS, else empty
+    pub fn path(&self) -> &str {
+      self.for_os().unwrap_or_default()
+    }
+  }
+
   /// Configuration of the commands to run
-  #[derive(Debug, Serialize, Deserialize, Clone)]
+  #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
   pub struct Wiskers {
     pub name: String,
-    pub binary: String,
+    pub binary: Binary,
     pub args: String,
     pub outfolder: String,
     pub input: String,
@@ -46,6 +283,8 @@ use serde::{Deserialize, Serialize};
     pub deps_github: String,
     #[serde(default = "serde_true")]
     pub para: bool,
+    #[serde(default)]
+    pub order: i32,
     #[serde(default = "serde_false")]
     pub script: bool,
     #[serde(default)]
@@ -54,15 +293,148 @@ use serde::{Deserialize, Serialize};
     pub chk_exists: bool,
     #[serde(default)]
     pub valid_path: String,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    #[serde(default)]
+    pub outputs: Vec<String>,
+    #[serde(default)]
+    pub max_memory: u64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub date_column: String,
+    #[serde(default)]
+    pub date_format: String,
+    #[serde(default)]
+    pub expected_columns: Vec<String>,
+    #[serde(default)]
+    pub quick: bool,
+    #[serde(default)]
+    pub run_if_artefact: String,
+    #[serde(default)]
+    pub shared_outfolder: bool,
+    #[serde(default)]
+    pub encoding: String,
+    #[serde(default)]
+    pub post_filter: Option<PostFilter>,
+    #[serde(default)]
+    pub server: bool,
+    #[serde(default)]
+    pub request: String,
+    #[serde(default)]
+    pub server_ready: String,
+    #[serde(default)]
+    pub min_version: String,
+    #[serde(default)]
+    pub expected_version: String,
+    #[serde(default)]
+    pub intermediate: bool,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub exit_code_messages: std::collections::HashMap<i32, String>,
+    #[serde(default)]
+    pub idempotent: bool,
+    #[serde(default)]
+    pub benchmark_input: String,
+    #[serde(default)]
+    pub output_must_match: String,
+    #[serde(default)]
+    pub output_must_not_match: String,
+  }
+
+  impl Wiskers {
+    /// check if the command is selected by the tags, matching any or all of them.
+    /// No tags selects every command
+    pub fn has_tags(&self, tags: &[String], match_all: bool) -> bool {
+      if tags.is_empty() {
+        return true;
+      }
+      let tagged = |t: &String| self.tags.iter().any(|s| s.eq_ignore_ascii_case(t));
+      if match_all {
+        tags.iter().all(tagged)
+      } else {
+        tags.iter().any(tagged)
+      }
+    }
+
+    /// check if the command is selected to run by the --tag, --quick and --retry-failed filters
+    pub fn selected(&self, main_args: &MainArgs) -> bool {
+      self.has_tags(&main_args.tags, main_args.tag_all)
+        && !matches!(main_args.quick, Some(q) if q != self.quick)
+        && (main_args.retry.is_empty() || main_args.retry.contains(&self.name))
+    }
+
+    /// the paths of the outputs of the command relative to the output folder, to infer the
+    /// commands that read them, which are its `outputs`, else its outfile
+    pub fn output_paths(&self) -> Vec<String> {
+      match self.outputs.is_empty() {
+        true => vec![self.out_file("").trim_start_matches('/').to_string()],
+        false => self.outputs.clone(),
+      }
+    }
+
+    /// the output folder of the command relative to the out_path, which is a
+    /// subfolder of the outfolder named by the entry, so commands writing the same
+    /// file name don't overwrite each other, unless it shares the outfolder. A command
+    /// with a category is put under the folder of its category instead, i.e. registry
+    pub fn out_dir(&self) -> String {
+      let folder = match self.category.is_empty() {
+        true => self.outfolder.to_string(),
+        false => file_ops::safe_name(&self.category),
+      };
+      match self.shared_outfolder {
+        true => folder,
+        false => format!("{}/{}", folder, file_ops::safe_name(&self.name)),
+      }
+    }
+
+    /// the path of the output file of the command, which may be a glob. The `${name}`
+    /// variables of the artefact instances match any, so it finds the output of each
+    pub fn out_file(&self, out_path: &str) -> String {
+      static VARIABLE: OnceLock<regex::Regex> = OnceLock::new();
+      let variable = VARIABLE.get_or_init(|| regex::Regex::new(r"\$\{\w+\}").unwrap());
+      let outfile = variable.replace_all(&self.outfile, "*").to_string();
+      format!("{}/{}/{}", out_path, self.out_dir(), outfile)
+    }
+  }
+
+  /// Filter of the csv or jsonl output of a command, to select columns and the rows
+  /// that match a pattern, written to a companion file so the original is kept
+  #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+  pub struct PostFilter {
+    #[serde(default)]
+    pub columns: Vec<String>,
+    #[serde(default)]
+    pub pattern: String,
+    #[serde(default)]
+    pub column: String,
+  }
+
+  /// Merge of the csv outputs of commands, removing duplicate rows by the key columns
+  #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+  pub struct Dedupe {
+    pub name: String,
+    pub inputs: Vec<String>,
+    #[serde(default)]
+    pub key_columns: Vec<String>,
+    pub outfolder: String,
+    pub outfile: String,
   }
 
   /// Artefact paths and type
-  #[derive(Debug, Serialize, Deserialize, Clone)]
+  #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
   pub struct Artefacts {
     pub name: String,
     pub path: String,
     #[serde(default)]
     pub legacy: String,
+    /// the fewest paths the glob is expected to match, i.e. the event log channels, where
+    /// fewer is reported as an incomplete collection. 0 for any number
+    #[serde(default)]
+    pub min_matches: usize,
   }
 
   // Set struct for interal args
@@ -75,7 +447,91 @@ use serde::{Deserialize, Serialize};
       pub ioc_file: String,
       pub silent: bool,
       pub out_log: String,
-      pub multi_pb: MultiProgress
+      pub multi_pb: MultiProgress,
+      pub run_id: String,
+      pub max_memory: u64,
+      pub threads: usize,
+      pub integrity: bool,
+      pub strict_integrity: bool,
+      pub window_check: bool,
+      pub strict_window: bool,
+      pub strict_matches: bool,
+      pub results: Arc<Mutex<Vec<CmdResult>>>,
+      pub tags: Vec<String>,
+      pub tag_all: bool,
+      pub deadline: Option<Instant>,
+      pub iocs: Option<Arc<Secret>>,
+      pub redact: bool,
+      pub simulate_failure: Vec<String>,
+      pub source_prefix: String,
+      pub scratch: String,
+      pub scratch_redirect: bool,
+      pub quick: Option<bool>,
+      pub completed_file: String,
+      pub art_patterns: std::collections::HashMap<String, String>,
+      pub max_output: u64,
+      pub output_policy: String,
+      pub intermediates: Vec<Intermediate>,
+      pub out_dirs: std::collections::HashMap<String, String>,
+      pub prewarm: bool,
+      pub output_prefix: String,
+      pub jobs: usize,
+      pub retry: Vec<String>,
+      pub case_name: String,
+      pub ignore: Ignore,
+      pub raw_copy: bool,
+      pub max_artefacts: usize,
+      pub max_artefacts_total: usize,
+      pub launch_rate: f64,
+  }
+
+  /// The glob patterns of the paths excluded from the artefacts, from the .wiskessignore of the
+  /// data source or --ignore-file, matched under the root of the data source
+  #[derive(Debug, Clone, Default)]
+  pub struct Ignore {
+      pub root: String,
+      pub patterns: Vec<glob::Pattern>,
+  }
+
+  impl Ignore {
+    /// the patterns of an ignore file under the root of the data source, where a pattern that
+    /// is not valid is an error
+    pub fn new(root: &str, patterns: &[String]) -> Result<Self, String> {
+      let patterns = patterns.iter()
+        .map(|p| glob::Pattern::new(p.trim_matches('/')).map_err(|e| format!("invalid ignore pattern '{}'. Error: {}", p, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+      Ok(Ignore { root: root.to_string(), patterns })
+    }
+
+    /// check if a path is excluded, where a pattern with a / is matched against the path
+    /// under the root, and one without against the name of the file or any folder of it,
+    /// as in .gitignore. A folder that is excluded excludes all in it
+    pub fn is_ignored(&self, path: &str) -> bool {
+      if self.patterns.is_empty() {
+        return false;
+      }
+      let rel = std::path::Path::new(path).strip_prefix(&self.root).unwrap_or(std::path::Path::new(path));
+      let rel = rel.to_string_lossy().replace('\\', "/");
+      let parts: Vec<&str> = rel.split('/').filter(|p| !p.is_empty()).collect();
+      let options = glob::MatchOptions {
+        case_sensitive: !cfg!(windows),
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+      };
+      self.patterns.iter().any(|pattern| match pattern.as_str().contains('/') {
+        true => (1..=parts.len()).any(|n| pattern.matches_with(&parts[..n].join("/"), options)),
+        false => parts.iter().any(|part| pattern.matches_with(part, options)),
+      })
+    }
+  }
+
+  /// The output of a command marked `intermediate`, that can be evicted to keep the
+  /// output under --max-output-size once the commands that depend on it have run
+  #[derive(Debug, Clone)]
+  pub struct Intermediate {
+      pub name: String,
+      pub out_file: String,
+      pub dependents: Vec<String>,
   }
 
   // Set struct for setup args
@@ -99,6 +555,83 @@ use serde::{Deserialize, Serialize};
     pub in_link: String,
     pub out_link: String,
     pub update: bool,
-    pub keep_evidence: bool
+    pub keep_evidence: bool,
+    pub run_id: String,
+    pub case_name: String,
+    pub prefix_run_id: bool,
+    pub resume_download: bool,
+    pub download_concurrency: usize,
+    pub source_prefix: String,
+    pub token_refresh_command: String,
+    pub encrypt_output: bool,
+    pub encrypt_key: String,
+    pub output_root_link: String,
+    pub list_remote: bool,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub storage_type: String,
+    pub download_command: String,
+    pub upload_command: String,
+    pub upload_if_valid: bool,
+    pub valid_threshold: u8,
+    pub no_download: bool,
+    pub out_suffix: String,
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+
+    fn config(wiskers: &str, enrichers: &str) -> Config {
+      serde_yaml::from_str(&format!("wiskers:\n{}enrichers:\n{}reporters: []\n", wiskers, enrichers)).unwrap()
+    }
+
+    fn command(name: &str, extra: &str) -> String {
+      format!("- {{name: {}, binary: b, args: a, outfolder: Out, input: none, outfile: {}.csv{}}}\n", name, name, extra)
+    }
+
+    #[test]
+    fn depends_are_inferred_from_the_outputs_an_input_reads() {
+      let mut config = config(
+        &format!("{}{}", command("mft", ""), command("evtx", ", outputs: [Out/evtx/*.csv]")),
+        &format!("{}{}", command("timeline", ", inputs: ['out\\mft\\mft.csv', Out/evtx/Security.csv]"), command("other", "")),
+      );
+      let inferred = config.infer_depends().unwrap();
+      assert_eq!(inferred, vec![
+        "timeline depends on mft, by its input out\\mft\\mft.csv",
+        "timeline depends on evtx, by its input Out/evtx/Security.csv",
+      ]);
+      assert_eq!(config.find("timeline").unwrap().depends_on, vec!["mft", "evtx"]);
+      assert!(config.find("other").unwrap().depends_on.is_empty());
+      // a dependency already written is not added again
+      assert!(config.infer_depends().is_ok());
+      assert_eq!(config.find("timeline").unwrap().depends_on, vec!["mft", "evtx"]);
+    }
+
+    #[test]
+    fn nothing_is_inferred_without_inputs() {
+      let mut config = config(&command("mft", ", depends_on: [mft]"), "");
+      assert_eq!(config.infer_depends(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn an_input_of_the_outputs_of_two_commands_is_ambiguous() {
+      let mut config = config(
+        &format!("{}{}", command("a", ", outputs: [Out/shared.csv]"), command("b", ", outputs: [Out/*.csv]")),
+        &command("c", ", inputs: [Out/shared.csv]"),
+      );
+      let problems = config.infer_depends().unwrap_err();
+      assert_eq!(problems, vec!["c: the input 'Out/shared.csv' matches the outputs of a, b, so which it depends on is ambiguous"]);
+    }
+
+    #[test]
+    fn circular_depends_are_an_error() {
+      let mut config = config(
+        &format!("{}{}", command("a", ", inputs: [Out/b/b.csv]"), command("b", ", depends_on: [a]")),
+        "",
+      );
+      let problems = config.infer_depends().unwrap_err();
+      assert_eq!(problems, vec!["a: depends_on is circular, a -> b -> a"]);
+    }
   }
 }
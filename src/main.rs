@@ -4,15 +4,16 @@ mod art;
 mod init;
 
 use crate::configs::config;
-use crate::ops::{file_ops, exe_ops};
+use crate::ops::{file_ops, exe_ops, config_ops, summary_ops, ioc_ops, table_ops, secret_ops, crypt_ops, trace_ops, mount_ops, usage_ops, report_ops, provenance_ops, status_ops, bench_ops};
 use crate::art::paths;
 use crate::init::{scripts, setup};
 use ops::valid_ops;
-use serde_yaml::{self};
 
-use std::fs::OpenOptions;
 use std::{path::Path,env};
-use clap::{Parser, ArgAction, Subcommand};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use clap::{Parser, ArgAction, Subcommand, CommandFactory, FromArgMatches};
+use clap::parser::ValueSource;
 use chrono::Utc;
 use ctrlc;
 use indicatif::MultiProgress;
@@ -25,11 +26,19 @@ use rand::seq::SliceRandom;
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// tool path, where binaries are stored. default gets from env var set internaly
-    #[arg(short, long, default_value = "")]
+    #[arg(short, long, default_value = "", env = "WISKESS_TOOL_PATH")]
     tool_path: String,
+    /// file of default args, i.e. `tool_path = 'D:\tools'`, used for the args not given on the command line.
+    /// default reads .wiskess.toml in the working directory, if it exists
+    #[arg(long, global = true, default_value = "")]
+    env_file: String,
     /// Silent mode, no user input
     #[arg(short, long, action = ArgAction::SetTrue)]
     silent: bool,
+    /// Name of the case the run belongs to, included in the log, run summary, reports and traces,
+    /// and set in the config args with {case}. default is the name of the output folder
+    #[arg(long, global = true, default_value = "")]
+    case_name: String,
     #[clap(subcommand)]
     command: Commands,
 }
@@ -45,68 +54,465 @@ enum Commands {
         #[arg(short, long)]
         verbose: bool
     },
-    /// whipped pipeline process commands
-    Whipped {
+    /// check the config for mistakes without running it
+    Lint {
         /// config file of the binaries to run as processors
-        #[arg(short, long, default_value = "config/main_win.yaml")]
+        #[arg(short, long, default_value = "config/main_win.yaml", env = "WISKESS_CONFIG")]
         config: String,
         /// config file of the artefact file paths
-        #[arg(short, long, default_value = "config/artefacts.yaml")]
+        #[arg(short, long, default_value = "config/artefacts.yaml", env = "WISKESS_ARTEFACTS_CONFIG")]
         artefacts_config: String,
-        /// file path to the data source; either mounted or the root folder
+        /// Run the help and version of each tool, to check the flags in the config are in its help, and its
+        /// version against the min_version or expected_version of the entry
+        #[arg(long)]
+        check_tools: bool,
+    },
+    /// print the JSON Schema of the config, for editors to validate a config
+    Schema {
+        /// file to write the schema to, instead of printing it
+        #[arg(short, long, default_value = "")]
+        out_file: String,
+    },
+    /// list the output files of the last run, grouped by stage
+    Outputs {
+        /// output folder of the run, that has the run_summary.json
         #[arg(short, long)]
-        data_source_list: String,
-        /// file path where the data is temporarily downloaded to and Wiskess output is stored locally
+        out_path: String,
+    },
+    /// print the summary of the last run again, without reprocessing
+    Summary {
+        /// output folder of the run, that has the run_summary.json
         #[arg(short, long)]
-        local_storage: String,
-        /// Start date - typically the earliest time of the incident, or a few days before
-        #[arg(long)]
-        start_date: String,
-        /// End date - the current date or end of the incident timeframe
-        #[arg(long)]
-        end_date: String,
-        /// IOC list file
+        out_path: String,
+        /// Exit with an error if the percent of the commands that ran that are valid is below this,
+        /// i.e. to check the run before its results are uploaded
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+        min_valid: Option<u8>,
+    },
+    /// render a report of the last run from a template, i.e. a branded html or markdown report
+    Report {
+        /// output folder of the run, that has the run_summary.json
         #[arg(short, long)]
-        ioc_file: String,
-        /// The link that the data is stored on, i.e https://myaccount.file.core.windows.net/myclient/?sp=rl&st=...VWjgWTY8uc%3D&sr=s
-        #[arg(long)]
-        in_link: String,
-        /// The link where you need the wiskess output uploaded to, 
-        /// i.e. https://myaccount.file.core.windows.net/results/myclient/?sp=rcwl&st=2023-04-21T20...2FZWEA%3D&sr=s
-        #[arg(long)]
-        out_link: String,
-        /// Set this flag to update the Wiskess results, such as changing the timeframe or after adding new IOCs to the list.
+        out_path: String,
+        /// template of the report, with the variables of the run, i.e. {{run_id}} and {{#each commands}}
         #[arg(short, long)]
-        update: bool,
-        /// Set this flag to keep the downloaded data on your local storage. Useful if wanting to process the data after Wiskess. 
-        /// Caution: make sure you have enough disk space for all the data source list.
+        template: String,
+    },
+    /// encrypt the files of an output folder with AES-256-GCM, i.e. before uploading them
+    Encrypt {
+        /// the wiskess output folder
+        #[arg(short, long)]
+        out_path: String,
+        /// file of the 256 bit key, as 64 hex characters or 32 bytes
         #[arg(short, long)]
-        keep_evidence: bool,
+        key_file: String,
     },
-    /// process the data with wiskess
-    Wiskess {
+    /// decrypt the files of an output folder encrypted with encrypt or --encrypt-output
+    Decrypt {
+        /// the folder with the .enc files and the wiskess_encryption.json manifest
+        #[arg(short, long)]
+        out_path: String,
+        /// file of the key used to encrypt the files
+        #[arg(short, long)]
+        key_file: String,
+    },
+    /// merge the summaries of several runs of a case into one overview
+    Merge {
+        /// the run_summary.json files, or the wiskess output folders that have them
+        #[arg(required = true)]
+        runs: Vec<String>,
+        /// output folder of case_overview.json and case_overview.html
+        #[arg(short, long, default_value = ".")]
+        out_path: String,
+    },
+    /// run one command of the config against one artefact, i.e. to rerun a tool after changing its config
+    Run {
         /// config file of the binaries to run as processors
-        #[arg(short, long, default_value = "config/main_win.yaml")]
+        #[arg(short, long, default_value = "config/main_win.yaml", env = "WISKESS_CONFIG")]
         config: String,
-        /// config file of the artefact file paths
-        #[arg(short, long, default_value = "config/artefacts.yaml")]
-        artefacts_config: String,
-        /// file path to the data source; either mounted or the root folder
+        /// name of the command in the config to run
         #[arg(short, long)]
-        data_source: String,
-        /// output folder that will be the destination of the processed results
+        wisker: String,
+        /// file path to the artefact to run it against, as its {input}
+        #[arg(short, long)]
+        artefact: String,
+        /// output folder of the run, the command writes to its usual folder in it
         #[arg(short, long)]
         out_path: String,
-        /// Start date - typically the earliest time of the incident, or a few days before
-        #[arg(long)]
+        /// Start date, if the command uses {start_date}
+        #[arg(long, default_value = "")]
         start_date: String,
-        /// End date - the current date or end of the incident timeframe
-        #[arg(long)]
+        /// End date, if the command uses {end_date}
+        #[arg(long, default_value = "")]
         end_date: String,
-        /// IOC list file
-        #[arg(short, long)]
+        /// IOC list file, if the command uses {ioc_file}
+        #[arg(short, long, default_value = "", env = "WISKESS_IOC_FILE")]
         ioc_file: String,
-    }
+    },
+    /// whipped pipeline process commands
+    Whipped(Box<WhippedCommand>),
+    /// process the data with wiskess
+    Wiskess(Box<WiskessCommand>),
+}
+
+/// the args of the whipped command, boxed as they are much larger than those of most other commands
+#[derive(Debug, clap::Args)]
+struct WhippedCommand {
+    /// config file of the binaries to run as processors
+    #[arg(short, long, default_value = "config/main_win.yaml", env = "WISKESS_CONFIG")]
+    config: String,
+    /// config file of the artefact file paths
+    #[arg(short, long, default_value = "config/artefacts.yaml", env = "WISKESS_ARTEFACTS_CONFIG")]
+    artefacts_config: String,
+    /// file path to the data source; either mounted or the root folder. Each can have its own in link
+    /// after a '|', i.e. "host1.E01 | s3://bucket/case", to download it from another storage
+    #[arg(short, long)]
+    data_source_list: String,
+    /// file path where the data is temporarily downloaded to and Wiskess output is stored locally
+    #[arg(short, long, env = "WISKESS_LOCAL_STORAGE")]
+    local_storage: String,
+    /// Start date - typically the earliest time of the incident, or a few days before
+    #[arg(long)]
+    start_date: String,
+    /// End date - the current date or end of the incident timeframe
+    #[arg(long)]
+    end_date: String,
+    /// IOC list file
+    #[arg(short, long, env = "WISKESS_IOC_FILE")]
+    ioc_file: String,
+    /// The link that the data is stored on, i.e https://myaccount.file.core.windows.net/myclient/?sp=rl&st=...VWjgWTY8uc%3D&sr=s
+    #[arg(long, env = "WISKESS_IN_LINK", hide_env_values = true, default_value = "", required_unless_present = "no_download")]
+    in_link: String,
+    /// The link where you need the wiskess output uploaded to, 
+    /// i.e. https://myaccount.file.core.windows.net/results/myclient/?sp=rcwl&st=2023-04-21T20...2FZWEA%3D&sr=s
+    #[arg(long, env = "WISKESS_OUT_LINK", hide_env_values = true)]
+    out_link: String,
+    /// Set this flag to update the Wiskess results, such as changing the timeframe or after adding new IOCs to the list.
+    #[arg(short, long)]
+    update: bool,
+    /// Set this flag to keep the downloaded data on your local storage. Useful if wanting to process the data after Wiskess. 
+    /// Caution: make sure you have enough disk space for all the data source list.
+    #[arg(short, long)]
+    keep_evidence: bool,
+    /// Correlation id for this run, shared by all data sources processed. default generates a UUID
+    #[arg(long, default_value = "")]
+    run_id: String,
+    /// Prefix the uploaded result paths with the run id, i.e. out_link/<run_id>/<image>-Wiskess
+    #[arg(long)]
+    prefix_run_id: bool,
+    /// Download single files in chunks to a .part file, which resumes from the last completed byte when rerun
+    #[arg(long)]
+    resume_download: bool,
+    /// Maximum simultaneous connections when downloading, to stay within the rate limits of the storage.
+    /// default 0 uses the default of the transfer tool
+    #[arg(long, default_value_t = 0)]
+    download_concurrency: usize,
+    /// Map the artefact paths of the config onto the layout of the downloaded files, passed to wiskess, see its --source-prefix
+    #[arg(long, default_value = "")]
+    source_prefix: String,
+    /// Command run to get a fresh in_link/out_link when a transfer fails as the token expired, 
+    /// outputting json i.e. {"in_link": "...", "out_link": "..."}. The transfer is then retried
+    #[arg(long, default_value = "")]
+    token_refresh_command: String,
+    /// Encrypt the wiskess output with AES-256-GCM before it is uploaded, using the key of --encrypt-key
+    #[arg(long, requires = "encrypt_key")]
+    encrypt_output: bool,
+    /// File of the 256 bit key to encrypt the output, as 64 hex characters or 32 bytes
+    #[arg(long, default_value = "")]
+    encrypt_key: String,
+    /// Upload each output file to this link as the command that made it completes, rather than all at the end,
+    /// i.e. the same as out_link. The final upload skips those already uploaded
+    #[arg(long, default_value = "")]
+    output_root_link: String,
+    /// List the files that would be downloaded for the data source list with their sizes and the total, then exit
+    #[arg(long)]
+    list_remote: bool,
+    /// Only download the files of the data sources that match this glob, by their path under the data source
+    /// or their name, i.e. *.evtx. Can be repeated
+    #[arg(long)]
+    include: Vec<String>,
+    /// Don't download the files of the data sources that match this glob, i.e. pagefile.sys. Can be repeated
+    #[arg(long)]
+    exclude: Vec<String>,
+    /// The storage of the links, where auto uses Azure or AWS by the link, and custom transfers with
+    /// --download-command and --upload-command, i.e. rclone
+    #[arg(long, default_value = "auto", value_parser = ["auto", "custom"])]
+    storage_type: String,
+    /// Command to download with the custom storage, with ${remote}, ${local} and ${link},
+    /// i.e. "rclone copy ${remote} ${local}"
+    #[arg(long, default_value = "", required_if_eq("storage_type", "custom"))]
+    download_command: String,
+    /// Command to upload with the custom storage, with ${remote}, ${local} and ${link},
+    /// i.e. "rclone copy ${local} ${remote}"
+    #[arg(long, default_value = "", required_if_eq("storage_type", "custom"))]
+    upload_command: String,
+    /// Free space in MB the volume of the local storage needs to start. 0 is no minimum
+    #[arg(long, default_value_t = 0)]
+    min_free_local: u64,
+    /// File of the glob patterns of the paths to not download, one per line as in a .wiskessignore,
+    /// which are added to --exclude
+    #[arg(long, default_value = "")]
+    ignore_file: String,
+    /// What to do if a volume has less than its minimum free space: fail to stop, or warn
+    #[arg(long, value_parser = ["fail", "warn"], default_value = "fail")]
+    low_space: String,
+    /// Only upload the results of a data source if its run is valid, else leave them on the local storage
+    #[arg(long)]
+    upload_if_valid: bool,
+    /// The percent of the commands that ran that must be valid to upload the results, with --upload-if-valid
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(0..=100))]
+    valid_threshold: u8,
+    /// Don't download the data sources, but process those already downloaded to the local storage, i.e. with
+    /// another --config. The downloaded data is kept, and the output written to a new folder, see --out-suffix
+    #[arg(long, conflicts_with_all = ["update", "list_remote"])]
+    no_download: bool,
+    /// Added to the name of the output folder of each data source, i.e. <image>-Wiskess-<suffix>, to keep the
+    /// output of each config apart. default is none, or the name of the config file with --no-download
+    #[arg(long, default_value = "")]
+    out_suffix: String,
+}
+
+/// the args of the wiskess command, boxed as they are much larger than those of most other commands
+#[derive(Debug, clap::Args)]
+struct WiskessCommand {
+    /// config file of the binaries to run as processors. If not given, and not --silent, the config
+    /// is picked from a list of those in --configs-dir
+    #[arg(short, long, default_value = "config/main_win.yaml", env = "WISKESS_CONFIG")]
+    config: String,
+    /// folder of the configs to pick from, when --config is not given
+    #[arg(long, default_value = "config", env = "WISKESS_CONFIGS_DIR")]
+    configs_dir: String,
+    /// file of the glob patterns of the paths to exclude from the artefacts, one per line.
+    /// default reads .wiskessignore at the root of the data source, if it exists
+    #[arg(long, default_value = "")]
+    ignore_file: String,
+    /// config file of the artefact file paths
+    #[arg(short, long, default_value = "config/artefacts.yaml", env = "WISKESS_ARTEFACTS_CONFIG")]
+    artefacts_config: String,
+    /// file path to the data source; either mounted, the root folder, a disk image (E01, dd, raw, vmdk, vhdx)
+    /// that is mounted read-only for the run, or `live` for the filesystem of this machine
+    #[arg(short, long)]
+    data_source: String,
+    /// What to do with the artefacts that are locked or permission denied, i.e. the registry hives of a live
+    /// system: raw to copy them by reading the NTFS volume when the data source is a drive, or skip them
+    #[arg(long, value_parser = ["raw", "skip"], default_value = "raw")]
+    locked_files: String,
+    /// The paths an artefact with capture groups can match, each run by its commands, before the run
+    /// is stopped as the pattern is too broad. 0 is no limit
+    #[arg(long, default_value_t = 1000)]
+    max_artefacts: usize,
+    /// The paths all the artefacts with capture groups can match together, before the run is stopped. 0 is no limit
+    #[arg(long, default_value_t = 5000)]
+    max_artefacts_total: usize,
+    /// The helper to mount a disk image data source read-only, with {image} and {mount} for the temp folder
+    /// to mount it to. Default uses ewfmount and guestmount on Linux, and Arsenal Image Mounter on Windows
+    #[arg(long, default_value = "")]
+    mount_command: String,
+    /// The helper to unmount the disk image at the end of the run, with {image} and {mount}
+    #[arg(long, default_value = "")]
+    unmount_command: String,
+    /// output folder that will be the destination of the processed results
+    #[arg(short, long)]
+    out_path: String,
+    /// Start date - typically the earliest time of the incident, or a few days before
+    #[arg(long)]
+    start_date: String,
+    /// End date - the current date or end of the incident timeframe
+    #[arg(long)]
+    end_date: String,
+    /// IOC list file
+    #[arg(short, long, default_value = "", required_unless_present = "ioc_secret", env = "WISKESS_IOC_FILE")]
+    ioc_file: String,
+    /// Map the {root} of the artefact paths onto where the files are in the data source: a subfolder,
+    /// i.e. uploads/auto/C%3A, `from=to` to replace the start of the paths, or `auto` to find the folder with Windows
+    #[arg(long, default_value = "")]
+    source_prefix: String,
+    /// Folder for the working and temp files of the commands, set as {scratch} in the config args, 
+    /// i.e. when the data source is a read-only mount. It is removed at the end unless --keep-evidence
+    #[arg(long, default_value = "")]
+    scratch_dir: String,
+    /// Keep the scratch dir at the end of the run
+    #[arg(long)]
+    keep_evidence: bool,
+    /// Put the scratch of the run in its own folder in the --scratch-dir, wiskess_<run_id>,
+    /// so runs can share the scratch dir and only the folder of the run is removed at the end
+    #[arg(long)]
+    scratch_per_run: bool,
+    /// Overlay config deep merged onto the config, adding entries, overriding fields by name,
+    /// or removing entries with `delete: true`. Can be repeated, applied in order
+    #[arg(long)]
+    overlay: Vec<String>,
+    /// Read the config from an Azure Key Vault secret uri into memory, instead of --config
+    #[arg(long, default_value = "")]
+    config_secret: String,
+    /// Read the IOC list from an Azure Key Vault secret uri into memory, instead of --ioc-file
+    #[arg(long, default_value = "")]
+    ioc_secret: String,
+    /// Correlation id for this run, included in the log and run summary. default generates a UUID
+    #[arg(long, default_value = "")]
+    run_id: String,
+    /// Memory limit in MB for each command and its child processes, which the OS enforces on each
+    /// process, and the tree is killed if their total exceeds it. 0 is no limit, and `max_memory` in the config overrides this per command
+    #[arg(long, default_value_t = 0)]
+    max_command_memory: u64,
+    /// Threads for each command to use, set in the config args with {threads}. 
+    /// default is the number of cores divided by the commands running at the same time
+    #[arg(long, default_value_t = 0)]
+    threads_per_command: usize,
+    /// Time the run can take, after which no more commands are started and wiskess
+    /// finishes with the outputs that completed, i.e. 2h, 90m or 30s (minutes if no unit). 0 is no limit
+    #[arg(long, default_value = "0", value_parser = parse_runtime)]
+    max_runtime: u64,
+    /// Size in MB the output folder can grow to, checked as each command completes. 0 is no limit
+    #[arg(long, default_value_t = 0)]
+    max_output_size: u64,
+    /// What to do once the output is over --max-output-size: warn, abort to skip the commands left, or evict
+    /// the oldest outputs of the commands marked `intermediate: true` that no command left to run depends on
+    #[arg(long, value_parser = ["warn", "abort", "evict"], default_value = "warn")]
+    output_size_policy: String,
+    /// Read through the artefacts of the commands before they run in parallel, so they are in the
+    /// OS cache instead of read cold by several at once, i.e. for evidence on a network share
+    #[arg(long)]
+    prewarm: bool,
+    /// Launch the processes of the parallel commands at no more than this many per second, staggering
+    /// them to smooth the burst of process creation as the pool starts. 0 for no limit
+    #[arg(long, default_value_t = 0.0)]
+    launch_rate: f64,
+    /// Threads to validate the outputs with after the run, hashing and counting the rows of each.
+    /// default 0 uses all the cores
+    #[arg(long, default_value_t = 0)]
+    jobs: usize,
+    /// Run only the commands that failed or were killed in the previous run of the output folder, and those
+    /// that depend on them, reusing the outputs of the rest, i.e. after fixing a tool that failed
+    #[arg(long)]
+    retry_failed: bool,
+    /// Check each artefact is unchanged after the commands that read it, warning if it changed during processing
+    #[arg(long)]
+    integrity_check: bool,
+    /// As --integrity-check, but fail the command if its artefact changed during processing
+    #[arg(long)]
+    strict_integrity: bool,
+    /// Check the timestamps in the `date_column` of the outputs are within the start and end date,
+    /// warning if many are outside as the tool may not honour the timeframe
+    #[arg(long)]
+    window_check: bool,
+    /// As --window-check, but fail the command if many of its timestamps are outside the start and end date
+    #[arg(long)]
+    strict_window: bool,
+    /// Stop the run if an artefact matches fewer paths than its min_matches in the artefacts config,
+    /// rather than warn, as the collection may be incomplete
+    #[arg(long)]
+    strict_matches: bool,
+    /// Stop the run before it starts if two commands would write the same output file, in this run or the
+    /// previous into the output folder, rather than warn
+    #[arg(long)]
+    strict_outputs: bool,
+    /// Run each wisker on its input one at a time, i.e. a small sample data source or its benchmark_input,
+    /// and report the wall time and bytes per second of each tool to benchmark.json, instead of the run
+    #[arg(long)]
+    benchmark: bool,
+    /// Only run these stages, reusing the existing outputs of the others. Can be repeated
+    #[arg(long, value_parser = ["wiskers", "enrichers", "reporters"])]
+    only_stage: Vec<String>,
+    /// Resume a run from this stage, skipping the stages before it and reusing their outputs, which
+    /// must exist for the commands that depend on them
+    #[arg(long, value_parser = ["enrichers", "reporters"], conflicts_with = "only_stage")]
+    continue_from_stage: Option<String>,
+    /// Run the stages in one pool, starting each command once what it depends on has finished,
+    /// so the enrichers and reporters overlap the wiskers still running
+    #[arg(long)]
+    parallel_stages: bool,
+    /// Only run the commands with this tag in the config, across all stages. Can be repeated
+    #[arg(long)]
+    tag: Vec<String>,
+    /// Whether a command must have any or all of the tags given with --tag
+    #[arg(long, value_parser = ["any", "all"], default_value = "any")]
+    tag_mode: String,
+    /// Testing only: fail the named command without running it, or time it out with name=timeout. 
+    /// Requires the env var WISKESS_ALLOW_SIMULATE=1. Can be repeated
+    #[arg(long, hide = true)]
+    simulate_failure: Vec<String>,
+    /// Testing only: fix the time of the run to this RFC 3339 time, i.e. 2024-01-01T00:00:00Z, for the
+    /// times in the log, outputs and summary and the {time} in the file names, so two runs can be diffed
+    #[arg(long, hide = true, value_parser = parse_fixed_time)]
+    fixed_time: Option<chrono::DateTime<Utc>>,
+    /// Write the results of each command to `out_path/results.csv`, for review in a spreadsheet
+    #[arg(long)]
+    results_csv: bool,
+    /// Append the output files of each command to this file as it completes, i.e. for whipped to upload them
+    #[arg(long, default_value = "")]
+    completed_file: String,
+    /// Append the timing spans of the run, stages and commands to this file as OpenTelemetry json, for a tracing backend
+    #[arg(long, default_value = "")]
+    trace_file: String,
+    /// Write provenance.json, linking each output file to the artefacts it was made from, the tool,
+    /// its version and the command line, for an auditable lineage of the results
+    #[arg(long)]
+    provenance: bool,
+    /// Also write the provenance as a Graphviz graph, provenance.dot
+    #[arg(long, requires = "provenance")]
+    provenance_dot: bool,
+    /// Hash the outputs of the commands that succeeded with SHA-256 during validation, for the chain
+    /// of custody, written to output_hashes.json
+    #[arg(long)]
+    hash_outputs: bool,
+    /// The number of outputs hashed at the same time with --hash-outputs and --manifest, to bound the load on the disk
+    #[arg(long, default_value_t = 4)]
+    hash_jobs: usize,
+    /// Write manifest.json of every file in the output folder, by its path relative to it, with its size,
+    /// SHA-256, and the command that made it, so the folder can be handed off and checked on its own
+    #[arg(long)]
+    manifest: bool,
+    /// Free space in MB the volume of the output needs to start. 0 is no minimum
+    #[arg(long, default_value_t = 0)]
+    min_free_output: u64,
+    /// Free space in MB the volume of the scratch needs to start, which is added to that of the
+    /// output if they are on the same volume. 0 is no minimum
+    #[arg(long, default_value_t = 0)]
+    min_free_scratch: u64,
+    /// What to do if a volume has less than its minimum free space: fail to stop, or warn
+    #[arg(long, value_parser = ["fail", "warn"], default_value = "fail")]
+    low_space: String,
+    /// Remove outputs at the end of a successful run: none to keep all, intermediate for the outputs of the
+    /// commands marked `intermediate: true`, or aggressive for those of the wiskers and enrichers that no
+    /// reporter depends on or reads by `{outdir:name}`, which is not run if a reporter reads `{out_path}`.
+    /// Overrides `cleanup` in the config
+    #[arg(long, value_parser = ["none", "intermediate", "aggressive"])]
+    cleanup: Option<String>,
+    /// The prefix of each line of the output of the commands in the log, with {name} of the command,
+    /// {time} the line was written and {stream} of stdout or stderr. An empty prefix logs the output as is
+    #[arg(long, default_value = exe_ops::DEFAULT_OUTPUT_PREFIX)]
+    output_prefix: String,
+    /// A regex of sensitive values to mask in the log, as well as SAS signatures, bearer tokens, access keys
+    /// and the values of secret env vars. Can be repeated
+    #[arg(long)]
+    redact_pattern: Vec<String>,
+    /// Only run the commands with `quick: true` in the config, for a fast initial triage
+    #[arg(long)]
+    quick: bool,
+    /// Run the quick commands first and write an early run summary, then run the rest
+    #[arg(long)]
+    quick_then_full: bool,
+    /// Print and log why each command in the config ran or was skipped, after the run
+    #[arg(long)]
+    explain: bool,
+    /// After the enrichers, scan their outputs for the IOCs and summarise the hits in ioc_hits.json
+    #[arg(long)]
+    ioc_hits: bool,
+    /// Also write the IOC hits as a STIX 2.1 bundle of indicators and their sightings in the
+    /// outputs, to hits.stix.json, for a threat intel platform to import
+    #[arg(long, requires = "ioc_hits")]
+    stix: bool,
+    /// Write the fully resolved config to `out_path/effective_config.yaml` before running
+    #[arg(long)]
+    dump_effective_config: bool,
+    /// Template of a report to render at the end of the run into `out_path/Reports`, i.e. a branded
+    /// html or markdown report. Can be repeated
+    #[arg(long)]
+    report_template: Vec<String>,
+    /// Write and print the fully resolved config, then exit without processing
+    #[arg(long)]
+    dump_effective_config_only: bool,
 }
 
 fn show_banner() {
@@ -122,18 +528,217 @@ fn show_banner() {
     println!("{}", style("version: 0.2.1").yellow());
 }
 
+/// parse the time given by --fixed-time, in RFC 3339
+fn parse_fixed_time(time: &str) -> Result<chrono::DateTime<Utc>, String> {
+    chrono::DateTime::parse_from_rfc3339(time)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| format!("'{}' is not an RFC 3339 time, i.e. 2024-01-01T00:00:00Z. Error: {}", time, e))
+}
+
+/// parse a runtime such as 2h, 90m or 30s into seconds, where no unit is minutes
+fn parse_runtime(runtime: &str) -> Result<u64, String> {
+    let runtime = runtime.trim().to_lowercase();
+    let (num, mult) = match runtime.chars().last() {
+        Some('h') => (&runtime[..runtime.len() - 1], 3600),
+        Some('m') => (&runtime[..runtime.len() - 1], 60),
+        Some('s') => (&runtime[..runtime.len() - 1], 1),
+        _ => (runtime.as_str(), 60),
+    };
+    num.parse::<u64>()
+        .map(|n| n * mult)
+        .map_err(|_| format!("'{}' is not a runtime, i.e. 2h, 90m or 30s", runtime))
+}
+
+/// format a duration as H:M:S
+fn format_duration(duration: chrono::Duration) -> String {
+    let seconds = duration.num_seconds() % 60;
+    let minutes = (duration.num_seconds() / 60) % 60;
+    let hours = (duration.num_seconds() / 60) / 60;
+    format!("{:0>2}:{:0>2}:{:0>2}", hours, minutes, seconds)
+}
+
+/// the file of default args in the working directory, read if --env-file is not given
+const ENV_FILE: &str = ".wiskess.toml";
+
+/// the args that can be set in the env file, which clap reads from the env var
+/// WISKESS_<ARG> when not given on the command line
+const ENV_ARGS: [&str; 7] = [
+    "tool_path",
+    "config",
+    "artefacts_config",
+    "ioc_file",
+    "local_storage",
+    "in_link",
+    "out_link",
+];
+
+/// load the env file into the env vars of the args, before the args are parsed
+/// so the command line overrides them
+///
+/// returns the args set from the env file
+fn load_env_file() -> Vec<String> {
+    let args: Vec<String> = env::args().collect();
+    let given = args.iter().position(|a| a == "--env-file").and_then(|i| args.get(i + 1).cloned())
+        .or_else(|| args.iter().find_map(|a| a.strip_prefix("--env-file=").map(|a| a.to_string())));
+    let env_file = match given {
+        Some(env_file) => env_file,
+        None if Path::new(ENV_FILE).is_file() => ENV_FILE.to_string(),
+        None => return Vec::new(),
+    };
+    let values = config_ops::read_env_file(&env_file).unwrap_or_else(|e| {
+        eprintln!("[!] Unable to read the env file {}. Error: {}", env_file, e);
+        std::process::exit(1);
+    });
+    let mut set = Vec::new();
+    for (key, value) in values {
+        if ENV_ARGS.contains(&key.as_str()) {
+            env::set_var(format!("WISKESS_{}", key.to_uppercase()), value);
+            set.push(key);
+        } else {
+            eprintln!("[!] Ignoring '{}' in the env file {}, it is not one of: {}", key, env_file, ENV_ARGS.join(", "));
+        }
+    }
+    set
+}
+
+/// get where each arg in the env file was resolved from, as the command line
+/// overrides it. The values are not given, as the links have SAS tokens
+fn env_sources(matches: &clap::ArgMatches, env_file: &str, keys: &[String]) -> Vec<String> {
+    keys.iter().map(|key| {
+        let source = [Some(matches), matches.subcommand().map(|(_, m)| m)].into_iter()
+            .flatten()
+            .filter(|m| m.ids().any(|id| id == key.as_str()))
+            .find_map(|m| m.value_source(key));
+        match source {
+            Some(ValueSource::CommandLine) => format!("[ ] {} is from the command line, overriding the env file {}", key, env_file),
+            Some(ValueSource::EnvVariable) => format!("[ ] {} is from the env file {}", key, env_file),
+            _ => format!("[ ] {} in the env file {} is not used by this command", key, env_file),
+        }
+    }).collect()
+}
+
+/// toggle the pause of the run on each SIGUSR1, so the machine can be yielded
+/// without stopping the run
+#[cfg(unix)]
+fn pause_on_signal() {
+    let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGUSR1])
+        .expect("Error setting the SIGUSR1 handler");
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            exe_ops::PAUSED.fetch_xor(true, std::sync::atomic::Ordering::SeqCst);
+        }
+    });
+}
+
+/// abandon the stage that is running on SIGQUIT, or once the abandon file is created in
+/// the output folder, which is removed so the next stage runs
+fn abandon_on_signal(abandon_file: std::path::PathBuf) {
+    #[cfg(unix)]
+    {
+        let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGQUIT])
+            .expect("Error setting the SIGQUIT handler");
+        std::thread::spawn(move || {
+            for _ in signals.forever() {
+                exe_ops::ABANDON_STAGE.store(true, std::sync::atomic::Ordering::SeqCst);
+            }
+        });
+    }
+    std::thread::spawn(move || loop {
+        if abandon_file.exists() {
+            let _ = std::fs::remove_file(&abandon_file);
+            exe_ops::ABANDON_STAGE.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    });
+}
+
+/// run the steps after a stage has finished, that use the outputs of all its commands
+///
+/// Args:
+/// * `stage` - the name of the stage, i.e. wiskers
+/// * `config` - the config of all stages
+/// * `main_args` - the arguments of the run
+/// * `data_paths` - the hash map of the artefact name and file path
+/// * `ioc_hits` - whether to summarise the IOC hits after the enrichers
+/// * `stix` - whether to also write the IOC hits as a STIX bundle
+fn after_stage(stage: &str, config: &config::Config, main_args: &config::MainArgs, data_paths: &std::collections::HashMap<String, String>, ioc_hits: bool, stix: bool) {
+    exe_ops::end_abandoned(stage, main_args);
+    if stage == "wiskers" {
+        // merge the outputs that overlap, removing duplicate rows
+        for dedupe in config.dedupe.iter().filter(|d| d.inputs.iter().any(|i| config.find(i).is_some_and(|w| w.selected(main_args)))) {
+            if let Err(e) = table_ops::dedupe(dedupe, config, main_args) {
+                file_ops::log_msg(&main_args.out_log, format!("[!] Dedupe {} failed. Error: {}", dedupe.name, e));
+            }
+        }
+    }
+    if stage == "enrichers" && ioc_hits {
+        let hits = ioc_ops::ioc_hits(&config.enrichers, main_args, data_paths);
+        let priority = ioc_ops::priority_hits(&hits, &config.ioc_severity, main_args);
+        if stix {
+            ioc_ops::write_stix(&hits, &priority, main_args);
+        }
+    }
+}
+
+/// use the case name given by the user, otherwise the name of the output folder
+fn get_case_name(case_name: &str, out_path: &str) -> String {
+    if case_name.is_empty() {
+        Path::new(out_path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+    } else {
+        case_name.to_string()
+    }
+}
+
+/// use the run id given by the user, otherwise generate a UUID for the run
+fn get_run_id(run_id: String) -> String {
+    if run_id.is_empty() {
+        uuid::Uuid::new_v4().to_string()
+    } else {
+        run_id
+    }
+}
+
 fn main() {
     // Set exit handler
     ctrlc::set_handler(move || {
-        std::process::exit(0);
+        // whipped gets the interrupt too, so wait for it to cancel the downloads and remove the partial files
+        if exe_ops::WHIPPED_RUNNING.load(std::sync::atomic::Ordering::SeqCst) {
+            eprintln!("[!] Interrupted, waiting for whipped to cancel the downloads...");
+            return;
+        }
+        summary_ops::write_crashed("interrupted");
+        mount_ops::exit(0);
     }).expect("Error setting Ctrl-C handler");
+    // Mark the output as partial if wiskess crashes
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        summary_ops::write_crashed(&info.to_string());
+        ioc_ops::remove_secret_iocs();
+        default_hook(info);
+    }));
  
     
-    // Get the args
-    let args = Args::parse();
+    // Get the args, with the defaults of those not given from the env file
+    let env_keys = load_env_file();
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // the env vars are only for clap, so they aren't passed to the tools
+    for key in &env_keys {
+        env::remove_var(format!("WISKESS_{}", key.to_uppercase()));
+    }
+    let env_file = match args.env_file.is_empty() {
+        true => ENV_FILE,
+        false => args.env_file.as_str(),
+    };
+    let env_msgs = env_sources(&matches, env_file, &env_keys);
 
-    // Display banner
-    show_banner();
+    // Display banner, unless printing the schema which is piped to a file
+    if !matches!(args.command, Commands::Schema { ref out_file } if out_file.is_empty()) {
+        show_banner();
+        for msg in &env_msgs {
+            println!("{}", msg);
+        }
+    }
 
     // Set tool path
     let tool_path = Path::new(&args.tool_path);
@@ -153,25 +758,275 @@ fn main() {
             // TODO: check if setup has been run, or if any binaries are missing
             scripts::run_setup(&tool_path, github_token, verbose);
         },
-        Commands::Whipped { 
+        Commands::Lint {
             config,
             artefacts_config,
-            data_source_list,
-            local_storage,
+            check_tools,
+        } => {
+            let report = config_ops::lint(&config, &artefacts_config, tool_path.to_str().unwrap(), check_tools);
+            config_ops::print_lint(&report);
+            if !report.errors.is_empty() {
+                std::process::exit(1);
+            }
+        },
+        Commands::Schema {
+            out_file
+        } => {
+            let schema = config_ops::schema();
+            if out_file.is_empty() {
+                println!("{}", schema);
+            } else {
+                std::fs::write(&out_file, schema).expect("Unable to write the config schema.");
+                println!("[+] Config schema written to: {}", out_file);
+            }
+        },
+        Commands::Outputs {
+            out_path
+        } => {
+            if let Err(e) = summary_ops::list_outputs(&out_path) {
+                eprintln!("[!] Unable to list the outputs: {}", e);
+                std::process::exit(1);
+            }
+        },
+        Commands::Summary {
+            out_path,
+            min_valid,
+        } => {
+            let valid_percent = match summary_ops::print_summary(&out_path) {
+                Ok(valid_percent) => valid_percent,
+                Err(e) => {
+                    eprintln!("[!] Unable to print the summary: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if let Some(min_valid) = min_valid {
+                if valid_percent < min_valid as f64 {
+                    eprintln!("[!] The run is not valid, {:.0}% of the commands that ran are valid, below the --min-valid of {}%", valid_percent, min_valid);
+                    std::process::exit(1);
+                }
+            }
+        },
+        Commands::Report {
+            out_path,
+            template
+        } => {
+            match report_ops::render_report(&out_path, &template) {
+                Ok(report) => println!("[+] Report written to: {}", report),
+                Err(e) => {
+                    eprintln!("[!] Unable to render the report: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        },
+        Commands::Encrypt {
+            out_path,
+            key_file
+        } => {
+            match crypt_ops::encrypt_output(&out_path, &key_file) {
+                Ok(count) => println!("[+] Encrypted {} files in {}, with the nonces in {}", count, out_path, crypt_ops::MANIFEST),
+                Err(e) => {
+                    eprintln!("[!] Unable to encrypt the output: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        },
+        Commands::Decrypt {
+            out_path,
+            key_file
+        } => {
+            match crypt_ops::decrypt_output(&out_path, &key_file) {
+                Ok(count) => println!("[+] Decrypted {} files in {}", count, out_path),
+                Err(e) => {
+                    eprintln!("[!] Unable to decrypt the output: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        },
+        Commands::Merge {
+            runs,
+            out_path
+        } => {
+            match summary_ops::merge_runs(&runs, &out_path) {
+                Ok(overview) => println!(
+                    "[+] Merged {} runs into {}/case_overview.json and case_overview.html, with {} failures",
+                    overview.runs.len(),
+                    out_path,
+                    overview.failures.len()
+                ),
+                Err(e) => {
+                    eprintln!("[!] Unable to merge the runs: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        },
+        Commands::Run {
+            config,
+            wisker,
+            artefact,
+            out_path,
             start_date,
             end_date,
             ioc_file,
-            in_link,
-            out_link,
-            update,
-            keep_evidence,
-        } => {            
+        } => {
+            let config = config_ops::read_config(&config).share_outfolders();
+            let Some((stage, wisker)) = config.stages().into_iter()
+                .find_map(|(stage, func)| func.iter().find(|w| w.name == wisker).map(|w| (stage, w.clone()))) else {
+                eprintln!("[!] No command named '{}' in the config", wisker);
+                std::process::exit(1);
+            };
+            if !Path::new(&artefact).exists() {
+                eprintln!("[!] The artefact does not exist: {}", artefact);
+                std::process::exit(1);
+            }
+
+            file_ops::make_folders(Path::new(&out_path));
+            let out_log = format!("{}/wiskess_{}.log", &out_path, Utc::now().format("%Y-%m-%dT%H%M%S"));
+            let run_id = get_run_id(String::new());
+            let case_name = get_case_name(&args.case_name, &out_path);
+            file_ops::log_msg(&out_log, format!("[ ] Running {} '{}' against {}, with run id: {}, for case: {}", stage.trim_end_matches('s'), &wisker.name, artefact, run_id, case_name));
+
+            // only ask for the dates if the command uses them
+            let uses = |placeholder: &str| [&wisker.args, &wisker.script_posh, &wisker.request].iter().any(|f| f.contains(placeholder));
+            let start_date = match uses("{start_date}") {
+                true => file_ops::check_date(start_date, &"start date".to_string()),
+                false => start_date,
+            };
+            let end_date = match uses("{end_date}") {
+                true => file_ops::check_date(end_date, &"end date".to_string()),
+                false => end_date,
+            };
+
+            let main_args = config::MainArgs {
+                scratch: format!("{}/Scratch", &out_path),
+                out_path,
+                start_date,
+                end_date,
+                tool_path: tool_path.to_str().unwrap().to_string(),
+                ioc_file,
+                silent: args.silent,
+                out_log,
+                multi_pb: MultiProgress::new(),
+                run_id,
+                max_memory: 0,
+                threads: 0,
+                integrity: false,
+                strict_integrity: false,
+                window_check: false,
+                strict_window: false,
+                strict_matches: false,
+                results: Arc::new(Mutex::new(Vec::new())),
+                tags: Vec::new(),
+                tag_all: false,
+                deadline: None,
+                iocs: None,
+                redact: false,
+                simulate_failure: Vec::new(),
+                source_prefix: String::new(),
+                scratch_redirect: false,
+                quick: None,
+                completed_file: String::new(),
+                art_patterns: std::collections::HashMap::new(),
+                max_output: 0,
+                output_policy: "warn".to_string(),
+                intermediates: Vec::new(),
+                out_dirs: config.out_dirs(),
+                prewarm: false,
+                launch_rate: 0.0,
+                output_prefix: exe_ops::DEFAULT_OUTPUT_PREFIX.to_string(),
+                jobs: 0,
+                retry: Vec::new(),
+                case_name,
+                ignore: config::Ignore::default(),
+                raw_copy: true,
+                max_artefacts: 0,
+                max_artefacts_total: 0,
+            };
+
+            // the artefact is the input of the command, and its other input is not read
+            let mut data_paths = std::collections::HashMap::from([(wisker.input.to_string(), artefact)]);
+            if !wisker.input_other.is_empty() {
+                file_ops::log_msg(&main_args.out_log, format!("[!] The input_other '{}' of {} is not given, so is empty", &wisker.input_other, &wisker.name));
+                data_paths.entry(wisker.input_other.to_string()).or_default();
+            }
+            match exe_ops::run_single(stage, &wisker, &config, &main_args, data_paths) {
+                Some(result) if result.status == summary_ops::Status::Success => {
+                    file_ops::log_msg(&main_args.out_log, format!("[+] {} completed, the output is in: {}", &wisker.name, result.output));
+                },
+                Some(result) => {
+                    file_ops::log_msg(&main_args.out_log, format!("[!] {} did not complete: {:?} {}", &wisker.name, result.status, result.reason));
+                    std::process::exit(1);
+                },
+                None => std::process::exit(1),
+            }
+        },
+        Commands::Whipped(whipped) => {
+            let WhippedCommand {
+                config,
+                artefacts_config,
+                data_source_list,
+                local_storage,
+                start_date,
+                end_date,
+                ioc_file,
+                in_link,
+                out_link,
+                update,
+                keep_evidence,
+                run_id,
+                prefix_run_id,
+                resume_download,
+                download_concurrency,
+                source_prefix,
+                token_refresh_command,
+                encrypt_output,
+                encrypt_key,
+                output_root_link,
+                list_remote,
+                include,
+                exclude,
+                storage_type,
+                download_command,
+                upload_command,
+                upload_if_valid,
+                valid_threshold,
+                min_free_local,
+                low_space,
+                ignore_file,
+                no_download,
+                out_suffix,
+            } = *whipped;
+
             // Confirm date is valid
             let start_date = file_ops::check_date(start_date, &"start date".to_string());
             let end_date = file_ops::check_date(end_date, &"end date".to_string());
 
+            // the paths of the ignore file are not downloaded, as are those of --exclude
+            let mut exclude = exclude;
+            if !ignore_file.is_empty() {
+                match file_ops::read_ignore_file(&ignore_file) {
+                    Ok(patterns) => {
+                        println!("[ ] Excluding the paths of {} patterns in the ignore file {}", patterns.len(), ignore_file);
+                        exclude.extend(patterns.iter().flat_map(|p| file_ops::ignore_globs(p)));
+                    },
+                    Err(e) => {
+                        eprintln!("[!] {}", e);
+                        std::process::exit(1);
+                    },
+                }
+            }
+
+            // the output of the data already downloaded is kept apart from that of the config it was first run with
+            let out_suffix = match out_suffix.is_empty() && no_download {
+                true => Path::new(&config).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default(),
+                false => out_suffix,
+            };
+            if no_download && !Path::new(&local_storage).is_dir() {
+                eprintln!("[!] The local storage {} does not exist, so there is no downloaded data to process", local_storage);
+                std::process::exit(1);
+            }
+
             // put the args into a whipped structure
-            let args = config::WhippedArgs {
+            let whipped_args = config::WhippedArgs {
                 config,
                 artefacts_config,
                 data_source_list,
@@ -183,38 +1038,210 @@ fn main() {
                 out_link,
                 update,
                 keep_evidence,
+                run_id: get_run_id(run_id),
+                case_name: args.case_name.to_string(),
+                prefix_run_id,
+                resume_download,
+                download_concurrency,
+                source_prefix,
+                token_refresh_command,
+                encrypt_output,
+                encrypt_key,
+                output_root_link,
+                list_remote,
+                include,
+                exclude,
+                storage_type,
+                download_command,
+                upload_command,
+                upload_if_valid,
+                valid_threshold,
+                no_download,
+                out_suffix,
             };
-            scripts::run_whipped(&tool_path, args)
+
+            // Check the transfer commands of the custom storage can be run, before downloading anything
+            if let Err(e) = exe_ops::check_storage(&whipped_args) {
+                eprintln!("[!] {}", e);
+                std::process::exit(1);
+            }
+
+            // Check the volume of the local storage has the free space to download to
+            let volumes = summary_ops::volume_space(&[("local storage", &whipped_args.local_storage, min_free_local)]);
+            if !summary_ops::check_free_space(&volumes, &low_space, None) {
+                eprintln!("[!] Stopping, the local storage has less than the free space it needs, set by --min-free-local");
+                std::process::exit(1);
+            }
+
+            // Review the run before committing to it, unless only listing what it would download
+            if !list_remote && !summary_ops::preflight(&summary_ops::whipped_preflight(&whipped_args), None, args.silent) {
+                return;
+            }
+            scripts::run_whipped(&tool_path, whipped_args)
         },
-        Commands::Wiskess { 
-            config, 
-            artefacts_config,
-            data_source, 
-            out_path, 
-            start_date, 
-            end_date, 
-            ioc_file 
-        } => {
+        Commands::Wiskess(wiskess) => {
+            let WiskessCommand {
+                config,
+                artefacts_config,
+                data_source,
+                locked_files,
+                max_artefacts,
+                max_artefacts_total,
+                mount_command,
+                unmount_command,
+                out_path,
+                start_date,
+                end_date,
+                ioc_file,
+                source_prefix,
+                scratch_dir,
+                keep_evidence,
+                scratch_per_run,
+                overlay,
+                config_secret,
+                configs_dir,
+                ignore_file,
+                ioc_secret,
+                run_id,
+                max_command_memory,
+                threads_per_command,
+                max_runtime,
+                max_output_size,
+                output_size_policy,
+                prewarm,
+                launch_rate,
+                jobs,
+                retry_failed,
+                integrity_check,
+                strict_integrity,
+                window_check,
+                strict_window,
+                strict_matches,
+                strict_outputs,
+                benchmark,
+                only_stage,
+                continue_from_stage,
+                parallel_stages,
+                tag,
+                tag_mode,
+                simulate_failure,
+                fixed_time,
+                quick,
+                quick_then_full,
+                explain,
+                results_csv,
+                completed_file,
+                trace_file,
+                provenance,
+                provenance_dot,
+                cleanup,
+                hash_outputs,
+                hash_jobs,
+                manifest,
+                min_free_output,
+                min_free_scratch,
+                low_space,
+                output_prefix,
+                redact_pattern,
+                ioc_hits,
+                stix,
+                dump_effective_config,
+                dump_effective_config_only,
+                report_template,
+            } = *wiskess;
+
+            // Pick the config from those in the configs folder, if it was not given
+            let config_given = matches.subcommand_matches("wiskess")
+                .is_some_and(|m| m.value_source("config") != Some(ValueSource::DefaultValue));
+            let config = match config_given || args.silent || !config_secret.is_empty() {
+                true => config,
+                false => config_ops::pick_config(&configs_dir, &config).unwrap_or_else(|e| {
+                    eprintln!("[!] Unable to pick the config: {}", e);
+                    std::process::exit(1);
+                }),
+            };
+
+            // Mask the sensitive values in the log, before the first message is written
+            if let Err(e) = file_ops::set_redactions(&redact_pattern) {
+                eprintln!("[!] {}", e);
+                std::process::exit(1);
+            }
+
+            // Resuming from a stage runs it and the stages after it
+            let only_stage = match &continue_from_stage {
+                Some(from) => ["wiskers", "enrichers", "reporters"].iter()
+                    .skip_while(|s| *s != from)
+                    .map(|s| s.to_string())
+                    .collect(),
+                None => only_stage,
+            };
+
             // Set output directories
             file_ops::make_folders(Path::new(&out_path));
             
             // Set the start time
+            if let Some(fixed_time) = fixed_time {
+                let _ = file_ops::FIXED_TIME.set(fixed_time);
+            }
             let date_time_fmt = "%Y-%m-%dT%H%M%S";
-            let wiskess_start = Utc::now();
+            let wiskess_start = file_ops::now();
             let wiskess_start_str = wiskess_start.format(date_time_fmt).to_string();
             
             // Set main log
             let out_log = format!("{}/wiskess_{}.log", &out_path, wiskess_start_str);
             file_ops::file_exists(&out_log, args.silent);
     	    
-            // Write start time to log
-            file_ops::log_msg(&out_log, format!("Starting wiskess at: {}", wiskess_start_str));
+            // Write start time and run id to log
+            let run_id = get_run_id(run_id);
+            let case_name = get_case_name(&args.case_name, &out_path);
+            file_ops::log_msg(&out_log, format!("Starting wiskess at: {}, with run id: {}, for case: {}", wiskess_start_str, run_id, case_name));
+            for msg in env_msgs {
+                file_ops::log_msg(&out_log, msg);
+            }
+
+            // Mark the output as partial if the run crashes, clearing the marker of a previous run
+            let _ = std::fs::remove_file(Path::new(&out_path).join(summary_ops::CRASH_FILE));
+            let _ = summary_ops::CRASH_INFO.set((out_path.to_string(), run_id.to_string(), out_log.to_string()));
+
+            // Pause the run while the pause file exists, or toggle it with SIGUSR1
+            let pause_file = Path::new(&out_path).join(exe_ops::PAUSE_FILE);
+            #[cfg(unix)]
+            {
+                pause_on_signal();
+                file_ops::log_msg(&out_log, format!(
+                    "[ ] To pause the run create {}, or send SIGUSR1 to process {}, and remove it or signal again to resume",
+                    pause_file.display(),
+                    std::process::id()
+                ));
+            }
+            #[cfg(not(unix))]
+            file_ops::log_msg(&out_log, format!("[ ] To pause the run create {}, and remove it to resume", pause_file.display()));
+
+            // Abandon the stage that is stuck, killing its commands, once the abandon file is created or on SIGQUIT
+            let abandon_file = Path::new(&out_path).join(exe_ops::ABANDON_FILE);
+            let _ = std::fs::remove_file(&abandon_file);
+            #[cfg(unix)]
+            file_ops::log_msg(&out_log, format!(
+                "[ ] To abandon the stage running create {}, or send SIGQUIT to process {}",
+                abandon_file.display(),
+                std::process::id()
+            ));
+            #[cfg(not(unix))]
+            file_ops::log_msg(&out_log, format!("[ ] To abandon the stage running create {}", abandon_file.display()));
+            abandon_on_signal(abandon_file);
 
             // Confirm date is valid
             let start_date = file_ops::check_date(start_date, &"start date".to_string());
             let end_date = file_ops::check_date(end_date, &"end date".to_string());
             
-            let main_args = config::MainArgs {
+            // the scratch of the run is the scratch dir, or its own folder in it if asked, so only it is removed at the end
+            let scratch = match (scratch_dir.is_empty(), scratch_per_run) {
+                (true, _) => format!("{}/Scratch", &out_path),
+                (false, false) => scratch_dir.to_string(),
+                (false, true) => format!("{}/wiskess_{}", scratch_dir, &run_id),
+            };
+
+            let mut main_args = config::MainArgs {
                 out_path,
                 start_date,
                 end_date,
@@ -222,22 +1249,179 @@ fn main() {
                 ioc_file,
                 silent: args.silent,
                 out_log,
-                multi_pb: MultiProgress::new()
+                multi_pb: MultiProgress::new(),
+                run_id,
+                max_memory: max_command_memory,
+                threads: threads_per_command,
+                integrity: integrity_check || strict_integrity,
+                strict_integrity,
+                window_check: window_check || strict_window,
+                strict_window,
+                strict_matches,
+                results: Arc::new(Mutex::new(Vec::new())),
+                tags: tag,
+                tag_all: tag_mode == "all",
+                deadline: match max_runtime {
+                    0 => None,
+                    s => Some(Instant::now() + Duration::from_secs(s)),
+                },
+                iocs: None,
+                redact: !config_secret.is_empty(),
+                simulate_failure,
+                source_prefix,
+                scratch,
+                scratch_redirect: !scratch_dir.is_empty(),
+                quick: None,
+                completed_file,
+                art_patterns: std::collections::HashMap::new(),
+                max_output: max_output_size,
+                output_policy: output_size_policy,
+                intermediates: Vec::new(),
+                out_dirs: std::collections::HashMap::new(),
+                prewarm,
+                launch_rate,
+                output_prefix,
+                jobs,
+                retry: Vec::new(),
+                case_name,
+                ignore: config::Ignore::default(),
+                raw_copy: locked_files == "raw",
+                max_artefacts,
+                max_artefacts_total,
+            };
+            if main_args.scratch_redirect {
+                file_ops::make_folders(Path::new(&main_args.scratch));
+                file_ops::log_msg(&main_args.out_log, format!("[ ] Scratch dir for the working and temp files of the commands: {}", &main_args.scratch));
+            }
+            if !main_args.simulate_failure.is_empty() {
+                if env::var(exe_ops::SIMULATE_ENV).as_deref() != Ok("1") {
+                    eprintln!("[!] --simulate-failure is for testing only, and requires the env var {}=1", exe_ops::SIMULATE_ENV);
+                    std::process::exit(1);
+                }
+                file_ops::log_msg(&main_args.out_log, format!("[!] Testing: simulating failures of {:?}", main_args.simulate_failure));
+            }
+            if !ioc_secret.is_empty() {
+                match secret_ops::get_secret(&ioc_secret) {
+                    Ok(iocs) => main_args.iocs = Some(Arc::new(iocs)),
+                    Err(e) => {
+                        file_ops::log_msg(&main_args.out_log, format!("[!] Stopping the run, unable to get the IOC secret. Error: {}", e));
+                        std::process::exit(1);
+                    },
+                }
+                file_ops::log_msg(&main_args.out_log, format!("[ ] IOCs read from secret: {}", ioc_secret));
+            }
+            // the commands that read {ioc_file} get the IOCs of the secret by a path that is not
+            // in the output, removed on any exit of the run
+            let secret_iocs = match &main_args.iocs {
+                Some(iocs) if main_args.ioc_file.is_empty() => match ioc_ops::SecretIocs::create(iocs) {
+                    Ok(secret_iocs) => {
+                        main_args.ioc_file = secret_iocs.path.to_string();
+                        Some(secret_iocs)
+                    },
+                    Err(e) => {
+                        file_ops::log_msg(&main_args.out_log, format!("[!] Stopping the run, unable to give the IOCs of the secret for {{ioc_file}}. Error: {}", e));
+                        std::process::exit(1);
+                    },
+                },
+                _ => None,
             };
+            if !main_args.tags.is_empty() {
+                file_ops::log_msg(&main_args.out_log, format!("[ ] Only running commands tagged with {} of: {:?}", tag_mode, main_args.tags));
+            }
         
             // Read the config
-            let f: std::fs::File = OpenOptions::new()
-                .read(true)
-                .open(config)
-                .expect("Unable to open config file.");
-            let config: config::Config = serde_yaml::from_reader(f).expect("Could not read values.");
-
-            // Read the artefacts config
-            let f: std::fs::File = OpenOptions::new()
-                .read(true)
-                .open(artefacts_config)
-                .expect("Unable to open artefacts config file.");
-            let config_artefacts: config::ConfigArt = serde_yaml::from_reader(f).expect("Could not read values of artefacts config.");
+            let config_path = config.to_string();
+            let mut config_from = match config_secret.is_empty() {
+                true => config_path.to_string(),
+                false => format!("secret {}", config_secret),
+            };
+            if !overlay.is_empty() {
+                config_from += &format!(", with overlays {:?}", overlay);
+            }
+            let config = if config_secret.is_empty() {
+                config_ops::read_config(&config)
+            } else {
+                file_ops::log_msg(&main_args.out_log, format!("[ ] Config read from secret: {}, the command arguments are redacted in the log", config_secret));
+                config_ops::read_config_secret(&config_secret).unwrap_or_else(|e| {
+                    file_ops::log_msg(&main_args.out_log, format!("[!] Stopping the run, {}", e));
+                    mount_ops::exit(1);
+                })
+            };
+            let mut config = match overlay.is_empty() {
+                true => config,
+                false => config_ops::apply_overlays(config, &overlay, &main_args.out_log),
+            }.share_outfolders();
+            // Infer the dependencies of the commands from their inputs and outputs
+            match config.infer_depends() {
+                Ok(inferred) => for note in inferred {
+                    file_ops::log_msg(&main_args.out_log, format!("[ ] Inferred that {}", note));
+                },
+                Err(problems) => {
+                    for problem in problems {
+                        file_ops::log_msg(&main_args.out_log, format!("[!] {}", problem));
+                    }
+                    file_ops::log_msg(&main_args.out_log, "[!] Stopping the run, the dependencies of the commands can not be inferred".to_string());
+                    mount_ops::exit(1);
+                },
+            }
+            let config = config;
+            main_args.intermediates = config.intermediates(&main_args.out_path);
+            main_args.out_dirs = config.out_dirs();
+            let unknown = config.unknown_depends();
+            if !unknown.is_empty() {
+                for problem in unknown {
+                    file_ops::log_msg(&main_args.out_log, format!("[!] {}", problem));
+                }
+                file_ops::log_msg(&main_args.out_log, "[!] Stopping the run, a command depends on one that is not in the config".to_string());
+                mount_ops::exit(1);
+            }
+
+            // Only run the commands that failed in the previous run, and those that depend on them
+            let previous = match retry_failed {
+                true => match summary_ops::retry_failed(&config, &main_args.out_path) {
+                    Ok((retry, _)) if retry.is_empty() => {
+                        file_ops::log_msg(&main_args.out_log, "[+] No commands failed in the previous run, there is nothing to retry".to_string());
+                        return;
+                    },
+                    Ok((retry, previous)) => {
+                        file_ops::log_msg(&main_args.out_log, format!("[ ] Retrying the commands that failed in the previous run {}, and those that depend on them: {}", previous.run_id, retry.join(", ")));
+                        main_args.retry = retry;
+                        Some(previous)
+                    },
+                    Err(e) => {
+                        file_ops::log_msg(&main_args.out_log, format!("[!] Unable to retry the failed commands, {}", e));
+                        mount_ops::exit(1);
+                    },
+                },
+                false => None,
+            };
+
+            // Stop before running if the config uses IOC placeholders the IOC input can't provide
+            if let Err(e) = ioc_ops::check_ioc_placeholders(&config, &main_args) {
+                file_ops::log_msg(&main_args.out_log, format!("[!] Stopping the run, {}", e));
+                mount_ops::exit(1);
+            }
+
+            // Write the config that will be run, and stop here if only that was asked for
+            if (dump_effective_config || dump_effective_config_only) && main_args.redact {
+                file_ops::log_msg(&main_args.out_log, "[!] The effective config is not written, as the config is from a secret".to_string());
+                if dump_effective_config_only {
+                    return;
+                }
+            } else if dump_effective_config || dump_effective_config_only {
+                config_ops::dump_effective(&config, &main_args.out_path, &main_args.out_log, dump_effective_config_only);
+                if dump_effective_config_only {
+                    return;
+                }
+            }
+
+            // Read the artefacts config, or the artefacts_file of the config, with any artefacts inline in the config
+            let (config_artefacts, art_file) = config_ops::read_artefacts(&config, &config_path, &artefacts_config)
+                .unwrap_or_else(|e| {
+                    file_ops::log_msg(&main_args.out_log, format!("[!] Unable to read the artefacts: {}", e));
+                    mount_ops::exit(1);
+                });
+            file_ops::log_msg(&main_args.out_log, format!("[ ] Artefacts read from {}, and {} inline in the config", art_file, config.artefacts.len()));
             
             // get a velo collection
             // let velo_source = HashMap::new();
@@ -245,46 +1429,268 @@ fn main() {
             // exe_ops::run_commands(&config.collectors, &main_args, &data_paths, num_threads, m.clone());
         
             // TODO: check or gracefully error when the yaml config misses keys
+
+            // mount a disk image read-only to run against, which is unmounted when the run ends
+            let summary_source = match mount_ops::is_live(&data_source) {
+                true => format!("live:{}", sysinfo::System::host_name().unwrap_or_default()),
+                false => data_source.clone(),
+            };
+            // resolve the artefact paths against the filesystem of this machine when live
+            let data_source = match mount_ops::is_live(&data_source) {
+                true => {
+                    let root = mount_ops::live_root();
+                    file_ops::log_msg(&main_args.out_log, format!("[!] Running against the live system at {}. The artefacts may change \
+                        during the run, and reading them changes what is on the system, so use an output folder on other storage", root));
+                    root
+                },
+                false => data_source,
+            };
+            let mounted = match mount_ops::is_image(&data_source) {
+                true => Some(mount_ops::mount_image(&data_source, &mount_command, &unmount_command, &main_args.run_id, &main_args.tool_path, &main_args.out_log)
+                    .unwrap_or_else(|e| {
+                        file_ops::log_msg(&main_args.out_log, format!("[!] Unable to mount the image: {}", e));
+                        mount_ops::exit(1);
+                    })),
+                false => None,
+            };
+            let data_source = match &mounted {
+                Some(mounted) => mounted.path.to_string(),
+                None => data_source,
+            };
+
+            // exclude the paths of the ignore file from the artefacts, by default the .wiskessignore of the data source
+            let ignore_given = !ignore_file.is_empty();
+            let ignore_file = match ignore_given {
+                true => ignore_file,
+                false => Path::new(&data_source).join(file_ops::IGNORE_FILE).display().to_string(),
+            };
+            if Path::new(&ignore_file).is_file() {
+                let ignore = file_ops::read_ignore_file(&ignore_file).and_then(|patterns| config::Ignore::new(&data_source, &patterns));
+                match ignore {
+                    Ok(ignore) => {
+                        file_ops::log_msg(&main_args.out_log, format!("[ ] Excluding the paths of {} patterns in the ignore file {}", ignore.patterns.len(), ignore_file));
+                        main_args.ignore = ignore;
+                    },
+                    Err(e) => {
+                        file_ops::log_msg(&main_args.out_log, format!("[!] Stopping the run, {}", e));
+                        mount_ops::exit(1);
+                    },
+                }
+            } else if ignore_given {
+                file_ops::log_msg(&main_args.out_log, format!("[!] Stopping the run, the ignore file {} does not exist", ignore_file));
+                mount_ops::exit(1);
+            }
         
             // check the file paths in the config exist and return a hash of the art paths
-            let data_paths = paths::check_art(
-                config_artefacts.artefacts, 
+            let (data_paths, art_patterns) = match paths::check_art(
+                config_artefacts, 
                 &data_source,
                 args.silent,
+                &config,
                 &main_args
-            );
+            ) {
+                Ok(found) => found,
+                Err(e) => {
+                    file_ops::log_msg(&main_args.out_log, format!("[!] Stopping the run, {}", e));
+                    mount_ops::exit(1);
+                }
+            };
+
+            main_args.art_patterns = art_patterns;
 
             // check access and copy unreadable artefacts
             let data_paths = paths::check_copy_art(data_paths, &main_args);
-            println!("{:#?}", data_paths);
 
+            // Run the quick commands first if --quick, and the rest after if --quick-then-full
+            let passes = match (quick, quick_then_full) {
+                (_, true) => vec![Some(true), Some(false)],
+                (true, false) => vec![Some(true)],
+                (false, false) => vec![None],
+            };
+
+            // Check no two commands write the same output, in this run or the previous into the output folder
+            let collisions = config_ops::output_collisions(&config, &main_args);
+            for collision in &collisions {
+                file_ops::log_msg(&main_args.out_log, format!("[!] Output collision, {}", collision));
+            }
+            if strict_outputs && !collisions.is_empty() {
+                file_ops::log_msg(&main_args.out_log, format!("[!] Stopping the run, {} commands would overwrite the output of another, with --strict-outputs", collisions.len()));
+                mount_ops::exit(1);
+            }
+
+            // Check the free space of each volume the run writes to, as they may be on different disks
+            let volumes = summary_ops::volume_space(&[
+                ("output", &main_args.out_path, min_free_output),
+                ("scratch", &main_args.scratch, min_free_scratch),
+            ]);
+            if !summary_ops::check_free_space(&volumes, &low_space, Some(&main_args.out_log)) {
+                file_ops::log_msg(&main_args.out_log, "[!] Stopping the run, a volume has less than the free space it needs, set by --min-free-output and --min-free-scratch".to_string());
+                mount_ops::exit(1);
+            }
+
+            // Review the run before committing to it
+            let mut items = summary_ops::wiskess_preflight(&config, &config_from, &main_args, &data_paths, &only_stage, &passes);
+            items.push(("volumes", volumes.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("; ")));
+            if !collisions.is_empty() {
+                items.push(("collisions", format!("{} outputs written by more than one command, see the log", collisions.len())));
+            }
+            if !summary_ops::preflight(&items, Some(&main_args.out_log), args.silent) {
+                return;
+            }
+
+            // Time each tool on its input rather than run the stages
+            if benchmark {
+                match bench_ops::write_benchmark(&config, &main_args, &data_paths) {
+                    Ok(path) => file_ops::log_msg(&main_args.out_log, format!("[+] Benchmark of the tools written to {}", path)),
+                    Err(e) => {
+                        file_ops::log_msg(&main_args.out_log, format!("[!] Unable to write the benchmark, {}", e));
+                        mount_ops::exit(1);
+                    },
+                }
+                return;
+            }
+
+            // Sample the resources used by the run
+            let sampler = usage_ops::start();
 
             // Setup progress bars
             let pb = setup::prog_spin_init(960, &main_args.multi_pb, "magenta");
            
-            // Run in parallel then in series (if applicable) each binary of   
-            // wiskers, enrichers and reporters
-            for func in [
-                &config.wiskers,
-                &config.enrichers,
-                &config.reporters] {
-	            setup::prog_spin_msg(&pb, "Wiskess - Running Wiskers / Enrichers / Reporters".to_string());            
-                    for num_threads in [0, 1] {
-                        exe_ops::run_commands(func, &main_args, &data_paths, num_threads);
+            // Check the outputs exist of the stages before those selected with --only-stage
+            if !only_stage.is_empty() {
+                let last_stage = config.stages().iter().rposition(|(stage, _)| only_stage.iter().any(|s| s == stage)).unwrap_or(0);
+                let mut missing = Vec::new();
+                for (stage, func) in config.stages().into_iter().take(last_stage) {
+                    if !only_stage.iter().any(|s| s == stage) {
+                        let selected: Vec<_> = func.iter().filter(|w| w.selected(&main_args)).cloned().collect();
+                        missing.extend(valid_ops::check_stage_outputs(stage, &selected, &main_args, &data_paths));
                     }
+                }
+                // the stages run need the outputs that their commands depend on
+                let required: Vec<String> = config.stages().iter()
+                    .filter(|(stage, _)| only_stage.iter().any(|s| s == stage))
+                    .flat_map(|(_, func)| func.iter().filter(|w| w.selected(&main_args)))
+                    .flat_map(|w| w.depends_on.iter())
+                    .filter(|dep| missing.contains(dep))
+                    .cloned()
+                    .collect();
+                match &continue_from_stage {
+                    Some(from) if !required.is_empty() => {
+                        file_ops::log_msg(&main_args.out_log, format!(
+                            "[!] Stopping the run, continuing from the stage {} needs the outputs of: {}",
+                            from,
+                            required.join(", ")
+                        ));
+                        mount_ops::exit(1);
+                    },
+                    Some(from) => file_ops::log_msg(&main_args.out_log, format!("[ ] Continuing the run from the stage {}, reusing the outputs of the stages before it", from)),
+                    None if !required.is_empty() => {
+                        let msg = format!("The stages {} need the outputs of: {}", only_stage.join(", "), required.join(", "));
+                        let run_anyway = !args.silent && matches!(
+                            inquire::Confirm::new(&format!("{}\nRun them anyway?", msg))
+                                .with_default(false)
+                                .with_help_message("The commands that depend on the missing outputs are skipped.")
+                                .prompt(),
+                            Ok(true)
+                        );
+                        if !run_anyway {
+                            file_ops::log_msg(&main_args.out_log, format!("[!] Stopping the run. {}", msg));
+                            mount_ops::exit(1);
+                        }
+                        file_ops::log_msg(&main_args.out_log, format!("[-] Running anyway, though {}", msg.to_lowercase()));
+                    },
+                    None => (),
+                }
+            }
+
+            // Write the progress of the run to status.json, for an orchestrator to poll
+            let status_writer = status_ops::start(&main_args.out_path, &main_args.run_id, &main_args.case_name);
+            for quick_pass in passes {
+                main_args.quick = quick_pass;
+                if quick_pass == Some(true) {
+                    file_ops::log_msg(&main_args.out_log, "[ ] Quick scan, only running the commands with quick: true".to_string());
+                } else if quick_pass == Some(false) {
+                    file_ops::log_msg(&main_args.out_log, "[ ] Full scan, running the rest of the commands".to_string());
+                }
+
+                // Run in parallel then in series (if applicable) each binary of   
+                // wiskers, enrichers and reporters
+                let mut stages = Vec::new();
+                for (stage, _) in config.stages() {
+                    if !only_stage.is_empty() && !only_stage.iter().any(|s| s == stage) {
+                        file_ops::log_msg(&main_args.out_log, format!("[ ] Skipping stage {}, not selected by --only-stage", stage));
+                        continue;
+                    }
+                    stages.push(stage);
+                }
+                setup::prog_spin_msg(&pb, "Wiskess - Running Wiskers / Enrichers / Reporters".to_string());
+                status_ops::add_total(config.stages().iter()
+                    .filter(|(s, _)| stages.contains(s))
+                    .map(|(_, func)| func.iter().filter(|w| w.selected(&main_args)).count())
+                    .sum());
+                if parallel_stages {
+                    exe_ops::run_stages_parallel(&stages, &config, &main_args, &data_paths, |stage| {
+                        after_stage(stage, &config, &main_args, &data_paths, ioc_hits, stix)
+                    });
+                } else {
+                    for (stage, func) in config.stages().into_iter().filter(|(s, _)| stages.contains(s)) {
+                        status_ops::set_stage(stage);
+                        for num_threads in [0, 1] {
+                            exe_ops::run_commands(stage, func, &config, &main_args, &data_paths, num_threads);
+                        }
+                        after_stage(stage, &config, &main_args, &data_paths, ioc_hits, stix);
+                    }
+                }
+                // list the results in the order of the config, rather than the order the commands finished
+                summary_ops::sort_results(&mut main_args.results.lock().unwrap(), &config);
+
+                // Write an early summary of the quick scan, before the full scan
+                if quick_then_full && quick_pass == Some(true) {
+                    let quick_stop = file_ops::now();
+                    let summary = summary_ops::RunSummary {
+                        run_id: main_args.run_id.clone(),
+                        case_name: main_args.case_name.clone(),
+                        start: wiskess_start_str.clone(),
+                        end: quick_stop.format(date_time_fmt).to_string(),
+                        duration: format_duration(quick_stop - wiskess_start),
+                        data_source: summary_source.clone(),
+                        out_path: main_args.out_path.clone(),
+                        time_boxed: false,
+                        commands: main_args.results.lock().unwrap().clone(),
+                        usage: sampler.usage(),
+                    };
+                    file_ops::log_msg(&main_args.out_log, "[+] Quick scan done, writing its early summary".to_string());
+                    summary_ops::write_summary(&summary, &main_args.out_log);
+                }
+            }
+            if quick_then_full {
+                main_args.quick = None;
             }
 
             // Validate wiskess has processed all input files into output files
-            valid_ops::valid_process(&config.wiskers, &main_args, &data_paths, &data_source, &main_args.out_log);
+            status_ops::set_stage("validation");
+            let selected: Vec<_> = config.wiskers.iter().filter(|w| w.selected(&main_args)).cloned().collect();
+            valid_ops::valid_process(&selected, &main_args, &data_paths, &data_source, &main_args.out_log);
+            if hash_outputs {
+                match valid_ops::hash_outputs(&main_args, hash_jobs) {
+                    Ok(path) => file_ops::log_msg(&main_args.out_log, format!("[ ] Hashes of the outputs written to: {}", path)),
+                    Err(e) => file_ops::log_msg(&main_args.out_log, format!("[!] Unable to hash the outputs. Error: {}", e)),
+                }
+            }
 
             // Set end time
             setup::prog_spin_stop(&pb, "Wiskess complete".to_string());
-            let wiskess_stop = Utc::now();
-            let wiskess_duration = wiskess_stop - wiskess_start;
-            let seconds = wiskess_duration.num_seconds() % 60;
-            let minutes = (wiskess_duration.num_seconds() / 60) % 60;
-            let hours = (wiskess_duration.num_seconds() / 60) / 60;
-            let duration = format!("{:0>2}:{:0>2}:{:0>2}", hours, minutes, seconds);
+            let usage = sampler.stop();
+            file_ops::log_msg(&main_args.out_log, format!(
+                "[ ] Resources used: peak CPU {:.0}%, average CPU {:.0}%, peak memory {} MB, read {} MB, written {} MB",
+                usage.peak_cpu_percent,
+                usage.avg_cpu_percent,
+                usage.peak_memory_mb,
+                usage.bytes_read / 1024 / 1024,
+                usage.bytes_written / 1024 / 1024
+            ));
+            let wiskess_stop = file_ops::now();
+            let duration = format_duration(wiskess_stop - wiskess_start);
             file_ops::log_msg(
                 &main_args.out_log, 
                 format!(
@@ -293,6 +1699,98 @@ fn main() {
                     duration
                 )
             );
+
+            // Write the summary of the run
+            if explain {
+                summary_ops::explain(&config, &main_args, &only_stage);
+            }
+            if main_args.scratch_redirect && !keep_evidence {
+                match std::fs::remove_dir_all(&main_args.scratch) {
+                    Ok(_) => file_ops::log_msg(&main_args.out_log, format!("[ ] Removed the scratch dir: {}", &main_args.scratch)),
+                    Err(e) => file_ops::log_msg(&main_args.out_log, format!("[!] Unable to remove the scratch dir: {}. Error: {}", &main_args.scratch, e)),
+                }
+            }
+            if results_csv {
+                if let Err(e) = summary_ops::write_results_csv(&config, &main_args) {
+                    file_ops::log_msg(&main_args.out_log, format!("[!] Unable to write the results csv. Error: {}", e));
+                }
+            }
+            let mut commands = main_args.results.lock().unwrap().clone();
+            if let Some(previous) = &previous {
+                // the verdict of the commands retried, with the results of the previous run for the rest
+                let retried: Vec<_> = commands.iter().filter(|c| c.status != summary_ops::Status::Skipped).collect();
+                let failed: Vec<&str> = retried.iter().filter(|c| matches!(c.status, summary_ops::Status::Failed | summary_ops::Status::Killed)).map(|c| c.name.as_str()).collect();
+                let msg = match failed.is_empty() {
+                    true => format!("[+] Retried {} commands, all succeeded", retried.len()),
+                    false => format!("[!] Retried {} commands, {} still failed: {}", retried.len(), failed.len(), failed.join(", ")),
+                };
+                file_ops::log_msg(&main_args.out_log, msg);
+                let kept = previous.commands.iter().filter(|c| !main_args.retry.contains(&c.name)).cloned();
+                commands = kept.chain(commands).collect();
+                summary_ops::sort_results(&mut commands, &config);
+            }
+            let time_boxed = commands.iter().any(|c| c.reason == exe_ops::MAX_RUNTIME_REASON);
+            if time_boxed {
+                file_ops::log_msg(&main_args.out_log, format!(
+                    "[!] The run was time-boxed by --max-runtime of {} seconds, these commands were skipped: {:?}",
+                    max_runtime,
+                    commands.iter().filter(|c| c.reason == exe_ops::MAX_RUNTIME_REASON).map(|c| c.name.as_str()).collect::<Vec<&str>>()
+                ));
+            }
+            if !trace_file.is_empty() {
+                let spans = trace_ops::run_spans(&commands, &main_args.run_id, &main_args.case_name, wiskess_start, wiskess_stop);
+                match trace_ops::write_spans(&trace_file, &spans) {
+                    Ok(_) => file_ops::log_msg(&main_args.out_log, format!("[ ] Trace spans written to: {}", trace_file)),
+                    Err(e) => file_ops::log_msg(&main_args.out_log, format!("[!] Unable to write the trace spans to {}. Error: {}", trace_file, e)),
+                }
+            }
+            if provenance {
+                match provenance_ops::write_provenance(&commands, &main_args.out_path, &main_args.tool_path, provenance_dot) {
+                    Ok(path) => file_ops::log_msg(&main_args.out_log, format!("[ ] Provenance of the outputs written to: {}", path)),
+                    Err(e) => file_ops::log_msg(&main_args.out_log, format!("[!] Unable to write the provenance. Error: {}", e)),
+                }
+            }
+            let summary = summary_ops::RunSummary {
+                run_id: main_args.run_id.clone(),
+                case_name: main_args.case_name.clone(),
+                start: wiskess_start_str,
+                end: wiskess_stop.format(date_time_fmt).to_string(),
+                duration,
+                data_source: summary_source,
+                out_path: main_args.out_path.clone(),
+                time_boxed,
+                commands,
+                usage,
+            };
+            summary_ops::write_summary(&summary, &main_args.out_log);
+            for template in &report_template {
+                match report_ops::render_report(&main_args.out_path, template) {
+                    Ok(report) => file_ops::log_msg(&main_args.out_log, format!("[+] Report written to: {}", report)),
+                    Err(e) => file_ops::log_msg(&main_args.out_log, format!("[!] Unable to render the report: {}", e)),
+                }
+            }
+            let cleanup = match cleanup.as_deref() {
+                Some("intermediate") => config::Cleanup::Intermediate,
+                Some("aggressive") => config::Cleanup::Aggressive,
+                Some(_) => config::Cleanup::None,
+                None => config.cleanup,
+            };
+            if cleanup != config::Cleanup::None {
+                if summary.commands.iter().any(|c| matches!(c.status, summary_ops::Status::Failed | summary_ops::Status::Killed)) {
+                    file_ops::log_msg(&main_args.out_log, "[ ] Skipping the cleanup of the outputs, the run had failed commands".to_string());
+                } else {
+                    exe_ops::cleanup_outputs(&config, &main_args, cleanup);
+                }
+            }
+            // the manifest is last, so it has the files written at the end of the run
+            if manifest {
+                match valid_ops::write_manifest(&summary.commands, &main_args, hash_jobs) {
+                    Ok(path) => file_ops::log_msg(&main_args.out_log, format!("[+] Manifest of the output written to: {}", path)),
+                    Err(e) => file_ops::log_msg(&main_args.out_log, format!("[!] Unable to write the manifest. Error: {}", e)),
+                }
+            }
+            status_writer.stop();
+            drop(secret_iocs);
         },
     }
 }
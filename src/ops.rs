@@ -2,4 +2,18 @@ pub mod file_ops;
 pub mod exe_ops;
 pub mod valid_ops;
 pub mod get_files;
-pub mod sector_reader;
\ No newline at end of file
+pub mod sector_reader;
+pub mod config_ops;
+pub mod summary_ops;
+pub mod ioc_ops;
+pub mod table_ops;
+pub mod secret_ops;
+pub mod crypt_ops;
+pub mod trace_ops;
+pub mod mount_ops;
+pub mod usage_ops;
+pub mod report_ops;
+pub mod provenance_ops;
+pub mod status_ops;
+pub mod bench_ops;
+pub mod job_ops;
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
+use serde::Serialize;
+use tabled::{Table, Tabled};
+use tabled::settings::Style;
+
+use crate::configs::config;
+use super::{exe_ops, file_ops};
+use super::summary_ops::Status;
+
+/// The throughput of a tool on its sample input, to predict how long a full case takes
+#[derive(Debug, Serialize, Tabled)]
+struct Benchmark {
+    name: String,
+    tool: String,
+    input: String,
+    input_bytes: u64,
+    wall_s: f64,
+    bytes_per_s: u64,
+    status: String,
+}
+
+/// The benchmark of the tools of a config, written to benchmark.json
+#[derive(Debug, Serialize)]
+struct BenchmarkRun {
+    run_id: String,
+    generated: String,
+    cpus: usize,
+    tools: Vec<Benchmark>,
+}
+
+/// the size of the files an input path matches, which is a file, a folder or a glob
+fn input_size(path: &str) -> u64 {
+    file_ops::walk_glob(path).0.iter().map(|p| file_ops::dir_size(p)).sum()
+}
+
+/// run each wisker selected on its input, one at a time so the tools don't compete, and
+/// time it to get its throughput. The input is the `benchmark_input` of the command if
+/// set, i.e. a small sample of its artefact, else its artefact in the data source. The
+/// commands whose artefact was not found are left out. The results are printed and written
+/// to `out_path/benchmark.json`
+///
+/// Args:
+/// * `config` - the config of the tools to benchmark
+/// * `main_args` - the arguments specified from the main.rs, i.e. out_path and out_log
+/// * `data_paths` - the hash map of the artefact name and file path
+///
+/// returns the file path of the benchmark, or the reason it could not be written
+pub fn write_benchmark(config: &config::Config, main_args: &config::MainArgs, data_paths: &HashMap<String, String>) -> Result<String, String> {
+    let mut tools = Vec::new();
+    for wisker in config.wiskers.iter().filter(|w| w.selected(main_args)) {
+        let mut data_paths = data_paths.clone();
+        if !wisker.benchmark_input.is_empty() {
+            data_paths.insert(wisker.input.to_string(), wisker.benchmark_input.to_string());
+        }
+        if !exe_ops::artefact_found(&data_paths, &wisker.input) {
+            file_ops::log_msg(&main_args.out_log, format!("[-] Not benchmarking {}, its input {} was not found", wisker.name, wisker.input));
+            continue;
+        }
+        let input = data_paths.get(&wisker.input).cloned().unwrap_or_default();
+        let input_bytes = input_size(&input);
+        file_ops::log_msg(&main_args.out_log, format!("[ ] Benchmarking {} on {} bytes of {}", wisker.name, input_bytes, input));
+        let start = Instant::now();
+        let result = exe_ops::run_single("wiskers", wisker, config, main_args, data_paths);
+        let wall_s = match &result {
+            Some(cmd) if cmd.status != Status::Skipped => cmd.duration_s,
+            _ => start.elapsed().as_secs_f64(),
+        };
+        tools.push(Benchmark {
+            name: wisker.name.to_string(),
+            tool: wisker.binary.path().to_string(),
+            input,
+            input_bytes,
+            wall_s: (wall_s * 1000.0).round() / 1000.0,
+            bytes_per_s: match wall_s > 0.0 {
+                true => (input_bytes as f64 / wall_s) as u64,
+                false => 0,
+            },
+            status: result.map(|cmd| format!("{:?}", cmd.status).to_lowercase()).unwrap_or("not run".to_string()),
+        });
+    }
+
+    let mut table = Table::new(&tools);
+    table.with(Style::psql());
+    println!("{}", table);
+    file_ops::log_msg(&main_args.out_log, table.to_string());
+
+    let benchmark = BenchmarkRun {
+        run_id: main_args.run_id.to_string(),
+        generated: file_ops::now().to_rfc3339(),
+        cpus: num_cpus::get(),
+        tools,
+    };
+    let json_path = Path::new(&main_args.out_path).join("benchmark.json");
+    let json = serde_json::to_string_pretty(&benchmark).map_err(|e| e.to_string())?;
+    std::fs::write(&json_path, json).map_err(|e| format!("unable to write {}. Error: {}", json_path.display(), e))?;
+    Ok(json_path.display().to_string())
+}
@@ -0,0 +1,804 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::art::paths;
+use crate::configs::config;
+use super::{exe_ops, file_ops, ioc_ops, secret_ops, summary_ops};
+
+/// read the config of the binaries to run, i.e. config/main_win.yaml
+pub fn read_config(config_path: &String) -> config::Config {
+    let f: std::fs::File = OpenOptions::new()
+        .read(true)
+        .open(config_path)
+        .expect("Unable to open config file.");
+    serde_yaml::from_reader(f).expect("Could not read values.")
+}
+
+/// pick the config to run from a numbered list of those in the configs folder, where the
+/// yaml files that are not a config of the binaries, i.e. the artefacts, are not listed
+///
+/// Args:
+/// * `configs_dir` - the folder of the configs
+/// * `default` - the config the list starts at, if it is in the folder
+///
+/// returns the file path of the config picked, or the reason none was
+pub fn pick_config(configs_dir: &str, default: &str) -> Result<String, String> {
+    let mut configs: Vec<String> = std::fs::read_dir(configs_dir)
+        .map_err(|e| format!("unable to read the configs folder {}. Error: {}", configs_dir, e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "yaml" || ext == "yml"))
+        .filter(|path| std::fs::read_to_string(path).is_ok_and(|text| serde_yaml::from_str::<config::Config>(&text).is_ok()))
+        .map(|path| path.display().to_string())
+        .collect();
+    if configs.is_empty() {
+        return Err(format!("no configs found in {}, give one with --config", configs_dir));
+    }
+    configs.sort();
+    let options: Vec<String> = configs.iter().enumerate().map(|(i, c)| format!("{}. {}", i + 1, c)).collect();
+    let start = configs.iter().position(|c| Path::new(c) == Path::new(default)).unwrap_or(0);
+    let picked = inquire::Select::new("Which config to run?", options)
+        .with_starting_cursor(start)
+        .raw_prompt()
+        .map_err(|e| e.to_string())?;
+    Ok(configs[picked.index].to_string())
+}
+
+/// read the config of the binaries to run from a secret, keeping it in memory. The
+/// error only has the location, as the message of a yaml error can quote the values
+///
+/// returns the config, or the reason it could not be read
+pub fn read_config_secret(uri: &str) -> Result<config::Config, String> {
+    let secret = secret_ops::get_secret(uri)
+        .map_err(|e| format!("unable to get the config secret. Error: {}", e))?;
+    serde_yaml::from_str(secret.expose())
+        .map_err(|e| format!("could not read values of the config secret {}, at {:?}", uri, e.location()))
+}
+
+/// read the default args from an env file, i.e. .wiskess.toml, which is TOML of
+/// `key = 'value'` lines. The keys of a [section] are read as if at the top
+///
+/// Args:
+/// * `env_file` - the path of the env file
+///
+/// returns the keys and values in the order of the file, or the reason it could not be read.
+/// The error only has the line, as the message of a toml error quotes the line, i.e. a link
+pub fn read_env_file(env_file: &str) -> Result<Vec<(String, String)>, String> {
+    let contents = std::fs::read_to_string(env_file).map_err(|e| e.to_string())?;
+    let table: toml::Table = contents.parse().map_err(|e: toml::de::Error| {
+        let line = e.span().map(|span| contents[..span.start].lines().count().max(1)).unwrap_or(0);
+        format!("it is not valid TOML at line {}", line)
+    })?;
+    let mut values = Vec::new();
+    for (key, value) in table.iter().flat_map(|(key, value)| match value {
+        toml::Value::Table(section) => section.iter().collect(),
+        _ => vec![(key, value)],
+    }) {
+        let value = match value {
+            toml::Value::String(s) => s.to_string(),
+            toml::Value::Table(_) | toml::Value::Array(_) => return Err(format!("'{}' is not a string, number or boolean", key)),
+            other => other.to_string(),
+        };
+        values.push((key.replace('-', "_").to_lowercase(), value));
+    }
+    Ok(values)
+}
+
+/// deep merge the overlay value onto the base, where the mappings are merged by
+/// key and any other value replaces the base
+fn merge_value(base: &mut serde_yaml::Value, overlay: &serde_yaml::Value) {
+    match (base.as_mapping_mut(), overlay.as_mapping()) {
+        (Some(base_map), Some(overlay_map)) => {
+            for (key, value) in overlay_map {
+                match base_map.get_mut(key) {
+                    Some(base_value) => merge_value(base_value, value),
+                    None => { base_map.insert(key.clone(), value.clone()); },
+                }
+            }
+        },
+        _ => *base = overlay.clone(),
+    }
+}
+
+/// apply an overlay config onto the base config. Each entry of an overlay stage
+/// is matched by name, and either removed if it has `delete: true`, has its
+/// fields overridden if it is in the base, or otherwise added to the stage.
+///
+/// Args:
+/// * `base` - the base config as yaml, which is changed by the overlay
+/// * `overlay_path` - the file path of the overlay config
+///
+/// returns the changes made to the base config
+fn apply_overlay(base: &mut serde_yaml::Value, overlay_path: &String) -> Vec<String> {
+    let overlay_str = std::fs::read_to_string(overlay_path).expect("Unable to open overlay file.");
+    let overlay: serde_yaml::Value = serde_yaml::from_str(&overlay_str).expect("Could not read values of overlay.");
+    let mut changes = Vec::new();
+    let overlay_map = match overlay.as_mapping() {
+        Some(m) => m,
+        None => return changes,
+    };
+    for (stage, entries) in overlay_map {
+        let stage_name = stage.as_str().unwrap_or_default();
+        if !["wiskers", "enrichers", "reporters", "dedupe"].contains(&stage_name) {
+            changes.push(format!("ignored unknown stage {}", stage_name));
+            continue;
+        }
+        let base_entries = match base.get_mut(stage).and_then(|v| v.as_sequence_mut()) {
+            Some(e) => e,
+            None => continue,
+        };
+        for entry in entries.as_sequence().into_iter().flatten() {
+            let name = match entry.get("name").and_then(|n| n.as_str()) {
+                Some(n) => n,
+                None => {
+                    changes.push(format!("ignored an entry of {} without a name", stage_name));
+                    continue;
+                }
+            };
+            let index = base_entries.iter().position(|e| e.get("name").and_then(|n| n.as_str()) == Some(name));
+            let delete = entry.get("delete").and_then(|d| d.as_bool()).unwrap_or(false);
+            match (index, delete) {
+                (Some(i), true) => {
+                    base_entries.remove(i);
+                    changes.push(format!("removed {}/{}", stage_name, name));
+                },
+                (None, true) => changes.push(format!("not removed {}/{}, as it is not in the config", stage_name, name)),
+                (Some(i), false) => {
+                    merge_value(&mut base_entries[i], entry);
+                    let fields: Vec<&str> = entry.as_mapping().into_iter().flatten()
+                        .filter_map(|(k, _)| k.as_str())
+                        .filter(|k| *k != "name")
+                        .collect();
+                    changes.push(format!("overrode {}/{}: {}", stage_name, name, fields.join(", ")));
+                },
+                (None, false) => {
+                    base_entries.push(entry.clone());
+                    changes.push(format!("added {}/{}", stage_name, name));
+                },
+            }
+        }
+    }
+    changes
+}
+
+/// apply the overlay configs in order onto the config, and log the net effect
+///
+/// Args:
+/// * `config` - the base config, i.e. from config/main_win.yaml
+/// * `overlays` - the file paths of the overlay configs
+/// * `out_log` - the file path to the wiskess log
+///
+/// returns the config with the overlays applied
+pub fn apply_overlays(config: config::Config, overlays: &[String], out_log: &String) -> config::Config {
+    let mut base = serde_yaml::to_value(&config).expect("Unable to serialise the config.");
+    for overlay_path in overlays {
+        let changes = apply_overlay(&mut base, overlay_path);
+        file_ops::log_msg(out_log, format!("[ ] Overlay {} made {} changes", overlay_path, changes.len()));
+        for change in changes {
+            file_ops::log_msg(out_log, format!("[ ]   {}", change));
+        }
+    }
+    serde_yaml::from_value(base).expect("Could not read values of the config with the overlays.")
+}
+
+/// read the config of the artefact file paths, i.e. config/artefacts.yaml
+pub fn read_config_art(artefacts_config: &String) -> config::ConfigArt {
+    let f: std::fs::File = OpenOptions::new()
+        .read(true)
+        .open(artefacts_config)
+        .expect("Unable to open artefacts config file.");
+    serde_yaml::from_reader(f).expect("Could not read values of artefacts config.")
+}
+
+/// get the path of the artefacts config of a config, which is its `artefacts_file`
+/// if set, relative to the folder of the config, otherwise the --artefacts-config
+fn artefacts_path(config: &config::Config, config_path: &str, artefacts_config: &str) -> String {
+    if config.artefacts_file.is_empty() {
+        return artefacts_config.to_string();
+    }
+    match Path::new(config_path).parent() {
+        Some(dir) if Path::new(&config.artefacts_file).is_relative() => dir.join(&config.artefacts_file).to_string_lossy().to_string(),
+        _ => config.artefacts_file.to_string(),
+    }
+}
+
+/// merge the artefacts read from several sources, where an artefact name must
+/// only be in one of them
+///
+/// returns the artefacts, or the error of the first name that is in more than one source
+pub fn merge_artefacts(sources: Vec<(String, Vec<config::Artefacts>)>) -> Result<Vec<config::Artefacts>, String> {
+    let mut merged: Vec<(String, config::Artefacts)> = Vec::new();
+    for (source, artefacts) in sources {
+        for art in artefacts {
+            if let Some((other, _)) = merged.iter().find(|(s, a)| a.name == art.name && s != &source) {
+                return Err(format!("artefact '{}' is in both {} and {}", art.name, other, source));
+            }
+            merged.push((source.to_string(), art));
+        }
+    }
+    Ok(merged.into_iter().map(|(_, art)| art).collect())
+}
+
+/// read the artefacts of a config, from its `artefacts_file` or the artefacts config,
+/// merged with the artefacts inline in the config. This lets the artefact paths be
+/// kept in a separate file to the commands
+///
+/// Args:
+/// * `config` - the config of the commands
+/// * `config_path` - the path of the config, which a relative `artefacts_file` is from
+/// * `artefacts_config` - the artefacts config given by --artefacts-config
+///
+/// returns the artefacts and the file they were read from, or the error if an artefact name is in more than one source
+pub fn read_artefacts(config: &config::Config, config_path: &str, artefacts_config: &str) -> Result<(Vec<config::Artefacts>, String), String> {
+    let art_file = artefacts_path(config, config_path, artefacts_config);
+    let external = read_config_art(&art_file).artefacts;
+    let artefacts = merge_artefacts(vec![
+        (art_file.to_string(), external),
+        ("the config".to_string(), config.artefacts.clone()),
+    ])?;
+    Ok((artefacts, art_file))
+}
+
+/// write the config that wiskess will run with, after it has been fully resolved,
+/// to `out_path/effective_config.yaml`. This is what is actually executed, so it
+/// can be used to reproduce a run or debug why a command ran.
+///
+/// Args:
+/// * `config` - the resolved config of the wiskers, enrichers and reporters
+/// * `out_path` - the wiskess output folder
+/// * `out_log` - the file path to the wiskess log
+/// * `to_stdout` - also print the resolved config to the terminal
+pub fn dump_effective(config: &config::Config, out_path: &str, out_log: &String, to_stdout: bool) {
+    let config_str = serde_yaml::to_string(config).expect("Unable to serialise the effective config.");
+    let dump_path = Path::new(out_path).join("effective_config.yaml");
+    std::fs::write(&dump_path, &config_str).expect("Unable to write the effective config.");
+    file_ops::log_msg(out_log, format!("[ ] Effective config written to: {}", dump_path.display()));
+    if to_stdout {
+        println!("{}", config_str);
+    }
+}
+
+/// the commands selected to run that write the same output file as another, so one would
+/// overwrite the other, by the path of its outfile in the output folder. The outputs of the
+/// commands of the previous run into the output folder, by its run_summary.json, are checked
+/// too, as it may have been run with another config or profile
+///
+/// Args:
+/// * `config` - the config of all stages, after the overlays
+/// * `main_args` - the arguments of the run, of the commands selected and the output folder
+///
+/// returns a description of each collision, naming the commands
+pub fn output_collisions(config: &config::Config, main_args: &config::MainArgs) -> Vec<String> {
+    let mut collisions = Vec::new();
+    let mut outputs: Vec<(String, String)> = Vec::new();
+    for (stage, func) in config.stages() {
+        for wisker in func.iter().filter(|w| w.selected(main_args)) {
+            let out_file = wisker.out_file(&main_args.out_path);
+            let id = format!("{}/{}", stage, wisker.name);
+            if let Some((_, other)) = outputs.iter().find(|(o, _)| o.eq_ignore_ascii_case(&out_file)) {
+                collisions.push(format!("{} and {} both write {}", other, id, out_file));
+            }
+            outputs.push((out_file, id));
+        }
+    }
+    // a command of the previous run by the same name is this one run again
+    if let Ok(previous) = summary_ops::read_summary(&main_args.out_path) {
+        for cmd in &previous.commands {
+            let other = outputs.iter().find(|(o, id)| o.eq_ignore_ascii_case(&cmd.output) && !id.ends_with(&format!("/{}", cmd.name)));
+            if let Some((out_file, id)) = other {
+                collisions.push(format!("{} writes {}, the output of {}/{} in the previous run {}", id, out_file, cmd.stage, cmd.name, previous.run_id));
+            }
+        }
+    }
+    collisions
+}
+
+/// the JSON Schema of the config, generated from the config types, for editors
+/// to validate and autocomplete a config
+pub fn schema() -> String {
+    let schema = schemars::schema_for!(config::Config);
+    serde_json::to_string_pretty(&schema).expect("Unable to serialise the config schema.")
+}
+
+/// Errors and warnings found by the config lint
+#[derive(Debug, Default)]
+pub struct LintReport {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// the keys each command in the config must have, the rest have defaults
+const REQUIRED_KEYS: [&str; 6] = ["name", "binary", "args", "outfolder", "input", "outfile"];
+
+/// check if a binary can be run, either as a file path or found in the PATH env var
+pub(crate) fn binary_exists(binary: &str) -> bool {
+    if binary.contains('/') || binary.contains('\\') {
+        return Path::new(binary).is_file();
+    }
+    let exts = if std::env::consts::OS == "windows" { vec!["", ".exe", ".cmd", ".bat"] } else { vec![""] };
+    match std::env::var_os("PATH") {
+        Some(paths) => std::env::split_paths(&paths).any(|dir| {
+            exts.iter().any(|ext| dir.join(format!("{}{}", binary, ext)).is_file())
+        }),
+        None => false,
+    }
+}
+
+/// the time to wait for a tool to print its help or version
+const TOOL_WAIT: Duration = Duration::from_secs(10);
+
+/// run the tool with each of the arguments until one prints something, with stdin
+/// closed and a timeout, so a tool waiting on input doesn't hang the lint
+fn tool_output(binary: &str, args: &[&str]) -> Option<String> {
+    for arg in args {
+        let mut child = match Command::new(binary).arg(arg)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn() {
+            Ok(child) => child,
+            Err(_) => return None,
+        };
+        let start = Instant::now();
+        while child.try_wait().ok().flatten().is_none() {
+            if start.elapsed() > TOOL_WAIT {
+                let _ = child.kill();
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        if let Ok(output) = child.wait_with_output() {
+            let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+            if !text.trim().is_empty() {
+                return Some(text);
+            }
+        }
+    }
+    None
+}
+
+/// the version numbers of a version string, i.e. `v2.13.0` is [2, 13, 0], to compare them
+fn version_parts(version: &str) -> Vec<u64> {
+    let re_version = regex::Regex::new(r"\d+(\.\d+)*").unwrap();
+    re_version.find(version)
+        .map(|m| m.as_str().split('.').filter_map(|p| p.parse().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// the flags passed to the tool in the args of a command, ignoring quoted values and
+/// what follows a pipe or redirect, as these are for the shell or another tool
+fn arg_flags(args: &str) -> Vec<String> {
+    let re_quoted = regex::Regex::new(r#""[^"]*"|'[^']*'"#).unwrap();
+    let unquoted = re_quoted.replace_all(args, " ");
+    let tool_args = unquoted.split(['|', ';', '>', '<', '&']).next().unwrap_or_default();
+    let re_flag = regex::Regex::new(r"(?:^|\s)(--?[A-Za-z][\w-]*)").unwrap();
+    let mut flags: Vec<String> = re_flag.captures_iter(tool_args).map(|c| c[1].to_string()).collect();
+    flags.dedup();
+    flags
+}
+
+/// check if the help of the tool mentions the flag, or each letter of combined short
+/// flags, i.e. `-la`
+fn help_has_flag(help: &str, flag: &str) -> bool {
+    let mentions = |f: &str| regex::Regex::new(&format!(r"(^|[^\w-]){}([^\w-]|$)", regex::escape(f)))
+        .is_ok_and(|re| re.is_match(help));
+    mentions(flag) || (!flag.starts_with("--") && flag.len() > 2 && flag[1..].chars().all(|c| mentions(&format!("-{}", c))))
+}
+
+/// check the installed tools against the config, running the help and version of each
+/// binary once, for the flags in the args that the help doesn't mention and versions
+/// that differ from `expected_version` or are older than `min_version`. This catches
+/// the config drifting from the tools after they are updated
+///
+/// Args:
+/// * `config` - the config of the commands to check
+/// * `tool_path` - the path where the binaries are stored, replacing `{tool_path}`
+/// * `report` - the lint report to add the warnings to
+fn check_tools(config: &config::Config, tool_path: &str, report: &mut LintReport) {
+    let mut tools: HashMap<String, (Option<String>, Option<String>)> = HashMap::new();
+    for (stage, func) in config.stages() {
+        for wisker in func.iter().filter(|w| w.chk_exists) {
+            let id = format!("{}/{}", stage, wisker.name);
+            let binary = wisker.binary.path().replace("{tool_path}", tool_path);
+            if !binary_exists(&binary) {
+                continue;
+            }
+            let (help, version) = tools.entry(binary.to_string()).or_insert_with(|| (
+                tool_output(&binary, &["--help", "-h", "help", "/?"]),
+                tool_output(&binary, &["--version", "-V", "-v", "version"]),
+            ));
+
+            match help {
+                Some(help) => {
+                    for flag in arg_flags(&wisker.args).iter().filter(|f| !help_has_flag(help, f)) {
+                        report.warnings.push(format!("{}: flag '{}' is not in the help of '{}', the tool may have changed", id, flag, binary));
+                    }
+                },
+                None => report.warnings.push(format!("{}: unable to get the help of '{}', its flags are not checked", id, binary)),
+            }
+
+            if wisker.min_version.is_empty() && wisker.expected_version.is_empty() {
+                continue;
+            }
+            let installed = version.as_deref().or(help.as_deref()).map(version_parts).unwrap_or_default();
+            if installed.is_empty() {
+                report.warnings.push(format!("{}: unable to get the version of '{}'", id, binary));
+                continue;
+            }
+            let installed_str = installed.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(".");
+            if !wisker.expected_version.is_empty() && installed != version_parts(&wisker.expected_version) {
+                report.warnings.push(format!("{}: '{}' is version {}, but {} is expected", id, binary, installed_str, wisker.expected_version));
+            }
+            if !wisker.min_version.is_empty() && installed < version_parts(&wisker.min_version) {
+                report.warnings.push(format!("{}: '{}' is version {}, older than the min_version {}", id, binary, installed_str, wisker.min_version));
+            }
+        }
+    }
+}
+
+/// statically check the config without running it, for missing keys, duplicate
+/// names, dangling `depends_on`, unknown placeholders, unknown fields, inputs not
+/// in the artefacts config and tool binaries that don't exist
+///
+/// Args:
+/// * `config_path` - the file path of the config, i.e. config/main_win.yaml
+/// * `artefacts_config` - the file path of the artefacts config, i.e. config/artefacts.yaml
+/// * `tool_path` - the path where the binaries are stored, replacing `{tool_path}`
+/// * `check_tools` - run the tools to check their flags and versions against the config
+///
+/// returns the errors and warnings found
+pub fn lint(config_path: &String, artefacts_config: &str, tool_path: &str, check_tools: bool) -> LintReport {
+    let mut report = LintReport::default();
+    let config_str = match std::fs::read_to_string(config_path) {
+        Ok(s) => s,
+        Err(e) => {
+            report.errors.push(format!("{}: unable to read the config. Error: {}", config_path, e));
+            return report;
+        }
+    };
+    let raw: serde_yaml::Value = match serde_yaml::from_str(&config_str) {
+        Ok(v) => v,
+        Err(e) => {
+            report.errors.push(format!("{}: unable to read the config. Error: {}", config_path, e));
+            return report;
+        }
+    };
+    let stages = ["wiskers", "enrichers", "reporters"];
+    // the top level keys are the fields of the config, by its schema, so they are not listed again here
+    let schema = schemars::schema_for!(config::Config);
+    let config_keys: Vec<&String> = schema.schema.object.as_ref().map(|o| o.properties.keys().collect()).unwrap_or_default();
+
+    // check the raw yaml for the keys, as deserialising stops at the first error
+    if let Some(top) = raw.as_mapping() {
+        for key in top.keys().filter_map(|k| k.as_str()) {
+            if !config_keys.iter().any(|k| k.as_str() == key) {
+                report.warnings.push(format!("{}: unknown stage, it will not be run", key));
+            }
+        }
+        for stage in stages {
+            let entries = match top.get(stage).and_then(|v| v.as_sequence()) {
+                Some(entries) => entries,
+                None => {
+                    report.errors.push(format!("{}: missing stage", stage));
+                    continue;
+                }
+            };
+            for (i, entry) in entries.iter().enumerate() {
+                let name = entry.get("name").and_then(|n| n.as_str()).map(|n| n.to_string()).unwrap_or(format!("#{}", i));
+                for key in REQUIRED_KEYS {
+                    if entry.get(key).is_none() {
+                        report.errors.push(format!("{}/{}: missing key '{}'", stage, name, key));
+                    }
+                }
+            }
+        }
+    } else {
+        report.errors.push(format!("{}: the config is not a mapping of stages", config_path));
+    }
+    if !report.errors.is_empty() {
+        return report;
+    }
+
+    // deserialise from the string, so the error has the line of the config
+    let mut config: config::Config = match serde_yaml::from_str::<config::Config>(&config_str) {
+        Ok(c) => c.share_outfolders(),
+        Err(e) => {
+            report.errors.push(format!("{}: {}", config_path, e));
+            return report;
+        }
+    };
+    report.errors.extend(config.unknown_depends());
+
+    // the dependencies inferred from the inputs and outputs of the commands, with those written
+    if let Err(problems) = config.infer_depends() {
+        report.errors.extend(problems);
+    }
+
+    // get the known fields from a parsed command, as it has every field
+    let known_keys: Vec<String> = config.stages().iter()
+        .find_map(|(_, func)| func.first())
+        .and_then(|w| serde_yaml::to_value(w).ok())
+        .and_then(|v| v.as_mapping().map(|m| m.keys().filter_map(|k| k.as_str().map(|k| k.to_string())).collect()))
+        .unwrap_or_default();
+
+    let art_file = artefacts_path(&config, config_path, artefacts_config);
+    let external = std::fs::read_to_string(&art_file).ok()
+        .and_then(|s| serde_yaml::from_str::<config::ConfigArt>(&s).ok())
+        .map(|c| c.artefacts);
+    if external.is_none() {
+        report.warnings.push(format!("{}: unable to read the artefacts config, inputs are not checked", art_file));
+    }
+    let merged = match merge_artefacts(vec![
+        (art_file.to_string(), external.clone().unwrap_or_default()),
+        ("the config".to_string(), config.artefacts.clone()),
+    ]) {
+        Ok(artefacts) => artefacts,
+        Err(e) => {
+            report.errors.push(e);
+            Vec::new()
+        }
+    };
+    let art_names: Option<Vec<String>> = match external.is_some() && report.errors.is_empty() {
+        true => Some(merged.iter().map(|a| a.name.to_string()).collect()),
+        false => None,
+    };
+    // the names of the capture groups in the path of each artefact, for `${name}` in the args
+    let art_captures: HashMap<&String, Vec<String>> = merged.iter()
+        .map(|a| (&a.name, [&a.path, &a.legacy].iter().flat_map(|p| paths::capture_names(p)).collect()))
+        .collect();
+
+    let re_placeholder = regex::Regex::new(r"(\$?)\{(\w+)\}").unwrap();
+    let re_outdir = regex::Regex::new(r"\{outdir:([^}]+)\}").unwrap();
+    let mut names: Vec<&String> = Vec::new();
+    let mut outputs: Vec<(String, &String)> = Vec::new();
+    for (stage, func) in config.stages() {
+        let raw_entries = raw.get(stage).and_then(|v| v.as_sequence());
+        for (i, wisker) in func.iter().enumerate() {
+            let id = format!("{}/{}", stage, wisker.name);
+            if names.contains(&&wisker.name) {
+                report.errors.push(format!("{}: duplicate name", id));
+            }
+            names.push(&wisker.name);
+
+            // commands sharing an outfolder can overwrite each other's output
+            let out_file = wisker.out_file("");
+            if let Some((_, other)) = outputs.iter().find(|(o, _)| o.eq_ignore_ascii_case(&out_file)) {
+                report.warnings.push(format!("{}: writes the same output as '{}', it will be overwritten", id, other));
+            }
+            outputs.push((out_file, &wisker.name));
+
+            if let Some(entry) = raw_entries.and_then(|e| e.get(i)).and_then(|e| e.as_mapping()) {
+                for key in entry.keys().filter_map(|k| k.as_str()) {
+                    if !known_keys.iter().any(|k| k == key) {
+                        report.warnings.push(format!("{}: unknown field '{}', it is ignored", id, key));
+                    }
+                }
+            }
+
+            for field in [&wisker.args, &wisker.script_posh, &wisker.request] {
+                for cap in re_outdir.captures_iter(field) {
+                    if config.find(&cap[1]).is_none() {
+                        report.errors.push(format!("{}: '{{outdir:{}}}' is not a command in the config", id, &cap[1]));
+                    }
+                }
+            }
+
+            for field in [wisker.binary.path(), &wisker.args, &wisker.script_posh, &wisker.request, &wisker.server_ready, &wisker.outfile] {
+                for placeholder in ioc_ops::missing_ioc_placeholders(field) {
+                    report.errors.push(format!("{}: '{}' is not provided by the IOC file, an untyped list that only provides {{ioc_file}}", id, placeholder));
+                }
+                for cap in re_placeholder.captures_iter(field) {
+                    let placeholder = &cap[2];
+                    if placeholder.starts_with("ioc_") && !ioc_ops::IOC_PLACEHOLDERS.contains(&placeholder) {
+                        continue;
+                    }
+                    if !cap[1].is_empty() {
+                        if external.is_some() && !art_captures.get(&wisker.input).is_some_and(|c| c.iter().any(|n| n == placeholder)) {
+                            report.warnings.push(format!("{}: variable '${{{}}}' is not a capture group of the input '{}', it will not be replaced", id, placeholder, wisker.input));
+                        }
+                        continue;
+                    }
+                    let numeric = placeholder.chars().all(|c| c.is_ascii_digit());
+                    if !numeric && !exe_ops::PLACEHOLDERS.contains(&placeholder) {
+                        report.warnings.push(format!("{}: unknown placeholder '{{{}}}', it will not be replaced", id, placeholder));
+                    }
+                }
+            }
+
+            if let Some(art_names) = &art_names {
+                for input in [&wisker.input, &wisker.input_other, &wisker.run_if_artefact] {
+                    if !input.is_empty() && !art_names.contains(input) {
+                        report.errors.push(format!("{}: input '{}' is not in the artefacts config", id, input));
+                    }
+                }
+            }
+
+            if wisker.server && wisker.request.is_empty() {
+                report.errors.push(format!("{}: server is set, but there is no request command to send each artefact", id));
+            }
+
+            if let Some(filter) = &wisker.post_filter {
+                if let Err(e) = regex::Regex::new(&filter.pattern) {
+                    report.errors.push(format!("{}: post_filter pattern is not valid. Error: {}", id, e));
+                }
+            }
+
+            for (field, pattern) in [("output_must_match", &wisker.output_must_match), ("output_must_not_match", &wisker.output_must_not_match)] {
+                if let Err(e) = regex::Regex::new(pattern) {
+                    report.errors.push(format!("{}: {} is not valid. Error: {}", id, field, e));
+                }
+            }
+
+            if !wisker.encoding.is_empty() && !file_ops::ENCODINGS.contains(&wisker.encoding.to_lowercase().as_str()) {
+                report.errors.push(format!("{}: encoding '{}' is not one of {}", id, wisker.encoding, file_ops::ENCODINGS.join(", ")));
+            }
+
+            let binary = match wisker.binary.for_os() {
+                Some(binary) => binary.replace("{tool_path}", tool_path),
+                None => {
+                    report.warnings.push(format!("{}: no binary for the OS {}, so it is skipped", id, std::env::consts::OS));
+                    continue;
+                },
+            };
+            if wisker.chk_exists && !binary_exists(&binary) {
+                report.warnings.push(format!("{}: binary '{}' not found", id, binary));
+            }
+        }
+    }
+    for dedupe in &config.dedupe {
+        for input in &dedupe.inputs {
+            if config.find(input).is_none() {
+                report.errors.push(format!("dedupe/{}: input '{}' is not in the config", dedupe.name, input));
+            }
+        }
+    }
+    if check_tools {
+        self::check_tools(&config, tool_path, &mut report);
+    }
+    if let Some(art_names) = &art_names {
+        for key in config.missing_severity.keys().filter(|k| *k != "default") {
+            let matched = glob::Pattern::new(key).is_ok_and(|p| art_names.iter().any(|a| p.matches(a)));
+            if !matched {
+                report.warnings.push(format!("missing_severity/{}: matches no artefact in the artefacts config", key));
+            }
+        }
+    }
+    report
+}
+
+/// print the lint report grouped into errors and warnings
+pub fn print_lint(report: &LintReport) {
+    if !report.errors.is_empty() {
+        println!("[!] Errors:");
+        for e in &report.errors {
+            println!("    - {}", e);
+        }
+    }
+    if !report.warnings.is_empty() {
+        println!("[?] Warnings:");
+        for w in &report.warnings {
+            println!("    - {}", w);
+        }
+    }
+    println!(
+        "[{}] Lint found {} errors and {} warnings",
+        if report.errors.is_empty() { "+" } else { "!" },
+        report.errors.len(),
+        report.warnings.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_overlay(dir: &tempfile::TempDir, yaml: &str) -> String {
+        let path = dir.path().join("overlay.yaml");
+        std::fs::write(&path, yaml).unwrap();
+        path.display().to_string()
+    }
+
+    fn names(base: &serde_yaml::Value, stage: &str) -> Vec<String> {
+        base[stage].as_sequence().unwrap().iter()
+            .map(|e| e["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn overlay_adds_overrides_and_removes_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut base: serde_yaml::Value = serde_yaml::from_str(
+            "wiskers:\n- {name: keep, args: -a, outfile: a.csv}\n- {name: change, args: -b, outfile: b.csv}\n- {name: drop, args: -c}\nenrichers: []\n",
+        ).unwrap();
+        let overlay = write_overlay(&dir,
+            "wiskers:\n- {name: change, args: -z}\n- {name: drop, delete: true}\n- {name: gone, delete: true}\n- {name: new, args: -n}\n- {args: -x}\nfoo:\n- {name: x}\n",
+        );
+        let changes = apply_overlay(&mut base, &overlay);
+        assert_eq!(changes, vec![
+            "overrode wiskers/change: args",
+            "removed wiskers/drop",
+            "not removed wiskers/gone, as it is not in the config",
+            "added wiskers/new",
+            "ignored an entry of wiskers without a name",
+            "ignored unknown stage foo",
+        ]);
+        assert_eq!(names(&base, "wiskers"), vec!["keep", "change", "new"]);
+        assert_eq!(base["wiskers"][1]["args"].as_str(), Some("-z"));
+        assert_eq!(base["wiskers"][1]["outfile"].as_str(), Some("b.csv"));
+        assert!(base.get("foo").is_none());
+    }
+
+    #[test]
+    fn merge_value_merges_mappings_and_replaces_the_rest() {
+        let mut base: serde_yaml::Value = serde_yaml::from_str("a: {b: 1, c: [1, 2]}\nd: x\n").unwrap();
+        let overlay: serde_yaml::Value = serde_yaml::from_str("a: {c: [3], e: 4}\nd: y\n").unwrap();
+        merge_value(&mut base, &overlay);
+        let expected: serde_yaml::Value = serde_yaml::from_str("a: {b: 1, c: [3], e: 4}\nd: y\n").unwrap();
+        assert_eq!(base, expected);
+    }
+
+    #[test]
+    fn lint_knows_the_top_level_keys_of_the_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml").display().to_string();
+        std::fs::write(&config_path, "wiskers: []\nenrichers: []\nreporters: []\nioc_severity: {}\ncleanup: none\nshared_outfolders: true\ntriage: []\n").unwrap();
+        let art_path = dir.path().join("artefacts.yaml").display().to_string();
+        std::fs::write(&art_path, "artefacts: []\n").unwrap();
+        let report = lint(&config_path, &art_path, "", false);
+        let unknown: Vec<&String> = report.warnings.iter().filter(|w| w.contains("unknown stage")).collect();
+        assert_eq!(unknown, vec!["triage: unknown stage, it will not be run"]);
+    }
+
+    fn art(name: &str) -> config::Artefacts {
+        config::Artefacts { name: name.to_string(), path: format!("{}/*", name), legacy: String::new(), min_matches: 0 }
+    }
+
+    #[test]
+    fn artefacts_are_merged_from_their_sources() {
+        let merged = merge_artefacts(vec![
+            ("artefacts.yaml".to_string(), vec![art("mft"), art("evtx")]),
+            ("the config".to_string(), vec![art("prefetch")]),
+        ]).unwrap();
+        let names: Vec<&str> = merged.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["mft", "evtx", "prefetch"]);
+    }
+
+    #[test]
+    fn an_artefact_in_two_sources_is_an_error() {
+        let err = merge_artefacts(vec![
+            ("artefacts.yaml".to_string(), vec![art("mft"), art("evtx")]),
+            ("the config".to_string(), vec![art("evtx")]),
+        ]).unwrap_err();
+        assert_eq!(err, "artefact 'evtx' is in both artefacts.yaml and the config");
+    }
+
+    #[test]
+    fn artefacts_file_is_relative_to_the_config() {
+        let mut config: config::Config = serde_yaml::from_str("wiskers: []\nenrichers: []\nreporters: []\n").unwrap();
+        assert_eq!(artefacts_path(&config, "config/main.yaml", "config/artefacts.yaml"), "config/artefacts.yaml");
+        config.artefacts_file = "art/paths.yaml".to_string();
+        assert_eq!(Path::new(&artefacts_path(&config, "config/main.yaml", "config/artefacts.yaml")), Path::new("config/art/paths.yaml"));
+        let absolute = std::env::temp_dir().join("paths.yaml").display().to_string();
+        config.artefacts_file = absolute.to_string();
+        assert_eq!(artefacts_path(&config, "config/main.yaml", "config/artefacts.yaml"), absolute);
+    }
+
+    #[test]
+    fn overlays_are_applied_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let config: config::Config = serde_yaml::from_str(
+            "wiskers:\n- {name: one, binary: b, args: -a, outfolder: o, input: i, outfile: f.csv}\nenrichers: []\nreporters: []\n",
+        ).unwrap();
+        let first = dir.path().join("first.yaml");
+        std::fs::write(&first, "wiskers:\n- {name: one, args: -first}\n").unwrap();
+        let second = dir.path().join("second.yaml");
+        std::fs::write(&second, "wiskers:\n- {name: one, args: -second}\n").unwrap();
+        let out_log = dir.path().join("wiskess.log").display().to_string();
+        let overlays = [first.display().to_string(), second.display().to_string()];
+        let config = apply_overlays(config, &overlays, &out_log);
+        assert_eq!(config.wiskers.len(), 1);
+        assert_eq!(config.wiskers[0].args, "-second");
+    }
+}
@@ -0,0 +1,253 @@
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use aes_gcm::{Aes256Gcm, KeyInit};
+use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+/// the manifest of the encrypted files, written in the output folder
+pub const MANIFEST: &str = "wiskess_encryption.json";
+
+/// the extension added to the encrypted files
+const ENC_EXT: &str = "enc";
+
+/// the size of the chunks each file is encrypted in, so large outputs are not read into memory
+#[cfg(not(test))]
+const CHUNK_SIZE: usize = 1024 * 1024;
+#[cfg(test)]
+const CHUNK_SIZE: usize = 64;
+
+/// the length of the GCM tag added to each chunk
+const TAG_SIZE: usize = 16;
+
+/// The encrypted files of an output folder, with the nonce of each
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Manifest {
+    algorithm: String,
+    chunk_size: usize,
+    files: Vec<EncFile>,
+}
+
+/// An encrypted file, by its path relative to the output folder before encryption
+#[derive(Debug, Serialize, Deserialize)]
+struct EncFile {
+    file: String,
+    nonce: String,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 == 1 {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+/// read the 256 bit key from a key file, which has either 64 hex characters or
+/// the 32 raw bytes of the key
+fn read_key(key_file: &str) -> Result<Aes256Gcm, String> {
+    let bytes = fs::read(key_file).map_err(|e| format!("unable to read the key file {}. Error: {}", key_file, e))?;
+    let key = match String::from_utf8(bytes.clone()).ok().and_then(|s| from_hex(s.trim())) {
+        Some(key) if key.len() == 32 => key,
+        _ if bytes.len() == 32 => bytes,
+        _ => return Err(format!("the key file {} does not have a 256 bit key, as 64 hex characters or 32 bytes", key_file)),
+    };
+    Aes256Gcm::new_from_slice(&key).map_err(|e| e.to_string())
+}
+
+/// the number of chunks of a file, where an empty file is one empty chunk
+fn num_chunks(len: u64, chunk_size: usize) -> u64 {
+    len.div_ceil(chunk_size as u64).max(1)
+}
+
+fn encrypt_file(cipher: Aes256Gcm, nonce: &[u8], src: &Path, dst: &Path) -> Result<(), String> {
+    let mut reader = File::open(src).map_err(|e| e.to_string())?;
+    let mut writer = File::create(dst).map_err(|e| e.to_string())?;
+    let chunks = num_chunks(reader.metadata().map_err(|e| e.to_string())?.len(), CHUNK_SIZE);
+    let mut encryptor = EncryptorBE32::from_aead(cipher, nonce.into());
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    for _ in 1..chunks {
+        reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
+        let chunk = encryptor.encrypt_next(buf.as_slice()).map_err(|e| e.to_string())?;
+        writer.write_all(&chunk).map_err(|e| e.to_string())?;
+    }
+    let mut last = Vec::new();
+    reader.read_to_end(&mut last).map_err(|e| e.to_string())?;
+    let chunk = encryptor.encrypt_last(last.as_slice()).map_err(|e| e.to_string())?;
+    writer.write_all(&chunk).map_err(|e| e.to_string())
+}
+
+fn decrypt_file(cipher: Aes256Gcm, nonce: &[u8], chunk_size: usize, src: &Path, dst: &Path) -> Result<(), String> {
+    let mut reader = File::open(src).map_err(|e| e.to_string())?;
+    let mut writer = File::create(dst).map_err(|e| e.to_string())?;
+    let chunks = num_chunks(reader.metadata().map_err(|e| e.to_string())?.len(), chunk_size + TAG_SIZE);
+    let mut decryptor = DecryptorBE32::from_aead(cipher, nonce.into());
+    let mut buf = vec![0u8; chunk_size + TAG_SIZE];
+    for _ in 1..chunks {
+        reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
+        let chunk = decryptor.decrypt_next(buf.as_slice()).map_err(|_| "the key is wrong or the file is corrupt".to_string())?;
+        writer.write_all(&chunk).map_err(|e| e.to_string())?;
+    }
+    let mut last = Vec::new();
+    reader.read_to_end(&mut last).map_err(|e| e.to_string())?;
+    let chunk = decryptor.decrypt_last(last.as_slice()).map_err(|_| "the key is wrong or the file is corrupt".to_string())?;
+    writer.write_all(&chunk).map_err(|e| e.to_string())
+}
+
+fn read_manifest(out_path: &str) -> Result<Manifest, String> {
+    let manifest_file = Path::new(out_path).join(MANIFEST);
+    if !manifest_file.exists() {
+        return Ok(Manifest::default());
+    }
+    let f = File::open(&manifest_file).map_err(|e| format!("unable to read {}. Error: {}", manifest_file.display(), e))?;
+    serde_json::from_reader(f).map_err(|e| format!("unable to read {}. Error: {}", manifest_file.display(), e))
+}
+
+fn write_manifest(out_path: &str, manifest: &Manifest) -> Result<(), String> {
+    let manifest_file = Path::new(out_path).join(MANIFEST);
+    let json = serde_json::to_string_pretty(manifest).map_err(|e| e.to_string())?;
+    fs::write(&manifest_file, json).map_err(|e| format!("unable to write {}. Error: {}", manifest_file.display(), e))
+}
+
+/// encrypt the files of the output folder with AES-256-GCM, before they are uploaded,
+/// so the results are encrypted independent of the storage. Each file is replaced
+/// by a .enc file, encrypted in chunks with the STREAM construction so it can be
+/// large, and its nonce is written to the manifest. Files already encrypted are skipped.
+///
+/// Args:
+/// * `out_path` - the wiskess output folder
+/// * `key_file` - the file of the 256 bit key, as 64 hex characters or 32 bytes
+///
+/// returns the number of files encrypted, or the reason it failed
+pub fn encrypt_output(out_path: &str, key_file: &str) -> Result<usize, String> {
+    let cipher = read_key(key_file)?;
+    let mut manifest = read_manifest(out_path)?;
+    manifest.algorithm = "AES-256-GCM STREAM-BE32".to_string();
+    manifest.chunk_size = CHUNK_SIZE;
+    let files: Vec<_> = WalkDir::new(out_path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .map(|e| e.into_path())
+        .filter(|p| p.extension() != Some(ENC_EXT.as_ref()) && p.file_name() != Some(MANIFEST.as_ref()))
+        .collect();
+    let mut count = 0;
+    for src in files {
+        let file = src.strip_prefix(out_path).unwrap_or(&src).to_string_lossy().to_string();
+        let mut nonce = [0u8; 7];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let dst = Path::new(&format!("{}.{}", src.display(), ENC_EXT)).to_path_buf();
+        if let Err(e) = encrypt_file(cipher.clone(), &nonce, &src, &dst) {
+            let _ = fs::remove_file(&dst);
+            return Err(format!("unable to encrypt {}. Error: {}", src.display(), e));
+        }
+        fs::remove_file(&src).map_err(|e| format!("unable to remove {} after encrypting it. Error: {}", src.display(), e))?;
+        manifest.files.retain(|f| f.file != file);
+        manifest.files.push(EncFile { file, nonce: to_hex(&nonce) });
+        count += 1;
+        // write the manifest as it goes, so the nonces are kept if it is stopped
+        write_manifest(out_path, &manifest)?;
+    }
+    Ok(count)
+}
+
+/// decrypt the files of an output folder encrypted by `encrypt_output`, using its
+/// manifest, replacing each .enc file with the original file
+///
+/// Args:
+/// * `out_path` - the folder with the .enc files and the manifest
+/// * `key_file` - the file of the key used to encrypt them
+///
+/// returns the number of files decrypted, or the reason it failed
+pub fn decrypt_output(out_path: &str, key_file: &str) -> Result<usize, String> {
+    let cipher = read_key(key_file)?;
+    let mut manifest = read_manifest(out_path)?;
+    if manifest.files.is_empty() {
+        return Err(format!("there are no encrypted files in the manifest {}/{}", out_path, MANIFEST));
+    }
+    let mut count = 0;
+    while let Some(enc) = manifest.files.first() {
+        let dst = Path::new(out_path).join(&enc.file);
+        let src = Path::new(&format!("{}.{}", dst.display(), ENC_EXT)).to_path_buf();
+        let nonce = from_hex(&enc.nonce)
+            .filter(|n| n.len() == 7)
+            .ok_or(format!("the nonce of {} in the manifest is not valid", enc.file))?;
+        if let Err(e) = decrypt_file(cipher.clone(), &nonce, manifest.chunk_size, &src, &dst) {
+            let _ = fs::remove_file(&dst);
+            return Err(format!("unable to decrypt {}. Error: {}", src.display(), e));
+        }
+        fs::remove_file(&src).map_err(|e| format!("unable to remove {} after decrypting it. Error: {}", src.display(), e))?;
+        manifest.files.remove(0);
+        count += 1;
+        write_manifest(out_path, &manifest)?;
+    }
+    fs::remove_file(Path::new(out_path).join(MANIFEST)).map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// an output folder with a file of several chunks and a part chunk, an empty file, and a key file
+    fn setup() -> (tempfile::TempDir, String, String, Vec<u8>) {
+        let dir = tempfile::tempdir().unwrap();
+        let out_path = dir.path().join("out");
+        fs::create_dir_all(out_path.join("sub")).unwrap();
+        let mut data = vec![0u8; 3 * CHUNK_SIZE + 5];
+        rand::thread_rng().fill_bytes(&mut data);
+        fs::write(out_path.join("sub/timeline.csv"), &data).unwrap();
+        fs::write(out_path.join("empty.txt"), b"").unwrap();
+        let key_file = dir.path().join("key.txt");
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        fs::write(&key_file, to_hex(&key)).unwrap();
+        (dir, out_path.display().to_string(), key_file.display().to_string(), data)
+    }
+
+    fn enc_path(out_path: &str) -> std::path::PathBuf {
+        Path::new(out_path).join("sub/timeline.csv.enc")
+    }
+
+    #[test]
+    fn round_trip() {
+        let (_dir, out_path, key_file, data) = setup();
+        assert_eq!(encrypt_output(&out_path, &key_file), Ok(2));
+        assert!(!Path::new(&out_path).join("sub/timeline.csv").exists());
+        assert_eq!(fs::metadata(enc_path(&out_path)).unwrap().len() as usize, data.len() + 4 * TAG_SIZE);
+        assert_eq!(decrypt_output(&out_path, &key_file), Ok(2));
+        assert_eq!(fs::read(Path::new(&out_path).join("sub/timeline.csv")).unwrap(), data);
+        assert_eq!(fs::read(Path::new(&out_path).join("empty.txt")).unwrap(), b"");
+        assert!(!Path::new(&out_path).join(MANIFEST).exists());
+    }
+
+    #[test]
+    fn truncated_final_chunk_is_rejected() {
+        let (_dir, out_path, key_file, _) = setup();
+        encrypt_output(&out_path, &key_file).unwrap();
+        let enc = fs::read(enc_path(&out_path)).unwrap();
+        // without the final chunk, the last full chunk is not marked as the last
+        fs::write(enc_path(&out_path), &enc[..3 * (CHUNK_SIZE + TAG_SIZE)]).unwrap();
+        assert!(decrypt_output(&out_path, &key_file).is_err());
+        assert!(!Path::new(&out_path).join("sub/timeline.csv").exists());
+    }
+
+    #[test]
+    fn tampered_final_chunk_is_rejected() {
+        let (_dir, out_path, key_file, _) = setup();
+        encrypt_output(&out_path, &key_file).unwrap();
+        let mut enc = fs::read(enc_path(&out_path)).unwrap();
+        let last = enc.len() - TAG_SIZE - 1;
+        enc[last] ^= 1;
+        fs::write(enc_path(&out_path), &enc).unwrap();
+        let err = decrypt_output(&out_path, &key_file).unwrap_err();
+        assert!(err.contains("the key is wrong or the file is corrupt"), "{}", err);
+        assert!(!Path::new(&out_path).join("sub/timeline.csv").exists());
+    }
+}
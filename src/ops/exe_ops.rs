@@ -1,11 +1,26 @@
-use std::{collections::HashMap, io::Write, path::Path, process::{Command, Stdio}};
+use std::{collections::HashMap, io::{BufRead, BufReader, Read, Write}, path::Path, process::{Command, Output, Stdio}, thread, time::{Duration, Instant, SystemTime}};
+use chrono::{DateTime, Utc};
 use execute::{shell, Execute};
 use rayon::ThreadPoolBuilder;
+use regex::Regex;
+use sysinfo::{Pid, System};
 use std::fs::{canonicalize, OpenOptions};
+use std::sync::{Mutex, OnceLock};
+use std::sync::mpsc::Sender;
+use indicatif::ProgressBar;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use crate::configs::config::{self, Wiskers};
+use crate::art::paths;
 use crate::init::setup;
-use super::file_ops;
+use super::{config_ops, file_ops, job_ops, status_ops, table_ops};
+use super::summary_ops::{CmdResult, Reason, ReasonKind, Status};
+
+/// the exit code of whipped when the download is cancelled, i.e. by Ctrl-C
+const CANCELLED_EXIT_CODE: i32 = 130;
+
+/// set while whipped runs, so an interrupt waits for it to cancel the downloads cleanly
+pub static WHIPPED_RUNNING: AtomicBool = AtomicBool::new(false);
 
 pub fn run_whipped_script(script: &String, args: config::WhippedArgs) {
     let mut pwsh = "pwsh".to_string();
@@ -29,13 +44,49 @@ pub fn run_whipped_script(script: &String, args: config::WhippedArgs) {
     if args.keep_evidence {
         command.arg("-keep_evidence");
     }
+    command.args(["-run_id", &args.run_id]);
+    command.args(["-case_name", &args.case_name]);
+    if args.prefix_run_id {
+        command.arg("-prefix_run_id");
+    }
+    if args.resume_download {
+        command.arg("-resume_download");
+    }
+    command.args(["-download_concurrency", &args.download_concurrency.to_string()]);
+    command.args(["-source_prefix", &args.source_prefix]);
+    command.args(["-token_refresh_command", &args.token_refresh_command]);
+    if args.encrypt_output {
+        command.arg("-encrypt_output");
+    }
+    command.args(["-encrypt_key", &args.encrypt_key]);
+    command.args(["-output_root_link", &args.output_root_link]);
+    if args.list_remote {
+        command.arg("-list_remote");
+    }
+    command.args(["-include", &args.include.join(";")]);
+    command.args(["-exclude", &args.exclude.join(";")]);
+    command.args(["-storage_type", &args.storage_type]);
+    command.args(["-download_command", &args.download_command]);
+    command.args(["-upload_command", &args.upload_command]);
+    if args.upload_if_valid {
+        command.arg("-upload_if_valid");
+    }
+    command.args(["-valid_threshold", &args.valid_threshold.to_string()]);
+    if args.no_download {
+        command.arg("-no_download");
+    }
+    command.args(["-out_suffix", &args.out_suffix]);
     // command.args(["-tool_path",tool_path]);
 
+    WHIPPED_RUNNING.store(true, Ordering::SeqCst);
     let output = command.execute_output().unwrap();
+    WHIPPED_RUNNING.store(false, Ordering::SeqCst);
 
     if let Some(exit_code) = output.status.code() {
         if exit_code == 0 {
             println!("Ok.");
+        } else if exit_code == CANCELLED_EXIT_CODE {
+            eprintln!("Cancelled, the partial downloads were removed.");
         } else {
             eprintln!("Failed.");
         }
@@ -44,6 +95,33 @@ pub fn run_whipped_script(script: &String, args: config::WhippedArgs) {
     }
 }
 
+/// check the whipped args of a custom storage, that its download and upload commands can
+/// be run, and that it is not asked to list the remote files, which it has no command for
+///
+/// Args:
+/// * `args` - the whipped args, of the storage type and its commands
+///
+/// returns the reason the storage can't be used
+pub fn check_storage(args: &config::WhippedArgs) -> Result<(), String> {
+    if args.storage_type != "custom" {
+        return Ok(());
+    }
+    if args.list_remote || !args.include.is_empty() || !args.exclude.is_empty() {
+        return Err("the custom storage can't list the remote files, for --list-remote, --include or --exclude".to_string());
+    }
+    for (name, command) in [("download", &args.download_command), ("upload", &args.upload_command)] {
+        // the binary is the first word, or in quotes if its path has spaces
+        let binary = match command.trim().strip_prefix('"') {
+            Some(quoted) => quoted.split('"').next().unwrap_or_default(),
+            None => command.split_whitespace().next().unwrap_or_default(),
+        };
+        if !config_ops::binary_exists(binary) {
+            return Err(format!("the {} command of the custom storage, {}, is not found: {}", name, binary, command));
+        }
+    }
+    Ok(())
+}
+
 /// run powershell, checking filepaths for powershell or pwsh
 /// 
 /// Args:
@@ -95,6 +173,14 @@ fn log_exe_output(out_log: &String, output: std::process::Output) {
 }
 
 
+/// the command to write in the log, hiding the arguments if redacted
+fn log_cmd(wisker_binary: &str, wisker_arg: &str, redact: bool) -> String {
+    match redact {
+        true => format!("{} <args redacted>", wisker_binary),
+        false => format!("{} {}", wisker_binary, wisker_arg),
+    }
+}
+
 /// check if the binary works
 /// 
 /// Args:
@@ -111,18 +197,272 @@ fn check_binary(binary: &String, arg: &str) -> bool {
     return true;
 }
 
-/// run the binary with the given argument, which is a string
-/// returns the output of what was ran, including the stdout and stderr
-fn run_wisker(wisker_binary: &String, wisker_arg: &String, out_log: &String) -> std::process::Output {
+/// how often to check if a command has exited or the stage was abandoned
+const WAIT_POLL: Duration = Duration::from_millis(100);
+/// how often to sample the memory of a command that has a limit
+const MEMORY_SAMPLE: Duration = Duration::from_secs(1);
+
+/// run the binary with the given argument, which is a string. If `max_memory` is set, the
+/// OS holds each of its processes to it, see `job_ops`, and the memory of them all is
+/// watched, where the process tree is killed once it is exceeded, rather than letting it
+/// take down the whole machine.
+/// 
+/// Args:
+/// * wisker_binary: the file path to the tool to run
+/// * wisker_arg: the arguments of the tool, with the placeholders replaced
+/// * out_log: the file path to the wiskess log
+/// * max_memory: the memory limit in MB of the command and its children, 0 is no limit
+/// * redact: log the binary only, as the arguments are from a secret config
+/// * temp_dir: the folder to redirect the temp files of the tool to, i.e. the scratch dir
+/// 
+/// returns the output of what was ran, including the stdout and stderr, the time of
+/// each of their lines, and the reason if it was killed
+fn run_wisker(wisker_binary: &String, wisker_arg: &String, out_log: &String, max_memory: u64, redact: bool, temp_dir: Option<&str>) -> (Output, LineTimes, Option<String>) {
     let wisker_cmd = format!("{} {}", 
         &wisker_binary, 
         &wisker_arg);
-    file_ops::log_msg(&out_log, format!("[ ] Running: {}", wisker_cmd));
+    file_ops::log_msg(out_log, format!("[ ] Running: {}", log_cmd(wisker_binary, wisker_arg, redact)));
     let mut command = shell(wisker_cmd);
+    if let Some(temp_dir) = temp_dir {
+        for var in ["TMP", "TEMP", "TMPDIR"] {
+            command.env(var, temp_dir);
+        }
+    }
     command.stdout(Stdio::piped());
     command.stderr(Stdio::piped());
-    let output = command.execute_output().unwrap();
-    output
+    job_ops::limit_memory(&mut command, max_memory);
+    let mut child = command.spawn().unwrap();
+    let _job = job_ops::Job::assign(&mut child, max_memory)
+        .inspect_err(|e| file_ops::log_msg(out_log, format!("[-] Running without a job object, as it could not be made. Error: {}", e)));
+
+    // read the pipes in threads, so the command doesn't block on a full buffer
+    let stdout = child.stdout.take().map(read_pipe);
+    let stderr = child.stderr.take().map(read_pipe);
+
+    let mut killed = None;
+    let mut sys = System::new();
+    let mut sampled = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if max_memory > 0 && sampled.elapsed() >= MEMORY_SAMPLE {
+            sampled = Instant::now();
+            let used = tree_memory(&mut sys, child.id()) / 1024 / 1024;
+            if used > max_memory {
+                kill_tree(&sys, child.id());
+                let _ = child.kill();
+                killed = Some(format!("memory use of {} MB exceeded the limit of {} MB", used, max_memory));
+                break child.wait().unwrap();
+            }
+        }
+        if ABANDON_STAGE.load(Ordering::SeqCst) {
+            sys.refresh_processes();
+            kill_tree(&sys, child.id());
+            let _ = child.kill();
+            killed = Some(ABANDON_REASON.to_string());
+            break child.wait().unwrap();
+        }
+        thread::sleep(WAIT_POLL);
+    };
+
+    let stdout = stdout.map(|h| h.join().unwrap_or_default()).unwrap_or_default();
+    let stderr = stderr.map(|h| h.join().unwrap_or_default()).unwrap_or_default();
+    let output = Output {
+        status,
+        stdout: stdout.bytes,
+        stderr: stderr.bytes,
+    };
+    (output, LineTimes { stdout: stdout.times, stderr: stderr.times }, killed)
+}
+
+/// the time to wait for a server to be ready to take requests
+const SERVER_WAIT: Duration = Duration::from_secs(60);
+
+/// A command run as a long-lived server, with the placeholders of its request and
+/// ready check replaced, and the files of the artefact to send to it
+struct Server {
+    request: String,
+    ready: String,
+    artefacts: Vec<String>,
+}
+
+/// start the tool as a long-lived server, then send each file of the artefact to it by
+/// running the request command, so the startup cost of the tool is paid once rather than
+/// for each file. The server and its child processes are stopped once all are sent
+///
+/// Args:
+/// * wisker_binary: the file path to the tool to run as the server
+/// * wisker_arg: the arguments to start the server, with the placeholders replaced
+/// * server: the request command, ready check and files of the artefact
+/// * out_log: the file path to the wiskess log
+/// * max_memory: the memory limit in MB of the server and of each request, 0 is no limit
+/// * redact: log the binary only, as the arguments are from a secret config
+/// * temp_dir: the folder to redirect the temp files of the tool to, i.e. the scratch dir
+///
+/// returns the output of the requests and the server, with the status of the first request
+/// that failed, else the last, the time of each line of the output, the reason if it was
+/// killed, and the reason if the server failed
+fn run_server(wisker_binary: &String, wisker_arg: &String, server: &Server, out_log: &String, max_memory: u64, redact: bool, temp_dir: Option<&str>) -> (Output, LineTimes, Option<String>, Option<String>) {
+    file_ops::log_msg(out_log, format!("[ ] Starting server: {}", log_cmd(wisker_binary, wisker_arg, redact)));
+    let mut command = shell(format!("{} {}", wisker_binary, wisker_arg));
+    if let Some(temp_dir) = temp_dir {
+        for var in ["TMP", "TEMP", "TMPDIR"] {
+            command.env(var, temp_dir);
+        }
+    }
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+    job_ops::limit_memory(&mut command, max_memory);
+    let mut child = command.spawn().unwrap();
+    let _job = job_ops::Job::assign(&mut child, max_memory)
+        .inspect_err(|e| file_ops::log_msg(out_log, format!("[-] Running the server without a job object, as it could not be made. Error: {}", e)));
+    let stdout = child.stdout.take().map(read_pipe);
+    let stderr = child.stderr.take().map(read_pipe);
+
+    let mut killed = None;
+    let mut failed = None;
+    let mut sys = System::new();
+
+    // wait for the ready check to succeed, if there is one
+    if !server.ready.is_empty() {
+        let start = Instant::now();
+        while !shell(&server.ready).stdout(Stdio::null()).stderr(Stdio::null()).status().is_ok_and(|s| s.success()) {
+            if child.try_wait().is_ok_and(|s| s.is_some()) {
+                failed = Some("server exited before it was ready".to_string());
+                break;
+            }
+            if start.elapsed() > SERVER_WAIT {
+                failed = Some(format!("server not ready after {} seconds", SERVER_WAIT.as_secs()));
+                break;
+            }
+            thread::sleep(Duration::from_millis(1000));
+        }
+    }
+
+    let mut requests: Vec<(Output, LineTimes)> = Vec::new();
+    if failed.is_none() {
+        file_ops::log_msg(out_log, format!("[ ] Sending {} files to the server", server.artefacts.len()));
+        for artefact in &server.artefacts {
+            if child.try_wait().is_ok_and(|s| s.is_some()) {
+                failed = Some(format!("server exited after {} of {} requests", requests.len(), server.artefacts.len()));
+                break;
+            }
+            if ABANDON_STAGE.load(Ordering::SeqCst) {
+                killed = Some(ABANDON_REASON.to_string());
+                break;
+            }
+            if max_memory > 0 {
+                let used = tree_memory(&mut sys, child.id()) / 1024 / 1024;
+                if used > max_memory {
+                    killed = Some(format!("memory use of the server of {} MB exceeded the limit of {} MB", used, max_memory));
+                    break;
+                }
+            }
+            let artefact_name = Path::new(artefact).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let request = server.request
+                .replace("{artefact}", artefact)
+                .replace("{artefact_name}", &file_ops::safe_name(&artefact_name));
+            let (request_binary, request_arg) = request.split_once(' ').unwrap_or((&request, ""));
+            let (output, times, request_killed) = run_wisker(&request_binary.to_string(), &request_arg.to_string(), out_log, max_memory, redact, temp_dir);
+            requests.push((output, times));
+            if request_killed.is_some() {
+                killed = request_killed;
+                break;
+            }
+        }
+    }
+
+    // stop the server and its child processes
+    sys.refresh_processes();
+    kill_tree(&sys, child.id());
+    let _ = child.kill();
+    let server_status = child.wait().unwrap();
+    file_ops::log_msg(out_log, format!("[ ] Stopped server: {}", log_cmd(wisker_binary, wisker_arg, redact)));
+
+    let status = requests.iter()
+        .map(|(o, _)| o)
+        .find(|o| !o.status.success())
+        .or(requests.last().map(|(o, _)| o))
+        .map(|o| o.status)
+        .unwrap_or(server_status);
+    let mut output = Output {
+        status,
+        stdout: requests.iter().flat_map(|(o, _)| o.stdout.clone()).collect(),
+        stderr: requests.iter().flat_map(|(o, _)| o.stderr.clone()).collect(),
+    };
+    let mut times = LineTimes {
+        stdout: requests.iter().flat_map(|(_, t)| t.stdout.clone()).collect(),
+        stderr: requests.iter().flat_map(|(_, t)| t.stderr.clone()).collect(),
+    };
+    let (stdout, stderr) = (stdout.map(|h| h.join().unwrap_or_default()).unwrap_or_default(), stderr.map(|h| h.join().unwrap_or_default()).unwrap_or_default());
+    output.stdout.extend(stdout.bytes);
+    output.stderr.extend(stderr.bytes);
+    times.stdout.extend(stdout.times);
+    times.stderr.extend(stderr.times);
+    (output, times, killed, failed)
+}
+
+/// the output of a pipe of a command, with the time each of its lines was read
+#[derive(Default)]
+struct Piped {
+    bytes: Vec<u8>,
+    times: Vec<DateTime<Utc>>,
+}
+
+/// the time each line of the stdout and stderr of a command was read, for the `{time}`
+/// in the prefix of each line of its output in the log
+#[derive(Default)]
+struct LineTimes {
+    stdout: Vec<DateTime<Utc>>,
+    stderr: Vec<DateTime<Utc>>,
+}
+
+/// read a pipe of a command by line as it is written, noting the time of each line
+fn read_pipe<R: Read + Send + 'static>(pipe: R) -> thread::JoinHandle<Piped> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        let mut piped = Piped::default();
+        while reader.read_until(b'\n', &mut piped.bytes).is_ok_and(|n| n > 0) {
+            piped.times.push(file_ops::now());
+        }
+        piped
+    })
+}
+
+/// get the process ids of a process and all of its descendants
+fn tree_pids(sys: &System, root: u32) -> Vec<Pid> {
+    let mut pids = vec![Pid::from_u32(root)];
+    let mut i = 0;
+    while i < pids.len() {
+        let parent = pids[i];
+        for (pid, process) in sys.processes() {
+            if process.parent() == Some(parent) && !pids.contains(pid) {
+                pids.push(*pid);
+            }
+        }
+        i += 1;
+    }
+    pids
+}
+
+/// get the memory in bytes used by a process and all of its descendants
+fn tree_memory(sys: &mut System, root: u32) -> u64 {
+    sys.refresh_processes();
+    tree_pids(sys, root)
+        .iter()
+        .filter_map(|pid| sys.process(*pid))
+        .map(|process| process.memory())
+        .sum()
+}
+
+/// kill the descendants of a process, the deepest first so none are orphaned
+fn kill_tree(sys: &System, root: u32) {
+    for pid in tree_pids(sys, root).iter().rev() {
+        if let Some(process) = sys.process(*pid) {
+            process.kill();
+        }
+    }
 }
 
 /// set the command to be run with the replacement of placeholders, as specified in the config yaml
@@ -139,7 +479,7 @@ fn set_wisker(wisker: &config::Wiskers, data_paths: &HashMap<String, String>, fo
     // replace the placeholders, i.e. {input}, in wisker.args with those from local variables, the yaml config, etc.
     if data_paths.contains_key(&wisker.input) {
         let wisker_arg = set_placeholder(&wisker.args, wisker, data_paths, folder_path, main_args);
-        let wisker_binary = wisker.binary
+        let wisker_binary = wisker.binary.path()
             .replace("{tool_path}", &main_args.tool_path);
         let mut wisker_script = String::new();
         if wisker.script {
@@ -153,6 +493,33 @@ fn set_wisker(wisker: &config::Wiskers, data_paths: &HashMap<String, String>, fo
     }
 }
 
+/// the placeholders that are replaced in the config args, binary and script,
+/// i.e. `{input}`, used by the config lint to check for unknown placeholders
+pub const PLACEHOLDERS: [&str; 14] = [
+    "input",
+    "input_other",
+    "outfile",
+    "outfolder",
+    "outdir",
+    "start_date",
+    "end_date",
+    "ioc_file",
+    "out_path",
+    "tool_path",
+    "threads",
+    "scratch",
+    "artefact",
+    "artefact_name",
+];
+
+/// get the scratch folder of a command, for its working and temp files, making
+/// it if it doesn't exist
+pub fn scratch_path(main_args: &config::MainArgs, name: &str) -> String {
+    let scratch = Path::new(&main_args.scratch).join(file_ops::safe_name(name));
+    file_ops::make_folders(&scratch);
+    scratch.to_string_lossy().to_string()
+}
+
 fn set_placeholder(wisker_field: &String, wisker: &Wiskers, data_paths: &HashMap<String, String>, folder_path: &String, main_args: &config::MainArgs) -> String {
     let input_path = get_wisker_art(data_paths, &wisker.input, main_args);
     let mut input_other_path = String::new();
@@ -165,20 +532,39 @@ fn set_placeholder(wisker_field: &String, wisker: &Wiskers, data_paths: &HashMap
         .replace("{input_other}", &input_other_path)
         .replace("{outfile}", &wisker.outfile.as_str())
         .replace("{outfolder}", folder_path)
+        .replace("{outdir}", folder_path)
         .replace("{start_date}", &main_args.start_date)
         .replace("{end_date}", &main_args.end_date)
         .replace("{ioc_file}", &main_args.ioc_file)
         .replace("{out_path}", &main_args.out_path)
-        .replace("{tool_path}", &main_args.tool_path);
+        .replace("{case}", &main_args.case_name)
+        .replace("{tool_path}", &main_args.tool_path)
+        .replace("{threads}", &main_args.threads.to_string());
+    // the output folder of another command by its name, i.e. {outdir:hayabusa}, as it may be under a category
+    let wisker_arg = outdir_regex().replace_all(&wisker_arg, |caps: &regex::Captures| {
+        match main_args.out_dirs.get(&caps[1]) {
+            Some(out_dir) => format!("{}/{}", main_args.out_path, out_dir),
+            None => caps[0].to_string(),
+        }
+    }).to_string();
+    if wisker_arg.contains("{scratch}") {
+        return wisker_arg.replace("{scratch}", &scratch_path(main_args, &wisker.name));
+    }
     wisker_arg
 }
 
+/// the regex of the `{outdir:name}` placeholder, the output folder of another command
+fn outdir_regex() -> &'static Regex {
+    static OUTDIR: OnceLock<Regex> = OnceLock::new();
+    OUTDIR.get_or_init(|| Regex::new(r"\{outdir:([^}]+)\}").unwrap())
+}
+
 fn get_wisker_art(data_paths: &HashMap<String, String>, input: &String, _main_args: &config::MainArgs) -> String {
     let input_path = data_paths[input].clone();
     
     if input_path != "" {
         match canonicalize(&input_path) {
-            Ok(p) => p.into_os_string().into_string().unwrap(),
+            Ok(p) => file_ops::simple_path(&p),
             Err(_e) => {
                 // println!("[!] Unable to get path: {input_path}. Error: {}\n", e);
                 input_path
@@ -191,7 +577,7 @@ fn get_wisker_art(data_paths: &HashMap<String, String>, input: &String, _main_ar
 
 pub fn load_wisker(main_args_c: &config::MainArgs, wisker: &config::Wiskers, data_paths_c: HashMap<String, String>) -> (String, String, String, bool) {
     // Make the output folders from the yaml config
-    let folder_path = Path::new(&main_args_c.out_path).join(&wisker.outfolder);
+    let folder_path = Path::new(&main_args_c.out_path).join(wisker.out_dir());
     file_ops::make_folders(&folder_path);
     let folder_path_str = &folder_path.into_os_string().into_string().unwrap();
     
@@ -215,7 +601,7 @@ pub fn load_wisker(main_args_c: &config::MainArgs, wisker: &config::Wiskers, dat
         installed = true;
     }
     if !installed {
-        eprintln!("[!] The path `{}` is not a correct executable binary file.", wisker_binary); 
+        eprintln!("{}", file_ops::redact(&format!("[!] The path `{}` is not a correct executable binary file.", wisker_binary)));
     }
             
     // Check if the outfile already exists, ask user to overwrite
@@ -227,7 +613,741 @@ pub fn load_wisker(main_args_c: &config::MainArgs, wisker: &config::Wiskers, dat
     (wisker_arg, wisker_binary, wisker_script, overwrite_file)
 }
 
-pub fn run_commands(func: &Vec<Wiskers>, main_args: &config::MainArgs, data_paths: &HashMap<String, String>, threads: usize) {
+/// check the output of a command is newer than the artefacts it reads and the outputs of the
+/// commands it depends on, so an idempotent command can be skipped when rerun
+///
+/// Args:
+/// * outfile: the output file of the command, which may be a glob
+/// * inputs: the file paths of the artefacts it reads
+/// * depends: the (name, outfile path) of the commands it depends on
+fn output_fresh(outfile: &str, inputs: &[String], depends: &[(String, String)]) -> bool {
+    let Some((size, modified)) = file_ops::art_snapshot(outfile) else {
+        return false;
+    };
+    size > 0 && inputs.iter()
+        .chain(depends.iter().map(|(_, dep_outfile)| dep_outfile))
+        .all(|input| file_ops::art_snapshot(input).is_some_and(|(_, input_modified)| input_modified <= modified))
+}
+
+/// get the output file paths of the commands that a command depends on, so
+/// these can be checked as produced before it runs
+/// 
+/// Args:
+/// * wisker: the command, with `depends_on` set to the names of other commands in the config
+/// * config: the config of all stages, to look up the commands by name
+/// * main_args: the arguments specified from the main.rs, i.e. out_path
+/// 
+/// returns a vector of (name, outfile path), the path is empty if the name is not in the config
+fn get_depends(wisker: &Wiskers, config: &config::Config, main_args: &config::MainArgs) -> Vec<(String, String)> {
+    wisker.depends_on.iter().map(|dep_name| {
+        let dep_outfile = match config.find(dep_name) {
+            Some(dep) => dep.out_file(&main_args.out_path),
+            None => String::new(),
+        };
+        (dep_name.to_string(), dep_outfile)
+    }).collect()
+}
+
+/// set to pause the run, which is toggled by SIGUSR1 on unix
+pub static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// held by the command waiting while the run is paused, so the pause is logged once
+static PAUSE_LOCK: Mutex<()> = Mutex::new(());
+
+/// the file in the output folder that pauses the run while it exists
+pub const PAUSE_FILE: &str = "wiskess.pause";
+
+fn is_paused(main_args: &config::MainArgs) -> bool {
+    PAUSED.load(Ordering::SeqCst) || Path::new(&main_args.out_path).join(PAUSE_FILE).exists()
+}
+
+/// wait before starting a command while the run is paused, so the commands that
+/// are running finish and no new ones start until it is resumed
+fn wait_if_paused(main_args: &config::MainArgs) {
+    if !is_paused(main_args) {
+        return;
+    }
+    let _lock = PAUSE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    if !is_paused(main_args) {
+        return;
+    }
+    file_ops::log_msg(&main_args.out_log, "[ ] Run paused, the running commands will finish but no new ones are started".to_string());
+    let start = Instant::now();
+    while is_paused(main_args) {
+        thread::sleep(Duration::from_secs(1));
+    }
+    file_ops::log_msg(&main_args.out_log, format!("[ ] Run resumed, after being paused for {}s", start.elapsed().as_secs()));
+}
+
+/// the time the next process of a parallel command may be launched at, with --launch-rate
+static NEXT_LAUNCH: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// wait for the next launch slot before starting a process of a parallel command, so
+/// the processes are launched at no more than --launch-rate per second, rather than in
+/// a burst as the pool starts. This is distinct from the threads, which cap how many run
+///
+/// Args:
+/// * main_args: the arguments specified from the main.rs, i.e. launch_rate
+fn wait_launch(main_args: &config::MainArgs) {
+    if main_args.launch_rate <= 0.0 {
+        return;
+    }
+    let slot = {
+        let mut next = NEXT_LAUNCH.lock().unwrap_or_else(|e| e.into_inner());
+        let slot = next.map_or(Instant::now(), |n| n.max(Instant::now()));
+        *next = Some(slot + Duration::from_secs_f64(1.0 / main_args.launch_rate));
+        slot
+    };
+    thread::sleep(slot.saturating_duration_since(Instant::now()));
+}
+
+/// set to abandon the stage that is running, killing its commands and skipping those
+/// not started, which is set by SIGQUIT on unix or creating the abandon file
+pub static ABANDON_STAGE: AtomicBool = AtomicBool::new(false);
+
+/// the file in the output folder that abandons the stage running once it is created
+pub const ABANDON_FILE: &str = "wiskess.abandon";
+
+/// the reason a command is killed or skipped once its stage is abandoned
+pub const ABANDON_REASON: &str = "stage abandoned";
+
+/// log the commands of a stage that were killed or skipped as it was abandoned, and
+/// clear it so the next stage runs
+///
+/// Args:
+/// * stage: the name of the stage that has finished, i.e. wiskers
+/// * main_args: the arguments specified from the main.rs
+pub fn end_abandoned(stage: &str, main_args: &config::MainArgs) {
+    if !ABANDON_STAGE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    let abandoned: Vec<String> = main_args.results.lock().unwrap().iter()
+        .filter(|r| r.stage == stage && r.reason == ABANDON_REASON)
+        .map(|r| r.name.to_string())
+        .collect();
+    file_ops::log_msg(&main_args.out_log, format!(
+        "[!] Abandoned the stage {}, these commands were killed or skipped: {:?}",
+        stage,
+        abandoned
+    ));
+}
+
+/// the reason a command is skipped once the --max-runtime has passed
+pub const MAX_RUNTIME_REASON: &str = "max runtime exceeded";
+
+/// the reason a command is skipped once the output is over --max-output-size, with the abort policy
+pub const MAX_OUTPUT_REASON: &str = "max output size exceeded";
+
+/// set once the output is over --max-output-size, so it is only warned of once, or the
+/// commands left are skipped with the abort policy
+static OUTPUT_FULL: AtomicBool = AtomicBool::new(false);
+
+/// held while the size of the output is checked, so the commands that finish together
+/// don't evict the same files
+static OUTPUT_LOCK: Mutex<()> = Mutex::new(());
+
+/// check the output is within --max-output-size once a command completes, and apply
+/// the policy if it is over. The evict policy removes the outputs of the intermediate
+/// commands, the oldest first, once the commands that depend on them have run, until
+/// the output is under the size
+///
+/// Args:
+/// * main_args: the arguments specified from the main.rs, i.e. max_output and intermediates
+fn check_output_size(main_args: &config::MainArgs) {
+    if main_args.max_output == 0 {
+        return;
+    }
+    let _lock = OUTPUT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let max = main_args.max_output * 1024 * 1024;
+    let mut size = file_ops::dir_size(&main_args.out_path);
+    if size <= max {
+        return;
+    }
+    if main_args.output_policy != "evict" {
+        if !OUTPUT_FULL.swap(true, Ordering::SeqCst) {
+            file_ops::log_msg(&main_args.out_log, format!(
+                "[!] The output is {} MB, over the --max-output-size of {} MB{}",
+                size / 1024 / 1024,
+                main_args.max_output,
+                if main_args.output_policy == "abort" { ", skipping the commands left" } else { "" }
+            ));
+        }
+        return;
+    }
+
+    // the outputs of the intermediate commands that no command left to run depends on
+    let done: Vec<String> = main_args.results.lock().unwrap().iter().map(|r| r.name.to_string()).collect();
+    let mut evictable: Vec<(SystemTime, u64, String, &str)> = main_args.intermediates.iter()
+        .filter(|i| done.contains(&i.name) && i.dependents.iter().all(|d| done.contains(d)))
+        .flat_map(|i| file_ops::find_files_glob(&i.out_file).into_iter().filter_map(move |f| {
+            let meta = std::fs::metadata(&f).ok()?;
+            Some((meta.modified().ok()?, meta.len(), f, i.name.as_str()))
+        }))
+        .collect();
+    evictable.sort();
+    for (_, len, file, name) in evictable {
+        if size <= max {
+            break;
+        }
+        match std::fs::remove_file(&file) {
+            Ok(_) => {
+                size -= len;
+                file_ops::log_msg(&main_args.out_log, format!(
+                    "[!] Evicted the intermediate output of {}: {} ({} MB), to keep the output under {} MB",
+                    name, file, len / 1024 / 1024, main_args.max_output
+                ));
+            },
+            Err(e) => file_ops::log_msg(&main_args.out_log, format!("[!] Unable to evict the intermediate output {}. Error: {}", file, e)),
+        }
+    }
+    if size > max {
+        file_ops::log_msg(&main_args.out_log, format!(
+            "[!] The output is {} MB, over the --max-output-size of {} MB, with no intermediate outputs left to evict",
+            size / 1024 / 1024,
+            main_args.max_output
+        ));
+    }
+}
+
+/// remove the outputs by the cleanup policy at the end of a successful run, logging each
+/// file removed and the space reclaimed. The intermediate policy removes the outputs of the
+/// commands marked `intermediate: true`, and the aggressive policy the outputs of the
+/// wiskers and enrichers that no reporter depends on or reads by `{outdir:name}`. The
+/// aggressive policy is not run if a reporter reads the whole output folder by `{out_path}`
+///
+/// Args:
+/// * config: the config of the commands, to find their outputs and dependents
+/// * main_args: the arguments specified from the main.rs, i.e. out_path and out_log
+/// * policy: which outputs to remove
+pub fn cleanup_outputs(config: &config::Config, main_args: &config::MainArgs, policy: config::Cleanup) {
+    let removable: Vec<&config::Wiskers> = match policy {
+        config::Cleanup::None => return,
+        config::Cleanup::Intermediate => config.stages().iter()
+            .flat_map(|(_, func)| func.iter())
+            .filter(|w| w.intermediate)
+            .collect(),
+        config::Cleanup::Aggressive => {
+            // the commands a reporter reads, by its depends_on or the {outdir:name} in its args or script
+            let mut referenced: Vec<String> = Vec::new();
+            for reporter in &config.reporters {
+                let text = format!("{} {}", reporter.args, reporter.script_posh);
+                // the outputs a reporter reads from the whole output folder can't be known, so none are removed
+                if text.contains("{out_path}") {
+                    file_ops::log_msg(&main_args.out_log, format!(
+                        "[!] Cleanup aggressive is not run, as the reporter {} reads the output folder by {{out_path}}, so the outputs it reads are not known. Use intermediate instead",
+                        reporter.name
+                    ));
+                    return;
+                }
+                referenced.extend(reporter.depends_on.iter().cloned());
+                referenced.extend(outdir_regex().captures_iter(&text).map(|cap| cap[1].to_string()));
+            }
+            config.wiskers.iter()
+                .chain(config.enrichers.iter())
+                .filter(|w| !referenced.contains(&w.name))
+                .collect()
+        },
+    };
+    let mut removed = 0;
+    let mut reclaimed = 0;
+    for wisker in removable {
+        for file in file_ops::find_files_glob(&wisker.out_file(&main_args.out_path)) {
+            let len = std::fs::metadata(&file).map(|m| m.len()).unwrap_or(0);
+            match std::fs::remove_file(&file) {
+                Ok(_) => {
+                    removed += 1;
+                    reclaimed += len;
+                    file_ops::log_msg(&main_args.out_log, format!("[-] Cleanup removed the output of {}: {} ({} KB)", wisker.name, file, len / 1024));
+                },
+                Err(e) => file_ops::log_msg(&main_args.out_log, format!("[!] Unable to remove the output {}. Error: {}", file, e)),
+            }
+        }
+    }
+    file_ops::log_msg(&main_args.out_log, format!(
+        "[+] Cleanup {} removed {} output files, reclaiming {} MB",
+        format!("{:?}", policy).to_lowercase(),
+        removed,
+        reclaimed / 1024 / 1024
+    ));
+}
+
+/// the env var that must be set to 1 to allow --simulate-failure, so it can't be
+/// triggered by accident in production
+pub const SIMULATE_ENV: &str = "WISKESS_ALLOW_SIMULATE";
+
+/// get the failure to simulate for a command, given as `name` or `name=timeout`
+/// to --simulate-failure
+///
+/// returns "failure" or "timeout", or None if the command is not to fail
+fn simulated_failure<'a>(name: &str, simulate: &'a [String]) -> Option<&'a str> {
+    simulate.iter().find_map(|s| match s.split_once('=') {
+        Some((n, "timeout")) if n == name => Some("timeout"),
+        Some((n, _)) if n == name => Some("failure"),
+        None if s == name => Some("failure"),
+        _ => None,
+    })
+}
+
+/// check an artefact was found in the data source and can be read
+pub(crate) fn artefact_found(data_paths: &HashMap<String, String>, art_name: &str) -> bool {
+    !matches!(data_paths.get(art_name).map(|p| p.as_str()), None | Some("wiskess_none") | Some("wiskess_locked"))
+}
+
+/// check an artefact was found in the data source and has content, for the
+/// `run_if_artefact` condition of a command
+pub fn artefact_present(data_paths: &HashMap<String, String>, art_name: &str) -> bool {
+    match data_paths.get(art_name).map(|p| p.as_str()) {
+        None | Some("wiskess_none") | Some("wiskess_locked") | Some("") => false,
+        Some(path) => file_ops::art_snapshot(path).is_some_and(|(size, _)| size > 0),
+    }
+}
+
+/// record the result of a command, to be written in the run summary
+fn record(main_args: &config::MainArgs, stage: &str, wisker: &Wiskers, status: Status, exit_code: Option<i32>, duration_s: f64, reason: impl Into<Reason>) {
+    let reason = reason.into();
+    let mut results = main_args.results.lock().unwrap();
+    // list the outputs of the command once it completes, for them to be uploaded
+    if status == Status::Success && !main_args.completed_file.is_empty() {
+        let outputs = file_ops::find_files_glob(&wisker.out_file(&main_args.out_path));
+        let completed = OpenOptions::new().create(true).append(true).open(&main_args.completed_file);
+        if let Err(e) = completed.and_then(|mut f| outputs.iter().try_for_each(|o| writeln!(f, "{}", o))) {
+            file_ops::log_msg(&main_args.out_log, format!("[!] Unable to write the outputs of {} to {}. Error: {}", &wisker.name, &main_args.completed_file, e));
+        }
+    }
+    results.push(CmdResult {
+        stage: stage.to_string(),
+        name: wisker.name.to_string(),
+        output: wisker.out_file(&main_args.out_path),
+        category: wisker.category.to_string(),
+        binary: String::new(),
+        command: String::new(),
+        script: String::new(),
+        request: String::new(),
+        inputs: Vec::new(),
+        status,
+        exit_code,
+        duration_s,
+        reason: reason.text,
+        reason_kind: reason.kind,
+        end: Some(file_ops::now()),
+    });
+}
+
+/// run a command of the config, unless it is skipped, recording its result and
+/// sending its stdout and stderr to be written to the log
+///
+/// Args:
+/// * stage: the name of the stage, i.e. wiskers
+/// * wisker: the command from the config
+/// * depends: the name and output file of the commands it depends on
+/// * main_args: the arguments specified from the main.rs
+/// * data_paths: the hash map of the artefact name and file path
+/// * pb: the progress bar of the stage
+/// * tx: the channel of the output to write to the log
+fn run_command(stage: &str, wisker: &Wiskers, depends: &[(String, String)], main_args: &config::MainArgs, data_paths: HashMap<String, String>, pb: &ProgressBar, tx: &Sender<Vec<u8>>) {
+    let stage_entry = stage.trim_end_matches('s').to_string();
+    wait_if_paused(main_args);
+    // skip if the run is out of time, so it finishes with what has completed
+    if main_args.deadline.is_some_and(|d| Instant::now() >= d) {
+        file_ops::log_msg(&main_args.out_log, format!(
+            "[ ] Skipping {} '{}' (max runtime exceeded)",
+            stage_entry,
+            &wisker.name
+        ));
+        record(main_args, stage, wisker, Status::Skipped, None, 0.0, MAX_RUNTIME_REASON.to_string());
+        return;
+    }
+    // skip if the stage was abandoned, so the run moves on to the next stage
+    if ABANDON_STAGE.load(Ordering::SeqCst) {
+        file_ops::log_msg(&main_args.out_log, format!(
+            "[ ] Skipping {} '{}' (stage abandoned)",
+            stage_entry,
+            &wisker.name
+        ));
+        record(main_args, stage, wisker, Status::Skipped, None, 0.0, ABANDON_REASON.to_string());
+        return;
+    }
+    // skip if the output is over its max size, so the disk doesn't fill
+    if main_args.output_policy == "abort" && OUTPUT_FULL.load(Ordering::SeqCst) {
+        file_ops::log_msg(&main_args.out_log, format!(
+            "[ ] Skipping {} '{}' (max output size exceeded)",
+            stage_entry,
+            &wisker.name
+        ));
+        record(main_args, stage, wisker, Status::Skipped, None, 0.0, MAX_OUTPUT_REASON.to_string());
+        return;
+    }
+    // inject a failure for testing, without running the command
+    if let Some(simulated) = simulated_failure(&wisker.name, &main_args.simulate_failure) {
+        file_ops::log_msg(&main_args.out_log, format!("[!] Simulating {} of {}", simulated, &wisker.name));
+        let status = match simulated {
+            "timeout" => Status::Killed,
+            _ => Status::Failed,
+        };
+        record(main_args, stage, wisker, status, None, 0.0, format!("simulated {}", simulated));
+        return;
+    }
+    // skip if it has no binary for the OS, as it is keyed by OS without this one
+    if wisker.binary.for_os().is_none() {
+        file_ops::log_msg(&main_args.out_log, format!(
+            "[ ] Skipping {} '{}' (no binary for the OS {})",
+            stage_entry,
+            &wisker.name,
+            std::env::consts::OS
+        ));
+        record(main_args, stage, wisker, Status::Skipped, None, 0.0, format!("no binary for the OS {}", std::env::consts::OS));
+        return;
+    }
+    // fail if it depends on a command that is not in the config, as the config is wrong
+    if let Some((dep_name, _)) = depends.iter().find(|(_, dep_outfile)| dep_outfile.is_empty()) {
+        file_ops::log_msg(&main_args.out_log, format!(
+            "[!] Not running {} '{}', its depends_on '{}' is not in the config",
+            stage_entry,
+            &wisker.name,
+            dep_name
+        ));
+        record(main_args, stage, wisker, Status::Failed, None, 0.0, format!("depends_on '{}' is not in the config", dep_name));
+        return;
+    }
+    // skip if an output it depends on, or a file in its inputs, was not produced, i.e. the artefact was missing
+    let missing_input = wisker.inputs.iter()
+        .find(|input| file_ops::walk_glob(&format!("{}/{}", main_args.out_path, input)).0.is_empty());
+    let missing_dep = depends.iter()
+        .find(|(_, dep_outfile)| !file_ops::output_exists(dep_outfile))
+        .map(|(dep_name, _)| dep_name);
+    if let Some(dep_name) = missing_dep.or(missing_input) {
+        file_ops::log_msg(&main_args.out_log, format!(
+            "[ ] Skipping {} '{}' (input '{}' not produced)",
+            stage_entry,
+            &wisker.name,
+            dep_name
+        ));
+        record(main_args, stage, wisker, Status::Skipped, None, 0.0, Reason::new(ReasonKind::InputMissing, format!("input '{}' not produced", dep_name)));
+        return;
+    }
+    // skip if the artefact it is conditional on was not found, or is empty
+    if !wisker.run_if_artefact.is_empty() && !artefact_present(&data_paths, &wisker.run_if_artefact) {
+        file_ops::log_msg(&main_args.out_log, format!(
+            "[ ] Skipping {} '{}' (run_if_artefact '{}' is missing or empty)",
+            stage_entry,
+            &wisker.name,
+            &wisker.run_if_artefact
+        ));
+        record(main_args, stage, wisker, Status::Skipped, None, 0.0, Reason::new(ReasonKind::ArtefactMissing, format!("artefact '{}' missing or empty", &wisker.run_if_artefact)));
+        return;
+    }
+    // skip if an artefact it reads could not be read when checked
+    if [&wisker.input, &wisker.input_other].iter().any(|i| data_paths.get(*i).is_some_and(|p| p == "wiskess_locked")) {
+        file_ops::log_msg(&main_args.out_log, format!(
+            "[ ] Skipping {} '{}' (artefact locked/permission denied)",
+            stage_entry,
+            &wisker.name
+        ));
+        record(main_args, stage, wisker, Status::Skipped, None, 0.0, "artefact locked/permission denied".to_string());
+        return;
+    }
+    let input_file = data_paths[&wisker.input].as_str();
+    // the request and ready check of a server, and the files of the artefact to send to it
+    let server = (wisker.server && input_file != "wiskess_none").then(|| {
+        let folder_path = Path::new(&main_args.out_path).join(wisker.out_dir()).display().to_string();
+        Server {
+            request: set_placeholder(&wisker.request, wisker, &data_paths, &folder_path, main_args),
+            ready: set_placeholder(&wisker.server_ready, wisker, &data_paths, &folder_path, main_args),
+            artefacts: file_ops::art_files(&get_wisker_art(&data_paths, &wisker.input, main_args)),
+        }
+    });
+    // skip a server with nothing to send to it
+    if server.as_ref().is_some_and(|s| s.artefacts.is_empty()) {
+        file_ops::log_msg(&main_args.out_log, format!(
+            "[ ] Skipping {} '{}' (no files in artefact '{}' to send to the server)",
+            stage_entry,
+            &wisker.name,
+            &wisker.input
+        ));
+        record(main_args, stage, wisker, Status::Skipped, None, 0.0, format!("no files in artefact '{}'", &wisker.input));
+        return;
+    }
+    if input_file != "wiskess_none" {
+        // the artefacts read by the command, to check they don't change while it runs
+        let input_paths: Vec<String> = [&wisker.input, &wisker.input_other].iter()
+            .filter_map(|i| data_paths.get(*i))
+            .filter(|p| !p.is_empty())
+            .cloned()
+            .collect();
+        let (wisker_arg, wisker_binary, wisker_script, overwrite_file) = load_wisker(
+            main_args, 
+            wisker, 
+            data_paths);
+        // an output that exists is only kept if the command is idempotent and its output is up to date,
+        // and not if the command is retried, as the output may be partial
+        let overwrite_file = overwrite_file || main_args.retry.contains(&wisker.name) || {
+            let rerun = match wisker.idempotent {
+                true => (!output_fresh(&wisker.out_file(&main_args.out_path), &input_paths, depends)).then_some("older than its inputs"),
+                false => Some("not idempotent"),
+            };
+            if let Some(why) = rerun {
+                file_ops::log_msg(&main_args.out_log, format!("[ ] Rerunning {} '{}', its output exists but it is {}", stage_entry, &wisker.name, why));
+            }
+            rerun.is_some()
+        };
+
+        let pb2_clone = setup::prog_spin_after(pb, 480, &main_args.multi_pb, "white");
+        setup::prog_spin_msg(&pb2_clone, format!("Running: {}", &wisker.name));
+        pb2_clone.inc(1);
+
+        if overwrite_file {
+            if wisker.para {
+                wait_launch(main_args);
+            }
+            if wisker.script {
+                run_posh("-c", &wisker_script, &main_args.out_log, &"".to_string());
+            }
+            
+
+            let before: Vec<_> = match main_args.integrity {
+                true => input_paths.iter().map(|p| file_ops::art_snapshot(p)).collect(),
+                false => Vec::new(),
+            };
+
+            let max_memory = match wisker.max_memory {
+                0 => main_args.max_memory,
+                m => m,
+            };
+            let start = Instant::now();
+            let temp_dir = main_args.scratch_redirect.then(|| scratch_path(main_args, &wisker.name));
+            let (output, times, killed, server_failed) = match &server {
+                Some(server) => run_server(&wisker_binary, &wisker_arg, server, &main_args.out_log, max_memory, main_args.redact, temp_dir.as_deref()),
+                None => {
+                    let (output, times, killed) = run_wisker(&wisker_binary, &wisker_arg, &main_args.out_log, max_memory, main_args.redact, temp_dir.as_deref());
+                    (output, times, killed, None)
+                },
+            };
+            let duration_s = start.elapsed().as_secs_f64();
+            let exit_code = output.status.code();
+            let (mut status, mut reason) = match (&killed, &server_failed, exit_code) {
+                (Some(reason), _, _) => (Status::Killed, reason.to_string()),
+                (None, Some(reason), _) => (Status::Failed, reason.to_string()),
+                (None, None, Some(0)) => (Status::Success, String::new()),
+                (None, None, Some(code)) => match wisker.exit_code_messages.get(&code) {
+                    Some(msg) => (Status::Failed, format!("exit code {}: {}", code, msg)),
+                    None => (Status::Failed, format!("exit code {}", code)),
+                },
+                (None, None, None) => (Status::Failed, "interrupted".to_string()),
+            };
+            // an allocation over the limit fails, so the tool exits rather than being killed
+            if status == Status::Failed && killed.is_none() && server_failed.is_none() && max_memory > 0 {
+                reason = format!("{}, it may have reached the memory limit of {} MB", reason, max_memory);
+            }
+            if status == Status::Failed && server_failed.is_none() {
+                file_ops::log_msg(&main_args.out_log, format!("[!] {} failed with {}", &wisker.name, reason));
+            }
+            if let Some(reason) = &server_failed {
+                file_ops::log_msg(&main_args.out_log, format!("[!] Server of {} failed: {}", &wisker.name, reason));
+            }
+            if let Some(reason) = killed {
+                file_ops::log_msg(&main_args.out_log, format!("[!] Killed {}: {}", &wisker.name, reason));
+                // the output of a killed command may be truncated, so it is not read as produced
+                let (marked, errors) = file_ops::mark_incomplete(&wisker.out_file(&main_args.out_path));
+                for file in marked {
+                    file_ops::log_msg(&main_args.out_log, format!("[!] Marked the output of {} as incomplete: {}", &wisker.name, file));
+                }
+                for e in errors {
+                    file_ops::log_msg(&main_args.out_log, format!("[!] Unable to mark the output of {} as incomplete, {}", &wisker.name, e));
+                }
+            }
+
+            // check the artefacts did not change while being read, as the output may be corrupt
+            if main_args.integrity {
+                for (path, snap) in input_paths.iter().zip(before) {
+                    if snap != file_ops::art_snapshot(path) {
+                        file_ops::log_msg(&main_args.out_log, format!(
+                            "[!] Artefact {} changed while {} was processing it, the output may be incomplete or corrupt",
+                            path,
+                            &wisker.name
+                        ));
+                        if main_args.strict_integrity && status == Status::Success {
+                            status = Status::Failed;
+                            reason = format!("artefact {} changed during processing", path);
+                        }
+                    }
+                }
+            }
+            // check the tool did not report a problem in its text that the exit code hides
+            if status == Status::Success {
+                match output_problem(wisker, &output) {
+                    Ok(Some(line)) => file_ops::log_msg(&main_args.out_log, format!(
+                        "[ ] The output of {} matched output_must_match: {}", &wisker.name, line
+                    )),
+                    Ok(_) => (),
+                    Err(problem) => {
+                        file_ops::log_msg(&main_args.out_log, format!("[!] {} failed the validation of its output, {}", &wisker.name, problem));
+                        status = Status::Failed;
+                        reason = problem;
+                    },
+                }
+            }
+            if status == Status::Success && !wisker.encoding.is_empty() {
+                transcode(main_args, wisker);
+            }
+            if let (Some(filter), Status::Success) = (&wisker.post_filter, &status) {
+                post_filter(main_args, wisker, filter);
+            }
+            record(main_args, stage, wisker, status, exit_code, duration_s, reason);
+            // what the command ran and read, with the placeholders replaced and the secrets masked,
+            // so its output can be reproduced and traced to its inputs
+            if let Some(result) = main_args.results.lock().unwrap().iter_mut().rev().find(|r| r.name == wisker.name) {
+                result.binary = wisker_binary.to_string();
+                result.command = file_ops::redact(&log_cmd(&wisker_binary, &wisker_arg, main_args.redact));
+                if wisker.script {
+                    result.script = match main_args.redact {
+                        true => "<script redacted>".to_string(),
+                        false => file_ops::redact(&wisker_script),
+                    };
+                }
+                if let Some(server) = &server {
+                    let (request_binary, request_arg) = server.request.split_once(' ').unwrap_or((&server.request, ""));
+                    result.request = file_ops::redact(&log_cmd(request_binary, request_arg, main_args.redact));
+                }
+                result.inputs = input_paths.iter().cloned()
+                    .chain(depends.iter().map(|(_, dep_outfile)| dep_outfile.to_string()))
+                    .collect();
+            }
+            check_output_size(main_args);
+        
+            file_ops::log_msg(&main_args.out_log, format!("[+] Done {} with command: {}", 
+                &wisker.name, 
+                log_cmd(&wisker_binary, &wisker_arg, main_args.redact)));
+                
+            tx.send(prefix_output(&output.stdout, &times.stdout, &wisker.name, "stdout", &main_args.output_prefix)).unwrap();
+            tx.send(prefix_output(&output.stderr, &times.stderr, &wisker.name, "stderr", &main_args.output_prefix)).unwrap();
+        } else {    
+            let folder_path = format!("{}/{}", &main_args.out_path, wisker.out_dir());
+            let file_path = format!("{}/{}", &folder_path, &wisker.outfile);
+            let msg = format!(
+                "[ ] Skipping {} '{}' (idempotent and its output is newer than its inputs: {})\n{}",
+                stage_entry,
+                &wisker.name,
+                file_path,
+                "If wanting to run the module again, please delete the output file"
+            );
+            file_ops::log_msg(&main_args.out_log, msg);
+            record(main_args, stage, wisker, Status::Skipped, None, 0.0, Reason::new(ReasonKind::UpToDate, "output exists"));
+        }
+        setup::prog_spin_stop(&pb2_clone, format!("Done: {}", &wisker.name));
+    } else {
+        record(main_args, stage, wisker, Status::Skipped, None, 0.0, Reason::new(ReasonKind::ArtefactMissing, format!("artefact '{}' not found", &wisker.input)));
+    }
+}
+
+/// the prefix of each line of the output of the commands in the log, by default
+pub const DEFAULT_OUTPUT_PREFIX: &str = "[{time}] [{name}] ";
+
+/// prefix each line of the output of a command, so the output of the commands that run
+/// at the same time can be told apart in the log. The format has `{name}`, `{time}` the
+/// line was written, and `{stream}` of stdout or stderr, where an empty format is no prefix
+fn prefix_output(output: &[u8], times: &[DateTime<Utc>], name: &str, stream: &str, format: &str) -> Vec<u8> {
+    if format.is_empty() || output.is_empty() {
+        return output.to_vec();
+    }
+    let prefix = format
+        .replace("{name}", name)
+        .replace("{stream}", stream);
+    String::from_utf8_lossy(output).lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let time = times.get(i).copied().unwrap_or_else(file_ops::now);
+            format!("{}{}\n", prefix.replace("{time}", &time.with_timezone(&chrono::Local).format("%Y%m%dT%H%M%S").to_string()), line)
+        })
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// run a command for each instance of its input artefact, if the path of the artefact
+/// has named capture groups, i.e. `Users/(?P<user>[^/]+)/NTUSER.DAT`. The `${name}` of
+/// each group in the args, outfile, script and request is replaced by the value captured
+/// from the path of the instance, so the outputs can be labeled by it, i.e. the user.
+/// Otherwise the command is run once
+///
+/// Args: the same as `run_command`
+fn run_instances(stage: &str, wisker: &Wiskers, depends: &[(String, String)], main_args: &config::MainArgs, data_paths: HashMap<String, String>, pb: &ProgressBar, tx: &Sender<Vec<u8>>) {
+    let instances = match main_args.art_patterns.get(&wisker.input) {
+        Some(pattern) if artefact_found(&data_paths, &wisker.input) => paths::art_instances(pattern, &main_args.ignore),
+        _ => Vec::new(),
+    };
+    if instances.is_empty() {
+        return run_command(stage, wisker, depends, main_args, data_paths, pb, tx);
+    }
+    file_ops::log_msg(&main_args.out_log, format!("[ ] Running {} for {} instances of {}", &wisker.name, instances.len(), &wisker.input));
+    for (path, vars) in instances {
+        let mut instance = wisker.clone();
+        for field in [&mut instance.args, &mut instance.outfile, &mut instance.script_posh, &mut instance.request] {
+            *field = paths::replace_vars(field, &vars);
+        }
+        let mut data_paths = data_paths.clone();
+        data_paths.insert(wisker.input.to_string(), path);
+        run_command(stage, &instance, depends, main_args, data_paths, pb, tx);
+    }
+}
+
+/// rewrite each output of the command in its `encoding` as UTF-8, so the validation and the
+/// commands that read it get UTF-8 without a byte order mark
+fn transcode(main_args: &config::MainArgs, wisker: &Wiskers) {
+    for output_file in file_ops::find_files_glob(&wisker.out_file(&main_args.out_path)) {
+        match file_ops::transcode_utf8(&output_file, &wisker.encoding.to_lowercase()) {
+            Ok(true) => file_ops::log_msg(&main_args.out_log, format!(
+                "[+] Transcoded the output of {} from {} to UTF-8: {}, keeping the original as {}{}",
+                &wisker.name, &wisker.encoding, output_file, output_file, file_ops::ORIGINAL_EXT
+            )),
+            Ok(false) => (),
+            Err(e) => file_ops::log_msg(&main_args.out_log, format!(
+                "[!] Unable to transcode the output of {} from {}: {}. Error: {}",
+                &wisker.name, &wisker.encoding, output_file, e
+            )),
+        }
+    }
+}
+
+/// check the stdout and stderr of a command against its `output_must_not_match` and
+/// `output_must_match`, line by line, for the tools that report a partial success in
+/// their text rather than the exit code, i.e. `WARNING: skipped 30 records`
+///
+/// returns the line that matched output_must_match if any, else why the output fails with
+/// the offending line
+fn output_problem(wisker: &Wiskers, output: &Output) -> Result<Option<String>, String> {
+    let text = format!("{}\n{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    if !wisker.output_must_not_match.is_empty() {
+        let re = Regex::new(&wisker.output_must_not_match).map_err(|e| format!("output_must_not_match is not valid. Error: {}", e))?;
+        if let Some(line) = text.lines().find(|l| re.is_match(l)) {
+            return Err(format!("output matched output_must_not_match: {}", file_ops::redact(line.trim())));
+        }
+    }
+    if wisker.output_must_match.is_empty() {
+        return Ok(None);
+    }
+    let re = Regex::new(&wisker.output_must_match).map_err(|e| format!("output_must_match is not valid. Error: {}", e))?;
+    match text.lines().find(|l| re.is_match(l)) {
+        Some(line) => Ok(Some(file_ops::redact(line.trim()))),
+        None => Err(format!("output did not match output_must_match '{}'", wisker.output_must_match)),
+    }
+}
+
+/// write the filtered companion of each output of the command, by its post_filter
+fn post_filter(main_args: &config::MainArgs, wisker: &Wiskers, filter: &config::PostFilter) {
+    for output_file in file_ops::find_files_glob(&wisker.out_file(&main_args.out_path)) {
+        match table_ops::post_filter(filter, &output_file) {
+            Ok((filtered, kept, read)) => file_ops::log_msg(&main_args.out_log, format!(
+                "[+] Post filter of {}: {} of {} rows written to {}",
+                &wisker.name, kept, read, filtered
+            )),
+            Err(e) => file_ops::log_msg(&main_args.out_log, format!(
+                "[!] Unable to post filter the output of {}: {}. Error: {}",
+                &wisker.name, output_file, e
+            )),
+        }
+    }
+}
+
+pub fn run_commands(stage: &str, func: &Vec<Wiskers>, config: &config::Config, main_args: &config::MainArgs, data_paths: &HashMap<String, String>, threads: usize) {
     let pool = ThreadPoolBuilder::new()
         .num_threads(threads)
         .build()
@@ -239,10 +1359,24 @@ pub fn run_commands(func: &Vec<Wiskers>, main_args: &config::MainArgs, data_path
     }
 
     let func_c = func.clone();
-    let wiskers: Vec<config::Wiskers> = func_c
+    let mut wiskers: Vec<config::Wiskers> = func_c
         .into_iter()
-        .filter(|w| w.para == run_para)
+        .filter(|w| w.para == run_para && w.selected(main_args))
         .collect();
+    // the serial commands run one at a time by their order, then the order in the config,
+    // whereas the parallel commands start in any order
+    if !run_para {
+        wiskers.sort_by_key(|w| w.order);
+    }
+
+    // set the threads each command can use, so the tools running at the same time
+    // don't oversubscribe the cores, unless set by the user
+    let mut main_args = main_args.clone();
+    if main_args.threads == 0 {
+        let concurrent = pool.current_num_threads().min(wiskers.len()).max(1);
+        main_args.threads = (num_cpus::get() / concurrent).max(1);
+    }
+    let main_args = &main_args;
 
     let (tx, rx) = std::sync::mpsc::channel();
     
@@ -251,53 +1385,21 @@ pub fn run_commands(func: &Vec<Wiskers>, main_args: &config::MainArgs, data_path
     let num_wiskers = wiskers.len();
     setup::prog_spin_msg(&pb, format!("Running {} processes", num_wiskers));
 
+    if main_args.prewarm && run_para {
+        prewarm(&wiskers.iter().collect::<Vec<_>>(), main_args, data_paths);
+    }
+
     for wisker in wiskers {
-        
         let tx = tx.clone();
         let main_args_c = main_args.clone();
         let data_paths_c = data_paths.clone();
         let pb_clone = pb.clone();
-        
+        let depends = get_depends(&wisker, config, main_args);
+        let stage = stage.to_string();
         pool.spawn(move || {
-            let input_file = data_paths_c[&wisker.input].as_str();
-            if input_file != "wiskess_none" {
-                let (wisker_arg, wisker_binary, wisker_script, overwrite_file) = load_wisker(
-                    &main_args_c, 
-                    &wisker, 
-                    data_paths_c);
-        
-                let pb2_clone = setup::prog_spin_after(&pb_clone, 480, &main_args_c.multi_pb, "white");
-                setup::prog_spin_msg(&pb2_clone, format!("Running: {}", &wisker.name));
-                pb2_clone.inc(1);
-
-                if overwrite_file {
-                    if wisker.script {
-                        run_posh("-c", &wisker_script, &main_args_c.out_log, &"".to_string());
-                    }
-                    
-                    let output = run_wisker(&wisker_binary, &wisker_arg, &main_args_c.out_log);
-                
-                    file_ops::log_msg(&main_args_c.out_log, format!("[+] Done {} with command: {} {}", 
-                        &wisker.name, 
-                        &wisker_binary,
-                        &wisker_arg));
-                        
-                    tx.send(output.stdout).unwrap();
-                    tx.send(output.stderr).unwrap();
-                } else {    
-                    let folder_path = format!("{}/{}", &main_args_c.out_path, &wisker.outfolder);
-                    let file_path = format!("{}/{}", &folder_path, &wisker.outfile);
-                    let msg = format!(
-                        "[ ] The file already exists: {}\n{} {}\n{}",
-                        file_path,
-                        "If wanting to run the module again,",
-                        &wisker.name,
-                        "please delete the output file or run wiskess without --silent mode"
-                    );
-                    file_ops::log_msg(&main_args_c.out_log, msg);
-                }
-                setup::prog_spin_stop(&pb2_clone, format!("Done: {}", &wisker.name));
-            }
+            status_ops::command_started(&wisker.name);
+            run_instances(&stage, &wisker, &depends, &main_args_c, data_paths_c, &pb_clone, &tx);
+            status_ops::command_done(&wisker.name);
         });
     }
     drop(tx);
@@ -311,6 +1413,227 @@ pub fn run_commands(func: &Vec<Wiskers>, main_args: &config::MainArgs, data_path
         .expect("Failed to open log file");
         
     for msg in rx {
-        file.write_all(&msg).expect("Failed to write to log file");
+        file.write_all(file_ops::redact(&String::from_utf8_lossy(&msg)).as_bytes()).expect("Failed to write to log file");
+    }
+}
+
+/// read through the artefacts of the commands about to run at the same time, one after
+/// the other, so they are in the OS cache rather than each read cold by several commands
+/// at once, i.e. for evidence on a network share or spinning disk
+///
+/// Args:
+/// * wiskers: the commands about to run
+/// * main_args: the arguments specified from the main.rs
+/// * data_paths: the hash map of the artefact name and file path
+fn prewarm(wiskers: &[&Wiskers], main_args: &config::MainArgs, data_paths: &HashMap<String, String>) {
+    let mut art_names: Vec<&String> = wiskers.iter()
+        .flat_map(|w| [&w.input, &w.input_other])
+        .filter(|a| artefact_found(data_paths, a) && !data_paths[*a].is_empty())
+        .collect();
+    art_names.sort();
+    art_names.dedup();
+    if art_names.is_empty() {
+        return;
+    }
+    let start = Instant::now();
+    let bytes: u64 = art_names.iter().map(|a| file_ops::read_through(&data_paths[*a])).sum();
+    file_ops::log_msg(&main_args.out_log, format!(
+        "[ ] Pre-read {} MB of the {} artefacts of the commands to run, in {:.1}s",
+        bytes / 1024 / 1024,
+        art_names.len(),
+        start.elapsed().as_secs_f64()
+    ));
+}
+
+/// run a single command of the config against one artefact, i.e. to rerun a tool after
+/// a change to its config, writing to its usual output folder
+///
+/// Args:
+/// * stage: the name of the stage of the command, i.e. wiskers
+/// * wisker: the command from the config
+/// * config: the config of all stages, to find the outputs it depends on
+/// * main_args: the arguments specified from the main.rs
+/// * data_paths: the hash map of the artefact name and file path, of its input
+///
+/// returns the result of the command
+pub fn run_single(stage: &str, wisker: &Wiskers, config: &config::Config, main_args: &config::MainArgs, data_paths: HashMap<String, String>) -> Option<CmdResult> {
+    let mut main_args = main_args.clone();
+    if main_args.threads == 0 {
+        main_args.threads = num_cpus::get();
+    }
+    let (tx, rx) = std::sync::mpsc::channel();
+    let pb = setup::prog_spin_init(960, &main_args.multi_pb, "yellow");
+    setup::prog_spin_msg(&pb, format!("Running {}", &wisker.name));
+    let depends = get_depends(wisker, config, &main_args);
+    run_command(stage, wisker, &depends, &main_args, data_paths, &pb, &tx);
+    drop(tx);
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&main_args.out_log)
+        .expect("Failed to open log file");
+    for msg in rx {
+        file.write_all(file_ops::redact(&String::from_utf8_lossy(&msg)).as_bytes()).expect("Failed to write to log file");
+    }
+    let result = main_args.results.lock().unwrap().last().cloned();
+    result
+}
+
+/// run the commands of the stages in one pool, each starting once the commands it
+/// depends on have finished, so the later stages overlap the earlier ones. A command
+/// with `depends_on` waits for those commands, otherwise it waits for all the commands
+/// of the earlier stages, as does one with a `depends_on` that is not a selected command.
+/// Those with `para: false` are started one at a time, by their stage then order, taking
+/// the first that is ready, so they don't hold a thread of the pool waiting for their turn.
+///
+/// Args:
+/// * stages: the names of the stages to run, i.e. wiskers
+/// * config: the config of all stages
+/// * main_args: the arguments specified from the main.rs
+/// * data_paths: the hash map of the artefact name and file path
+/// * after_stage: called with the name of each stage once it and the stages before it have finished
+pub fn run_stages_parallel(stages: &[&str], config: &config::Config, main_args: &config::MainArgs, data_paths: &HashMap<String, String>, mut after_stage: impl FnMut(&str)) {
+    let pool = ThreadPoolBuilder::new().build().unwrap();
+
+    // the selected commands, with the index of their stage
+    let stages: Vec<(&str, &Vec<Wiskers>)> = config.stages().into_iter().filter(|(s, _)| stages.contains(s)).collect();
+    let commands: Vec<(usize, &str, &Wiskers)> = stages.iter().enumerate()
+        .flat_map(|(i, (stage, func))| func.iter().filter(|w| w.selected(main_args)).map(move |w| (i, *stage, w)))
+        .collect();
+    let waits_on: Vec<Vec<usize>> = commands.iter().map(|(_, _, wisker)| {
+        commands.iter().enumerate()
+            .filter(|(_, (_, _, other))| wisker.depends_on.contains(&other.name))
+            .map(|(j, _)| j)
+            .collect()
+    }).collect();
+    // a command waits for the earlier stages unless all it depends on are selected commands,
+    // so a depends_on that is not selected, or is misspelt, doesn't start it straight away
+    let waits_on_stages: Vec<bool> = commands.iter().map(|(_, _, wisker)| {
+        wisker.depends_on.is_empty() || wisker.depends_on.iter().any(|dep| !commands.iter().any(|(_, _, other)| &other.name == dep))
+    }).collect();
+    // the serial commands in the order they are started, by their stage then order
+    let mut serial: Vec<usize> = (0..commands.len()).filter(|j| !commands[*j].2.para).collect();
+    serial.sort_by_key(|j| (commands[*j].0, commands[*j].2.order));
+
+    let mut main_args = main_args.clone();
+    if main_args.threads == 0 {
+        let concurrent = pool.current_num_threads().min(commands.len()).max(1);
+        main_args.threads = (num_cpus::get() / concurrent).max(1);
+    }
+    let main_args = &main_args;
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let (done_tx, done_rx) = std::sync::mpsc::channel();
+    let pb = setup::prog_spin_init(960, &main_args.multi_pb, "yellow");
+    setup::prog_spin_msg(&pb, format!("Running {} processes across {} stages", commands.len(), stages.len()));
+    file_ops::log_msg(&main_args.out_log, format!(
+        "[ ] Running the {} commands of the stages {} in one pool, each once what it depends on has finished",
+        commands.len(),
+        stages.iter().map(|(s, _)| *s).collect::<Vec<_>>().join(", ")
+    ));
+
+    if main_args.prewarm {
+        prewarm(&commands.iter().map(|(_, _, w)| *w).collect::<Vec<_>>(), main_args, data_paths);
+    }
+
+    let mut started = vec![false; commands.len()];
+    let mut done = vec![false; commands.len()];
+    let mut stages_done = 0;
+    let mut serial_running = false;
+    loop {
+        // the stages finish in order, so a command waiting on the earlier stages starts after their after_stage
+        while stages_done < stages.len() && commands.iter().zip(&done).all(|((i, _, _), d)| *i > stages_done || *d) {
+            after_stage(stages[stages_done].0);
+            stages_done += 1;
+        }
+        if let Some((stage, _)) = stages.get(stages_done) {
+            status_ops::set_stage(stage);
+        }
+        let ready = |j: usize| !started[j]
+            && (!waits_on_stages[j] || stages_done >= commands[j].0)
+            && waits_on[j].iter().all(|k| done[*k]);
+        let mut to_start: Vec<usize> = (0..commands.len()).filter(|j| commands[*j].2.para && ready(*j)).collect();
+        if !serial_running {
+            if let Some(j) = serial.iter().copied().find(|j| ready(*j)) {
+                to_start.push(j);
+                serial_running = true;
+            }
+        }
+        for j in to_start {
+            let (_, stage, wisker) = commands[j];
+            started[j] = true;
+            let tx = tx.clone();
+            let done_tx = done_tx.clone();
+            let main_args_c = main_args.clone();
+            let data_paths_c = data_paths.clone();
+            let pb_clone = pb.clone();
+            let depends = get_depends(wisker, config, main_args);
+            let stage = stage.to_string();
+            let wisker = (*wisker).clone();
+            pool.spawn(move || {
+                status_ops::command_started(&wisker.name);
+                run_instances(&stage, &wisker, &depends, &main_args_c, data_paths_c, &pb_clone, &tx);
+                status_ops::command_done(&wisker.name);
+                done_tx.send(j).unwrap();
+            });
+        }
+        if done.iter().all(|d| *d) {
+            break;
+        }
+        // nothing is running but some commands have not started, as they depend on each other
+        if started.iter().zip(&done).all(|(s, d)| !s || *d) {
+            let blocked: Vec<&str> = commands.iter().zip(&started).filter(|(_, s)| !**s).map(|((_, _, w), _)| w.name.as_str()).collect();
+            file_ops::log_msg(&main_args.out_log, format!("[!] Not running {}, as their depends_on is circular", blocked.join(", ")));
+            break;
+        }
+        match done_rx.recv() {
+            Ok(j) => {
+                done[j] = true;
+                serial_running &= commands[j].2.para;
+            },
+            Err(_) => break,
+        }
+        // write the output of the commands to the log as they finish
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&main_args.out_log)
+            .expect("Failed to open log file");
+        for msg in rx.try_iter() {
+            file.write_all(file_ops::redact(&String::from_utf8_lossy(&msg)).as_bytes()).expect("Failed to write to log file");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn each_line_is_prefixed_with_its_time() {
+        let times = [Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(), Utc.with_ymd_and_hms(2024, 1, 1, 0, 1, 0).unwrap()];
+        let local = |t: DateTime<Utc>| t.with_timezone(&chrono::Local).format("%Y%m%dT%H%M%S").to_string();
+        let prefixed = prefix_output(b"first\r\nsecond\n", &times, "tool", "stdout", "[{time}] [{name}] [{stream}] ");
+        assert_eq!(String::from_utf8(prefixed).unwrap(), format!(
+            "[{}] [tool] [stdout] first\n[{}] [tool] [stdout] second\n",
+            local(times[0]),
+            local(times[1])
+        ));
+        assert_eq!(prefix_output(b"as is\n", &times, "tool", "stdout", ""), b"as is\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_time_of_each_line_is_when_it_was_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_log = dir.path().join("wiskess.log").display().to_string();
+        let (output, times, killed) = run_wisker(&"sh".to_string(), &"-c 'echo one; sleep 1.2; echo two; echo three >&2'".to_string(), &out_log, 0, false, None);
+        assert!(killed.is_none());
+        assert_eq!(output.stdout, b"one\ntwo\n");
+        assert_eq!(output.stderr, b"three\n");
+        assert_eq!((times.stdout.len(), times.stderr.len()), (2, 1));
+        assert!(times.stdout[1] - times.stdout[0] >= chrono::Duration::seconds(1));
     }
 }
\ No newline at end of file
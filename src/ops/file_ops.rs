@@ -1,6 +1,6 @@
 use std::{fs, io};
-use std::fs::OpenOptions;
-use std::io::{BufReader, BufRead};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufRead, BufWriter, Read};
 use std::io::Write;
 use core::result::Result::Ok;
 use inquire::Confirm;
@@ -9,13 +9,82 @@ use inquire::InquireError;
 
 use walkdir::WalkDir;
 use std::path::Path;
+use std::sync::OnceLock;
+use std::time::SystemTime;
 use chrono::NaiveDate;
 use glob::glob;
+use regex::Regex;
+
+/// the patterns of the known sensitive values masked in the log, where the `keep` group
+/// is not masked: the signature and keys of SAS and presigned urls, bearer tokens and
+/// AWS access key ids
+const REDACT_PATTERNS: [&str; 3] = [
+    r#"(?i)(?P<keep>\b(?:sig|signature|token|access_token|api_?key|password|secret|x-amz-signature|x-amz-security-token|x-amz-credential)=)[^&\s'"]+"#,
+    r"(?i)(?P<keep>\bbearer\s+)[\w\-.~+/]+=*",
+    r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b",
+];
+
+/// the deepest a `**` of a glob is walked, so a deep or recursive folder can't hang the run
+const MAX_GLOB_DEPTH: usize = 32;
+
+/// the env vars of which the values are masked in the log, by their name
+const REDACT_ENV: &str = r"(?i)(secret|token|password|passwd|sas|api_?key|credential)";
+
+/// the patterns and values masked in the log, set by `set_redactions`
+static REDACTIONS: OnceLock<(Vec<Regex>, Vec<String>)> = OnceLock::new();
 
 pub fn make_folders(out_path: &Path) {
     fs::create_dir_all(out_path).expect("Failed to create folder");
 }
 
+/// the longest path Windows takes without the verbatim prefix `\\?\`
+const MAX_PATH: usize = 260;
+
+/// simple_path - the path without the verbatim prefix that canonicalize gives on Windows,
+/// i.e. `\\?\UNC\server\share\x` as `\\server\share\x` and `\\?\C:\x` as `C:\x`, as
+/// many tools and the globs don't take it. A path too long for Windows without it is kept
+pub(crate) fn simple_path(path: &Path) -> String {
+    let path_str = path.display().to_string();
+    let simple = match (path_str.strip_prefix(r"\\?\UNC\"), path_str.strip_prefix(r"\\?\")) {
+        (Some(share), _) => format!(r"\\{}", share),
+        (None, Some(disk)) if disk.chars().nth(1) == Some(':') => disk.to_string(),
+        _ => return path_str,
+    };
+    match simple.len() < MAX_PATH {
+        true => simple,
+        false => path_str,
+    }
+}
+
+/// trim_separators - the path without the separators at its end, i.e. `\\server\share\case\`
+/// as `\\server\share\case`, so the paths joined to it with `/` don't have two in a row,
+/// which the globs don't match. A path of only separators, i.e. `/`, is kept
+pub(crate) fn trim_separators(path: &str) -> String {
+    match path.trim_end_matches(['/', '\\']) {
+        "" => path.to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// is_local_drive - check the path is the root of a local disk, i.e. `C:` or `D:\`, that the
+/// files can be copied from raw. A UNC share or a mapped network drive is not, as only the
+/// fixed and removable disks are listed
+pub(crate) fn is_local_drive(path: &str) -> bool {
+    drive_root(path).is_some_and(|drive| sysinfo::Disks::new_with_refreshed_list().list().iter()
+        .any(|d| d.mount_point().display().to_string().trim_end_matches(['/', '\\']).eq_ignore_ascii_case(drive)))
+}
+
+/// the drive of a path that is the root of a drive letter, i.e. `C:` of `C:\`, else none
+fn drive_root(path: &str) -> Option<&str> {
+    let drive = path.trim_end_matches(['/', '\\']);
+    (drive.len() == 2 && drive.ends_with(':') && drive.starts_with(|c: char| c.is_ascii_alphabetic())).then_some(drive)
+}
+
+/// safe_name - replace the characters of a name that are not safe in a folder name
+pub(crate) fn safe_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect()
+}
+
 pub(crate) fn line_count(file_path: &String) -> usize {
     let path = Path::new(&file_path);
     if path.exists() && path.is_file() {
@@ -52,10 +121,311 @@ pub(crate) fn file_exists(file_path: &String, silent: bool) -> bool {
     return ret;
 }
 
+/// output_exists - check if a file, or the first match of a glob, exists and
+/// is not empty. An empty file is treated as not produced, as redirecting the
+/// output of a command that failed will still create the file.
+pub(crate) fn output_exists(file_path: &String) -> bool {
+    let path = Path::new(&file_path);
+    let found = if path.is_file() {
+        file_path.to_string()
+    } else {
+        find_file_glob(file_path)
+    };
+    match fs::metadata(&found) {
+        Ok(meta) => meta.is_file() && meta.len() > 0,
+        Err(_) => false,
+    }
+}
+
+/// art_snapshot - get the total size and latest modified time of an artefact,
+/// which can be a file, the files directly in a folder, or a glob. This is 
+/// compared before and after a command to check it did not change while read.
+pub(crate) fn art_snapshot(path_str: &str) -> Option<(u64, SystemTime)> {
+    let path = Path::new(path_str);
+    let files: Vec<String> = if path.is_file() {
+        vec![path_str.to_string()]
+    } else if path.is_dir() {
+        WalkDir::new(path)
+            .min_depth(1)
+            .max_depth(1)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_file())
+            .map(|e| e.path().display().to_string())
+            .collect()
+    } else {
+        find_files_glob(path_str)
+    };
+    let mut size = 0;
+    let mut modified = SystemTime::UNIX_EPOCH;
+    for file in files {
+        let meta = fs::metadata(&file).ok()?;
+        size += meta.len();
+        modified = modified.max(meta.modified().ok()?);
+    }
+    Some((size, modified))
+}
+
+/// dir_size - get the total size in bytes of the files in a folder and its subfolders
+pub(crate) fn dir_size(path_str: &str) -> u64 {
+    WalkDir::new(path_str)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// art_files - get the files of an artefact, which is the file itself, or the files in the folder
+pub(crate) fn art_files(path_str: &str) -> Vec<String> {
+    let mut files: Vec<String> = WalkDir::new(path_str)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.path().display().to_string())
+        .collect();
+    files.sort();
+    files
+}
+
+/// read_through - read the files of an artefact, which is a file, folder or glob, discarding
+/// what is read, so they are in the OS cache for the commands that read them next
+pub(crate) fn read_through(path_str: &str) -> u64 {
+    let files = match Path::new(path_str).exists() {
+        true => art_files(path_str),
+        false => find_files_glob(path_str),
+    };
+    files.iter()
+        .filter_map(|f| fs::File::open(f).ok())
+        .filter_map(|mut f| io::copy(&mut f, &mut io::sink()).ok())
+        .sum()
+}
+
+/// the name of the file of the paths to exclude from the artefacts, at the root of the data source
+pub const IGNORE_FILE: &str = ".wiskessignore";
+
+/// read_ignore_file - read the glob patterns of an ignore file, one per line, where blank
+/// lines and comments starting with # are skipped
+///
+/// Return: the patterns, or the reason the file could not be read
+pub(crate) fn read_ignore_file(path_str: &str) -> Result<Vec<String>, String> {
+    let contents = fs::read_to_string(path_str).map_err(|e| format!("unable to read the ignore file {}. Error: {}", path_str, e))?;
+    Ok(contents.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.to_string())
+        .collect())
+}
+
+/// ignore_globs - the globs of a pattern of an ignore file for the -exclude of whipped, which
+/// matches the path under the data source or the name of a file. A pattern with a / is
+/// anchored at the root, and one without matches a file or folder at any depth, with the
+/// files in a folder excluded too
+pub(crate) fn ignore_globs(pattern: &str) -> Vec<String> {
+    let pattern = pattern.trim_matches('/');
+    match pattern.contains('/') {
+        true => vec![pattern.to_string(), format!("{}/*", pattern)],
+        false => vec![pattern.to_string(), format!("{}/*", pattern), format!("*/{}/*", pattern)],
+    }
+}
+
+/// find_files_glob - get all the files matching a file path, which may be a glob, except
+/// the outputs marked incomplete and the originals of those transcoded
+pub(crate) fn find_files_glob(path_str: &str) -> Vec<String> {
+    walk_glob(path_str).0.into_iter()
+        .filter(|path| Path::new(path).is_file() && !is_incomplete(path) && !is_original(path))
+        .collect()
+}
+
+/// the extension added to the outputs of a command that was killed, as they may be truncated
+pub(crate) const INCOMPLETE_EXT: &str = ".incomplete";
+
+/// is_incomplete - check if a file is the output of a command that was killed
+pub(crate) fn is_incomplete(path_str: &str) -> bool {
+    path_str.ends_with(INCOMPLETE_EXT)
+}
+
+/// mark_incomplete - rename each file matching the output path of a command that was
+/// killed, adding the .incomplete extension, so it is not read as if it were produced
+///
+/// Return: the files renamed, with the reason each that could not be renamed
+pub(crate) fn mark_incomplete(path_str: &str) -> (Vec<String>, Vec<String>) {
+    let mut marked = Vec::new();
+    let mut errors = Vec::new();
+    for file in find_files_glob(path_str) {
+        let renamed = format!("{}{}", file, INCOMPLETE_EXT);
+        match fs::rename(&file, &renamed) {
+            Ok(_) => marked.push(renamed),
+            Err(e) => errors.push(format!("unable to rename {}. Error: {}", file, e)),
+        }
+    }
+    (marked, errors)
+}
+
+/// the extension added to the output of a command as written by the tool, once it is
+/// transcoded to UTF-8 by its `encoding`
+pub(crate) const ORIGINAL_EXT: &str = ".orig";
+
+/// is_original - check if a file is the original of an output transcoded to UTF-8
+pub(crate) fn is_original(path_str: &str) -> bool {
+    path_str.ends_with(ORIGINAL_EXT)
+}
+
+/// the encodings an output can be transcoded to UTF-8 from
+pub(crate) const ENCODINGS: [&str; 5] = ["utf-8", "utf-16", "utf-16le", "utf-16be", "latin1"];
+
+/// transcode_utf8 - rewrite an output in the encoding as UTF-8 without a byte order mark,
+/// keeping the original beside it with the .orig extension. A utf-16 output is read as
+/// little endian unless its byte order mark is big endian, and the characters that can't
+/// be decoded are replaced. A utf-8 output without a byte order mark is left as it is
+///
+/// Return: whether the output was rewritten, or the reason it could not be
+pub(crate) fn transcode_utf8(path_str: &str, encoding: &str) -> Result<bool, String> {
+    let mut reader = BufReader::new(File::open(path_str).map_err(|e| e.to_string())?);
+    let bom = reader.fill_buf().map_err(|e| e.to_string())?;
+    let (bom_len, encoding) = match (encoding, bom) {
+        ("utf-8", [0xEF, 0xBB, 0xBF, ..]) => (3, "utf-8"),
+        ("utf-8", _) => return Ok(false),
+        ("utf-16" | "utf-16le", [0xFF, 0xFE, ..]) => (2, "utf-16le"),
+        ("utf-16" | "utf-16be", [0xFE, 0xFF, ..]) => (2, "utf-16be"),
+        ("utf-16", _) => (0, "utf-16le"),
+        (encoding, _) => (0, encoding),
+    };
+    reader.consume(bom_len);
+    let tmp_path = format!("{}.utf8", path_str);
+    let mut writer = BufWriter::new(File::create(&tmp_path).map_err(|e| e.to_string())?);
+    let written = match encoding {
+        "utf-8" => io::copy(&mut reader, &mut writer).map(|_| ()),
+        "latin1" => loop {
+            let text: String = match reader.fill_buf() {
+                Ok([]) => break Ok(()),
+                Ok(bytes) => bytes.iter().map(|b| *b as char).collect(),
+                Err(e) => break Err(e),
+            };
+            // each byte is one character, of one or two bytes in UTF-8
+            reader.consume(text.chars().count());
+            if let Err(e) = writer.write_all(text.as_bytes()) {
+                break Err(e);
+            }
+        },
+        "utf-16le" | "utf-16be" => {
+            let mut units = Vec::new();
+            let mut pair = [0u8; 2];
+            let mut result = Ok(());
+            loop {
+                match reader.read_exact(&mut pair) {
+                    Ok(_) => units.push(match encoding {
+                        "utf-16le" => u16::from_le_bytes(pair),
+                        _ => u16::from_be_bytes(pair),
+                    }),
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                    Err(e) => { result = Err(e); break; },
+                }
+                // decode in blocks, keeping a high surrogate for the unit after it
+                if units.len() >= 65536 && !(0xD800..0xDC00).contains(units.last().unwrap()) {
+                    let text: String = char::decode_utf16(units.drain(..)).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect();
+                    if let Err(e) = writer.write_all(text.as_bytes()) { result = Err(e); break; }
+                }
+            }
+            result.and_then(|_| {
+                let text: String = char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect();
+                writer.write_all(text.as_bytes())
+            })
+        },
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unknown encoding {}, expected one of {}", encoding, ENCODINGS.join(", ")))),
+    };
+    if let Err(e) = written.and_then(|_| writer.flush()) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e.to_string());
+    }
+    drop(writer);
+    fs::rename(path_str, format!("{}{}", path_str, ORIGINAL_EXT)).map_err(|e| format!("unable to keep the original. Error: {}", e))?;
+    fs::rename(&tmp_path, path_str).map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+/// walk_glob - get the paths matching a glob, following the symlinks but skipping those that
+/// loop back to a folder above them, i.e. a recursive link in a mounted image, which would
+/// otherwise be walked forever. The folders that do not match the glob are not walked, and
+/// a `**` is walked no deeper than MAX_GLOB_DEPTH folders
+///
+/// Return: the paths that match, with the paths of the symlink loops that were skipped
+pub(crate) fn walk_glob(path_str: &str) -> (Vec<String>, Vec<String>) {
+    let Some(wild) = path_str.find(['*', '?', '[']) else {
+        return match Path::new(path_str).exists() {
+            true => (vec![path_str.to_string()], Vec::new()),
+            false => (Vec::new(), Vec::new()),
+        };
+    };
+    let Ok(pattern) = glob::Pattern::new(path_str) else {
+        return (Vec::new(), Vec::new());
+    };
+    // walk from the folder before the first wildcard, as deep as the glob has folders
+    let base = path_str[..wild].rfind(['/', '\\']).map(|i| &path_str[..=i]).unwrap_or("");
+    let depth = match path_str.contains("**") {
+        true => MAX_GLOB_DEPTH,
+        false => path_str[base.len()..].matches(['/', '\\']).count() + 1,
+    };
+    let options = glob::MatchOptions {
+        case_sensitive: !cfg!(windows),
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    // the pattern of the folders at each depth, up to a `**`, so those that can not match are not walked
+    let mut prefixes = Vec::new();
+    for (i, c) in path_str.char_indices().skip(base.len()) {
+        if matches!(c, '/' | '\\') {
+            if path_str[..i].ends_with("**") {
+                break;
+            }
+            prefixes.extend(glob::Pattern::new(&path_str[..i]));
+        }
+    }
+    // the path of an entry, as the glob has it when it is relative to the working folder
+    let relative = |path: &Path| {
+        let path = path.display().to_string();
+        match base.is_empty() {
+            true => path.strip_prefix("./").unwrap_or(&path).to_string(),
+            false => path,
+        }
+    };
+    let mut paths = Vec::new();
+    let mut loops = Vec::new();
+    let walk = WalkDir::new(if base.is_empty() { "." } else { base })
+        .follow_links(true)
+        .min_depth(1)
+        .max_depth(depth)
+        .into_iter()
+        .filter_entry(|entry| {
+            let Some(prefix) = entry.depth().checked_sub(1).and_then(|d| prefixes.get(d)) else {
+                return true;
+            };
+            prefix.matches_with(&relative(entry.path()), options)
+        });
+    for entry in walk {
+        match entry {
+            Ok(entry) => {
+                let path = relative(entry.path());
+                if pattern.matches_with(&path, options) {
+                    paths.push(path);
+                }
+            },
+            Err(e) if e.loop_ancestor().is_some() => {
+                loops.push(e.path().map(|p| p.display().to_string()).unwrap_or_default());
+            },
+            Err(_) => (),
+        }
+    }
+    paths.sort();
+    (paths, loops)
+}
+
 fn find_file_glob(path_str: &String) -> String {
     // Get path from glob based path  
     for entry in glob(path_str).expect("Unable to read glob pattern") {
         match entry {
+            Ok(path) if is_incomplete(&path.display().to_string()) || is_original(&path.display().to_string()) => continue,
             Ok(path) => {
                 return path.display().to_string();
             }
@@ -115,6 +485,56 @@ pub fn check_date(date: String, date_type: &String) -> String {
     ret_date
 }
 
+/// set the patterns masked in the log, which are those known to be sensitive and the
+/// custom patterns, and the values of the env vars named as secrets, i.e. AWS_SESSION_TOKEN
+///
+/// Args:
+/// * `patterns` - the custom regex patterns to mask, from --redact-pattern
+///
+/// returns the reason if a pattern is not valid
+pub fn set_redactions(patterns: &[String]) -> Result<(), String> {
+    let regexes = REDACT_PATTERNS.iter().map(|p| p.to_string())
+        .chain(patterns.iter().cloned())
+        .map(|p| Regex::new(&p).map_err(|e| format!("invalid redact pattern '{}'. Error: {}", p, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let re_env = Regex::new(REDACT_ENV).unwrap();
+    let values = std::env::vars()
+        .filter(|(k, v)| re_env.is_match(k) && v.len() >= 8)
+        .map(|(_, v)| v)
+        .collect();
+    let _ = REDACTIONS.set((regexes, values));
+    Ok(())
+}
+
+/// mask the sensitive values in a message, so it can be written to the log
+pub fn redact(msg: &str) -> String {
+    let Some((regexes, values)) = REDACTIONS.get() else {
+        return msg.to_string();
+    };
+    let mut msg = msg.to_string();
+    for value in values {
+        msg = msg.replace(value.as_str(), "<redacted>");
+    }
+    for re in regexes {
+        msg = re.replace_all(&msg, "${keep}<redacted>").to_string();
+    }
+    msg
+}
+
+/// the time the run is fixed at by --fixed-time, for reproducible runs in testing
+pub(crate) static FIXED_TIME: OnceLock<chrono::DateTime<chrono::Utc>> = OnceLock::new();
+
+/// now - the current time, or that given by --fixed-time, so the times and file names
+/// written by a run are the same each time it is run
+pub(crate) fn now() -> chrono::DateTime<chrono::Utc> {
+    now_or_fixed(FIXED_TIME.get())
+}
+
+/// the fixed time if there is one, otherwise the current time
+fn now_or_fixed(fixed: Option<&chrono::DateTime<chrono::Utc>>) -> chrono::DateTime<chrono::Utc> {
+    fixed.copied().unwrap_or_else(chrono::Utc::now)
+}
+
 pub fn log_msg(out_log: &String, msg: String) {
     let mut file = OpenOptions::new()
         .write(true)
@@ -123,7 +543,7 @@ pub fn log_msg(out_log: &String, msg: String) {
         .open(&out_log)
         .expect("Failed to open log file");
     
-    writeln!(file, "[{}] {}", chrono::Local::now().format("%Y%m%dT%H%M%S"), msg).unwrap();
+    writeln!(file, "[{}] {}", now().with_timezone(&chrono::Local).format("%Y%m%dT%H%M%S"), redact(&msg)).unwrap();
 }
 
 /// check_access - get attr, try read, regex root to see if match \w:\\Windows\\
@@ -187,3 +607,81 @@ fn has_any_lines(filepath: &String) -> Result<String, io::Error> {
         Ok(_) => Ok("this is a file".to_string()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn walk_glob_skips_symlink_loops() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().display().to_string();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        fs::create_dir_all(dir.path().join("c/b")).unwrap();
+        fs::write(dir.path().join("a/b/x.txt"), "x").unwrap();
+        fs::write(dir.path().join("c/b/y.txt"), "y").unwrap();
+        // a link back up to the folder above it, as in a recursive link in a mounted image
+        std::os::unix::fs::symlink(dir.path().join("c"), dir.path().join("c/b/loop")).unwrap();
+
+        let (paths, loops) = walk_glob(&format!("{}/**/*.txt", root));
+        assert_eq!(paths, vec![format!("{}/a/b/x.txt", root), format!("{}/c/b/y.txt", root)]);
+        assert_eq!(loops, vec![format!("{}/c/b/loop", root)]);
+
+        // the folders that do not match the pattern are not walked, so the loop in c is not
+        let (paths, loops) = walk_glob(&format!("{}/[ab]/*/*.txt", root));
+        assert_eq!(paths, vec![format!("{}/a/b/x.txt", root)]);
+        assert!(loops.is_empty());
+    }
+
+    #[test]
+    fn simple_path_strips_the_verbatim_prefix() {
+        assert_eq!(simple_path(Path::new(r"\\?\UNC\server\share\x")), r"\\server\share\x");
+        assert_eq!(simple_path(Path::new(r"\\?\C:\x")), r"C:\x");
+        assert_eq!(simple_path(Path::new(r"C:\x")), r"C:\x");
+        // a path too long without the prefix keeps it
+        let long = format!(r"\\?\C:\{}", "x".repeat(MAX_PATH));
+        assert_eq!(simple_path(Path::new(&long)), long);
+    }
+
+    #[test]
+    fn trim_separators_keeps_a_root() {
+        assert_eq!(trim_separators(r"\\server\share\case\"), r"\\server\share\case");
+        assert_eq!(trim_separators("/mnt/case//"), "/mnt/case");
+        assert_eq!(trim_separators("C:\\"), "C:");
+        assert_eq!(trim_separators("/"), "/");
+    }
+
+    #[test]
+    fn drive_root_is_only_a_drive_letter() {
+        assert_eq!(drive_root("C:"), Some("C:"));
+        assert_eq!(drive_root(r"d:\"), Some("d:"));
+        assert_eq!(drive_root("E:/"), Some("E:"));
+        assert_eq!(drive_root(r"C:\Windows"), None);
+        assert_eq!(drive_root(r"\\server\share"), None);
+        assert_eq!(drive_root("/mnt/case"), None);
+        assert_eq!(drive_root("1:"), None);
+    }
+
+    #[test]
+    fn is_local_drive_is_only_a_disk_root() {
+        assert!(!is_local_drive(r"C:\Windows"));
+        assert!(!is_local_drive(r"\\server\share"));
+        assert!(!is_local_drive("/mnt/case"));
+        #[cfg(windows)]
+        {
+            let system_drive = std::env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string());
+            assert!(is_local_drive(&system_drive));
+            assert!(is_local_drive(&format!("{}\\", system_drive)));
+        }
+    }
+
+    #[test]
+    fn now_is_the_fixed_time() {
+        let fixed = chrono::DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z").unwrap().with_timezone(&chrono::Utc);
+        assert_eq!(now_or_fixed(Some(&fixed)), fixed);
+        let before = chrono::Utc::now();
+        let current = now_or_fixed(None);
+        assert!(current >= before && current <= chrono::Utc::now());
+    }
+}
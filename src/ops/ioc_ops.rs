@@ -0,0 +1,451 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use serde::{Deserialize, Serialize};
+
+use crate::configs::config::{self, IocSeverity, Wiskers};
+use super::{file_ops, summary_ops};
+use super::secret_ops::Secret;
+
+/// An indicator found in the outputs, with the files it was found in
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IocHit {
+    pub ioc: String,
+    pub total: usize,
+    pub files: Vec<IocFile>,
+}
+
+/// The count of an indicator in an output file of an enricher
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IocFile {
+    pub source: String,
+    pub path: String,
+    pub count: usize,
+}
+
+/// An indicator found in the outputs, ranked by its severity then the times it was found
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PriorityHit {
+    pub rank: usize,
+    pub ioc: String,
+    pub severity: Option<IocSeverity>,
+    pub total: usize,
+    pub sources: Vec<String>,
+}
+
+/// read the IOC list, where each indicator is on a separate line. Empty
+/// lines are ignored.
+pub fn parse_iocs(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+/// read the IOC list file, see `parse_iocs`
+pub fn load_iocs(ioc_file: &String) -> Vec<String> {
+    match fs::read_to_string(ioc_file) {
+        Ok(contents) => parse_iocs(&contents),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// the IOC placeholders that the IOC input provides, which as a list of indicators of
+/// any type is only the file of them, not those of a type, i.e. `{ioc_domains}`
+pub const IOC_PLACEHOLDERS: [&str; 1] = ["ioc_file"];
+
+/// the IOC placeholders used in a field of a command, i.e. `{ioc_domains}` or `${ioc_domains}`,
+/// that the IOC input does not provide
+pub fn missing_ioc_placeholders(field: &str) -> Vec<String> {
+    let re_ioc = regex::Regex::new(r"\$?\{(ioc_\w+)\}").unwrap();
+    re_ioc.captures_iter(field)
+        .filter(|cap| !IOC_PLACEHOLDERS.contains(&&cap[1]))
+        .map(|cap| cap[0].to_string())
+        .collect()
+}
+
+/// check the commands that will run only use the IOC placeholders that the IOC input
+/// provides, so the run stops before it starts rather than a command failing late in
+/// the run, after the wiskers, with the placeholder not replaced
+///
+/// Args:
+/// * `config` - the config of the commands
+/// * `main_args` - the arguments specified from the main.rs, i.e. the tags selecting the commands
+///
+/// returns the placeholders that are not provided, with the commands that use them
+pub fn check_ioc_placeholders(config: &config::Config, main_args: &config::MainArgs) -> Result<(), String> {
+    let mut missing = Vec::new();
+    for (stage, func) in config.stages() {
+        for wisker in func.iter().filter(|w| w.selected(main_args)) {
+            for field in [wisker.binary.path(), &wisker.args, &wisker.script_posh, &wisker.request] {
+                for placeholder in missing_ioc_placeholders(field) {
+                    missing.push(format!("{} ({}/{})", placeholder, stage, wisker.name));
+                }
+            }
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let source = match main_args.iocs.is_some() {
+        true => "the IOCs from the secret are",
+        false => "the IOC file is",
+    };
+    Err(format!(
+        "the config uses {}, but {} an untyped list of indicators, which only provides {{{}}}",
+        missing.join(", "),
+        source,
+        IOC_PLACEHOLDERS.join("}, {")
+    ))
+}
+
+/// the file of the IOCs of a secret on disk, outside the output, to be removed on any exit
+/// of the run, not only at its end, see `remove_secret_iocs`
+static SECRET_IOC_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// The IOCs of a secret for the commands that read `{ioc_file}`, which are never written to
+/// disk on Linux, where they are in a memory file read by its path in /proc. Elsewhere they
+/// are in a file in the temp folder, outside the output, that only the user running wiskess
+/// can read, removed once this is dropped or the run exits
+pub struct SecretIocs {
+    pub path: String,
+    #[cfg(target_os = "linux")]
+    _memfd: std::os::fd::OwnedFd,
+}
+
+impl SecretIocs {
+    /// put the IOCs of the secret where the commands can read them by a path
+    ///
+    /// Args:
+    /// * `iocs` - the IOC list read from the secret
+    ///
+    /// returns the IOCs with the path to give as `{ioc_file}`, or the reason they could not be written
+    #[cfg(target_os = "linux")]
+    pub fn create(iocs: &Secret) -> Result<SecretIocs, String> {
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+        let fd = unsafe { libc::memfd_create(c"wiskess_iocs".as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(format!("unable to create a memory file. Error: {}", std::io::Error::last_os_error()));
+        }
+        let memfd = unsafe { OwnedFd::from_raw_fd(fd) };
+        File::from(memfd.try_clone().map_err(|e| e.to_string())?)
+            .write_all(iocs.expose().as_bytes())
+            .map_err(|e| format!("unable to write the memory file. Error: {}", e))?;
+        // each command that opens the path reads it from the start
+        let path = format!("/proc/{}/fd/{}", std::process::id(), memfd.as_raw_fd());
+        Ok(SecretIocs { path, _memfd: memfd })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn create(iocs: &Secret) -> Result<SecretIocs, String> {
+        let path = std::env::temp_dir().join(format!("wiskess_iocs_{}.txt", uuid::Uuid::new_v4()));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&path).map_err(|e| format!("unable to create {}. Error: {}", path.display(), e))?;
+        *SECRET_IOC_PATH.lock().unwrap_or_else(|e| e.into_inner()) = Some(path.clone());
+        file.write_all(iocs.expose().as_bytes()).map_err(|e| format!("unable to write {}. Error: {}", path.display(), e))?;
+        Ok(SecretIocs { path: path.display().to_string() })
+    }
+}
+
+impl Drop for SecretIocs {
+    fn drop(&mut self) {
+        remove_secret_iocs();
+    }
+}
+
+/// remove the file of the IOCs of a secret, if one was written, when the run ends or exits
+/// early, i.e. on an error, a panic or Ctrl-C
+pub fn remove_secret_iocs() {
+    if let Some(path) = SECRET_IOC_PATH.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// count the case-insensitive occurrences of each indicator in a file, reading
+/// it line by line so large outputs are not loaded into memory
+fn count_iocs(file_path: &String, iocs_lower: &[String]) -> Vec<usize> {
+    let mut counts = vec![0; iocs_lower.len()];
+    let file = match File::open(file_path) {
+        Ok(f) => f,
+        Err(_) => return counts,
+    };
+    let mut reader = BufReader::new(file);
+    let mut buf = Vec::new();
+    while let Ok(n) = reader.read_until(b'\n', &mut buf) {
+        if n == 0 {
+            break;
+        }
+        let line = String::from_utf8_lossy(&buf).to_lowercase();
+        for (i, ioc) in iocs_lower.iter().enumerate() {
+            counts[i] += line.matches(ioc.as_str()).count();
+        }
+        buf.clear();
+    }
+    counts
+}
+
+/// scan the output files of the enrichers for the indicators in the IOC list,
+/// and write a summary of which were found, in which files and how many times to
+/// `out_path/ioc_hits.json`
+///
+/// Args:
+/// * `enrichers` - the enrichers from the config, of which the outputs are scanned
+/// * `main_args` - the arguments specified from the main.rs, i.e. ioc_file and out_path
+/// * `data_paths` - the hash map of the artefact name and file path, to skip enrichers that didn't run
+///
+/// returns the indicators that were found
+pub fn ioc_hits(enrichers: &[Wiskers], main_args: &config::MainArgs, data_paths: &HashMap<String, String>) -> Vec<IocHit> {
+    let iocs = match &main_args.iocs {
+        Some(secret) => parse_iocs(secret.expose()),
+        None => load_iocs(&main_args.ioc_file),
+    };
+    let iocs_lower: Vec<String> = iocs.iter().map(|i| i.to_lowercase()).collect();
+    let mut hits: Vec<IocHit> = iocs.iter().map(|ioc| IocHit {
+        ioc: ioc.to_string(),
+        total: 0,
+        files: Vec::new(),
+    }).collect();
+
+    for enricher in enrichers {
+        if matches!(data_paths.get(&enricher.input).map(|p| p.as_str()), Some("wiskess_none") | Some("wiskess_locked")) {
+            continue;
+        }
+        let outfile = enricher.out_file(&main_args.out_path);
+        for path in file_ops::find_files_glob(&outfile) {
+            let counts = count_iocs(&path, &iocs_lower);
+            for (hit, count) in hits.iter_mut().zip(counts) {
+                if count > 0 {
+                    hit.total += count;
+                    hit.files.push(IocFile {
+                        source: enricher.name.to_string(),
+                        path: path.to_string(),
+                        count,
+                    });
+                }
+            }
+        }
+    }
+    hits.retain(|h| h.total > 0);
+
+    let hits_path = Path::new(&main_args.out_path).join("ioc_hits.json");
+    let hits_str = serde_json::to_string_pretty(&hits).expect("Unable to serialise the IOC hits.");
+    match fs::write(&hits_path, hits_str) {
+        Ok(_) => file_ops::log_msg(&main_args.out_log, format!(
+            "[+] {} of {} IOCs found in the enricher outputs, written to: {}",
+            hits.len(),
+            iocs.len(),
+            hits_path.display()
+        )),
+        Err(e) => file_ops::log_msg(&main_args.out_log, format!("[!] Unable to write IOC hits: {}. Error: {}", hits_path.display(), e)),
+    }
+    hits
+}
+
+/// the IOC hits as a html page, with a table of the hits in the order they are ranked
+fn priority_html(priority: &[PriorityHit], case_name: &str) -> String {
+    let case_name = summary_ops::html_escape(case_name);
+    let mut html = format!("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Wiskess Priority IOC Hits - {}</title>\n", case_name);
+    html.push_str("<style>body{font-family:sans-serif}table{border-collapse:collapse}td,th{border:1px solid #999;padding:4px 8px}</style>\n</head>\n<body>\n");
+    html.push_str(&format!("<h1>Wiskess Priority IOC Hits - {}</h1>\n", case_name));
+    html.push_str("<table>\n<tr><th>Rank</th><th>Indicator</th><th>Severity</th><th>Hits</th><th>Found by</th></tr>\n");
+    for hit in priority {
+        let severity = hit.severity.map(|s| format!("{:?}", s).to_lowercase()).unwrap_or_default();
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            hit.rank, summary_ops::html_escape(&hit.ioc), severity, hit.total, summary_ops::html_escape(&hit.sources.join(", "))
+        ));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// rank the IOC hits by the severity of each indicator in the config, then by the times it
+/// was found, so the highest severity matches are first. Those without a severity are
+/// ranked after, by the times found. Written to `out_path/priority_hits.json` and
+/// `out_path/priority_hits.html`
+///
+/// Args:
+/// * `hits` - the indicators found in the enricher outputs
+/// * `ioc_severity` - the severity of the indicators, matched case-insensitive
+/// * `main_args` - the arguments specified from the main.rs, i.e. out_path
+///
+/// returns the hits in the order they are ranked
+pub fn priority_hits(hits: &[IocHit], ioc_severity: &HashMap<String, IocSeverity>, main_args: &config::MainArgs) -> Vec<PriorityHit> {
+    let severities: HashMap<String, IocSeverity> = ioc_severity.iter().map(|(ioc, s)| (ioc.to_lowercase(), *s)).collect();
+    let mut priority: Vec<PriorityHit> = hits.iter().map(|hit| {
+        let mut sources: Vec<String> = hit.files.iter().map(|f| f.source.to_string()).collect();
+        sources.dedup();
+        PriorityHit {
+            rank: 0,
+            ioc: hit.ioc.to_string(),
+            severity: severities.get(&hit.ioc.to_lowercase()).copied(),
+            total: hit.total,
+            sources,
+        }
+    }).collect();
+    priority.sort_by(|a, b| b.severity.cmp(&a.severity).then(b.total.cmp(&a.total)));
+    for (i, hit) in priority.iter_mut().enumerate() {
+        hit.rank = i + 1;
+    }
+
+    let json_path = Path::new(&main_args.out_path).join("priority_hits.json");
+    let html_path = Path::new(&main_args.out_path).join("priority_hits.html");
+    let priority_str = serde_json::to_string_pretty(&priority).expect("Unable to serialise the priority IOC hits.");
+    match fs::write(&json_path, priority_str).and_then(|_| fs::write(&html_path, priority_html(&priority, &main_args.case_name))) {
+        Ok(_) => file_ops::log_msg(&main_args.out_log, format!(
+            "[+] Ranked {} IOC hits by severity then hits, written to: {}",
+            priority.len(),
+            json_path.display()
+        )),
+        Err(e) => file_ops::log_msg(&main_args.out_log, format!("[!] Unable to write the priority IOC hits: {}. Error: {}", json_path.display(), e)),
+    }
+    priority
+}
+
+/// the STIX cyber-observable property an indicator is matched on, by the form of its value,
+/// i.e. `ipv4-addr:value` for an IP address or `file:hashes.'SHA-256'` for a SHA-256 hash.
+/// Those of no other form are matched as a file name
+fn stix_property(ioc: &str) -> &'static str {
+    static DOMAIN: OnceLock<regex::Regex> = OnceLock::new();
+    let domain = DOMAIN.get_or_init(|| regex::Regex::new(r"^(?i)([a-z0-9-]+\.)+[a-z]{2,}$").unwrap());
+    let is_hex = |len: usize| ioc.len() == len && ioc.chars().all(|c| c.is_ascii_hexdigit());
+    if ioc.parse::<std::net::Ipv4Addr>().is_ok() {
+        "ipv4-addr:value"
+    } else if ioc.parse::<std::net::Ipv6Addr>().is_ok() {
+        "ipv6-addr:value"
+    } else if is_hex(32) {
+        "file:hashes.'MD5'"
+    } else if is_hex(40) {
+        "file:hashes.'SHA-1'"
+    } else if is_hex(64) {
+        "file:hashes.'SHA-256'"
+    } else if ioc.contains("://") {
+        "url:value"
+    } else if ioc.contains('@') && !ioc.contains(['/', '\\', ' ']) {
+        "email-addr:value"
+    } else if domain.is_match(ioc) && !ioc.contains(['/', '\\']) {
+        "domain-name:value"
+    } else {
+        "file:name"
+    }
+}
+
+/// a STIX identifier of the type, i.e. `indicator--<uuid>`
+fn stix_id(stix_type: &str) -> String {
+    format!("{}--{}", stix_type, uuid::Uuid::new_v4())
+}
+
+/// the IOC hits as a STIX 2.1 bundle, of an indicator for each IOC found, typed by the form
+/// of its value, and a sighting of it, with the output files it was found in as the file
+/// objects of the observed data, by their path relative to the output folder
+///
+/// Args:
+/// * `hits` - the indicators found in the enricher outputs
+/// * `priority` - the hits ranked, for the severity of each indicator
+/// * `main_args` - the arguments specified from the main.rs, i.e. out_path and case_name
+pub fn stix_bundle(hits: &[IocHit], priority: &[PriorityHit], main_args: &config::MainArgs) -> serde_json::Value {
+    let now = file_ops::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+    let identity = stix_id("identity");
+    let mut objects = vec![serde_json::json!({
+        "type": "identity",
+        "spec_version": "2.1",
+        "id": identity,
+        "created": now,
+        "modified": now,
+        "name": format!("Wiskess - {}", main_args.case_name),
+        "identity_class": "system",
+    })];
+    // the output files are shared by the sightings of the indicators found in them
+    let mut file_ids: HashMap<String, String> = HashMap::new();
+    for hit in hits {
+        let value = hit.ioc.replace('\\', "\\\\").replace('\'', "\\'");
+        let indicator = stix_id("indicator");
+        let mut labels = vec![format!("case:{}", main_args.case_name)];
+        if let Some(severity) = priority.iter().find(|p| p.ioc == hit.ioc).and_then(|p| p.severity) {
+            labels.push(format!("severity:{}", format!("{:?}", severity).to_lowercase()));
+        }
+        objects.push(serde_json::json!({
+            "type": "indicator",
+            "spec_version": "2.1",
+            "id": indicator,
+            "created_by_ref": identity,
+            "created": now,
+            "modified": now,
+            "name": hit.ioc,
+            "pattern": format!("[{} = '{}']", stix_property(&hit.ioc), value),
+            "pattern_type": "stix",
+            "valid_from": now,
+            "labels": labels,
+        }));
+        let mut files = Vec::new();
+        for file in &hit.files {
+            let file_id = file_ids.entry(file.path.to_string()).or_insert_with(|| {
+                let path = Path::new(&file.path).strip_prefix(&main_args.out_path)
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_else(|_| file.path.to_string());
+                let file_id = stix_id("file");
+                objects.push(serde_json::json!({
+                    "type": "file",
+                    "spec_version": "2.1",
+                    "id": file_id,
+                    "name": path,
+                }));
+                file_id
+            });
+            files.push(file_id.to_string());
+        }
+        let observed = stix_id("observed-data");
+        objects.push(serde_json::json!({
+            "type": "observed-data",
+            "spec_version": "2.1",
+            "id": observed,
+            "created_by_ref": identity,
+            "created": now,
+            "modified": now,
+            "first_observed": now,
+            "last_observed": now,
+            "number_observed": hit.total,
+            "object_refs": files,
+        }));
+        let mut sources: Vec<&str> = hit.files.iter().map(|f| f.source.as_str()).collect();
+        sources.dedup();
+        objects.push(serde_json::json!({
+            "type": "sighting",
+            "spec_version": "2.1",
+            "id": stix_id("sighting"),
+            "created_by_ref": identity,
+            "created": now,
+            "modified": now,
+            "sighting_of_ref": indicator,
+            "observed_data_refs": [observed],
+            "where_sighted_refs": [identity],
+            "count": hit.total,
+            "description": format!("Found {} times in the outputs of {}", hit.total, sources.join(", ")),
+        }));
+    }
+    serde_json::json!({
+        "type": "bundle",
+        "id": stix_id("bundle"),
+        "objects": objects,
+    })
+}
+
+/// write the IOC hits as a STIX 2.1 bundle to `out_path/hits.stix.json`, see `stix_bundle`,
+/// for a threat intel platform to import
+pub fn write_stix(hits: &[IocHit], priority: &[PriorityHit], main_args: &config::MainArgs) {
+    let stix_path = Path::new(&main_args.out_path).join("hits.stix.json");
+    let stix_str = serde_json::to_string_pretty(&stix_bundle(hits, priority, main_args)).expect("Unable to serialise the STIX bundle.");
+    match fs::write(&stix_path, stix_str) {
+        Ok(_) => file_ops::log_msg(&main_args.out_log, format!("[+] STIX bundle of {} IOC hits written to: {}", hits.len(), stix_path.display())),
+        Err(e) => file_ops::log_msg(&main_args.out_log, format!("[!] Unable to write the STIX bundle: {}. Error: {}", stix_path.display(), e)),
+    }
+}
@@ -0,0 +1,194 @@
+use std::process::{Child, Command};
+#[cfg(windows)]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// the bytes read and written by the commands whose job has closed, on Windows
+#[cfg(windows)]
+static JOBS_READ: AtomicU64 = AtomicU64::new(0);
+#[cfg(windows)]
+static JOBS_WRITTEN: AtomicU64 = AtomicU64::new(0);
+
+/// the bytes read and written by wiskess and the commands whose job has closed, on Windows,
+/// which counts all their IO, i.e. of files, devices and pipes
+#[cfg(windows)]
+pub fn jobs_io() -> Option<(u64, u64)> {
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, GetProcessIoCounters, IO_COUNTERS};
+    let mut own: IO_COUNTERS = unsafe { std::mem::zeroed() };
+    if unsafe { GetProcessIoCounters(GetCurrentProcess(), &mut own) } == 0 {
+        return None;
+    }
+    Some((
+        own.ReadTransferCount + JOBS_READ.load(Ordering::SeqCst),
+        own.WriteTransferCount + JOBS_WRITTEN.load(Ordering::SeqCst),
+    ))
+}
+
+/// limit the memory of each process the command starts to `max_memory` MB, by the rlimit of
+/// its address space set in the child before it runs, which the processes it starts inherit.
+/// An allocation over the limit fails rather than the host running out of memory. As this is
+/// the virtual address space, not the memory used, a tool that reserves far more than it uses,
+/// i.e. a JVM or a Go binary, fails under a limit below what it reserves, so set it well above.
+/// On Windows the command is created suspended, so it starts nothing before it is put in its
+/// job, where the limit is set, see `Job::assign`
+///
+/// Args:
+/// * `command` - the command before it is spawned
+/// * `max_memory` - the limit in MB, 0 for no limit
+pub fn limit_memory(command: &mut Command, max_memory: u64) {
+    #[cfg(unix)]
+    if max_memory > 0 {
+        use std::os::unix::process::CommandExt;
+        let bytes = (max_memory * 1024 * 1024) as libc::rlim_t;
+        // only setrlimit is called between the fork and the exec, which is async-signal-safe
+        unsafe {
+            command.pre_exec(move || {
+                let limit = libc::rlimit { rlim_cur: bytes, rlim_max: bytes };
+                match libc::setrlimit(libc::RLIMIT_AS, &limit) {
+                    0 => Ok(()),
+                    _ => Err(std::io::Error::last_os_error()),
+                }
+            });
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        use windows_sys::Win32::System::Threading::CREATE_SUSPENDED;
+        let _ = max_memory;
+        command.creation_flags(CREATE_SUSPENDED);
+    }
+    #[cfg(not(any(unix, windows)))]
+    let _ = (command, max_memory);
+}
+
+/// resume the threads of a process that was created suspended, which is only its main thread
+#[cfg(windows)]
+fn resume(pid: u32) -> Result<(), String> {
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+    };
+    use windows_sys::Win32::System::Threading::{OpenThread, ResumeThread, THREAD_SUSPEND_RESUME};
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        let mut entry: THREADENTRY32 = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+        let mut resumed = false;
+        let mut more = Thread32First(snapshot, &mut entry) != 0;
+        while more {
+            if entry.th32OwnerProcessID == pid {
+                let thread = OpenThread(THREAD_SUSPEND_RESUME, 0, entry.th32ThreadID);
+                if thread != 0 {
+                    resumed |= ResumeThread(thread) != u32::MAX;
+                    CloseHandle(thread);
+                }
+            }
+            more = Thread32Next(snapshot, &mut entry) != 0;
+        }
+        CloseHandle(snapshot);
+        match resumed {
+            true => Ok(()),
+            false => Err(format!("unable to resume the process {}", pid)),
+        }
+    }
+}
+
+/// The job object a command runs in on Windows, which holds it and the processes it starts
+/// to the memory limit, and counts the bytes they read and write. Once dropped the job is
+/// closed, adding its bytes to those of the run. On other systems it does nothing
+pub struct Job {
+    #[cfg(windows)]
+    handle: windows_sys::Win32::Foundation::HANDLE,
+}
+
+impl Job {
+    /// put the spawned command in a new job, with the memory of each of its processes, and
+    /// of all of them, limited to `max_memory` MB, then resume it. As it was created suspended
+    /// by `limit_memory`, all the processes it starts are in the job
+    ///
+    /// Args:
+    /// * `child` - the command that was spawned suspended
+    /// * `max_memory` - the limit in MB, 0 for no limit
+    ///
+    /// returns the job, or the reason it could not be made, in which case the command runs without
+    /// it. If the command could not be resumed it is killed
+    #[cfg(windows)]
+    pub fn assign(child: &mut Child, max_memory: u64) -> Result<Job, String> {
+        let job = Self::create(child, max_memory);
+        if let Err(e) = resume(child.id()) {
+            let _ = child.kill();
+            return Err(format!("{}, so the command was stopped", e));
+        }
+        job
+    }
+
+    /// make the job with the memory limit and put the command in it
+    #[cfg(windows)]
+    fn create(child: &Child, max_memory: u64) -> Result<Job, String> {
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::System::JobObjects::{
+            AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation, SetInformationJobObject,
+            JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_JOB_MEMORY, JOB_OBJECT_LIMIT_PROCESS_MEMORY,
+        };
+        let last_error = || std::io::Error::last_os_error().to_string();
+        unsafe {
+            let handle = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if handle == 0 {
+                return Err(last_error());
+            }
+            let job = Job { handle };
+            if max_memory > 0 {
+                let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+                info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_PROCESS_MEMORY | JOB_OBJECT_LIMIT_JOB_MEMORY;
+                info.ProcessMemoryLimit = (max_memory * 1024 * 1024) as usize;
+                info.JobMemoryLimit = info.ProcessMemoryLimit;
+                let set = SetInformationJobObject(
+                    handle,
+                    JobObjectExtendedLimitInformation,
+                    &info as *const _ as *const std::ffi::c_void,
+                    std::mem::size_of_val(&info) as u32,
+                );
+                if set == 0 {
+                    return Err(last_error());
+                }
+            }
+            if AssignProcessToJobObject(handle, child.as_raw_handle() as isize) == 0 {
+                return Err(last_error());
+            }
+            Ok(job)
+        }
+    }
+
+    #[cfg(not(windows))]
+    pub fn assign(_child: &mut Child, _max_memory: u64) -> Result<Job, String> {
+        Ok(Job {})
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Job {
+    fn drop(&mut self) {
+        use windows_sys::Win32::Foundation::CloseHandle;
+        use windows_sys::Win32::System::JobObjects::{
+            JobObjectBasicAndIoAccountingInformation, QueryInformationJobObject, JOBOBJECT_BASIC_AND_IO_ACCOUNTING_INFORMATION,
+        };
+        unsafe {
+            let mut info: JOBOBJECT_BASIC_AND_IO_ACCOUNTING_INFORMATION = std::mem::zeroed();
+            let queried = QueryInformationJobObject(
+                self.handle,
+                JobObjectBasicAndIoAccountingInformation,
+                &mut info as *mut _ as *mut std::ffi::c_void,
+                std::mem::size_of_val(&info) as u32,
+                std::ptr::null_mut(),
+            );
+            if queried != 0 {
+                JOBS_READ.fetch_add(info.IoInfo.ReadTransferCount, Ordering::SeqCst);
+                JOBS_WRITTEN.fetch_add(info.IoInfo.WriteTransferCount, Ordering::SeqCst);
+            }
+            CloseHandle(self.handle);
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::{file_ops, ioc_ops, status_ops};
+
+/// the extensions of the disk images that are mounted, rather than read as a folder
+const IMAGE_EXTS: [&str; 9] = ["e01", "ex01", "dd", "raw", "img", "001", "vmdk", "vhd", "vhdx"];
+
+/// the time to wait for the mounted image to have files in it
+const MOUNT_WAIT: Duration = Duration::from_secs(60);
+
+/// The helper to unmount the image mounted for the run
+struct Mount {
+    path: String,
+    image: String,
+    mount_dir: String,
+    unmount: String,
+    out_log: String,
+}
+
+/// the image mounted for the run, so it is unmounted by `unmount` before wiskess exits,
+/// as `std::process::exit` does not drop the `Mounted` of the run
+static MOUNT: Mutex<Option<Mount>> = Mutex::new(None);
+
+/// An image mounted read-only for the run, which is unmounted when dropped
+pub struct Mounted {
+    pub path: String,
+}
+
+impl Drop for Mounted {
+    fn drop(&mut self) {
+        unmount();
+    }
+}
+
+/// unmount the image mounted for the run, unless there is none or it is unmounted
+pub fn unmount() {
+    let mount = MOUNT.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(mount) = mount {
+        run_helper(&mount.unmount, &mount.image, &mount.mount_dir, &mount.out_log);
+        file_ops::log_msg(&mount.out_log, format!("[ ] Unmounted the image from {}", &mount.path));
+        let _ = std::fs::remove_dir(&mount.mount_dir);
+    }
+}
+
+/// exit wiskess with the code, unmounting the image of the run and removing the file of the
+/// secret IOCs first. An exit on an error sets the status of the run as crashed, if it has started
+pub fn exit(code: i32) -> ! {
+    if code != 0 {
+        status_ops::write_crashed();
+    }
+    unmount();
+    ioc_ops::remove_secret_iocs();
+    std::process::exit(code)
+}
+
+/// check the data source is a disk image file, by its extension
+pub fn is_image(data_source: &str) -> bool {
+    let path = Path::new(data_source);
+    path.is_file() && path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .is_some_and(|e| IMAGE_EXTS.contains(&e.as_str()))
+}
+
+/// check the data source is `live`, to run against the filesystem of this machine
+pub fn is_live(data_source: &str) -> bool {
+    data_source.eq_ignore_ascii_case("live")
+}
+
+/// the root of the live filesystem the artefact paths are resolved against, the system
+/// drive on Windows, i.e. `C:` as read raw by check_copy_art, otherwise `/`
+pub fn live_root() -> String {
+    match std::env::consts::OS {
+        "windows" => std::env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string()),
+        _ => "/".to_string(),
+    }
+}
+
+/// the default mount and unmount helpers of the OS. On Linux an E01 is exposed as a raw
+/// image by ewfmount, and the raw image is mounted with guestmount, which finds the
+/// Windows partition. On Windows it is mounted by Arsenal Image Mounter to a drive letter
+fn default_helpers(image: &str, tool_path: &str) -> (String, String) {
+    let ewf = Path::new(image).extension().is_some_and(|e| e.eq_ignore_ascii_case("e01") || e.eq_ignore_ascii_case("ex01"));
+    match (std::env::consts::OS, ewf) {
+        ("windows", _) => (
+            format!("\"{}\\tools\\Arsenal-Image-Mounter\\aim_cli.exe\" --mount --readonly --filename=\"{{image}}\" --fakesig --background", tool_path),
+            format!("\"{}\\tools\\Arsenal-Image-Mounter\\aim_cli.exe\" --dismount --force", tool_path),
+        ),
+        (_, true) => (
+            "mkdir -p {mount}.ewf && ewfmount {image} {mount}.ewf && guestmount --ro --format=raw -a {mount}.ewf/ewf1 -i {mount}".to_string(),
+            "guestunmount {mount}; fusermount -u {mount}.ewf && rmdir {mount}.ewf".to_string(),
+        ),
+        (_, false) => (
+            "guestmount --ro -a {image} -i {mount}".to_string(),
+            "guestunmount {mount}".to_string(),
+        ),
+    }
+}
+
+/// the shell command of a mount or unmount helper, where the image and mount folder are
+/// given to the shell as arguments, or on Windows as variables, rather than put in the
+/// command line, so a quote or other character of the shell in a path is not run. The
+/// `{image}` and `{mount}` of the template, with any quotes around them, are replaced by
+/// the quoted variable, so they must not be inside other quotes, i.e. `'{mount}.ewf'`
+fn helper_command(template: &str, image: &str, mount_dir: &str) -> Command {
+    #[cfg(not(windows))]
+    let vars = [("{image}", "\"$1\""), ("{mount}", "\"$2\"")];
+    #[cfg(windows)]
+    let vars = [("{image}", "\"!WISKESS_IMAGE!\""), ("{mount}", "\"!WISKESS_MOUNT!\"")];
+    let mut script = template.to_string();
+    for (placeholder, var) in vars {
+        for quote in ["'", "\"", ""] {
+            script = script.replace(&format!("{}{}{}", quote, placeholder, quote), var);
+        }
+    }
+    #[cfg(not(windows))]
+    {
+        let mut command = execute::shell(script);
+        command.args(["wiskess", image, mount_dir]);
+        command
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        // the delayed expansion of cmd puts in the variables after the line is parsed,
+        // and /s only strips the outer quotes, so the quotes of the line are kept
+        let mut command = Command::new("cmd.exe");
+        command.args(["/v:on", "/s", "/c"]).raw_arg(format!("\"{}\"", script)).env("WISKESS_IMAGE", image).env("WISKESS_MOUNT", mount_dir);
+        command
+    }
+}
+
+/// run the mount or unmount helper in a shell, logging its output
+fn run_helper(template: &str, image: &str, mount_dir: &str, out_log: &String) -> bool {
+    let command = template.replace("{image}", image).replace("{mount}", mount_dir);
+    file_ops::log_msg(out_log, format!("[ ] Running: {}", command));
+    match helper_command(template, image, mount_dir).output() {
+        Ok(output) => {
+            for out in [&output.stdout, &output.stderr] {
+                if !out.is_empty() {
+                    file_ops::log_msg(out_log, String::from_utf8_lossy(out).trim().to_string());
+                }
+            }
+            output.status.success()
+        },
+        Err(e) => {
+            file_ops::log_msg(out_log, format!("[!] Unable to run: {}. Error: {}", command, e));
+            false
+        },
+    }
+}
+
+/// the drive letters that exist, to find the drive an image is mounted to on Windows
+fn drive_letters() -> Vec<String> {
+    ('D'..='Z').map(|l| format!("{}:", l)).filter(|d| Path::new(&format!("{}\\", d)).exists()).collect()
+}
+
+/// mount a disk image read-only with the mount helper, to run wiskess against it.
+/// The helpers are templates of `{image}` and `{mount}`, the temp folder it is mounted
+/// to. If the helper mounts it to a new drive letter instead, the drive that has the
+/// Windows folder is used
+///
+/// Args:
+/// * `image` - the file path to the disk image, i.e. the E01
+/// * `mount_command` - the mount helper, empty for the default of the OS
+/// * `unmount_command` - the unmount helper, empty for the default of the OS
+/// * `run_id` - the id of the run, to name the temp mount folder
+/// * `tool_path` - the path to the tools, for the default helper on Windows
+/// * `out_log` - the file path to the wiskess log
+///
+/// returns the mounted image, which is unmounted when dropped, or the reason it could not be mounted
+pub fn mount_image(image: &str, mount_command: &str, unmount_command: &str, run_id: &str, tool_path: &str, out_log: &String) -> Result<Mounted, String> {
+    let (default_mount, default_unmount) = default_helpers(image, tool_path);
+    let mount_command = if mount_command.is_empty() { default_mount } else { mount_command.to_string() };
+    let unmount_command = if unmount_command.is_empty() { default_unmount } else { unmount_command.to_string() };
+
+    let mount_dir = std::env::temp_dir().join(format!("wiskess_mount_{}", run_id)).display().to_string();
+    file_ops::make_folders(Path::new(&mount_dir));
+    let drives_before = drive_letters();
+
+    file_ops::log_msg(out_log, format!("[ ] Mounting the image {} read-only", image));
+    if !run_helper(&mount_command, image, &mount_dir, out_log) {
+        run_helper(&unmount_command, image, &mount_dir, out_log);
+        let _ = std::fs::remove_dir(&mount_dir);
+        return Err(format!("the mount helper failed for {}, see the log", image));
+    }
+
+    // wait for the files to be in the mount folder, or a new drive with the Windows folder
+    let start = Instant::now();
+    let path = loop {
+        if std::fs::read_dir(&mount_dir).is_ok_and(|mut d| d.next().is_some()) {
+            break Some(mount_dir.to_string());
+        }
+        let new_drive = drive_letters().into_iter()
+            .filter(|d| !drives_before.contains(d))
+            .find(|d| Path::new(&format!("{}\\Windows", d)).is_dir());
+        if new_drive.is_some() || start.elapsed() > MOUNT_WAIT {
+            break new_drive;
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    };
+
+    let mounted = Mounted {
+        path: path.unwrap_or_default(),
+    };
+    *MOUNT.lock().unwrap_or_else(|e| e.into_inner()) = Some(Mount {
+        path: mounted.path.to_string(),
+        image: image.to_string(),
+        mount_dir,
+        unmount: unmount_command,
+        out_log: out_log.to_string(),
+    });
+    if mounted.path.is_empty() {
+        return Err(format!("no files found where {} was mounted, after {} seconds", image, MOUNT_WAIT.as_secs()));
+    }
+    file_ops::log_msg(out_log, format!("[+] Mounted the image {} read-only at {}", image, &mounted.path));
+    Ok(mounted)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn helper_paths_are_not_run_by_the_shell() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("host'; touch pwned; echo '.dd");
+        let mount_dir = dir.path().join("mnt \"$(touch pwned)\"");
+        let template = "printf '%s\\n%s' {image} \"{mount}\"";
+        let output = helper_command(template, &image.display().to_string(), &mount_dir.display().to_string())
+            .current_dir(dir.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), format!("{}\n{}", image.display(), mount_dir.display()));
+        assert!(!dir.path().join("pwned").exists());
+    }
+}
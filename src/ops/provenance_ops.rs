@@ -0,0 +1,141 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+use super::file_ops;
+use super::summary_ops::{CmdResult, Status};
+
+/// The lineage of an output file, from the artefacts and outputs the command read, with
+/// the tool, its version and the command line that made it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Provenance {
+    pub output: String,
+    pub size_bytes: u64,
+    pub name: String,
+    pub stage: String,
+    pub inputs: Vec<String>,
+    pub tool: String,
+    pub version: String,
+    pub command: String,
+}
+
+/// the manifest in the tool path of the version of each tool installed by the setup, by the
+/// folder of the tool, i.e. `{"hayabusa": "v2.13.0"}`
+pub const VERSIONS_FILE: &str = "versions.json";
+
+/// read the versions of the tools installed, none if there is no manifest
+fn read_versions(tool_path: &str) -> HashMap<String, String> {
+    std::fs::read_to_string(Path::new(tool_path).join(VERSIONS_FILE))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// the version of a tool in the manifest, by the folder it is in under the tool path, else
+/// the name of the binary for a tool on the PATH, or unknown if it is not in the manifest
+fn tool_version(binary: &str, tool_path: &str, versions: &HashMap<String, String>) -> String {
+    let binary = Path::new(binary);
+    let name = match binary.strip_prefix(tool_path) {
+        Ok(rel) if !tool_path.is_empty() => rel.components().next().map(|c| c.as_os_str().to_string_lossy().to_string()),
+        _ => binary.file_stem().map(|s| s.to_string_lossy().to_string()),
+    };
+    name.and_then(|n| versions.get(&n).cloned()).unwrap_or_else(|| "unknown".to_string())
+}
+
+/// the provenance of each output file of the commands that ran successfully, where the
+/// version of each tool is read from the versions manifest of the tools installed
+///
+/// Args:
+/// * `commands` - the results of the commands, with what each ran and read
+/// * `tool_path` - the path of the tools, with the versions manifest
+pub fn provenance(commands: &[CmdResult], tool_path: &str) -> Vec<Provenance> {
+    let versions = read_versions(tool_path);
+    let mut records = Vec::new();
+    for cmd in commands.iter().filter(|c| c.status == Status::Success && !c.binary.is_empty()) {
+        let version = tool_version(&cmd.binary, tool_path, &versions);
+        for output in file_ops::find_files_glob(&cmd.output) {
+            records.push(Provenance {
+                size_bytes: std::fs::metadata(&output).map(|m| m.len()).unwrap_or(0),
+                output,
+                name: cmd.name.to_string(),
+                stage: cmd.stage.to_string(),
+                inputs: cmd.inputs.clone(),
+                tool: cmd.binary.to_string(),
+                version: version.to_string(),
+                command: cmd.command.to_string(),
+            });
+        }
+    }
+    records
+}
+
+/// quote a label for Graphviz
+fn dot_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// the provenance as a Graphviz graph, of the inputs to each command and the command to
+/// its outputs, so the outputs of a command read by another are linked in a chain
+fn provenance_dot(records: &[Provenance]) -> String {
+    let mut dot = String::from("digraph provenance {\n  rankdir=LR;\n  node [shape=box];\n");
+    let mut edges = Vec::new();
+    for record in records {
+        let command = format!("{}/{}", record.stage, record.name);
+        edges.push(format!(
+            "  {} [shape=ellipse, label={}];\n",
+            dot_quote(&command),
+            dot_quote(&format!("{} ({} {})", record.name, record.tool, record.version))
+        ));
+        for input in &record.inputs {
+            edges.push(format!("  {} -> {};\n", dot_quote(input), dot_quote(&command)));
+        }
+        edges.push(format!("  {} -> {};\n", dot_quote(&command), dot_quote(&record.output)));
+    }
+    let mut seen = HashSet::new();
+    for edge in edges.into_iter().filter(|e| seen.insert(e.to_string())) {
+        dot.push_str(&edge);
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// write the provenance of the outputs of the run to `out_path/provenance.json`, and as a
+/// Graphviz graph to `out_path/provenance.dot` if asked, for an auditable lineage of
+/// each result
+///
+/// Args:
+/// * `commands` - the results of the commands, with what each ran and read
+/// * `out_path` - the wiskess output folder
+/// * `tool_path` - the path of the tools, with the versions manifest
+/// * `dot` - also write the Graphviz graph
+///
+/// returns the file path of the provenance, or the reason it could not be written
+pub fn write_provenance(commands: &[CmdResult], out_path: &str, tool_path: &str, dot: bool) -> Result<String, String> {
+    let records = provenance(commands, tool_path);
+    let json_path = Path::new(out_path).join("provenance.json");
+    let json = serde_json::to_string_pretty(&records).map_err(|e| e.to_string())?;
+    std::fs::write(&json_path, json).map_err(|e| format!("unable to write {}. Error: {}", json_path.display(), e))?;
+    if dot {
+        let dot_path = Path::new(out_path).join("provenance.dot");
+        std::fs::write(&dot_path, provenance_dot(&records)).map_err(|e| format!("unable to write {}. Error: {}", dot_path.display(), e))?;
+    }
+    Ok(json_path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_version_is_read_from_the_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let tool_path = dir.path().display().to_string();
+        std::fs::write(dir.path().join(VERSIONS_FILE), r#"{"hayabusa": "v2.13.0", "chainsaw": "v2.9.1"}"#).unwrap();
+        let versions = read_versions(&tool_path);
+        let hayabusa = dir.path().join("hayabusa").join("hayabusa.exe").display().to_string();
+        assert_eq!(tool_version(&hayabusa, &tool_path, &versions), "v2.13.0");
+        assert_eq!(tool_version("chainsaw", &tool_path, &versions), "v2.9.1");
+        assert_eq!(tool_version("python3", &tool_path, &versions), "unknown");
+        assert!(read_versions(&dir.path().join("none").display().to_string()).is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+use std::path::Path;
+use handlebars::Handlebars;
+use serde_json::{json, Value};
+
+use crate::art::paths;
+use super::file_ops;
+use super::summary_ops::{self, Status};
+
+/// the folder in the output of the reports rendered from the templates
+pub const REPORT_DIR: &str = "Reports";
+
+/// the variables of the context, as their paths, to list in the error of an unknown
+/// one. The fields of the items of a list are shown as `list[].field`
+fn variables(value: &Value, prefix: &str, vars: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let path = if prefix.is_empty() { key.to_string() } else { format!("{}.{}", prefix, key) };
+                vars.push(path.to_string());
+                variables(value, &path, vars);
+            }
+        },
+        Value::Array(list) => {
+            if let Some(item) = list.first() {
+                variables(item, &format!("{}[]", prefix), vars);
+            }
+        },
+        _ => (),
+    }
+}
+
+/// render a template with the variables of the context by Handlebars, i.e. `{{run_id}}`,
+/// the blocks `{{#each commands}}..{{/each}}` and `{{#if failures}}..{{else}}..{{/if}}`,
+/// escaping the variables for html unless in `{{{triple}}}` braces
+///
+/// Args:
+/// * `template` - the text of the template
+/// * `context` - the variables of the run
+/// * `html` - escape the variables, as the report is html
+///
+/// returns the report, or the reason it could not be rendered, with the variables that can be used
+pub fn render_template(template: &str, context: &Value, html: bool) -> Result<String, String> {
+    let mut handlebars = Handlebars::new();
+    // an unknown variable is an error, rather than rendered as empty
+    handlebars.set_strict_mode(true);
+    if !html {
+        handlebars.register_escape_fn(handlebars::no_escape);
+    }
+    handlebars.render_template(template, context).map_err(|e| {
+        let mut vars = Vec::new();
+        variables(context, "", &mut vars);
+        format!("{}, the variables are: {}", e, vars.join(", "))
+    })
+}
+
+/// the variables of the run for the templates: the fields of the run summary, with the
+/// counts of the command statuses, the commands that failed, the output files, the
+/// outputs that are empty, and the missing artefacts
+///
+/// Args:
+/// * `out_path` - the wiskess output folder, that has the run_summary.json
+pub fn report_context(out_path: &str) -> Result<Value, String> {
+    let summary = summary_ops::read_summary(out_path)?;
+    let count = |status: Status| summary.commands.iter().filter(|c| c.status == status).count();
+    let failures: Vec<_> = summary.commands.iter().filter(|c| matches!(c.status, Status::Failed | Status::Killed)).collect();
+    let mut outputs = Vec::new();
+    let mut empty_outputs = Vec::new();
+    for cmd in summary.commands.iter().filter(|c| !c.output.is_empty()) {
+        let files = file_ops::find_files_glob(&cmd.output);
+        if cmd.status == Status::Success && files.iter().all(|o| file_ops::line_count(o) <= 1) {
+            empty_outputs.push(cmd.name.to_string());
+        }
+        for file in files {
+            outputs.push(json!({
+                "name": cmd.name,
+                "stage": cmd.stage,
+                "category": cmd.category,
+                "file": file,
+                "size_bytes": std::fs::metadata(&file).map(|m| m.len()).unwrap_or(0),
+                "rows": file_ops::line_count(&file),
+            }));
+        }
+    }
+    let missing_artefacts = std::fs::read_to_string(Path::new(out_path).join(paths::MISSING_FILE)).ok()
+        .and_then(|m| serde_json::from_str(&m).ok())
+        .unwrap_or(json!([]));
+
+    let mut context = serde_json::to_value(&summary).map_err(|e| e.to_string())?;
+    let fields = json!({
+        "counts": {
+            "total": summary.commands.len(),
+            "success": count(Status::Success),
+            "failed": count(Status::Failed),
+            "killed": count(Status::Killed),
+            "skipped": count(Status::Skipped),
+        },
+        "failures": failures,
+        "outputs": outputs,
+        "empty_outputs": empty_outputs,
+        "missing_artefacts": missing_artefacts,
+        "generated": file_ops::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+    });
+    if let (Some(context), Value::Object(fields)) = (context.as_object_mut(), fields) {
+        context.extend(fields);
+    }
+    Ok(context)
+}
+
+/// render a report of the run in the output folder from a template, i.e. a branded html
+/// or markdown report, written to the Reports folder with the name of the template. The
+/// variables are escaped for html if the template is html
+///
+/// Args:
+/// * `out_path` - the wiskess output folder, that has the run_summary.json
+/// * `template` - the file path to the template
+///
+/// returns the file path of the report, or the reason it could not be rendered
+pub fn render_report(out_path: &str, template: &str) -> Result<String, String> {
+    let template_str = std::fs::read_to_string(template)
+        .map_err(|e| format!("unable to read the template {}. Error: {}", template, e))?;
+    // the report is named by the template, without an extension of the template itself
+    let name = Path::new(template).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let name = [".hbs", ".tmpl", ".tpl"].iter().find_map(|ext| name.strip_suffix(ext)).unwrap_or(&name).to_string();
+    let html = name.ends_with(".html") || name.ends_with(".htm");
+
+    let context = report_context(out_path)?;
+    let report = render_template(&template_str, &context, html).map_err(|e| format!("{} {}", template, e))?;
+    let report_dir = Path::new(out_path).join(REPORT_DIR);
+    file_ops::make_folders(&report_dir);
+    let report_path = report_dir.join(name);
+    std::fs::write(&report_path, report).map_err(|e| format!("unable to write {}. Error: {}", report_path.display(), e))?;
+    Ok(report_path.display().to_string())
+}
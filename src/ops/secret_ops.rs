@@ -0,0 +1,57 @@
+use std::fmt;
+use std::process::Command;
+
+/// A value read from a secret store, which is kept in memory only. Debug
+/// hides the value, so it is not printed or logged by mistake
+#[derive(Clone)]
+pub struct Secret(String);
+
+impl Secret {
+    /// the plaintext of the secret, which must not be written to disk or the log
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+/// check the uri is of an Azure Key Vault secret, i.e.
+/// https://myvault.vault.azure.net/secrets/mysecret with an optional version
+fn is_key_vault_uri(uri: &str) -> bool {
+    let re = regex::Regex::new(r"^https://[\w-]+\.vault\.azure\.net/secrets/[\w-]+(/[\w-]+)?/?$").unwrap();
+    re.is_match(uri)
+}
+
+/// get the value of a secret from an Azure Key Vault, using the Azure CLI so it
+/// authenticates as the logged in user or managed identity. The value is read
+/// from the stdout of the CLI into memory, and is never written to disk.
+///
+/// Args:
+/// * `uri` - the secret identifier, i.e. https://myvault.vault.azure.net/secrets/mysecret
+///
+/// returns the secret, or the reason it could not be read
+pub fn get_secret(uri: &str) -> Result<Secret, String> {
+    if !is_key_vault_uri(uri) {
+        return Err(format!("'{}' is not an Azure Key Vault secret uri, i.e. https://myvault.vault.azure.net/secrets/mysecret", uri));
+    }
+    let output = Command::new("az")
+        .args(["keyvault", "secret", "show", "--id", uri, "--query", "value", "-o", "json"])
+        .output()
+        .map_err(|e| format!("unable to run the Azure CLI (az), is it installed? Error: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "unable to read the secret {}. Error: {}",
+            uri,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    // the value is a json string, so multi-line secrets are read exactly
+    match serde_json::from_slice::<String>(&output.stdout) {
+        Ok(value) => Ok(Secret(value)),
+        Err(_) => Err(format!("the secret {} has no value", uri)),
+    }
+}
@@ -0,0 +1,148 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+use super::{exe_ops, file_ops};
+
+/// the time between the writes of the status of the run
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// the file in the output folder with the status of the run
+pub const STATUS_FILE: &str = "status.json";
+
+/// The progress of the run, written to `out_path/status.json` for an orchestrator to poll
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RunStatus {
+    pub run_id: String,
+    pub case_name: String,
+    /// running, paused, finished or crashed
+    pub state: String,
+    pub stage: String,
+    pub running: Vec<String>,
+    pub completed: usize,
+    pub total: usize,
+    pub started: String,
+    pub updated: String,
+}
+
+/// the status of the run, updated as the stages and commands start and finish
+static STATUS: Mutex<RunStatus> = Mutex::new(RunStatus {
+    run_id: String::new(),
+    case_name: String::new(),
+    state: String::new(),
+    stage: String::new(),
+    running: Vec::new(),
+    completed: 0,
+    total: 0,
+    started: String::new(),
+    updated: String::new(),
+});
+
+/// the output folder of the run once the status is written, for the status to be set as crashed
+static OUT_PATH: OnceLock<String> = OnceLock::new();
+
+/// set once the run has crashed or was stopped, so the status is not written again as running
+static CRASHED: AtomicBool = AtomicBool::new(false);
+
+fn status() -> std::sync::MutexGuard<'static, RunStatus> {
+    STATUS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// set the stage that is running, i.e. wiskers or validation
+pub fn set_stage(stage: &str) {
+    status().stage = stage.to_string();
+}
+
+/// add the commands selected to run to the total, for each pass of the stages
+pub fn add_total(commands: usize) {
+    status().total += commands;
+}
+
+/// mark a command as running
+pub fn command_started(name: &str) {
+    status().running.push(name.to_string());
+}
+
+/// mark a command as completed, whether it ran, failed or was skipped
+pub fn command_done(name: &str) {
+    let mut status = status();
+    if let Some(i) = status.running.iter().position(|r| r == name) {
+        status.running.remove(i);
+    }
+    status.completed += 1;
+}
+
+/// write the status to the output folder, to a temp file renamed over the last, so it is
+/// never read half written. Once crashed, only the crashed state is written
+fn write_status(out_path: &str, state: &str) {
+    // hold the status while it is written, so a crash is not written over by the writer thread
+    let mut status = status();
+    if CRASHED.load(Ordering::SeqCst) && state != "crashed" {
+        return;
+    }
+    status.state = state.to_string();
+    status.updated = file_ops::now().to_rfc3339();
+    let json = serde_json::to_string_pretty(&*status).expect("Unable to serialise the run status.");
+    let status_path = Path::new(out_path).join(STATUS_FILE);
+    let tmp_path = Path::new(out_path).join(format!("{}.tmp", STATUS_FILE));
+    if std::fs::write(&tmp_path, json).is_ok() {
+        let _ = std::fs::rename(&tmp_path, &status_path);
+    }
+}
+
+/// write the status as crashed, when the run panics, is stopped by Ctrl-C or exits on an
+/// error, if the status of the run has started to be written
+pub fn write_crashed() {
+    if let Some(out_path) = OUT_PATH.get() {
+        CRASHED.store(true, Ordering::SeqCst);
+        write_status(out_path, "crashed");
+    }
+}
+
+/// Writes the status of the run in a thread, until it is stopped
+pub struct StatusWriter {
+    out_path: String,
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+/// start writing the status of the run to `out_path/status.json` every STATUS_INTERVAL,
+/// with the stage, the commands running and the number completed of the total
+///
+/// Args:
+/// * `out_path` - the wiskess output folder
+/// * `run_id` - the correlation id of the run
+/// * `case_name` - the name of the case the run is for
+pub fn start(out_path: &str, run_id: &str, case_name: &str) -> StatusWriter {
+    {
+        let mut status = status();
+        status.run_id = run_id.to_string();
+        status.case_name = case_name.to_string();
+        status.started = file_ops::now().to_rfc3339();
+    }
+    let _ = OUT_PATH.set(out_path.to_string());
+    let stop = Arc::new(AtomicBool::new(false));
+    let (out_path_c, stop_c) = (out_path.to_string(), stop.clone());
+    let handle = thread::spawn(move || {
+        while !stop_c.load(Ordering::SeqCst) {
+            let paused = exe_ops::PAUSED.load(Ordering::SeqCst) || Path::new(&out_path_c).join(exe_ops::PAUSE_FILE).exists();
+            write_status(&out_path_c, if paused { "paused" } else { "running" });
+            thread::park_timeout(STATUS_INTERVAL);
+        }
+    });
+    StatusWriter { out_path: out_path.to_string(), stop, handle }
+}
+
+impl StatusWriter {
+    /// stop writing the status, writing it a last time as finished
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.handle.thread().unpark();
+        let _ = self.handle.join();
+        status().stage = String::new();
+        write_status(&self.out_path, "finished");
+    }
+}
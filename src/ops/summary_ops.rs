@@ -0,0 +1,924 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::OnceLock;
+use inquire::Confirm;
+use serde::{Deserialize, Serialize};
+use tabled::{Tabled, Table};
+use tabled::settings::Style;
+
+use crate::configs::config;
+use super::{file_ops, ioc_ops, status_ops, usage_ops};
+
+/// Summary of a wiskess run, written to `out_path/run_summary.json`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RunSummary {
+    pub run_id: String,
+    #[serde(default)]
+    pub case_name: String,
+    pub start: String,
+    pub end: String,
+    pub duration: String,
+    pub data_source: String,
+    pub out_path: String,
+    #[serde(default)]
+    pub time_boxed: bool,
+    #[serde(default)]
+    pub commands: Vec<CmdResult>,
+    #[serde(default)]
+    pub usage: usage_ops::Usage,
+}
+
+/// The outcome of a command
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Success,
+    Failed,
+    Killed,
+    Skipped,
+}
+
+/// The kind of reason a command was skipped, for `--explain` to branch on rather than its text
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasonKind {
+    #[default]
+    Other,
+    UpToDate,
+    ArtefactMissing,
+    InputMissing,
+}
+
+/// The reason of the result of a command, with its kind
+pub struct Reason {
+    pub kind: ReasonKind,
+    pub text: String,
+}
+
+impl Reason {
+    pub fn new(kind: ReasonKind, text: impl Into<String>) -> Reason {
+        Reason { kind, text: text.into() }
+    }
+}
+
+impl From<String> for Reason {
+    fn from(text: String) -> Reason {
+        Reason::new(ReasonKind::Other, text)
+    }
+}
+
+/// The result of a command that wiskess ran or skipped
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CmdResult {
+    pub stage: String,
+    pub name: String,
+    pub status: Status,
+    pub exit_code: Option<i32>,
+    pub duration_s: f64,
+    pub reason: String,
+    #[serde(default)]
+    pub output: String,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub binary: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub command: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub script: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub request: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+    #[serde(skip)]
+    pub end: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub reason_kind: ReasonKind,
+}
+
+/// the marker written to the output folder when wiskess crashes or is stopped
+pub const CRASH_FILE: &str = "CRASHED";
+
+/// the output folder, run id and log of the running wiskess, for the crash marker
+pub static CRASH_INFO: OnceLock<(String, String, String)> = OnceLock::new();
+
+/// write the `CRASHED` marker to the output folder of the run with the reason, so
+/// whipped knows to upload the partial output and the reviewer that it is incomplete.
+/// The state in status.json is set as crashed too
+///
+/// Args:
+/// * `reason` - why the run stopped, i.e. the panic message
+pub fn write_crashed(reason: &str) {
+    status_ops::write_crashed();
+    if let Some((out_path, run_id, out_log)) = CRASH_INFO.get() {
+        let msg = format!(
+            "Wiskess run {} stopped at {}, the output at {} is partial. Reason: {}",
+            run_id,
+            file_ops::now().with_timezone(&chrono::Local).format("%Y-%m-%dT%H%M%S"),
+            out_path,
+            reason
+        );
+        let _ = std::fs::write(Path::new(out_path).join(CRASH_FILE), &msg);
+        file_ops::log_msg(out_log, format!("[!] {}", msg));
+    }
+}
+
+/// sort the results of the commands by their stage, then their place in the config, so the
+/// summary lists them in the same order each run rather than the order they finished in.
+/// The instances of a command keep the order they ran in
+///
+/// Args:
+/// * `commands` - the results of the commands
+/// * `config` - the config of all stages, of which the order is used
+pub fn sort_results(commands: &mut [CmdResult], config: &config::Config) {
+    let stages = config.stages();
+    commands.sort_by_key(|c| stages.iter().enumerate()
+        .find(|(_, (stage, _))| *stage == c.stage)
+        .map(|(i, (_, func))| (i, func.iter().position(|w| w.name == c.name).unwrap_or(usize::MAX)))
+        .unwrap_or((stages.len(), usize::MAX)));
+}
+
+/// write the summary of the run as json to `out_path/run_summary.json`
+///
+/// Args:
+/// * `summary` - the summary of the run, including the run id and duration
+/// * `out_log` - the file path to the wiskess log
+pub fn write_summary(summary: &RunSummary, out_log: &String) {
+    let summary_path = Path::new(&summary.out_path).join("run_summary.json");
+    let summary_str = serde_json::to_string_pretty(summary).expect("Unable to serialise the run summary.");
+    match std::fs::write(&summary_path, summary_str) {
+        Ok(_) => file_ops::log_msg(out_log, format!("[ ] Run summary written to: {}", summary_path.display())),
+        Err(e) => file_ops::log_msg(out_log, format!("[!] Unable to write run summary: {}. Error: {}", summary_path.display(), e)),
+    }
+}
+
+/// write the results of the commands as csv to `out_path/results.csv`, one row
+/// per command, for reviewing the run in a spreadsheet
+///
+/// Args:
+/// * `config` - the config of all stages, to get the binary and output of each command
+/// * `main_args` - the arguments specified from the main.rs, i.e. results, out_path and tool_path
+pub fn write_results_csv(config: &config::Config, main_args: &config::MainArgs) -> csv::Result<()> {
+    let results_path = Path::new(&main_args.out_path).join("results.csv");
+    let mut writer = csv::Writer::from_path(&results_path)?;
+    writer.write_record(["stage", "name", "binary", "status", "exit_code", "duration_s", "output_file", "output_rows"])?;
+    for result in main_args.results.lock().unwrap().iter() {
+        let (binary, output_file) = match config.find(&result.name) {
+            Some(wisker) => (
+                wisker.binary.path().replace("{tool_path}", &main_args.tool_path),
+                wisker.out_file(&main_args.out_path),
+            ),
+            None => (String::new(), String::new()),
+        };
+        // the rows of a csv output don't include the header
+        let output_rows = match output_file.is_empty() {
+            true => 0,
+            false => {
+                let lines = file_ops::line_count(&output_file);
+                if output_file.ends_with(".csv") { lines.saturating_sub(1) } else { lines }
+            },
+        };
+        writer.write_record([
+            result.stage.as_str(),
+            result.name.as_str(),
+            binary.as_str(),
+            &format!("{:?}", result.status).to_lowercase(),
+            &result.exit_code.map(|c| c.to_string()).unwrap_or_default(),
+            &format!("{:.3}", result.duration_s),
+            output_file.as_str(),
+            &output_rows.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    file_ops::log_msg(&main_args.out_log, format!("[ ] Results csv written to: {}", results_path.display()));
+    Ok(())
+}
+
+/// the decision and reason of a command that was run or skipped, for --explain
+fn explain_result(result: &CmdResult) -> String {
+    let reason = &result.reason;
+    match (&result.status, result.reason_kind) {
+        (Status::Success, _) => "ran".to_string(),
+        (Status::Failed | Status::Killed, _) if reason.is_empty() => format!("ran: {:?}", result.status).to_lowercase(),
+        (Status::Failed | Status::Killed, _) => format!("ran: {} ({})", format!("{:?}", result.status).to_lowercase(), reason),
+        (Status::Skipped, ReasonKind::UpToDate) => "skipped: up to date".to_string(),
+        (Status::Skipped, ReasonKind::ArtefactMissing) => format!("skipped: artefact missing ({})", reason),
+        (Status::Skipped, ReasonKind::InputMissing) => format!("blocked: dependency failed ({})", reason),
+        (Status::Skipped, ReasonKind::Other) => format!("skipped: {}", reason),
+    }
+}
+
+/// print and log why each command in the config ran or was skipped
+///
+/// Args:
+/// * `config` - the config of all stages, to explain every command in it
+/// * `main_args` - the arguments specified from the main.rs, i.e. results and tags
+/// * `only_stage` - the stages selected with --only-stage, empty is all stages
+pub fn explain(config: &config::Config, main_args: &config::MainArgs, only_stage: &[String]) {
+    let results = main_args.results.lock().unwrap();
+    let mut lines = vec!["[ ] Explain why each command ran or was skipped:".to_string()];
+    for (stage, func) in config.stages() {
+        for wisker in func {
+            let decision = match results.iter().find(|r| r.stage == stage && r.name == wisker.name) {
+                Some(result) => explain_result(result),
+                None if !only_stage.is_empty() && !only_stage.iter().any(|s| s == stage) => "skipped: filtered by --only-stage".to_string(),
+                None if !wisker.has_tags(&main_args.tags, main_args.tag_all) => "skipped: filtered by --tag".to_string(),
+                None if !wisker.selected(main_args) => "skipped: not a quick command, filtered by --quick".to_string(),
+                None => "not run".to_string(),
+            };
+            lines.push(format!("    {}/{}: {}", stage, wisker.name, decision));
+        }
+    }
+    let msg = lines.join("\n");
+    println!("{}", file_ops::redact(&msg));
+    file_ops::log_msg(&main_args.out_log, msg);
+}
+
+/// The number of commands by status
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct StatusCounts {
+    pub success: usize,
+    pub failed: usize,
+    pub killed: usize,
+    pub skipped: usize,
+}
+
+impl StatusCounts {
+    fn add(&mut self, status: &Status) {
+        match status {
+            Status::Success => self.success += 1,
+            Status::Failed => self.failed += 1,
+            Status::Killed => self.killed += 1,
+            Status::Skipped => self.skipped += 1,
+        }
+    }
+}
+
+/// The status of a run and its command counts, in the case overview
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RunOverview {
+    pub run_id: String,
+    #[serde(default)]
+    pub case_name: String,
+    pub data_source: String,
+    pub out_path: String,
+    pub start: String,
+    pub end: String,
+    pub duration: String,
+    pub status: String,
+    pub counts: StatusCounts,
+}
+
+/// A command that failed or was killed, in the case overview
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Failure {
+    pub run_id: String,
+    pub data_source: String,
+    pub stage: String,
+    pub name: String,
+    pub status: Status,
+    pub reason: String,
+}
+
+/// Overview of the runs of a case, written to `case_overview.json`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CaseOverview {
+    pub runs: Vec<RunOverview>,
+    pub total_duration: String,
+    pub counts: StatusCounts,
+    pub failures: Vec<Failure>,
+}
+
+/// convert a duration of H:M:S to seconds, 0 if it is not valid
+fn duration_secs(duration: &str) -> i64 {
+    duration.split(':').try_fold(0, |secs, part| part.parse::<i64>().ok().map(|p| secs * 60 + p)).unwrap_or(0)
+}
+
+/// read the summary of a run, from either the run_summary.json or the wiskess
+/// output folder that has it
+pub(crate) fn read_summary(run: &str) -> Result<RunSummary, String> {
+    let path = Path::new(run);
+    let path = if path.is_dir() { path.join("run_summary.json") } else { path.to_path_buf() };
+    let summary_str = std::fs::read_to_string(&path)
+        .map_err(|e| format!("unable to read {}. Error: {}", path.display(), e))?;
+    serde_json::from_str(&summary_str)
+        .map_err(|e| format!("unable to parse {}. Error: {}", path.display(), e))
+}
+
+/// the commands to run again with --retry-failed, which are those that failed or were killed
+/// in the previous run of the output folder, and the commands that depend on them
+///
+/// Args:
+/// * `config` - the config of the commands, for the commands that depend on those that failed
+/// * `out_path` - the wiskess output folder of the previous run, that has the run_summary.json
+///
+/// returns the names of the commands to retry, with the summary of the previous run
+pub fn retry_failed(config: &config::Config, out_path: &str) -> Result<(Vec<String>, RunSummary), String> {
+    let previous = read_summary(out_path)?;
+    let mut retry: Vec<String> = previous.commands.iter()
+        .filter(|c| matches!(c.status, Status::Failed | Status::Killed))
+        .map(|c| c.name.to_string())
+        .collect();
+    // add the dependents of the commands to retry, until there are no more
+    let commands: Vec<&config::Wiskers> = config.stages().into_iter().flat_map(|(_, func)| func.iter()).collect();
+    loop {
+        let dependents: Vec<String> = commands.iter()
+            .filter(|w| !retry.contains(&w.name) && w.depends_on.iter().any(|d| retry.contains(d)))
+            .map(|w| w.name.to_string())
+            .collect();
+        if dependents.is_empty() {
+            break;
+        }
+        retry.extend(dependents);
+    }
+    Ok((retry, previous))
+}
+
+/// escape the text to put in the html report
+pub(crate) fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// the case overview as a html page, with a table of the runs and of the failures
+fn overview_html(overview: &CaseOverview) -> String {
+    // the title has the case name if the runs are all of the same case
+    let mut cases: Vec<&str> = overview.runs.iter().map(|r| r.case_name.as_str()).collect();
+    cases.dedup();
+    let title = match cases[..] {
+        [case_name] if !case_name.is_empty() => format!("Wiskess Case Overview - {}", html_escape(case_name)),
+        _ => "Wiskess Case Overview".to_string(),
+    };
+    let mut html = format!("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{}</title>\n", title);
+    html.push_str("<style>body{font-family:sans-serif}table{border-collapse:collapse}td,th{border:1px solid #999;padding:4px 8px}</style>\n</head>\n<body>\n");
+    html.push_str(&format!("<h1>{}</h1>\n", title));
+    html.push_str(&format!(
+        "<p>{} runs, total duration {} [H:M:S]. Commands: {} succeeded, {} failed, {} killed, {} skipped</p>\n",
+        overview.runs.len(), overview.total_duration,
+        overview.counts.success, overview.counts.failed, overview.counts.killed, overview.counts.skipped
+    ));
+    html.push_str("<h2>Runs</h2>\n<table>\n<tr><th>Run id</th><th>Case</th><th>Data source</th><th>Start</th><th>Duration</th><th>Status</th><th>Success</th><th>Failed</th><th>Killed</th><th>Skipped</th></tr>\n");
+    for run in &overview.runs {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            html_escape(&run.run_id), html_escape(&run.case_name), html_escape(&run.data_source), html_escape(&run.start), html_escape(&run.duration),
+            html_escape(&run.status), run.counts.success, run.counts.failed, run.counts.killed, run.counts.skipped
+        ));
+    }
+    html.push_str("</table>\n<h2>Failures</h2>\n<table>\n<tr><th>Run id</th><th>Data source</th><th>Stage</th><th>Name</th><th>Status</th><th>Reason</th></tr>\n");
+    for failure in &overview.failures {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:?}</td><td>{}</td></tr>\n",
+            html_escape(&failure.run_id), html_escape(&failure.data_source), html_escape(&failure.stage),
+            html_escape(&failure.name), failure.status, html_escape(&failure.reason)
+        ));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// merge the summaries of several runs of a case into one overview, written to
+/// `out_path/case_overview.json` and `out_path/case_overview.html`
+///
+/// Args:
+/// * `runs` - the run_summary.json files, or the wiskess output folders that have them
+/// * `out_path` - the folder to write the case overview to
+///
+/// returns the case overview, or the reason a summary could not be read
+pub fn merge_runs(runs: &[String], out_path: &str) -> Result<CaseOverview, String> {
+    let mut overview = CaseOverview {
+        runs: Vec::new(),
+        total_duration: String::new(),
+        counts: StatusCounts::default(),
+        failures: Vec::new(),
+    };
+    let mut total_secs = 0;
+    for run in runs {
+        let summary = read_summary(run)?;
+        total_secs += duration_secs(&summary.duration);
+        let mut counts = StatusCounts::default();
+        for cmd in &summary.commands {
+            counts.add(&cmd.status);
+            overview.counts.add(&cmd.status);
+            if cmd.status == Status::Failed || cmd.status == Status::Killed {
+                overview.failures.push(Failure {
+                    run_id: summary.run_id.clone(),
+                    data_source: summary.data_source.clone(),
+                    stage: cmd.stage.clone(),
+                    name: cmd.name.clone(),
+                    status: cmd.status.clone(),
+                    reason: cmd.reason.clone(),
+                });
+            }
+        }
+        let status = if counts.failed + counts.killed > 0 {
+            "failed"
+        } else if summary.time_boxed {
+            "time_boxed"
+        } else {
+            "success"
+        };
+        overview.runs.push(RunOverview {
+            run_id: summary.run_id,
+            case_name: summary.case_name,
+            data_source: summary.data_source,
+            out_path: summary.out_path,
+            start: summary.start,
+            end: summary.end,
+            duration: summary.duration,
+            status: status.to_string(),
+            counts,
+        });
+    }
+    overview.total_duration = format!("{:0>2}:{:0>2}:{:0>2}", total_secs / 3600, (total_secs / 60) % 60, total_secs % 60);
+
+    file_ops::make_folders(Path::new(out_path));
+    let json_path = Path::new(out_path).join("case_overview.json");
+    let json_str = serde_json::to_string_pretty(&overview).expect("Unable to serialise the case overview.");
+    std::fs::write(&json_path, json_str).map_err(|e| format!("unable to write {}. Error: {}", json_path.display(), e))?;
+    let html_path = Path::new(out_path).join("case_overview.html");
+    std::fs::write(&html_path, overview_html(&overview)).map_err(|e| format!("unable to write {}. Error: {}", html_path.display(), e))?;
+    Ok(overview)
+}
+
+#[derive(Tabled)]
+struct OutputFile<'a> {
+    name: &'a str,
+    output_file: String,
+    size_bytes: u64,
+    rows: usize,
+}
+
+/// print the output files of the last run in the output folder, grouped by category,
+/// or by stage if the commands have no category, with the command that produced each,
+/// its size and row count
+///
+/// Args:
+/// * `out_path` - the wiskess output folder, that has the run_summary.json
+///
+/// returns the number of output files, or the reason the summary could not be read
+pub fn list_outputs(out_path: &str) -> Result<usize, String> {
+    let summary = read_summary(out_path)?;
+    let by_category = summary.commands.iter().any(|c| !c.category.is_empty());
+    // the commands without a category are listed last
+    let groups: Vec<&str> = match by_category {
+        true => {
+            let mut categories: Vec<&str> = summary.commands.iter().map(|c| c.category.as_str()).filter(|c| !c.is_empty()).collect();
+            categories.sort();
+            categories.dedup();
+            categories.push("");
+            categories
+        },
+        false => vec!["wiskers", "enrichers", "reporters"],
+    };
+    let mut total = 0;
+    for group in groups {
+        let in_group = |c: &&CmdResult| match by_category {
+            true => c.category == group,
+            false => c.stage == group,
+        };
+        let mut contents: Vec<OutputFile> = Vec::new();
+        for cmd in summary.commands.iter().filter(in_group).filter(|c| !c.output.is_empty()) {
+            for output_file in file_ops::find_files_glob(&cmd.output) {
+                let size_bytes = std::fs::metadata(&output_file).map(|m| m.len()).unwrap_or(0);
+                let rows = file_ops::line_count(&output_file);
+                contents.push(OutputFile { name: &cmd.name, output_file, size_bytes, rows });
+            }
+        }
+        if contents.is_empty() {
+            continue;
+        }
+        total += contents.len();
+        let mut table = Table::new(&contents);
+        table.with(Style::psql());
+        println!("{}", file_ops::redact(&format!("[+] {} outputs of {}:\n{}\n", contents.len(), if group.is_empty() { "no category" } else { group }, table)));
+    }
+    println!("{}", file_ops::redact(&format!("[+] {} output files of run {} in {}", total, summary.run_id, summary.out_path)));
+    Ok(total)
+}
+
+#[derive(Tabled)]
+struct StageCounts<'a> {
+    stage: &'a str,
+    success: usize,
+    failed: usize,
+    killed: usize,
+    skipped: usize,
+}
+
+#[derive(Tabled)]
+struct FailedCommand<'a> {
+    stage: &'a str,
+    name: &'a str,
+    status: String,
+    reason: &'a str,
+}
+
+/// print the summary of the last run in the output folder again, without reprocessing.
+/// This has its duration, the counts of each stage, the commands that failed or were
+/// killed, and the verdict of validating the outputs of the commands that succeeded
+///
+/// Args:
+/// * `out_path` - the wiskess output folder, that has the run_summary.json
+///
+/// returns the percent of the commands that ran that are valid, where a run that crashed
+/// is not, or the reason if the summary could not be read
+pub fn print_summary(out_path: &str) -> Result<f64, String> {
+    let summary = read_summary(out_path)?;
+    println!("{}", file_ops::redact(&format!("[ ] Run {} of {}, for case: {}", summary.run_id, summary.data_source, summary.case_name)));
+    println!("[ ] Started at {}, finished at {}, which took: {} [H:M:S]", summary.start, summary.end, summary.duration);
+    if summary.time_boxed {
+        println!("[!] The run was time-boxed by --max-runtime, some commands were skipped");
+    }
+    let crashed = std::fs::read_to_string(Path::new(out_path).join(CRASH_FILE));
+    if let Ok(crashed) = &crashed {
+        println!("{}", file_ops::redact(&format!("[!] {}", crashed.trim())));
+    }
+    if summary.usage.samples > 0 {
+        println!(
+            "[ ] Resources used: peak CPU {:.0}%, average CPU {:.0}%, peak memory {} MB, read {} MB, written {} MB",
+            summary.usage.peak_cpu_percent,
+            summary.usage.avg_cpu_percent,
+            summary.usage.peak_memory_mb,
+            summary.usage.bytes_read / 1024 / 1024,
+            summary.usage.bytes_written / 1024 / 1024
+        );
+    }
+
+    let mut counts: Vec<StageCounts> = Vec::new();
+    for stage in ["wiskers", "enrichers", "reporters"] {
+        let mut stage_counts = StatusCounts::default();
+        for cmd in summary.commands.iter().filter(|c| c.stage == stage) {
+            stage_counts.add(&cmd.status);
+        }
+        counts.push(StageCounts {
+            stage,
+            success: stage_counts.success,
+            failed: stage_counts.failed,
+            killed: stage_counts.killed,
+            skipped: stage_counts.skipped,
+        });
+    }
+    let mut table = Table::new(&counts);
+    table.with(Style::psql());
+    println!("{}", file_ops::redact(&format!("[+] Commands of each stage:\n{}\n", table)));
+
+    let failures: Vec<FailedCommand> = summary.commands.iter()
+        .filter(|c| matches!(c.status, Status::Failed | Status::Killed))
+        .map(|c| FailedCommand {
+            stage: &c.stage,
+            name: &c.name,
+            status: format!("{:?}", c.status).to_lowercase(),
+            reason: &c.reason,
+        })
+        .collect();
+    if !failures.is_empty() {
+        let mut table = Table::new(&failures);
+        table.with(Style::psql());
+        println!("{}", file_ops::redact(&format!("[!] {} commands failed or were killed:\n{}\n", failures.len(), table)));
+    }
+
+    // the outputs of the commands that succeeded are expected to have more than the header
+    let succeeded: Vec<&CmdResult> = summary.commands.iter()
+        .filter(|c| c.status == Status::Success && !c.output.is_empty())
+        .collect();
+    let empty: Vec<&str> = succeeded.iter()
+        .filter(|c| file_ops::find_files_glob(&c.output).iter().all(|o| file_ops::line_count(o) <= 1))
+        .map(|c| c.name.as_str())
+        .collect();
+    match empty.is_empty() {
+        true => println!("[+] Validation: the outputs of all {} commands that succeeded have content", succeeded.len()),
+        false => println!("{}", file_ops::redact(&format!("[!] Validation: {} of {} commands that succeeded have no output, or only a header: {:?}", empty.len(), succeeded.len(), empty))),
+    }
+
+    // the commands that ran are valid if they succeeded with more than the header
+    let ran = summary.commands.iter().filter(|c| c.status != Status::Skipped).count();
+    let valid_percent = match (ran, crashed.is_ok()) {
+        (_, true) => 0.0,
+        (0, false) => 100.0,
+        (_, false) => (ran - failures.len() - empty.len()) as f64 * 100.0 / ran as f64,
+    };
+    println!("[ ] Validation: {:.0}% of the {} commands that ran are valid", valid_percent, ran);
+    Ok(valid_percent)
+}
+
+#[derive(Tabled)]
+struct PreflightItem<'a> {
+    check: &'a str,
+    value: &'a str,
+}
+
+/// print the pre-flight summary of a run, and ask to confirm it before starting,
+/// unless silent
+///
+/// Args:
+/// * `items` - the name and value of each line of the summary
+/// * `out_log` - the file path to the wiskess log, None if there is no log yet
+/// * `silent` - don't ask to confirm, i.e. when run by whipped
+///
+/// returns true to start the run
+pub fn preflight(items: &[(&str, String)], out_log: Option<&String>, silent: bool) -> bool {
+    let contents: Vec<PreflightItem> = items.iter().map(|(check, value)| PreflightItem { check, value }).collect();
+    let mut table = Table::new(&contents);
+    table.with(Style::psql());
+    let msg = format!("[ ] Pre-flight summary:\n{}", table);
+    println!("{}\n", file_ops::redact(&msg));
+    if let Some(out_log) = out_log {
+        file_ops::log_msg(out_log, msg);
+    }
+    if silent {
+        return true;
+    }
+    let ans = Confirm::new("Start the run?")
+        .with_default(false)
+        .with_help_message("Check the summary is right before committing to the run.")
+        .prompt();
+    let start = matches!(ans, Ok(true));
+    match out_log {
+        Some(out_log) if !start => file_ops::log_msg(out_log, "[-] Run cancelled at the pre-flight summary".to_string()),
+        _ if !start => println!("[-] Run cancelled at the pre-flight summary"),
+        _ => (),
+    }
+    start
+}
+
+/// the free space in MB of the disk the path is on, by the longest mount point it is under
+pub fn free_space_mb(path: &str) -> Option<u64> {
+    volume_of(path).map(|(_, free_mb)| free_mb)
+}
+
+/// the mount point and free space in MB of the disk a path is on, where a path that doesn't
+/// exist yet is on the disk of the closest folder above it that does
+fn volume_of(path: &str) -> Option<(String, u64)> {
+    let path = Path::new(path).ancestors().find_map(|p| std::fs::canonicalize(p).ok())?;
+    let path = std::path::PathBuf::from(file_ops::simple_path(&path));
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    disks.list().iter()
+        .filter(|d| path.starts_with(d.mount_point()))
+        .max_by_key(|d| d.mount_point().as_os_str().len())
+        .map(|d| (d.mount_point().display().to_string(), d.available_space() / 1024 / 1024))
+}
+
+/// The free space of a volume that backs the paths of a run, i.e. the output and scratch
+pub struct VolumeSpace {
+    pub mount: String,
+    pub paths: Vec<&'static str>,
+    pub free_mb: Option<u64>,
+    pub min_mb: u64,
+}
+
+impl VolumeSpace {
+    /// the volume has less free space than the paths on it need
+    pub fn low(&self) -> bool {
+        self.free_mb.is_some_and(|free| free < self.min_mb)
+    }
+}
+
+impl std::fmt::Display for VolumeSpace {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let free = self.free_mb.map(|mb| format!("{} MB free", mb)).unwrap_or("unknown free space".to_string());
+        write!(f, "{} ({}) {}", self.mount, self.paths.join(", "), free)?;
+        if self.min_mb > 0 {
+            write!(f, ", needs {} MB", self.min_mb)?;
+        }
+        Ok(())
+    }
+}
+
+/// the free space of each volume backing the paths, checked independently as they may be on
+/// different disks. The paths on the same volume share its free space, so the minimum free
+/// space of the volume is the sum of what each of them needs
+///
+/// Args:
+/// * `paths` - the name of each path, i.e. output, with the path and the minimum free space in MB it needs
+pub fn volume_space(paths: &[(&'static str, &str, u64)]) -> Vec<VolumeSpace> {
+    let mut volumes: Vec<VolumeSpace> = Vec::new();
+    for (name, path, min_mb) in paths {
+        let (mount, free_mb) = match volume_of(path) {
+            Some((mount, free_mb)) => (mount, Some(free_mb)),
+            None => (path.to_string(), None),
+        };
+        match volumes.iter_mut().find(|v| v.mount == mount) {
+            Some(volume) => {
+                volume.paths.push(name);
+                volume.min_mb += min_mb;
+            },
+            None => volumes.push(VolumeSpace { mount, paths: vec![name], free_mb, min_mb: *min_mb }),
+        }
+    }
+    volumes
+}
+
+/// log the free space of each volume, warning of those below the free space they need
+///
+/// Args:
+/// * `volumes` - the free space of each volume, from `volume_space`
+/// * `policy` - fail to stop the run if a volume is low on space, else warn
+/// * `out_log` - the file path to the wiskess log, None if there is no log
+///
+/// returns false if the run should stop, as a volume is low on space with the fail policy
+pub fn check_free_space(volumes: &[VolumeSpace], policy: &str, out_log: Option<&String>) -> bool {
+    let mut ok = true;
+    for volume in volumes {
+        let msg = match volume.low() {
+            true => {
+                if policy == "fail" {
+                    ok = false;
+                }
+                format!("[!] Low free space on the volume {}", volume)
+            },
+            false => format!("[ ] Free space on the volume {}", volume),
+        };
+        match out_log {
+            Some(out_log) => file_ops::log_msg(out_log, msg),
+            None => println!("{}", file_ops::redact(&msg)),
+        }
+    }
+    ok
+}
+
+/// the items of the pre-flight summary of a wiskess run
+///
+/// Args:
+/// * `config` - the config of all stages, to count the commands that will run
+/// * `config_from` - where the config was read from, with any overlays
+/// * `main_args` - the arguments specified from the main.rs, i.e. the date window and tags
+/// * `data_paths` - a hash of the artefact name and filepath of it, or if it is missing or locked
+/// * `only_stage` - the stages selected with --only-stage, empty is all stages
+/// * `passes` - the quick passes of the run, None for all commands
+pub fn wiskess_preflight(
+    config: &config::Config,
+    config_from: &str,
+    main_args: &config::MainArgs,
+    data_paths: &HashMap<String, String>,
+    only_stage: &[String],
+    passes: &[Option<bool>]
+) -> Vec<(&'static str, String)> {
+    let mut profile = Vec::new();
+    if !main_args.tags.is_empty() {
+        profile.push(format!("tagged {} of {:?}", if main_args.tag_all { "all" } else { "any" }, main_args.tags));
+    }
+    match passes {
+        [Some(true)] => profile.push("quick".to_string()),
+        [Some(true), Some(false)] => profile.push("quick then full".to_string()),
+        _ => (),
+    }
+    if !only_stage.is_empty() {
+        profile.push(format!("only stages {:?}", only_stage));
+    }
+    if profile.is_empty() {
+        profile.push("all commands".to_string());
+    }
+
+    let iocs = match &main_args.iocs {
+        Some(secret) => format!("{} from a secret", ioc_ops::parse_iocs(secret.expose()).len()),
+        None => format!("{} in {}", ioc_ops::load_iocs(&main_args.ioc_file).len(), main_args.ioc_file),
+    };
+
+    let by_status = |status: &str| -> Vec<&str> {
+        let mut names: Vec<&str> = data_paths.iter()
+            .filter(|(name, path)| *name != "none" && path.as_str() == status)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort();
+        names
+    };
+    let (missing, locked) = (by_status("wiskess_none"), by_status("wiskess_locked"));
+    let found = data_paths.keys().filter(|n| *n != "none").count() - missing.len() - locked.len();
+    let mut artefacts = format!("found {}, missing {}", found, missing.len());
+    if !missing.is_empty() {
+        artefacts += &format!(" ({})", missing.join(", "));
+    }
+    if !locked.is_empty() {
+        artefacts += &format!(", locked {} ({})", locked.len(), locked.join(", "));
+    }
+
+    // the commands that will run in any pass, and the artefacts they read
+    let mut commands = Vec::new();
+    let mut inputs = HashSet::new();
+    for (stage, func) in config.stages() {
+        if !only_stage.is_empty() && !only_stage.iter().any(|s| s == stage) {
+            continue;
+        }
+        let selected: Vec<_> = func.iter()
+            .filter(|w| w.has_tags(&main_args.tags, main_args.tag_all) && passes.iter().any(|q| q.is_none_or(|q| q == w.quick)))
+            .collect();
+        for wisker in &selected {
+            inputs.extend([&wisker.input, &wisker.input_other].into_iter().filter_map(|i| data_paths.get(i)));
+        }
+        commands.push(format!("{} {}", stage, selected.len()));
+    }
+    let input_mb = inputs.iter()
+        .filter(|p| !p.is_empty() && !p.starts_with("wiskess_"))
+        .filter_map(|p| file_ops::art_snapshot(p))
+        .map(|(size, _)| size)
+        .sum::<u64>() / 1024 / 1024;
+    let free = match free_space_mb(&main_args.out_path) {
+        Some(mb) => format!("{} MB free", mb),
+        None => "unknown free space".to_string(),
+    };
+
+    vec![
+        ("config", config_from.to_string()),
+        ("profile", profile.join(", ")),
+        ("date window", format!("{} to {}", main_args.start_date, main_args.end_date)),
+        ("iocs", iocs),
+        ("artefacts", artefacts),
+        ("commands", commands.join(", ")),
+        ("disk", format!("about {} MB of artefacts to read, {} at the output", input_mb, free)),
+        ("output", main_args.out_path.to_string()),
+    ]
+}
+
+/// hide the SAS token or query of a cloud link, so it is not printed
+fn redact_link(link: &str) -> String {
+    match link.split_once('?') {
+        Some((url, _)) => format!("{}?<redacted>", url),
+        None => link.to_string(),
+    }
+}
+
+/// the items of the pre-flight summary of a whipped run
+///
+/// Args:
+/// * `args` - the whipped args, of the data sources and cloud links
+pub fn whipped_preflight(args: &config::WhippedArgs) -> Vec<(&'static str, String)> {
+    let sources = match std::fs::read_to_string(&args.data_source_list) {
+        Ok(list) => format!("{} in {}", list.lines().filter(|l| !l.trim().is_empty()).count(), args.data_source_list),
+        // hide the tokens of the data sources with their own in_link, after a |
+        Err(_) => args.data_source_list.split([',', '\n'])
+            .map(|entry| match entry.split_once('|') {
+                Some((source, link)) => format!("{} | {}", source.trim(), redact_link(link.trim())),
+                None => entry.trim().to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    let free = match free_space_mb(&args.local_storage) {
+        Some(mb) => format!("{} ({} MB free)", args.local_storage, mb),
+        None => format!("{} (unknown free space)", args.local_storage),
+    };
+    let mut items = vec![
+        ("config", args.config.to_string()),
+        ("artefacts", args.artefacts_config.to_string()),
+        ("data sources", sources),
+        ("date window", format!("{} to {}", args.start_date, args.end_date)),
+        ("iocs", format!("{} in {}", ioc_ops::load_iocs(&args.ioc_file).len(), args.ioc_file)),
+        ("local storage", free),
+        ("in link", match args.no_download {
+            true => "not downloading, processing the data in the local storage".to_string(),
+            false => redact_link(&args.in_link),
+        }),
+        ("out link", redact_link(&args.out_link)),
+    ];
+    if !args.out_suffix.is_empty() {
+        items.push(("output folders", format!("<data source>-Wiskess-{}", args.out_suffix)));
+    }
+    if args.storage_type == "custom" {
+        items.push(("storage", format!("download: {}, upload: {}", args.download_command, args.upload_command)));
+    }
+    if !args.output_root_link.is_empty() {
+        items.push(("output root link", redact_link(&args.output_root_link)));
+    }
+    if !args.include.is_empty() || !args.exclude.is_empty() {
+        items.push(("download filter", format!("include {:?}, exclude {:?}", args.include, args.exclude)));
+    }
+    if args.encrypt_output {
+        items.push(("encrypt", format!("with the key in {}", args.encrypt_key)));
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    #[test]
+    fn sort_results_is_independent_of_the_order_run() {
+        let config: config::Config = serde_yaml::from_str(r#"
+wiskers:
+  - {name: mft, binary: mft, args: '', outfolder: FileSystem, outfile: mft.csv, input: mft}
+  - {name: evtx, binary: evtx, args: '', outfolder: EventLogs, outfile: evtx.json, input: winevt}
+enrichers:
+  - {name: iocs, binary: rg, args: '', outfolder: IOC, outfile: iocs.txt, input: none}
+reporters:
+  - {name: timeline, binary: py, args: '', outfolder: Timeline, outfile: tln.csv, input: none}
+"#).unwrap();
+        let results: Vec<CmdResult> = [("reporters", "timeline"), ("wiskers", "evtx"), ("enrichers", "iocs"), ("wiskers", "mft"), ("intense", "thor")]
+            .iter()
+            .map(|(stage, name)| serde_json::from_value(serde_json::json!({
+                "stage": stage, "name": name, "status": "success", "exit_code": 0, "duration_s": 1.0, "reason": "",
+            })).unwrap())
+            .collect();
+        let mut first = results.clone();
+        let mut second = results;
+        first.shuffle(&mut StdRng::seed_from_u64(1));
+        second.shuffle(&mut StdRng::seed_from_u64(2));
+        sort_results(&mut first, &config);
+        sort_results(&mut second, &config);
+
+        let names = |results: &[CmdResult]| results.iter().map(|r| r.name.to_string()).collect::<Vec<_>>();
+        assert_eq!(names(&first), ["mft", "evtx", "iocs", "timeline", "thor"]);
+        assert_eq!(serde_json::to_string(&first).unwrap(), serde_json::to_string(&second).unwrap());
+    }
+}
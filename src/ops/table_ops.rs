@@ -0,0 +1,304 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use regex::Regex;
+
+use crate::configs::config::{self, Dedupe, PostFilter};
+use super::file_ops;
+
+/// the name of the column added to flag the rows of which the date could not be parsed
+const DATE_FLAG_COLUMN: &str = "wiskess_date_flag";
+
+/// the date formats tried when an output has a date column but no date format
+const DATE_FORMATS: [&str; 6] = [
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.fZ",
+    "%Y/%m/%d %H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%d/%m/%Y %H:%M:%S",
+];
+
+/// get the output files of the commands by name, resolving any glob in the outfile,
+/// with the date column and format of the command
+fn get_outputs(names: &[String], config: &config::Config, out_path: &str) -> Vec<(String, String, String, String)> {
+    let mut outputs = Vec::new();
+    for name in names {
+        if let Some(wisker) = config.find(name) {
+            let outfile = wisker.out_file(out_path);
+            for path in file_ops::find_files_glob(&outfile) {
+                outputs.push((name.to_string(), path, wisker.date_column.to_string(), wisker.date_format.to_string()));
+            }
+        }
+    }
+    outputs
+}
+
+/// parse the date of a row, with the date format of the output if set, otherwise
+/// trying RFC 3339 and the common formats. A date without a time is midnight
+///
+/// Args:
+/// * `value` - the value in the date column
+/// * `date_format` - the chrono strftime format, i.e. %Y-%m-%d %H:%M:%S, or empty to guess
+///
+/// returns the date, or None if it could not be parsed
+pub fn parse_date(value: &str, date_format: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    let formats: Vec<&str> = if date_format.is_empty() {
+        if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+            return Some(dt.naive_utc());
+        }
+        DATE_FORMATS.to_vec()
+    } else {
+        vec![date_format]
+    };
+    for format in formats {
+        if let Ok(dt) = DateTime::parse_from_str(value, format) {
+            return Some(dt.naive_utc());
+        }
+        if let Ok(dt) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(dt);
+        }
+        if let Ok(d) = NaiveDate::parse_from_str(value, format) {
+            return d.and_hms_opt(0, 0, 0);
+        }
+    }
+    if date_format.is_empty() {
+        return NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().and_then(|d| d.and_hms_opt(0, 0, 0));
+    }
+    None
+}
+
+/// the start and end of the date window, from the start of the start date to the
+/// end of the end date
+pub fn date_window(main_args: &config::MainArgs) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    let start = NaiveDate::parse_from_str(&main_args.start_date, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0)?;
+    let end = NaiveDate::parse_from_str(&main_args.end_date, "%Y-%m-%d").ok()?.and_hms_opt(23, 59, 59)?;
+    Some((start, end))
+}
+
+/// merge the csv outputs of several commands into one file, removing the rows
+/// that are duplicates by the key columns. The header is the union of the
+/// columns of the outputs, in the order they are first seen.
+/// 
+/// The outputs with a `date_column` set in the config are windowed to the start
+/// and end dates. The rows of which the date can't be parsed are kept, but flagged
+/// in the `wiskess_date_flag` column.
+///
+/// Args:
+/// * `dedupe` - the config of the outputs to merge, the key columns and the output file
+/// * `config` - the config of all stages, to look up the outputs by command name
+/// * `main_args` - the arguments specified from the main.rs, i.e. out_path, start_date and end_date
+///
+/// returns the number of rows written and the number of duplicates removed
+pub fn dedupe(dedupe: &Dedupe, config: &config::Config, main_args: &config::MainArgs) -> csv::Result<(usize, usize)> {
+    let outputs = get_outputs(&dedupe.inputs, config, &main_args.out_path);
+
+    // get the columns of all the outputs, so rows can be merged by column name
+    let mut header: Vec<String> = Vec::new();
+    let mut inputs = Vec::new();
+    for (name, path, date_column, date_format) in outputs {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(&path)?;
+        let columns: Vec<String> = reader.headers()?.iter().map(|h| h.to_string()).collect();
+        let mut missing: Vec<&String> = dedupe.key_columns.iter().filter(|k| !columns.contains(k)).collect();
+        if !date_column.is_empty() && !columns.contains(&date_column) {
+            missing.push(&date_column);
+        }
+        if !missing.is_empty() {
+            file_ops::log_msg(&main_args.out_log, format!(
+                "[!] Dedupe {}: skipping output of {} at {}, as it is missing the key columns: {:?}",
+                &dedupe.name, name, path, missing
+            ));
+            continue;
+        }
+        for column in &columns {
+            if !header.contains(column) {
+                header.push(column.to_string());
+            }
+        }
+        let date_index = columns.iter().position(|c| c == &date_column);
+        inputs.push((path, columns, date_index, date_format));
+    }
+    let window = date_window(main_args);
+    if inputs.iter().any(|(_, _, date_index, _)| date_index.is_some()) {
+        header.push(DATE_FLAG_COLUMN.to_string());
+    }
+
+    let folder_path = Path::new(&main_args.out_path).join(&dedupe.outfolder);
+    file_ops::make_folders(&folder_path);
+    let dedupe_path = folder_path.join(&dedupe.outfile);
+    let mut writer = csv::Writer::from_path(&dedupe_path)?;
+    writer.write_record(&header)?;
+
+    let key_index: Vec<usize> = dedupe.key_columns.iter()
+        .filter_map(|k| header.iter().position(|h| h == k))
+        .collect();
+    let mut seen = HashSet::new();
+    let (mut rows, mut removed, mut outside, mut unparsed) = (0, 0, 0, 0);
+    for (path, columns, date_index, date_format) in inputs {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(&path)?;
+        for record in reader.records() {
+            let record = record?;
+            // keep the rows in the date window, flagging those with a date that can't be parsed
+            let mut date_flag = "";
+            if let (Some(i), Some((start, end))) = (date_index, window) {
+                match parse_date(record.get(i).unwrap_or(""), &date_format) {
+                    Some(dt) if dt < start || dt > end => {
+                        outside += 1;
+                        continue;
+                    },
+                    Some(_) => {},
+                    None => {
+                        date_flag = "unparsed_date";
+                        unparsed += 1;
+                    },
+                }
+            }
+            // put the values of the row into the order of the merged header
+            let row: Vec<&str> = header.iter()
+                .map(|h| match h.as_str() {
+                    DATE_FLAG_COLUMN => date_flag,
+                    _ => columns.iter().position(|c| c == h).and_then(|i| record.get(i)).unwrap_or(""),
+                })
+                .collect();
+            let key = if key_index.is_empty() {
+                row.join("\u{1f}")
+            } else {
+                key_index.iter().map(|i| row[*i]).collect::<Vec<&str>>().join("\u{1f}")
+            };
+            if seen.insert(key) {
+                writer.write_record(&row)?;
+                rows += 1;
+            } else {
+                removed += 1;
+            }
+        }
+    }
+    writer.flush()?;
+
+    file_ops::log_msg(&main_args.out_log, format!(
+        "[+] Dedupe {}: {} rows written to {}, {} duplicates removed, {} outside the dates removed, {} with unparsed dates flagged",
+        &dedupe.name, rows, dedupe_path.display(), removed, outside, unparsed
+    ));
+    Ok((rows, removed))
+}
+
+/// the folder beside the outputs of a command that their filtered companions are written to
+const FILTERED_DIR: &str = "filtered";
+
+/// the path of the filtered companion of an output, i.e. out.csv to filtered/out.csv, in a
+/// folder so it does not match the outfile of the command, which it would beside it
+pub fn filtered_path(path: &str) -> String {
+    let path = Path::new(path);
+    let dir = path.parent().unwrap_or(Path::new("")).join(FILTERED_DIR);
+    dir.join(path.file_name().unwrap_or_default()).display().to_string()
+}
+
+/// keep a json row of the output if it matches the pattern of the filter, in its column if
+/// set or else anywhere in the row, with only the columns selected
+fn filter_json_row(filter: &PostFilter, re: &Option<Regex>, row: serde_json::Map<String, serde_json::Value>, text: &str) -> Option<serde_json::Map<String, serde_json::Value>> {
+    let matched = match (re, filter.column.is_empty()) {
+        (None, _) => true,
+        (Some(re), true) => re.is_match(text),
+        (Some(re), false) => row.get(&filter.column).is_some_and(|v| match v {
+            serde_json::Value::String(s) => re.is_match(s),
+            v => re.is_match(&v.to_string()),
+        }),
+    };
+    if !matched {
+        return None;
+    }
+    match filter.columns.is_empty() {
+        true => Some(row),
+        false => Some(filter.columns.iter().filter_map(|c| row.get(c).map(|v| (c.to_string(), v.clone()))).collect()),
+    }
+}
+
+/// filter the output of a command by its `post_filter`, keeping the rows that match
+/// the pattern, in the column if set or anywhere in the row, and only the columns
+/// selected. The filtered rows are written to a companion file in the folder `filtered`
+/// beside the output, which is kept as it is. A .json output may be an array of the rows,
+/// which is written as an array, else it is read as a row on each line
+///
+/// Args:
+/// * `filter` - the columns to keep and the pattern the rows must match
+/// * `path` - the file path of the csv, jsonl or json output
+///
+/// returns the companion file path, the rows kept and the rows read, or the reason it failed
+pub fn post_filter(filter: &PostFilter, path: &str) -> Result<(String, usize, usize), String> {
+    let re = match filter.pattern.is_empty() {
+        true => None,
+        false => Some(Regex::new(&filter.pattern).map_err(|e| format!("invalid pattern. Error: {}", e))?),
+    };
+    let out_path = filtered_path(path);
+    let (mut kept, mut read) = (0, 0);
+    file_ops::make_folders(Path::new(&out_path).parent().unwrap_or(Path::new("")));
+    // a .json output may be an array of the rows rather than a row on each line
+    let json_array = path.ends_with(".json") && File::open(path).ok()
+        .and_then(|f| BufReader::new(f).bytes().map_while(Result::ok).find(|c| !c.is_ascii_whitespace())) == Some(b'[');
+    if json_array {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let rows: Vec<serde_json::Value> = serde_json::from_str(&contents).map_err(|e| format!("not a json array. Error: {}", e))?;
+        let mut filtered = Vec::new();
+        for row in rows {
+            let row = match row {
+                serde_json::Value::Object(row) => row,
+                _ => continue,
+            };
+            read += 1;
+            let text = serde_json::to_string(&row).unwrap_or_default();
+            if let Some(row) = filter_json_row(filter, &re, row, &text) {
+                filtered.push(serde_json::Value::Object(row));
+            }
+        }
+        kept = filtered.len();
+        let json = serde_json::to_string_pretty(&filtered).map_err(|e| e.to_string())?;
+        std::fs::write(&out_path, json).map_err(|e| e.to_string())?;
+    } else if path.ends_with(".jsonl") || path.ends_with(".json") {
+        let reader = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+        let mut writer = BufWriter::new(File::create(&out_path).map_err(|e| e.to_string())?);
+        for line in reader.lines().map_while(Result::ok) {
+            let row = match serde_json::from_str::<serde_json::Value>(&line) {
+                Ok(serde_json::Value::Object(row)) => row,
+                _ => continue,
+            };
+            read += 1;
+            if let Some(row) = filter_json_row(filter, &re, row, &line) {
+                writeln!(writer, "{}", serde_json::Value::Object(row)).map_err(|e| e.to_string())?;
+                kept += 1;
+            }
+        }
+        writer.flush().map_err(|e| e.to_string())?;
+    } else {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(path).map_err(|e| e.to_string())?;
+        let header: Vec<String> = reader.headers().map_err(|e| e.to_string())?.iter().map(|h| h.to_string()).collect();
+        let find = |c: &String| header.iter().position(|h| h == c).ok_or(format!("column '{}' is not in the output", c));
+        let index: Vec<usize> = match filter.columns.is_empty() {
+            true => (0..header.len()).collect(),
+            false => filter.columns.iter().map(find).collect::<Result<_, _>>()?,
+        };
+        let match_index = match filter.column.is_empty() {
+            true => None,
+            false => Some(find(&filter.column)?),
+        };
+        let mut writer = csv::Writer::from_path(&out_path).map_err(|e| e.to_string())?;
+        writer.write_record(index.iter().map(|i| &header[*i])).map_err(|e| e.to_string())?;
+        for record in reader.records() {
+            let record = record.map_err(|e| e.to_string())?;
+            read += 1;
+            let matched = match (&re, match_index) {
+                (None, _) => true,
+                (Some(re), None) => record.iter().any(|v| re.is_match(v)),
+                (Some(re), Some(i)) => re.is_match(record.get(i).unwrap_or("")),
+            };
+            if matched {
+                writer.write_record(index.iter().map(|i| record.get(*i).unwrap_or(""))).map_err(|e| e.to_string())?;
+                kept += 1;
+            }
+        }
+        writer.flush().map_err(|e| e.to_string())?;
+    }
+    Ok((out_path, kept, read))
+}
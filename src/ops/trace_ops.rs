@@ -0,0 +1,112 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use serde_json::{json, Value};
+
+use super::file_ops;
+use super::summary_ops::{CmdResult, Status};
+
+/// a random id of the number of bytes as hex, for the span ids
+fn random_id(bytes: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..bytes).map(|_| format!("{:02x}", rng.gen::<u8>())).collect()
+}
+
+/// the attributes of a span in the OTLP format
+fn attributes(pairs: &[(&str, &str)]) -> Vec<Value> {
+    pairs.iter().map(|(key, value)| json!({"key": key, "value": {"stringValue": value}})).collect()
+}
+
+/// a span in the OTLP format, with the times as nanoseconds since the unix epoch
+fn span(trace_id: &str, span_id: &str, parent_id: &str, name: &str, (start, end): (DateTime<Utc>, DateTime<Utc>), ok: bool, attrs: &[(&str, &str)]) -> Value {
+    json!({
+        "traceId": trace_id,
+        "spanId": span_id,
+        "parentSpanId": parent_id,
+        "name": name,
+        "kind": 1,
+        "startTimeUnixNano": start.timestamp_nanos_opt().unwrap_or(0).to_string(),
+        "endTimeUnixNano": end.timestamp_nanos_opt().unwrap_or(0).to_string(),
+        "attributes": attributes(attrs),
+        "status": {"code": if ok { 1 } else { 2 }},
+    })
+}
+
+/// the spans of the run, its stages and commands, as an OpenTelemetry (OTLP) json
+/// export of traces. A stage spans from its first command starting to its last ending
+///
+/// Args:
+/// * `commands` - the results of the commands, with when each ended
+/// * `run_id` - the id of the run, used as the trace id if it is a uuid
+/// * `case_name` - the case the run belongs to
+/// * `start` - when the run started
+/// * `end` - when the run ended
+pub fn run_spans(commands: &[CmdResult], run_id: &str, case_name: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Value {
+    let trace_id = match uuid::Uuid::parse_str(run_id) {
+        Ok(id) => id.simple().to_string(),
+        Err(_) => random_id(16),
+    };
+    let run_span_id = random_id(8);
+    let run_ok = !commands.iter().any(|c| matches!(c.status, Status::Failed | Status::Killed));
+    let mut spans = vec![span(&trace_id, &run_span_id, "", "wiskess run", (start, end), run_ok, &[
+        ("wiskess.run_id", run_id),
+        ("wiskess.case", case_name),
+    ])];
+
+    for stage in ["wiskers", "enrichers", "reporters"] {
+        let stage_cmds: Vec<(&CmdResult, DateTime<Utc>, DateTime<Utc>)> = commands.iter()
+            .filter(|c| c.stage == stage)
+            .map(|c| {
+                let cmd_end = c.end.unwrap_or(end);
+                (c, cmd_end - Duration::milliseconds((c.duration_s * 1000.0) as i64), cmd_end)
+            })
+            .collect();
+        let (stage_start, stage_end) = match (stage_cmds.iter().map(|c| c.1).min(), stage_cmds.iter().map(|c| c.2).max()) {
+            (Some(s), Some(e)) => (s, e),
+            _ => continue,
+        };
+        let stage_span_id = random_id(8);
+        let stage_ok = !stage_cmds.iter().any(|(c, _, _)| matches!(c.status, Status::Failed | Status::Killed));
+        spans.push(span(&trace_id, &stage_span_id, &run_span_id, stage, (stage_start, stage_end), stage_ok, &[
+            ("wiskess.run_id", run_id),
+            ("wiskess.stage", stage),
+        ]));
+        for (cmd, cmd_start, cmd_end) in stage_cmds {
+            let status = serde_json::to_value(&cmd.status).ok().and_then(|s| s.as_str().map(|s| s.to_string())).unwrap_or_default();
+            let ok = !matches!(cmd.status, Status::Failed | Status::Killed);
+            // the reason can have a path or error of the data, so it is redacted as in the log
+            let reason = file_ops::redact(&cmd.reason);
+            spans.push(span(&trace_id, &random_id(8), &stage_span_id, &cmd.name, (cmd_start, cmd_end), ok, &[
+                ("wiskess.run_id", run_id),
+                ("wiskess.stage", stage),
+                ("wiskess.command", &cmd.name),
+                ("wiskess.status", &status),
+                ("wiskess.reason", &reason),
+            ]));
+        }
+    }
+
+    json!({
+        "resourceSpans": [{
+            "resource": {"attributes": attributes(&[("service.name", "wiskess")])},
+            "scopeSpans": [{
+                "scope": {"name": "wiskess", "version": env!("CARGO_PKG_VERSION")},
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+/// append the spans of the run as a line of json to the trace file, which the
+/// OpenTelemetry collector can ingest with its otlpjsonfile receiver
+///
+/// Args:
+/// * `trace_file` - the file path to append the spans to
+/// * `spans` - the spans of the run, from `run_spans`
+///
+/// returns the reason if it could not be written
+pub fn write_spans(trace_file: &str, spans: &Value) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(trace_file)?;
+    writeln!(file, "{}", spans)
+}
@@ -0,0 +1,125 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use sysinfo::{Pid, System};
+
+/// the time between the samples of the resource usage of the run
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The resources used by wiskess and the commands it ran, for capacity planning.
+/// The CPU is the percent of all the cores of the machine
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Usage {
+    pub peak_cpu_percent: f32,
+    pub avg_cpu_percent: f32,
+    pub peak_memory_mb: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub samples: u64,
+}
+
+/// Samples the resource usage of the run in a thread, until it is stopped
+pub struct Sampler {
+    usage: Arc<Mutex<Usage>>,
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+/// get the process of wiskess and its child processes, which are those of the commands
+fn tree_pids(sys: &System, root: Pid) -> Vec<Pid> {
+    let mut pids = vec![root];
+    let mut i = 0;
+    while i < pids.len() {
+        let parent = pids[i];
+        for (pid, process) in sys.processes() {
+            if process.parent() == Some(parent) && !pids.contains(pid) {
+                pids.push(*pid);
+            }
+        }
+        i += 1;
+    }
+    pids
+}
+
+/// get the bytes read and written from storage by wiskess and the child processes it has
+/// waited for, from /proc/self/io, as these are counted to the parent once they exit
+#[cfg(target_os = "linux")]
+fn proc_io() -> Option<(u64, u64)> {
+    let io = std::fs::read_to_string("/proc/self/io").ok()?;
+    let field = |name: &str| io.lines()
+        .find_map(|l| l.strip_prefix(name))
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    Some((field("read_bytes:")?, field("write_bytes:")?))
+}
+
+/// start sampling the CPU, memory and IO of wiskess and the commands it runs
+pub fn start() -> Sampler {
+    let usage = Arc::new(Mutex::new(Usage::default()));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (usage_c, stop_c) = (usage.clone(), stop.clone());
+    let handle = thread::spawn(move || {
+        let root = Pid::from_u32(std::process::id());
+        let cores = num_cpus::get().max(1) as f32;
+        let mut sys = System::new();
+        let mut cpu_total = 0.0;
+        // the most read and written by each process, as it is lost once the process exits
+        let mut io: std::collections::HashMap<Pid, (u64, u64)> = std::collections::HashMap::new();
+        sys.refresh_processes();
+        while !stop_c.load(Ordering::SeqCst) {
+            thread::sleep(SAMPLE_INTERVAL);
+            sys.refresh_processes();
+            let processes: Vec<_> = tree_pids(&sys, root).into_iter().filter_map(|pid| sys.process(pid)).collect();
+            let cpu = processes.iter().map(|p| p.cpu_usage()).sum::<f32>() / cores;
+            let memory = processes.iter().map(|p| p.memory()).sum::<u64>() / 1024 / 1024;
+            for process in &processes {
+                let disk = process.disk_usage();
+                io.insert(process.pid(), (disk.total_read_bytes, disk.total_written_bytes));
+            }
+
+            let mut usage = usage_c.lock().unwrap();
+            usage.samples += 1;
+            cpu_total += cpu;
+            usage.avg_cpu_percent = cpu_total / usage.samples as f32;
+            usage.peak_cpu_percent = usage.peak_cpu_percent.max(cpu);
+            usage.peak_memory_mb = usage.peak_memory_mb.max(memory);
+            usage.bytes_read = io.values().map(|(r, _)| r).sum();
+            usage.bytes_written = io.values().map(|(_, w)| w).sum();
+        }
+    });
+    Sampler { usage, stop, handle }
+}
+
+/// the usage sampled so far, where on Linux the IO of the commands that have exited
+/// is counted by the kernel, and on Windows by the jobs they ran in, which also counts
+/// the IO of pipes and devices, not just of files
+fn current(usage: &Mutex<Usage>) -> Usage {
+    #[allow(unused_mut)]
+    let mut usage = usage.lock().unwrap().clone();
+    #[cfg(target_os = "linux")]
+    if let Some((read, written)) = proc_io() {
+        usage.bytes_read = read;
+        usage.bytes_written = written;
+    }
+    #[cfg(windows)]
+    if let Some((read, written)) = super::job_ops::jobs_io() {
+        usage.bytes_read = usage.bytes_read.max(read);
+        usage.bytes_written = usage.bytes_written.max(written);
+    }
+    usage
+}
+
+impl Sampler {
+    /// the usage sampled so far
+    pub fn usage(&self) -> Usage {
+        current(&self.usage)
+    }
+
+    /// stop sampling, returning the usage of the run
+    pub fn stop(self) -> Usage {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = self.handle.join();
+        current(&self.usage)
+    }
+}
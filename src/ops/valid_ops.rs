@@ -1,9 +1,45 @@
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use crate::configs::config::{Wiskers, self};
-use super::file_ops;
+use crate::init::setup;
+use super::{exe_ops, file_ops, status_ops, table_ops};
+use super::summary_ops::{CmdResult, Status};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use serde::{Deserialize, Serialize};
+use sha2::digest::generic_array::GenericArray;
+use walkdir::WalkDir;
 use tabled::{Tabled, Table};
 use tabled::settings::{Width, Style};
 
+#[derive(Tabled)]
+struct ColumnsDiff<'a> {
+    name: &'a str,
+    output_file: String,
+    missing: String,
+    unexpected: String,
+    reordered: bool,
+}
+
+#[derive(Tabled)]
+struct WindowCheck<'a> {
+    name: &'a str,
+    output_file: String,
+    earliest: String,
+    latest: String,
+    sampled: usize,
+    outside: usize,
+}
+
+/// the rows read from each output to check its timestamps are in the date window
+const WINDOW_SAMPLE: usize = 10000;
+
+/// the fraction of the sampled timestamps outside the date window that is reported
+const WINDOW_THRESHOLD: f64 = 0.05;
+
 #[derive(Tabled)]
 struct Summary<'a> {
     name: &'a str,
@@ -16,41 +52,42 @@ struct Summary<'a> {
 // Needs the wisker: outfolder, outfile, input
 // data_paths is a hashmap of the 'artefact_name : path/to/artefact'
 // wiskers is a vector of type Wiskers, which is built from the config file, i.e. config/main_win.yaml
+// The outputs are checked in parallel by --jobs threads, and collected in the order of the config
 pub fn valid_process<'a>(wiskers: &'a Vec<Wiskers>, main_args: &config::MainArgs, data_paths: &'a HashMap<String, String>, data_source: &String, out_log: &String) {
-    let mut contents: Vec<Summary> = Vec::new();
-    // let w = wiskers;
-    // let mut success = Vec::new();
-    for wisker in wiskers {
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(main_args.jobs)
+        .build()
+        .unwrap();
+    let contents: Vec<Summary> = pool.install(|| wiskers.par_iter().filter_map(|wisker| {
         // for each function in the wiskers config
+        if !wisker.run_if_artefact.is_empty() && !exe_ops::artefact_present(data_paths, &wisker.run_if_artefact) {
+            return None;
+        }
         let input_file = match &wisker.valid_path.is_empty() {
             true => data_paths[&wisker.input].clone(),
             false => wisker.valid_path.replace("{root}", data_source)
         };
         // Get input paths that exist in the data source
-        if input_file != "wiskess_none" {
-            let folder_path = format!("{}/{}", &main_args.out_path, &wisker.outfolder);
-            let check_outfile = format!("{}/{}", &folder_path, &wisker.outfile);
-            // Check if the outfile exists, file_exists returns false if exists
-            let input_not_processed = file_ops::file_exists(
-                &check_outfile,
-                true
-            );
-            let mut file_lines = 0;
-            if !input_not_processed {
-                file_lines = file_ops::line_count(&check_outfile);
-            }
-            if input_not_processed || file_lines <= 1 {
-                // let outfile = check_outfile;
-                let content = Summary {
-                    name: &wisker.name,
-                    data_source: check_outfile,
-                    analysis_file: input_file,
-                    lines: file_lines
-                };
-                contents.push(content);
-            }
+        if input_file == "wiskess_none" || input_file == "wiskess_locked" {
+            return None;
         }
-    }
+        let check_outfile = wisker.out_file(&main_args.out_path);
+        // Check if the outfile exists, file_exists returns false if exists
+        let input_not_processed = file_ops::file_exists(
+            &check_outfile,
+            true
+        );
+        let mut file_lines = 0;
+        if !input_not_processed {
+            file_lines = file_ops::line_count(&check_outfile);
+        }
+        (input_not_processed || file_lines <= 1).then(|| Summary {
+            name: &wisker.name,
+            data_source: check_outfile,
+            analysis_file: input_file,
+            lines: file_lines
+        })
+    }).collect());
     let msg = format!(
         "{}\n{}\n{}{out_log}, {}\n{}",
         "[!] Please check the logs and config of the above wiskers.",
@@ -60,6 +97,464 @@ pub fn valid_process<'a>(wiskers: &'a Vec<Wiskers>, main_args: &config::MainArgs
         "[ ] Also please check the output file, as validation checks for it having > 1 line."
     );
     out_table(contents, &out_log, msg);
+    pool.install(|| {
+        check_columns(wiskers, main_args, data_paths);
+        if main_args.window_check {
+            check_window(wiskers, main_args, data_paths);
+        }
+    });
+}
+
+/// read the column names of an output, from the header of a csv or the keys of
+/// the first line of a jsonl
+fn read_columns(file_path: &str) -> Option<Vec<String>> {
+    if file_path.ends_with(".jsonl") || file_path.ends_with(".json") {
+        let mut line = String::new();
+        BufReader::new(File::open(file_path).ok()?).read_line(&mut line).ok()?;
+        // read as yaml, which keeps the order of the keys, as a json object is also yaml
+        let first: serde_yaml::Mapping = serde_yaml::from_str(&line).ok()?;
+        Some(first.keys().filter_map(|k| k.as_str().map(|k| k.to_string())).collect())
+    } else {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(file_path).ok()?;
+        Some(reader.headers().ok()?.iter().map(|h| h.to_string()).collect())
+    }
+}
+
+/// read the values of the date column from the first rows of an output, which is
+/// a csv or jsonl
+fn read_dates(file_path: &str, date_column: &str) -> Option<Vec<String>> {
+    if file_path.ends_with(".jsonl") || file_path.ends_with(".json") {
+        let reader = BufReader::new(File::open(file_path).ok()?);
+        Some(reader.lines()
+            .map_while(Result::ok)
+            .take(WINDOW_SAMPLE)
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(&line).ok())
+            .filter_map(|row| row.get(date_column).and_then(|v| v.as_str()).map(|v| v.to_string()))
+            .collect())
+    } else {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(file_path).ok()?;
+        let index = reader.headers().ok()?.iter().position(|h| h == date_column)?;
+        Some(reader.records()
+            .take(WINDOW_SAMPLE)
+            .filter_map(|r| r.ok())
+            .filter_map(|r| r.get(index).map(|v| v.to_string()))
+            .collect())
+    }
+}
+
+/// check the timestamps in the `date_column` of the outputs are within the start
+/// and end date, as some tools don't honour the timeframe. The first rows of each
+/// output are sampled, and it is reported with the earliest and latest timestamps
+/// if many are outside. With --strict-window its result in the run summary is set to failed.
+///
+/// Args:
+/// * `wiskers` - the commands from the config, those with `date_column` are checked
+/// * `main_args` - the arguments specified from the main.rs, i.e. start_date
+/// * `data_paths` - the hash map of the artefact name and file path
+///
+/// returns the names of the commands with many timestamps outside the window
+pub fn check_window(wiskers: &[Wiskers], main_args: &config::MainArgs, data_paths: &HashMap<String, String>) -> Vec<String> {
+    let (start, end) = match table_ops::date_window(main_args) {
+        Some(window) => window,
+        None => return Vec::new(),
+    };
+    // the outputs are read in parallel, in the order of the config
+    let contents: Vec<WindowCheck> = wiskers.par_iter()
+        .filter(|w| !w.date_column.is_empty())
+        .filter(|w| !matches!(data_paths.get(&w.input).map(|p| p.as_str()).unwrap_or("wiskess_none"), "wiskess_none" | "wiskess_locked"))
+        .flat_map_iter(|wisker| file_ops::find_files_glob(&wisker.out_file(&main_args.out_path)).into_iter().map(move |f| (wisker, f)))
+        .filter_map(|(wisker, output_file)| {
+            let dates: Vec<_> = read_dates(&output_file, &wisker.date_column)?.iter()
+                .filter_map(|v| table_ops::parse_date(v, &wisker.date_format))
+                .collect();
+            let outside = dates.iter().filter(|d| **d < start || **d > end).count();
+            if dates.is_empty() || (outside as f64) < (dates.len() as f64) * WINDOW_THRESHOLD {
+                return None;
+            }
+            Some(WindowCheck {
+                name: &wisker.name,
+                output_file,
+                earliest: dates.iter().min().map(|d| d.to_string()).unwrap_or_default(),
+                latest: dates.iter().max().map(|d| d.to_string()).unwrap_or_default(),
+                sampled: dates.len(),
+                outside,
+            })
+        })
+        .collect();
+    let outside: Vec<String> = contents.iter().map(|c| c.name.to_string()).collect();
+    if !contents.is_empty() {
+        if main_args.strict_window {
+            for result in main_args.results.lock().unwrap().iter_mut().filter(|r| outside.contains(&r.name) && r.status == Status::Success) {
+                result.status = Status::Failed;
+                result.reason = "timestamps outside the date window".to_string();
+            }
+        }
+        let msg = format!(
+            "{}{} to {}.\n{}",
+            "[!] Many of the timestamps of the above wiskers are outside the date window of ",
+            &main_args.start_date,
+            &main_args.end_date,
+            "[ ] Please check if the tool honours the timeframe, before drawing conclusions from its output."
+        );
+        let mut table = Table::new(&contents);
+        table.with(Style::psql());
+        table.with(Width::wrap(200));
+        println!("{}", file_ops::redact(&table.to_string()));
+        println!("{}", file_ops::redact(&msg));
+        file_ops::log_msg(&main_args.out_log, table.to_string());
+        file_ops::log_msg(&main_args.out_log, msg);
+    }
+    outside
+}
+
+/// check the columns of the outputs match the `expected_columns` in the config,
+/// as a new version of a tool can change its output and break the reporters.
+/// Validation fails for a command if columns are missing or out of order, and
+/// its result in the run summary is set to failed.
+/// 
+/// Args:
+/// * `wiskers` - the commands from the config, those with `expected_columns` are checked
+/// * `main_args` - the arguments specified from the main.rs, i.e. out_path
+/// * `data_paths` - the hash map of the artefact name and file path
+/// 
+/// returns the names of the commands that failed the check
+pub fn check_columns(wiskers: &[Wiskers], main_args: &config::MainArgs, data_paths: &HashMap<String, String>) -> Vec<String> {
+    // the outputs are read in parallel, in the order of the config
+    let contents: Vec<ColumnsDiff> = wiskers.par_iter()
+        .filter(|w| !w.expected_columns.is_empty())
+        .filter(|w| !matches!(data_paths.get(&w.input).map(|p| p.as_str()).unwrap_or("wiskess_none"), "wiskess_none" | "wiskess_locked"))
+        .flat_map_iter(|wisker| file_ops::find_files_glob(&wisker.out_file(&main_args.out_path)).into_iter().map(move |f| (wisker, f)))
+        .filter_map(|(wisker, output_file)| {
+            let columns = read_columns(&output_file)?;
+            let missing: Vec<&str> = wisker.expected_columns.iter().filter(|c| !columns.contains(c)).map(|c| c.as_str()).collect();
+            let unexpected: Vec<&str> = columns.iter().filter(|c| !wisker.expected_columns.contains(c)).map(|c| c.as_str()).collect();
+            // the expected columns that are in the output should be in the same order
+            let present: Vec<&String> = wisker.expected_columns.iter().filter(|c| columns.contains(c)).collect();
+            let actual: Vec<&String> = columns.iter().filter(|c| wisker.expected_columns.contains(c)).collect();
+            let reordered = present != actual;
+            (!missing.is_empty() || reordered).then(|| ColumnsDiff {
+                name: &wisker.name,
+                output_file,
+                missing: missing.join(", "),
+                unexpected: unexpected.join(", "),
+                reordered,
+            })
+        })
+        .collect();
+    let failed: Vec<String> = contents.iter().map(|c| c.name.to_string()).collect();
+    if !contents.is_empty() {
+        for result in main_args.results.lock().unwrap().iter_mut().filter(|r| failed.contains(&r.name)) {
+            result.status = Status::Failed;
+            result.reason = "output columns do not match expected_columns".to_string();
+        }
+        let msg = format!(
+            "{}\n{}",
+            "[!] The output columns of the above wiskers do not match their expected_columns in the config.",
+            "[ ] Please check if the version of the tool has changed its output, as this can break the reporters."
+        );
+        let mut table = Table::new(&contents);
+        table.with(Style::psql());
+        table.with(Width::wrap(200));
+        println!("{}", file_ops::redact(&table.to_string()));
+        println!("{}", file_ops::redact(&msg));
+        file_ops::log_msg(&main_args.out_log, table.to_string());
+        file_ops::log_msg(&main_args.out_log, msg);
+    }
+    failed
+}
+
+/// check the outputs of a stage that is not being run exist, as a later stage
+/// being run on its own relies on them. Commands with a missing input artefact
+/// are not expected to have an output.
+/// 
+/// Args:
+/// * `stage` - the name of the stage, i.e. wiskers
+/// * `wiskers` - the commands of the stage from the config
+/// * `main_args` - the arguments specified from the main.rs, i.e. out_path
+/// * `data_paths` - the hash map of the artefact name and file path
+/// 
+/// returns the names of the commands that have no output
+pub fn check_stage_outputs(stage: &str, wiskers: &[Wiskers], main_args: &config::MainArgs, data_paths: &HashMap<String, String>) -> Vec<String> {
+    let mut missing = Vec::new();
+    for wisker in wiskers {
+        let input_file = data_paths.get(&wisker.input).map(|p| p.as_str()).unwrap_or("wiskess_none");
+        if input_file == "wiskess_none" || input_file == "wiskess_locked" {
+            continue;
+        }
+        let check_outfile = wisker.out_file(&main_args.out_path);
+        if !file_ops::output_exists(&check_outfile) {
+            missing.push(wisker.name.to_string());
+        }
+    }
+    if !missing.is_empty() {
+        let msg = format!(
+            "[!] Stage {} is not being run, but has no output for: {}",
+            stage,
+            missing.join(", ")
+        );
+        println!("{}", file_ops::redact(&msg));
+        file_ops::log_msg(&main_args.out_log, msg);
+    }
+    missing
+}
+
+/// The SHA-256 of an output file, for the chain of custody of the results
+#[derive(Debug, Serialize)]
+struct OutputHash {
+    file: String,
+    size_bytes: u64,
+    sha256: String,
+}
+
+/// the size of the chunks an output is read in to hash it, so a large file is not read into memory
+const HASH_CHUNK: usize = 1024 * 1024;
+
+/// the folder in the output of the checkpoints of the hashes of the large outputs
+pub const HASH_CHECKPOINTS: &str = "hash_checkpoints";
+
+/// the size of an output from which its hash is checkpointed, so it can be resumed
+#[cfg(not(test))]
+const CHECKPOINT_SIZE: u64 = 1024 * 1024 * 1024;
+#[cfg(test)]
+const CHECKPOINT_SIZE: u64 = 4 * HASH_CHUNK as u64;
+
+/// how often the hash of a large output is checkpointed, by the bytes hashed, which is a
+/// multiple of HASH_CHUNK
+#[cfg(not(test))]
+const CHECKPOINT_EVERY: u64 = 256 * 1024 * 1024;
+#[cfg(test)]
+const CHECKPOINT_EVERY: u64 = 2 * HASH_CHUNK as u64;
+
+/// the initial state of SHA-256
+const SHA256_INIT: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// The state of the SHA-256 of a large output part way through, with the offset it has been
+/// hashed to, so an interrupted hash resumes from there if the file is unchanged
+#[derive(Debug, Serialize, Deserialize)]
+struct HashCheckpoint {
+    file: String,
+    size_bytes: u64,
+    modified: u64,
+    offset: u64,
+    state: [u32; 8],
+}
+
+/// read into the buffer until it is full or the end of the file, so the chunks hashed are
+/// whole blocks of SHA-256 until the last
+fn read_chunk(file: &mut File, buf: &mut [u8]) -> Result<usize, String> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..]).map_err(|e| e.to_string())? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+/// add the whole 64 byte blocks of the data to the state of SHA-256
+fn sha256_blocks(state: &mut [u32; 8], data: &[u8]) {
+    for block in data.chunks_exact(64) {
+        sha2::compress256(state, std::slice::from_ref(GenericArray::from_slice(block)));
+    }
+}
+
+/// hash a file with SHA-256, reading it in chunks. A file over CHECKPOINT_SIZE has the state
+/// of its hash written to the checkpoints folder every CHECKPOINT_EVERY bytes, and resumed
+/// from if one is there for it with the same size and modified time, i.e. after the run was
+/// interrupted, so a very large timeline is not hashed from the start again
+///
+/// Args:
+/// * `file_path` - the file to hash
+/// * `checkpoints` - the folder of the checkpoints, i.e. out_path/hash_checkpoints
+/// * `out_log` - the log of the run, to note a hash being resumed
+fn sha256_file(file_path: &str, checkpoints: &Path, out_log: &String) -> Result<String, String> {
+    let mut file = File::open(file_path).map_err(|e| e.to_string())?;
+    let meta = file.metadata().map_err(|e| e.to_string())?;
+    let modified = meta.modified().ok()
+        .and_then(|m| m.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+    let checkpointed = meta.len() >= CHECKPOINT_SIZE;
+    let checkpoint_path = checkpoints.join(format!("{}.json", file_ops::safe_name(file_path)));
+
+    let mut state = SHA256_INIT;
+    let mut offset = 0;
+    let resume = std::fs::read_to_string(&checkpoint_path).ok()
+        .and_then(|text| serde_json::from_str::<HashCheckpoint>(&text).ok())
+        .filter(|c| checkpointed && c.file == file_path && c.size_bytes == meta.len() && c.modified == modified);
+    if let Some(checkpoint) = resume {
+        file.seek(SeekFrom::Start(checkpoint.offset)).map_err(|e| e.to_string())?;
+        file_ops::log_msg(out_log, format!("[ ] Resuming the hash of {} from {} MB", file_path, checkpoint.offset / 1024 / 1024));
+        state = checkpoint.state;
+        offset = checkpoint.offset;
+    }
+
+    let mut buf = vec![0; HASH_CHUNK];
+    let tail = loop {
+        let read = read_chunk(&mut file, &mut buf)?;
+        let whole = read - read % 64;
+        sha256_blocks(&mut state, &buf[..whole]);
+        offset += whole as u64;
+        if read < HASH_CHUNK {
+            break buf[whole..read].to_vec();
+        }
+        if checkpointed && offset % CHECKPOINT_EVERY == 0 {
+            let checkpoint = HashCheckpoint { file: file_path.to_string(), size_bytes: meta.len(), modified, offset, state };
+            let written = std::fs::create_dir_all(checkpoints)
+                .and_then(|_| std::fs::write(&checkpoint_path, serde_json::to_string(&checkpoint).unwrap_or_default()));
+            if let Err(e) = written {
+                file_ops::log_msg(out_log, format!("[-] Unable to checkpoint the hash of {}. Error: {}", file_path, e));
+            }
+        }
+    };
+
+    // pad the last block with the length of the file in bits
+    let bits = (offset + tail.len() as u64) * 8;
+    let mut last = tail;
+    last.push(0x80);
+    while last.len() % 64 != 56 {
+        last.push(0);
+    }
+    last.extend(bits.to_be_bytes());
+    sha256_blocks(&mut state, &last);
+    let _ = std::fs::remove_file(&checkpoint_path);
+    Ok(state.iter().map(|word| format!("{:08x}", word)).collect())
+}
+
+/// hash the outputs of the commands that succeeded with SHA-256, for the chain of custody,
+/// written to `out_path/output_hashes.json`. The files are hashed by --hash-jobs threads,
+/// each read in chunks, so many large outputs don't strain the disk or memory
+///
+/// Args:
+/// * `main_args` - the arguments specified from the main.rs, i.e. results, out_path and multi_pb
+/// * `hash_jobs` - the number of files hashed at the same time
+///
+/// returns the file path of the hashes, or the reason they could not be written
+pub fn hash_outputs(main_args: &config::MainArgs, hash_jobs: usize) -> Result<String, String> {
+    let files: Vec<String> = main_args.results.lock().unwrap().iter()
+        .filter(|r| r.status == Status::Success && !r.output.is_empty())
+        .flat_map(|r| file_ops::find_files_glob(&r.output))
+        .collect();
+    let hashes = hash_files(&files, main_args, hash_jobs)?;
+    let json_path = Path::new(&main_args.out_path).join("output_hashes.json");
+    let json = serde_json::to_string_pretty(&hashes).map_err(|e| e.to_string())?;
+    std::fs::write(&json_path, json).map_err(|e| format!("unable to write {}. Error: {}", json_path.display(), e))?;
+    Ok(json_path.display().to_string())
+}
+
+/// hash the files with SHA-256 by hash_jobs threads, showing the progress, where a file
+/// that could not be read is logged and left out
+fn hash_files(files: &[String], main_args: &config::MainArgs, hash_jobs: usize) -> Result<Vec<OutputHash>, String> {
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(hash_jobs)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let pb = setup::prog_spin_init(960, &main_args.multi_pb, "cyan");
+    let done = AtomicUsize::new(0);
+    let checkpoints = Path::new(&main_args.out_path).join(HASH_CHECKPOINTS);
+    let hashes: Vec<OutputHash> = pool.install(|| files.par_iter().filter_map(|file| {
+        let hash = sha256_file(file, &checkpoints, &main_args.out_log);
+        setup::prog_spin_msg(&pb, format!("Hashing the outputs: {} of {}", done.fetch_add(1, Ordering::SeqCst) + 1, files.len()));
+        match hash {
+            Ok(sha256) => Some(OutputHash {
+                file: file.to_string(),
+                size_bytes: std::fs::metadata(file).map(|m| m.len()).unwrap_or(0),
+                sha256,
+            }),
+            Err(e) => {
+                file_ops::log_msg(&main_args.out_log, format!("[!] Unable to hash the output {}. Error: {}", file, e));
+                None
+            },
+        }
+    }).collect());
+    setup::prog_spin_stop(&pb, format!("Hashed {} outputs", hashes.len()));
+    // the checkpoints are removed as each hash completes, leaving the folder empty
+    let _ = std::fs::remove_dir(&checkpoints);
+    Ok(hashes)
+}
+
+/// A file of the output folder in the manifest, by its path relative to the folder, with the
+/// command that made it if any, i.e. not the run summary
+#[derive(Debug, Serialize)]
+struct ManifestFile {
+    path: String,
+    size_bytes: u64,
+    sha256: String,
+    stage: String,
+    name: String,
+}
+
+/// The files of the output folder, so it describes itself when handed off without the paths
+/// of the machine that made it
+#[derive(Debug, Serialize)]
+struct Manifest {
+    run_id: String,
+    case_name: String,
+    generated: String,
+    files: Vec<ManifestFile>,
+}
+
+/// write `out_path/manifest.json` of every file in the output folder, by its path relative
+/// to it, with its size, SHA-256, and the stage and name of the command that made it. A file
+/// is of the command that has it as an output, else of the command with the folder it is in,
+/// unless the folder is shared. The log and status of the run are left out, as they are still
+/// written to
+///
+/// Args:
+/// * `commands` - the results of the commands, with the glob of each output
+/// * `main_args` - the arguments specified from the main.rs, i.e. out_path, out_dirs and out_log
+/// * `hash_jobs` - the number of files hashed at the same time
+///
+/// returns the file path of the manifest, or the reason it could not be written
+pub fn write_manifest(commands: &[CmdResult], main_args: &config::MainArgs, hash_jobs: usize) -> Result<String, String> {
+    let out_path = Path::new(&main_args.out_path);
+    let json_path = out_path.join("manifest.json");
+    let files: Vec<String> = WalkDir::new(out_path).sort_by_file_name().into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && e.path() != json_path && e.path() != Path::new(&main_args.out_log))
+        .filter(|e| !e.file_name().to_string_lossy().starts_with(status_ops::STATUS_FILE))
+        .filter(|e| !e.path().starts_with(out_path.join(HASH_CHECKPOINTS)))
+        .map(|e| e.path().display().to_string())
+        .collect();
+
+    // the command of each output, else of the folder it is in if no other command shares it
+    let mut by_output: HashMap<String, &CmdResult> = HashMap::new();
+    for cmd in commands.iter().filter(|c| c.status == Status::Success) {
+        by_output.extend(file_ops::find_files_glob(&cmd.output).into_iter().map(|f| (f, cmd)));
+    }
+    let mut by_dir: HashMap<&str, Vec<&CmdResult>> = HashMap::new();
+    for cmd in commands {
+        if let Some(out_dir) = main_args.out_dirs.get(&cmd.name) {
+            by_dir.entry(out_dir.as_str()).or_default().push(cmd);
+        }
+    }
+    let command_of = |rel: &str| by_dir.iter()
+        .filter(|(dir, cmds)| cmds.len() == 1 && rel.starts_with(&format!("{}/", dir)))
+        .max_by_key(|(dir, _)| dir.len())
+        .map(|(_, cmds)| cmds[0]);
+
+    let hashes = hash_files(&files, main_args, hash_jobs)?;
+    let manifest = Manifest {
+        run_id: main_args.run_id.to_string(),
+        case_name: main_args.case_name.to_string(),
+        generated: file_ops::now().to_rfc3339(),
+        files: hashes.into_iter().map(|hash| {
+            let rel = Path::new(&hash.file).strip_prefix(out_path).map(|p| p.to_string_lossy().replace('\\', "/")).unwrap_or(hash.file.to_string());
+            let cmd = by_output.get(&hash.file).copied().or_else(|| command_of(&rel));
+            ManifestFile {
+                path: rel,
+                size_bytes: hash.size_bytes,
+                sha256: hash.sha256,
+                stage: cmd.map(|c| c.stage.to_string()).unwrap_or_default(),
+                name: cmd.map(|c| c.name.to_string()).unwrap_or_default(),
+            }
+        }).collect(),
+    };
+    let json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    std::fs::write(&json_path, json).map_err(|e| format!("unable to write {}. Error: {}", json_path.display(), e))?;
+    Ok(json_path.display().to_string())
 }
 
 fn out_table(contents: Vec<Summary>, out_log: &String, msg: String) {
@@ -67,8 +562,65 @@ fn out_table(contents: Vec<Summary>, out_log: &String, msg: String) {
     table.with(Style::psql());
     table.with(Width::wrap(200));
     table.with(Width::increase(75));
-    println!("{}", table.to_string());
-    println!("{}", msg);
+    println!("{}", file_ops::redact(&table.to_string()));
+    println!("{}", file_ops::redact(&msg));
     file_ops::log_msg(&out_log, table.to_string());
     file_ops::log_msg(&out_log, msg);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+    use sha2::{Digest, Sha256};
+
+    /// a file of random bytes of the length in a temp folder
+    fn random_file(dir: &Path, len: usize) -> (String, Vec<u8>) {
+        let mut data = vec![0; len];
+        rand::thread_rng().fill_bytes(&mut data);
+        let path = dir.join(format!("{}.bin", len));
+        std::fs::write(&path, &data).unwrap();
+        (path.display().to_string(), data)
+    }
+
+    fn hex(data: &[u8]) -> String {
+        Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn sha256_file_matches_sha256() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_log = dir.path().join("wiskess.log").display().to_string();
+        for len in [0, 55, 56, 64, HASH_CHUNK, HASH_CHUNK + 1] {
+            let (path, data) = random_file(dir.path(), len);
+            assert_eq!(sha256_file(&path, &dir.path().join(HASH_CHECKPOINTS), &out_log).unwrap(), hex(&data), "length {}", len);
+        }
+    }
+
+    #[test]
+    fn sha256_file_resumes_from_a_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_log = dir.path().join("wiskess.log").display().to_string();
+        let checkpoints = dir.path().join(HASH_CHECKPOINTS);
+        let (path, data) = random_file(dir.path(), CHECKPOINT_SIZE as usize + 100);
+
+        // the checkpoint of the hash stopped after the first CHECKPOINT_EVERY bytes
+        let mut state = SHA256_INIT;
+        sha256_blocks(&mut state, &data[..CHECKPOINT_EVERY as usize]);
+        let meta = std::fs::metadata(&path).unwrap();
+        let checkpoint = HashCheckpoint {
+            file: path.to_string(),
+            size_bytes: meta.len(),
+            modified: meta.modified().unwrap().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
+            offset: CHECKPOINT_EVERY,
+            state,
+        };
+        let checkpoint_path = checkpoints.join(format!("{}.json", file_ops::safe_name(&path)));
+        std::fs::create_dir_all(&checkpoints).unwrap();
+        std::fs::write(&checkpoint_path, serde_json::to_string(&checkpoint).unwrap()).unwrap();
+
+        assert_eq!(sha256_file(&path, &checkpoints, &out_log).unwrap(), hex(&data));
+        assert!(std::fs::read_to_string(&out_log).unwrap().contains("Resuming the hash"));
+        assert!(!checkpoint_path.exists());
+    }
+}
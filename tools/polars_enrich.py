@@ -41,12 +41,18 @@ def cleanup(file):
         
         
 
-def get_indicators(out_filepath, out_file, tool_path):
+def out_dir(outdirs, name, out_filepath, folder):
+    # the output folder of the named command given by --outdir name=path, else its folder under the out path
+    return outdirs.get(name, os.path.join(out_filepath, folder))
+
+
+
+def get_indicators(out_filepath, out_file, tool_path, outdirs, out_folder):
     dict_tln = {
         'amcache': {
             'regex_file': r'(?:Amcache_UnassociatedFileEntries)\.csv$',
-            'file': f'{out_filepath}\\FileExecution\\',
-            'out': f'{out_filepath}\\Timeline\\amcache.csv',
+            'file': out_dir(outdirs, 'AmcacheParser', out_filepath, 'FileExecution'),
+            'out': os.path.join(out_filepath, 'Timeline', 'amcache.csv'),
             'msg': ['SHA1','FullPath','FileExtension','ProductName'],
             'times': ['FileKeyLastWriteTimestamp','FileIDLastWriteTimestamp'],
             'fmt_time': '%F %T'
@@ -54,8 +60,8 @@ def get_indicators(out_filepath, out_file, tool_path):
         'browser-hist': {
             # TODO: resolve parsing error of none utf-8
             'regex_file': r'BrowsingHistory\.csv$',
-            'file': f'{out_filepath}\\Network\\',
-            'out': f'{out_filepath}\\Timeline\\browser-hist.csv',
+            'file': out_dir(outdirs, 'Browsing History', out_filepath, 'Network'),
+            'out': os.path.join(out_filepath, 'Timeline', 'browser-hist.csv'),
             'msg': ['URL','Title','Visited From','Visit Type','Web Browser','User Profile'],
             'times': ['Visit Time'],
             'fmt_time': '%D %r'
@@ -66,12 +72,12 @@ def get_indicators(out_filepath, out_file, tool_path):
     urls = get_indicator('URL', 'browser-hist', dict_tln)
     indicators = pl.concat([amhashes, urls]).collect()
     
-    indicator_file = f'{out_filepath}\\IOC_Findings\\temp_indicators.list'
+    indicator_file = os.path.join(out_folder, 'temp_indicators.list')
     indicators.write_csv(indicator_file, has_header=False)
     
     enrich = f'{tool_path}/enrich.exe'
     config = f'{tool_path}/enrich_config.yaml'
-    output_file = f'{out_filepath}\\IOC_Findings\\{out_file}'
+    output_file = os.path.join(out_folder, out_file)
     subprocess.run([enrich, '-silent', '-o', output_file, '-config', config, '-otx', '-i', indicator_file])
 
     cleanup(indicator_file)
@@ -82,9 +88,13 @@ def main():
     parser.add_argument('out_filepath')
     parser.add_argument('out_file')
     parser.add_argument('tool_path')
+    parser.add_argument('--outdir', action='append', default=[], help='the output folder of a command as name=path, i.e. AmcacheParser={outdir:AmcacheParser}')
+    parser.add_argument('--out-folder', help='the folder to write the out_file to, default out_filepath/IOC_Findings')
     args = parser.parse_args()
     
-    get_indicators(args.out_filepath, args.out_file, args.tool_path)
+    outdirs = dict(o.split('=', 1) for o in args.outdir)
+    out_folder = args.out_folder or os.path.join(args.out_filepath, 'IOC_Findings')
+    get_indicators(args.out_filepath, args.out_file, args.tool_path, outdirs, out_folder)
   
 
 
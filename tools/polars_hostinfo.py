@@ -14,6 +14,12 @@ import re
 
 
 
+def out_dir(outdirs, name, out_filepath, folder):
+    # the output folder of the named command given by --outdir name=path, else its folder under the out path
+    return outdirs.get(name, os.path.join(out_filepath, folder))
+
+
+
 def get_reg_val(find_value, dict_tln):
     if os.path.exists(dict_tln['registry']['file']):
         for file in os.listdir(dict_tln['registry']['file']):
@@ -64,19 +70,19 @@ def get_hostname(dict_tln):
 
 
 
-def get_hostinfo(out_filepath, out_file):
+def get_hostinfo(out_filepath, out_file, outdirs, out_folder):
     dict_tln = {
         'registry': {
         'regex_file': r'reg-System\.csv$',
-        'file': f'{out_filepath}\\Registry\\',
-        'out': f'{out_filepath}\\Timeline\\registry.csv',
+        'file': out_dir(outdirs, 'RegSystem', out_filepath, 'Registry'),
+        'out': os.path.join(out_folder, 'registry.csv'),
         'msg': ['HivePath','Description','Category','ValueName','ValueData','ValueData2','ValueData3','Comment'],
         'times': ['LastWriteTimestamp'],
         'fmt_time': '%F %T%.f'    
         },
         'hayabusa': {
-        'file': f'{out_filepath}\\EventLogs\\hayabusa.csv',
-        'out': f'{out_filepath}\\Timeline\\hayabusa.csv',
+        'file': os.path.join(out_dir(outdirs, 'hayabusa', out_filepath, 'EventLogs'), 'hayabusa.csv'),
+        'out': os.path.join(out_folder, 'hayabusa.csv'),
         'msg': ['Computer','Channel','EventID','Level','MitreTactics','MitreTags','OtherTags','RecordID','Details','ExtraFieldInfo','RuleFile','EvtxFile'],
         'times': ['datetime'],    
         'fmt_time': '%FT%T%.f'    
@@ -101,7 +107,7 @@ def get_hostinfo(out_filepath, out_file):
         'Last Logged On User': get_reg_val("LastLoggedOnUser", dict_tln),
     }
 
-    out_file = os.path.join(f"{out_filepath}/{out_file}")
+    out_file = os.path.join(out_folder, out_file)
     with open(out_file, 'w') as file:
         file.write("WISKESS\n----------------\n\nHost Information\n----------------\n\n")
     for i in host_info:
@@ -115,9 +121,12 @@ def main():
     parser = argparse.ArgumentParser()
     parser.add_argument('out_filepath')
     parser.add_argument('out_file')
+    parser.add_argument('--outdir', action='append', default=[], help='the output folder of a command as name=path, i.e. hayabusa={outdir:hayabusa}')
+    parser.add_argument('--out-folder', help='the folder to write the out_file to, default out_filepath')
     args = parser.parse_args()
     
-    get_hostinfo(args.out_filepath, args.out_file)
+    outdirs = dict(o.split('=', 1) for o in args.outdir)
+    get_hostinfo(args.out_filepath, args.out_file, outdirs, args.out_folder or args.out_filepath)
   
 
 
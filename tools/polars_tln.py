@@ -36,27 +36,44 @@ def conv_file_to_utf8(src_file):
   except UnicodeEncodeError:
       print('Encode Error')
 
+def out_dir(outdirs, name, out_filepath, folder):
+  # the output folder of the named command given by --outdir name=path, else its folder under the out path
+  return outdirs.get(name, os.path.join(out_filepath, folder))
+
+
+def art_files(path, regex_file):
+  # the files of an artefact, where the path is a file, or a folder or list of folders with files matching regex_file
+  files = []
+  for folder in (path if isinstance(path, list) else [path]):
+    if os.path.isdir(folder):
+      for file in os.listdir(folder):
+        if re.search(regex_file, file):
+          files.append(os.path.join(folder, file))
+    else:
+      files.append(folder)
+  return files
+
+
 def get_hostname(dict_tln):
   host = ''
-  if os.path.exists(dict_tln['registry']['file']):
-    for file in os.listdir(dict_tln['registry']['file']):
-      if re.search(dict_tln['registry']['regex_file'], file):
-        try:
-          df = pl.scan_csv(os.path.join(dict_tln['registry']['file'],file))
-          host = df.select(
-            ["ValueName","ValueData"]
-            ).filter(
-              pl.col("ValueName") == "ComputerName"
-            ).select(
-              pl.col("ValueData")
-            )
-          host = host.collect()[0].item()
-          return host
-        except Exception as e:
-          print(f'Ran into an error when trying to get the hostname from the registry.')
-          print('Error was:', e)
-      else:
-        print(f"Unable to get hostname from registry file: {file}")
+  for file in art_files(dict_tln['registry']['file'], dict_tln['registry']['regex_file']):
+    if os.path.exists(file):
+      try:
+        df = pl.scan_csv(file)
+        host = df.select(
+          ["ValueName","ValueData"]
+          ).filter(
+            pl.col("ValueName") == "ComputerName"
+          ).select(
+            pl.col("ValueData")
+          )
+        host = host.collect()[0].item()
+        return host
+      except Exception as e:
+        print(f'Ran into an error when trying to get the hostname from the registry.')
+        print('Error was:', e)
+    else:
+      print(f"Unable to get hostname from registry file: {file}")
   # hostname not found in registry
   if os.path.exists(dict_tln['hayabusa']['file']):
     # get the hostname from the last line in the hayabusa output
@@ -125,14 +142,8 @@ def get_all_tln(dict_tln, time_from, time_to, host):
   # create empty dataframe for all the artefact timelines
   all_tln = pl.DataFrame({})
   for art in dict_tln:
-    # for each file in dict_tln[art]['file'], which can have asterisk
-    files = []
-    if os.path.isdir(dict_tln[art]['file']):
-      for file in os.listdir(dict_tln[art]['file']):
-        if re.search(dict_tln[art]['regex_file'], file):
-          files.append(os.path.join(dict_tln[art]['file'], file))
-    else:
-      files.append(dict_tln[art]['file'])
+    # for each file in dict_tln[art]['file'], which can be a folder or list of folders
+    files = art_files(dict_tln[art]['file'], dict_tln[art].get('regex_file', ''))
 
     # create empty dataframe for each artefact timeline
     files_tln = pl.DataFrame({})
@@ -172,131 +183,131 @@ def get_all_tln(dict_tln, time_from, time_to, host):
   # return all_tln
 
 
-def csv_to_tln(out_filepath, time_from, time_to):
+def csv_to_tln(out_filepath, time_from, time_to, outdirs, out_folder):
   # dict_tln needs the file, out, msg, times and fmt_time. If the file is a dir, the regex_file is needed to match the file name
   dict_tln = {
     'amcache': {
       'regex_file': r'(?:Amcache_UnassociatedFileEntries)\.csv$',
-      'file': os.path.join(*[f'{out_filepath}','FileExecution']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','amcache.csv']),
+      'file': out_dir(outdirs, 'AmcacheParser', out_filepath, 'FileExecution'),
+      'out': os.path.join(out_folder, 'amcache.csv'),
       'msg': ['SHA1','FullPath','FileExtension','ProductName'],
       'times': ['FileKeyLastWriteTimestamp','FileIDLastWriteTimestamp'],
       'fmt_time': '%F %T'
     },
     'prefetch': {
-      'file': os.path.join(*[f'{out_filepath}','FileExecution','prefetch_Timeline.csv']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','prefetch.csv']),
+      'file': os.path.join(out_dir(outdirs, 'Prefetch', out_filepath, 'FileExecution'), 'prefetch_Timeline.csv'),
+      'out': os.path.join(out_folder, 'prefetch.csv'),
       'msg': ['ExecutableName'],
       'times': ['RunTime'],
       'fmt_time': '%F %T%.f'
     },
     'appcompatcache': {
-      'file': os.path.join(*[f'{out_filepath}','FileExecution','appcompatcache.csv']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','appcompatcache.csv']),
+      'file': os.path.join(out_dir(outdirs, 'AppCompatCache', out_filepath, 'FileExecution'), 'appcompatcache.csv'),
+      'out': os.path.join(out_folder, 'appcompatcache.csv'),
       'msg': ['ControlSet','CacheEntryPosition','Path','Executed','Duplicate','SourceFile'],
       'times': ['LastModifiedTimeUTC'],
       'fmt_time': '%F %T'
     },
     'sccm_execution': {
-      'file': os.path.join(*[f'{out_filepath}','FileExecution','SCCM_RecentlyUsedApplication.psv']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','sccm_recentlyused.csv']),
+      'file': os.path.join(out_dir(outdirs, 'SCCM_RecentlyUsedApplication', out_filepath, 'FileExecution'), 'SCCM_RecentlyUsedApplication.psv'),
+      'out': os.path.join(out_folder, 'sccm_recentlyused.csv'),
       'msg': ['FolderPath','ExplorerFileName','LastUserName','LaunchCount','FileDescription','CompanyName','ProductName'],
       'times': ['LastUsedTime'],
       'fmt_time': '%F %T'
     },
     'network_sum': {
       'regex_file': r'(?:SumECmd_DETAIL_ClientsDetailed_Output)\.csv$',
-      'file': os.path.join(*[f'{out_filepath}','Network']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','network.csv']),
+      'file': out_dir(outdirs, 'SumECmd', out_filepath, 'Network'),
+      'out': os.path.join(out_folder, 'network.csv'),
       'msg': ['Count','DayNumber','RoleGuid','RoleDescription','AuthenticatedUserName','TotalAccesses','IpAddress','ClientName','TenantId','SourceFile'],
       'times': ['InsertDate','LastAccess'],
       'fmt_time': '%F %T'
     },
     'browser-hist': {
       # TODO: resolve parsing error of none utf-8
-      'file': os.path.join(*[f'{out_filepath}','Network','BrowsingHistory.csv']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','browser-hist.csv']),
+      'file': os.path.join(out_dir(outdirs, 'Browsing History', out_filepath, 'Network'), 'BrowsingHistory.csv'),
+      'out': os.path.join(out_folder, 'browser-hist.csv'),
       'msg': ['URL','Title','Visited From','Visit Type','Web Browser','User Profile'],
       'times': ['Visit Time'],
       'fmt_time': '%d/%m/%Y %T'
     },
     'shellbags': {
       'regex_file': r'(?:UsrClass|NTUSER)\.csv$',
-      'file': os.path.join(*[f'{out_filepath}','UserActivity']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','shellbags.csv']),
+      'file': out_dir(outdirs, 'SBE', out_filepath, 'UserActivity'),
+      'out': os.path.join(out_folder, 'shellbags.csv'),
       'msg': ['AbsolutePath','ShellType','Value'],
       'times': ['CreatedOn','ModifiedOn','AccessedOn','LastWriteTime','FirstInteracted','LastInteracted'],
       'fmt_time': '%F %T'
     },
     'jump-lists': {
       'regex_file': r'(?:AutomaticDestinations|CustomDestinations)\.csv$',
-      'file': os.path.join(*[f'{out_filepath}','UserActivity']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','jump-lists.csv']),
+      'file': out_dir(outdirs, 'JLECmd', out_filepath, 'UserActivity'),
+      'out': os.path.join(out_folder, 'jump-lists.csv'),
       'msg': ['SourceFile','AppIdDescription','MachineID','LocalPath','CommonPath','TargetIDAbsolutePath','FileSize','Arguments'],
       'times': ['SourceCreated','SourceModified','SourceAccessed','TargetCreated','TargetModified','TargetAccessed','TrackerCreatedOn'],
       'fmt_time': '%F %T'
     },
     'registry': {
       'regex_file': r'reg-(?:System|User)\.csv$',
-      'file': os.path.join(*[f'{out_filepath}','Registry']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','registry.csv']),
+      'file': [out_dir(outdirs, 'RegSystem', out_filepath, 'Registry'), out_dir(outdirs, 'RegUser', out_filepath, 'Registry')],
+      'out': os.path.join(out_folder, 'registry.csv'),
       'msg': ['HivePath','Description','Category','ValueName','ValueData','ValueData2','ValueData3','Comment'],
       'times': ['LastWriteTimestamp'],
       'fmt_time': '%F %T%.f'
     },
     'lnk-files': {
-      'file': os.path.join(*[f'{out_filepath}','FileSystem','lnk-files.csv']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','lnk-files.csv']),
+      'file': os.path.join(out_dir(outdirs, 'lnk', out_filepath, 'FileSystem'), 'lnk-files.csv'),
+      'out': os.path.join(out_folder, 'lnk-files.csv'),
       'msg': ['SourceFile','FileSize','RelativePath','WorkingDirectory','LocalPath','NetworkPath','CommonPath','Arguments','MachineID'],
       'times': ['SourceCreated','SourceModified','SourceAccessed','TargetCreated','TargetModified','TargetAccessed','TrackerCreatedOn'],
       'fmt_time': '%F %T'
     },
     'recycle-bin': {
       'regex_file': r'RBCmd_Output\.csv$',
-      'file': os.path.join(*[f'{out_filepath}','FileSystem']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','recycle-bin.csv']),
+      'file': out_dir(outdirs, 'rbcmd', out_filepath, 'FileSystem'),
+      'out': os.path.join(out_folder, 'recycle-bin.csv'),
       'msg': ['FileName','FileSize'],
       'times': ['DeletedOn'],
       'fmt_time': '%F %T'
     },
     'mft':{
-      'file': os.path.join(*[f'{out_filepath}','FileSystem','MFTECmd.csv']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','MFTECmd.csv']),
+      'file': os.path.join(out_dir(outdirs, 'MFTECmd', out_filepath, 'FileSystem'), 'MFTECmd.csv'),
+      'out': os.path.join(out_folder, 'MFTECmd.csv'),
       'msg':['ParentPath','FileName','Extension','FileSize'],
       'times':['Created0x10','Created0x30','LastModified0x10','LastModified0x30','LastRecordChange0x10','LastRecordChange0x30','LastAccess0x10','LastAccess0x30'],
       'fmt_time': '%F %T%.f'
     },
     'event-logs': {
-      'file': os.path.join(*[f'{out_filepath}','EventLogs','EvtxECmd-All.csv']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','event-logs.csv']),
+      'file': os.path.join(out_dir(outdirs, 'EvtxECmd', out_filepath, 'EventLogs'), 'EvtxECmd-All.csv'),
+      'out': os.path.join(out_folder, 'event-logs.csv'),
       'msg': ['EventId','Level','Provider','Channel','Computer','UserId','MapDescription','UserName','RemoteHost','Payload'],
       'times': ['TimeCreated'],
       'fmt_time': '%F %T%.f'
     },
     'usnjrnl-j':{
-      'file': os.path.join(*[f'{out_filepath}','FileSystem','usnjrnl-j-file.csv']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','usnjrnl-j-file.csv']),
+      'file': os.path.join(out_dir(outdirs, 'usnjrnl-j', out_filepath, 'FileSystem'), 'usnjrnl-j-file.csv'),
+      'out': os.path.join(out_folder, 'usnjrnl-j-file.csv'),
       'msg': ['Name','Extension','EntryNumber','ParentEntryNumber','ParentPath','UpdateReasons','FileAttributes'],
       'times': ['UpdateTimestamp'],
       'fmt_time': '%F %T%.f'
     },
     'hayabusa': {
-      'file': os.path.join(*[f'{out_filepath}','EventLogs','hayabusa.csv']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','hayabusa.csv']),
+      'file': os.path.join(out_dir(outdirs, 'hayabusa', out_filepath, 'EventLogs'), 'hayabusa.csv'),
+      'out': os.path.join(out_folder, 'hayabusa.csv'),
       'msg': ['Computer','Channel','EventID','Level','MitreTactics','MitreTags','OtherTags','RecordID','Details','ExtraFieldInfo','RuleFile','EvtxFile'],
       'times': ['datetime'],
       'fmt_time': '%FT%T%.f'
     },
     'rusty_usnjrnl': {
-      'file': os.path.join(*[f'{out_filepath}','FileSystem','usnjrnl_j.json']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','usnjrnl_j_rusty.csv']),
+      'file': os.path.join(out_dir(outdirs, 'j_file', out_filepath, 'FileSystem'), 'usnjrnl_j.json'),
+      'out': os.path.join(out_folder, 'usnjrnl_j_rusty.csv'),
       'msg': ['file_name','full_name','file_name_length','reason','file_attributes'],
       'times': ['timestamp'],
       'fmt_time': '%FT%T%.f'
     },
     'mft_dump': {
-      'file': os.path.join(*[f'{out_filepath}','FileSystem','mft.csv']),
-      'out': os.path.join(*[f'{out_filepath}','Timeline','mft_dump.csv']),
+      'file': os.path.join(out_dir(outdirs, 'mft', out_filepath, 'FileSystem'), 'mft.csv'),
+      'out': os.path.join(out_folder, 'mft_dump.csv'),
       'msg': ['FullPath','TotalEntrySize','FileSize','StandardInfoFlags','FileNameFlags','IsADirectory','IsDeleted','HasAlternateDataStreams'],
       'times': ['StandardInfoLastModified','StandardInfoLastAccess','StandardInfoCreated','FileNameLastModified','FileNameLastAccess','FileNameCreated'],
       'fmt_time': '%FT%T%.f'
@@ -326,9 +337,13 @@ def main():
   parser.add_argument('out_filepath')
   parser.add_argument('time_from')
   parser.add_argument('time_to')
+  parser.add_argument('--outdir', action='append', default=[], help='the output folder of a command as name=path, i.e. hayabusa={outdir:hayabusa}')
+  parser.add_argument('--out-folder', help='the folder to write the timelines to, default out_filepath/Timeline')
   args = parser.parse_args()
 
-  csv_to_tln(args.out_filepath, args.time_from, args.time_to)
+  outdirs = dict(o.split('=', 1) for o in args.outdir)
+  out_folder = args.out_folder or os.path.join(args.out_filepath, 'Timeline')
+  csv_to_tln(args.out_filepath, args.time_from, args.time_to, outdirs, out_folder)
 
 if __name__ == '__main__':
   main()
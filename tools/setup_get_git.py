@@ -3,6 +3,7 @@ import urllib.request
 from urllib.parse import urlparse
 import zipfile
 import argparse
+import json
 import os
 import stat
 import filetype
@@ -79,6 +80,18 @@ def make_symlink(target_dir: str, program: str, script_os: str):
     os.chmod(target_file, tf_stats.st_mode | stat.S_IEXEC)
 
 
+def record_version(program: str, version: str):
+  # keep the version of each tool installed in versions.json, for wiskess to read
+  versions_file = os.path.join(os.getcwd(), 'versions.json')
+  versions = {}
+  if os.path.exists(versions_file):
+    with open(versions_file) as f:
+      versions = json.load(f)
+  versions[program] = version
+  with open(versions_file, 'w') as f:
+    json.dump(versions, f, indent=2, sort_keys=True)
+
+
 def get_release(token: str, url: str, script_os: str):
     repo = urlparse(url).path
     repo = re.sub('\.git$', '', repo)
@@ -101,6 +114,7 @@ def get_release(token: str, url: str, script_os: str):
       get_files(response, target_dir)
       # symlink the main .exe to program.exe
       make_symlink(target_dir, program, script_os)
+      record_version(program, response.json().get('tag_name', 'unknown'))
     else:
       print(f'[!] Unable to get the repo from link: {url}')
       print('[ ] Please check the link exists')
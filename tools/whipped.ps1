@@ -21,10 +21,12 @@
     Required. The config used to pass to wiskess
 .PARAMETER data_source_list
     Required. The paths to the file, folder of images, collections, etc. Must be separated by comma ','
+    A data source can have its own in_link after a '|', i.e. "host1.E01 | s3://bucket/case", for a case with evidence in
+    both Azure and AWS. It is downloaded from that link, and the others from -in_link, into the same local_storage.
 .PARAMETER local_storage
     Required. The path to where the data is temporarily downloaded to and Wiskess output is stored locally
 .PARAMETER in_link
-    Required. The link that the data is stored on, i.e. https://myaccount.file.core.windows.net/myclient/?sp=rl&st=...VWjgWTY8uc%3D&sr=s
+    Required, unless -no_download. The link that the data is stored on, i.e. https://myaccount.file.core.windows.net/myclient/?sp=rl&st=...VWjgWTY8uc%3D&sr=s
 .PARAMETER out_link
     Required. The link where you need the wiskess output uploaded to, i.e. https://myaccount.file.core.windows.net/results/myclient/?sp=rcwl&st=2023-04-21T20...2FZWEA%3D&sr=s
 .PARAMETER start_date
@@ -38,6 +40,71 @@
 .PARAMETER keep_evidence
     Optional. Set this flag to keep the downloaded data on your local storage. Useful if wanting to process the data after Wiskess.
     Caution: make sure you have enough disk space for all the data source list.
+.PARAMETER run_id
+    Optional. The correlation id of this run, passed to wiskess for each data source so the logs and summaries can be tied together.
+.PARAMETER case_name
+    Optional. The name of the case, passed to wiskess for each data source so its log, summary and reports are tied to the case.
+    default is the name of the wiskess output folder of each data source
+.PARAMETER prefix_run_id
+    Optional. Set this flag to upload the results under a folder named by the run id, i.e. out_link/<run_id>/<image>-Wiskess
+.PARAMETER resume_download
+    Optional. Set this flag to download single files in chunks to a .part file, which resumes from the last completed byte on restart
+    and has its size verified on completion.
+.PARAMETER download_chunk_mb
+    Optional. The size of each chunk in MB when using -resume_download. Default is 64.
+.PARAMETER download_concurrency
+    Optional. The maximum number of simultaneous connections when downloading, to stay within the rate limits of the
+    storage service. Sets the concurrency of azcopy and aws s3, which also retries with backoff when throttled. Default 0
+    uses the default of the transfer tool.
+.PARAMETER token_refresh_command
+    Optional. A command run to get fresh credentials when a transfer fails as the SAS or STS token expired, so long runs
+    survive token rotation. It outputs json with the new "in_link" and/or "out_link", and optionally "env" of variables to
+    set, i.e. {"env": {"AWS_SESSION_TOKEN": "..."}}. The transfer is then retried with the new link, up to 3 times.
+.PARAMETER source_prefix
+    Optional. Map the artefact paths of the config onto where the downloaded files are under local_storage, passed to wiskess
+    as --source-prefix. Either a subfolder, i.e. uploads/auto/C%3A, from=to to replace the start of the paths, or auto.
+.PARAMETER encrypt_output
+    Optional. Set this flag to encrypt the wiskess output with AES-256-GCM before it is uploaded, so it is encrypted independent
+    of the storage. Each file is replaced by a .enc file, with the nonces in wiskess_encryption.json. Decrypt with
+    wiskess_rust.exe decrypt. The output is not uploaded if it can't be encrypted.
+.PARAMETER encrypt_key
+    Optional. The file of the 256 bit key used with -encrypt_output, as 64 hex characters or 32 bytes.
+.PARAMETER output_root_link
+    Optional. The link to upload each output file to as the wiskess command that made it completes, rather than all at the
+    end, so reviewers can access the early outputs of a long run, i.e. the same as out_link. The files uploaded are tracked,
+    so they aren't uploaded again while wiskess runs, and the final upload only copies those that are new or changed.
+    Not used with encrypt_output, as the outputs are only uploaded once encrypted.
+.PARAMETER list_remote
+    Optional. Set this flag to list the files that would be downloaded for the data source list, with the size of each and the
+    total, using the list API of the storage, then exit without transferring anything. Use it to check the disk space and
+    bandwidth needed before a large download.
+.PARAMETER include
+    Optional. Only download the files of the data sources that match these globs, separated by ';', i.e. "*.evtx;*/config/SYSTEM".
+    Each is matched against the path of the file under the data source and its name. The files are listed then downloaded
+    one at a time, logging the number and size of those skipped.
+.PARAMETER exclude
+    Optional. Don't download the files of the data sources that match these globs, separated by ';', i.e. "pagefile.sys;*.vhdx".
+.PARAMETER storage_type
+    Optional. The storage of the links. Default auto uses Azure or AWS by the link. custom transfers with -download_command
+    and -upload_command instead, to use a tool such as rclone or s5cmd. The custom storage can't list the remote files, so
+    each data source is processed even if its output was uploaded, and the whole output is uploaded at the end.
+.PARAMETER download_command
+    Optional. The command to download with the custom storage, where ${remote} is the link joined with the path of the data
+    source, ${local} is the folder to download to and ${link} is the link, i.e. "rclone copy ${remote} ${local}".
+.PARAMETER upload_command
+    Optional. The command to upload with the custom storage, where ${remote} is the link joined with the folder to upload to,
+    ${local} is the file or folder to upload and ${link} is the link, i.e. "rclone copy ${local} ${remote}".
+.PARAMETER upload_if_valid
+    Optional. Set this flag to only upload the results of a data source if the run is valid, checked by wiskess summary,
+    otherwise they are left on the local storage. The results are then uploaded once the run ends, not as each command completes.
+.PARAMETER valid_threshold
+    Optional. The percent of the commands that ran that must be valid to upload the results, with -upload_if_valid. Default 100.
+.PARAMETER no_download
+    Optional. Set this flag to not download the data sources, but process those already downloaded to local_storage, i.e.
+    with another config. The data is not removed after, as with -keep_evidence, so it can be processed again.
+.PARAMETER out_suffix
+    Optional. Added to the name of the output folder of each data source, i.e. <image>-Wiskess-<suffix>, to keep the
+    output of each config apart.
 .EXAMPLE
     Run with a list of data sources (needs to be the path from the azure storage), where each is separated by a comma or new line:
     .\whipped.ps1 -data_source_list "image.vmdk, folder with collection, surge.zip, velociraptor_collection.7z" `
@@ -58,13 +125,34 @@ param (
     [Parameter(Mandatory)] [string] $config,
     [Parameter(Mandatory)] [string] $data_source_list,
     [Parameter(Mandatory)] [string] $local_storage,
-    [Parameter(Mandatory)] [string] $in_link,
+    [Parameter()] [string] $in_link = "",
     [Parameter(Mandatory)] [string] $out_link,
     [Parameter()] [string] $ioc_file = "$PSScriptRoot\iocs.txt",
     [Parameter(Mandatory)] [string] $start_date,
     [Parameter(Mandatory)] [string] $end_date,
     [Parameter()] [switch] $update = $False,
     [Parameter()] [switch] $keep_evidence = $False,
+    [Parameter()] [string] $run_id = "",
+    [Parameter()] [string] $case_name = "",
+    [Parameter()] [switch] $prefix_run_id = $False,
+    [Parameter()] [switch] $resume_download = $False,
+    [Parameter()] [int] $download_chunk_mb = 64,
+    [Parameter()] [int] $download_concurrency = 0,
+    [Parameter()] [string] $source_prefix = "",
+    [Parameter()] [string] $token_refresh_command = "",
+    [Parameter()] [switch] $encrypt_output = $False,
+    [Parameter()] [string] $encrypt_key = "",
+    [Parameter()] [string] $output_root_link = "",
+    [Parameter()] [switch] $list_remote = $False,
+    [Parameter()] [string] $include = "",
+    [Parameter()] [string] $exclude = "",
+    [Parameter()] [string] $storage_type = "auto",
+    [Parameter()] [string] $download_command = "",
+    [Parameter()] [string] $upload_command = "",
+    [Parameter()] [switch] $upload_if_valid = $False,
+    [Parameter()] [int] $valid_threshold = 100,
+    [Parameter()] [switch] $no_download = $False,
+    [Parameter()] [string] $out_suffix = "",
     [Parameter()] [string] $tool_path = $PSScriptRoot
 )
 
@@ -181,9 +269,69 @@ function Start-Wiskess ($dataSource, $wiskess_folder, $start_date, $end_date, $i
         "--ioc-file $ioc_file " +
         "--start-date $start_date " +
         "--end-date $end_date"
+    if ($run_id -ne "") {
+        $cmdline += " --run-id $run_id"
+    }
+    if ($case_name -ne "") {
+        $cmdline += " --case-name `"$case_name`""
+    }
+    if ($source_prefix -ne "") {
+        $cmdline += " --source-prefix `"$source_prefix`""
+    }
+
+    # the outputs are only uploaded once encrypted, when encrypting them, or once validated
+    if ($output_root_link -eq "" -or $encrypt_output -or $upload_if_valid) {
+        Write-Host "[+] Running command: $binary $cmdline"
+        Start-Process $binary $cmdline -NoNewWindow -Wait
+        return
+    }
 
+    # upload the outputs of each command as it completes, from the list wiskess appends them to
+    $completed_file = "$local_storage\$($wiskess_folder)-completed.txt"
+    $cmdline += " --completed-file `"$completed_file`""
     Write-Host "[+] Running command: $binary $cmdline"
-    Start-Process $binary $cmdline -NoNewWindow -Wait
+    $proc = Start-Process $binary $cmdline -NoNewWindow -PassThru
+    while (!$proc.HasExited) {
+        Start-Sleep -Seconds 30
+        Upload-Completed $completed_file $wiskess_folder
+    }
+    Upload-Completed $completed_file $wiskess_folder
+}
+
+# Upload-Completed - upload the output files listed in the completed file, that have not been uploaded yet
+function Upload-Completed ($completed_file, $wiskess_folder) {
+    if (!(Test-Path $completed_file)) {
+        return
+    }
+    if ($null -eq $script:uploaded) {
+        $script:uploaded = [System.Collections.Generic.HashSet[string]]::new()
+    }
+    $upload_folder = $wiskess_folder
+    if ($prefix_run_id -and $run_id -ne "") {
+        $upload_folder = "$run_id/$wiskess_folder"
+    }
+    $out_folder = "$local_storage\$($wiskess_folder)"
+    foreach ($file in Get-Content $completed_file) {
+        if ($file -eq "" -or $script:uploaded.Contains($file) -or !(Test-Path -PathType Leaf $file)) {
+            continue
+        }
+        $rel = [System.IO.Path]::GetRelativePath($out_folder, $file) -replace "\\","/"
+        Write-Host "[ ] Uploading completed output $rel"
+        Invoke-Transfer {
+            param ($dst)
+            if ($storage_type -eq "custom") {
+                Invoke-CustomTransfer $upload_command "$($dst -replace "/*$",'')/$upload_folder/$rel" "$file" $dst
+            } elseif ($dst -match "^s3") {
+                # if the cloud storage is AWS
+                aws s3 cp "$file" "$($dst -replace "/*$",'')/$upload_folder/$rel"
+            } elseif ($dst -match "^https://[^/]+.core.windows.net") {
+                # if the cloud storage is Azure
+                $url = '{0}/{1}/{2}?{3}' -f $dst.Split("?")[0].TrimEnd("/"),$upload_folder,$rel,$dst.Split("?",2)[1]
+                & "$tool_path\tools\azcopy\azcopy.exe" copy "$file" "$url"
+            }
+        } $output_root_link "output_root_link"
+        [void]$script:uploaded.Add($file)
+    }
 }
 
 # TODO: List the data_source_list to get size of largest file 
@@ -191,7 +339,10 @@ function Start-Wiskess ($dataSource, $wiskess_folder, $start_date, $end_date, $i
 function Find-Uploaded($out_URL) {
     Write-Host "[ ] Checking if already done $out_URL"
     $uploaded = $False
-    if ($out_URL -match "^s3") {
+    if ($storage_type -eq "custom") {
+        # the custom storage has no command to list what was uploaded
+        return $False
+    } elseif ($out_URL -match "^s3") {
         # if the cloud storage is AWS
         $size = $(aws s3 ls $out_URL --summarize --recursive) -match "Total Size" -replace ".*Total Size:\s*"
         $uploaded = [int]$size[0] -gt 50
@@ -220,38 +371,448 @@ function Get-VMDKDescriptor ($dataS, $in_link) {
     }
 }
 
-# Download-Cloud - src is Cloud url, dst is local folder
-function Download-Cloud ($src, $dst) {
-    Write-Host "[ ] Copying data from $src to $dst"
+# Get-RemoteList - list the files of a data source on the in_link with their size in bytes, without
+# downloading them. A vmdk also lists its descriptor or flat file, as both are downloaded
+function Get-RemoteList ($dataS, $in_URL) {
+    $files = @()
+    if ($in_link -match "^s3") {
+        # if the cloud storage is AWS, list the keys under the data source, or starting with the vmdk stub
+        $bucket, $prefix = $($in_URL -replace "^s3://","") -split "/",2
+        if ($dataS -match "\.vmdk$") {
+            $prefix = $prefix -replace "(?:-flat\.vmdk|\.vmdk)$",""
+        }
+        $files = aws s3 ls "s3://$bucket/$prefix" --recursive | ForEach-Object {
+            if ($_ -match "^\S+\s+\S+\s+(\d+)\s+(.+)$") {
+                [pscustomobject]@{ Path = "s3://$bucket/$($Matches[2])"; Size = [int64]$Matches[1] }
+            }
+        } | Where-Object {
+            $key = $_.Path -replace "^s3://$bucket/",""
+            $key -eq $prefix -or $key.StartsWith("$($prefix.TrimEnd('/'))/") -or ($dataS -match "\.vmdk$" -and $key.StartsWith($prefix))
+        }
+    } elseif ($in_link -match "^https://[^/]+.core.windows.net") {
+        # if the cloud storage is Azure, list the in_link and keep those of the data source
+        $stub = $("$dataS" -Replace "(?:-flat\.vmdk|\.vmdk)$","") -replace "\\","/"
+        $files = & "$tool_path\tools\azcopy\azcopy.exe" list "$in_link" --machine-readable | ForEach-Object {
+            if ($_ -match "^INFO:\s*(.+?);\s*Content Length:\s*(\d+)") {
+                [pscustomobject]@{ Path = $Matches[1]; Size = [int64]$Matches[2] }
+            }
+        } | Where-Object { $_.Path -eq $stub -or $_.Path.StartsWith("$stub/") -or ($dataS -match "\.vmdk$" -and $_.Path.StartsWith($stub)) }
+    }
+    return $files
+}
+
+# Get-RemotePath - the path of a listed remote file under the data source, empty if it is the data source
+function Get-RemotePath ($path, $dataS) {
+    $stub = "$dataS" -replace "\\","/"
+    return $path -replace "^.*?$([regex]::Escape($stub))/?",""
+}
+
+# Select-RemoteFiles - keep the listed remote files of a data source that match an -include glob, if any, and
+# none of the -exclude globs, by their path under the data source or their name
+function Select-RemoteFiles ($files, $dataS) {
+    $includes = @($include.Split(";").Trim() | Where-Object { $_ })
+    $excludes = @($exclude.Split(";").Trim() | Where-Object { $_ })
+    $files | Where-Object {
+        $rel = Get-RemotePath $_.Path $dataS
+        $name = $_.Path -replace "^.*/",""
+        $matched = { param ($globs) @($globs | Where-Object { $rel -like $_ -or $name -like $_ }).Count -gt 0 }
+        ($includes.Count -eq 0 -or (& $matched $includes)) -and !(& $matched $excludes)
+    }
+}
 
+# Download-Filtered - download the files of a data source that match -include and -exclude one at a time, to
+# its folder under local_storage, logging the number and size of the files skipped
+function Download-Filtered ($dataS, $in_URL) {
+    $files = @(Get-RemoteList $dataS $in_URL)
+    $selected = @(Select-RemoteFiles $files $dataS)
+    $skipped = @($files | Where-Object { $selected.Path -notcontains $_.Path })
+    $skipped_size = ($skipped | Measure-Object -Property Size -Sum).Sum
+    Write-Host "[+] Downloading $($selected.Count) of $($files.Count) files of $dataS, skipping $($skipped.Count) files of $([math]::Round($skipped_size / 1MB, 2)) MB by -include and -exclude"
+    foreach ($file in $selected) {
+        $rel = Get-RemotePath $file.Path $dataS
+        $dst = Split-Path -Parent "$local_storage\$dataS\$rel"
+        New-Item -ItemType Directory $dst -Force | Out-Null
+        if ($in_link -match "^s3") {
+            $src = $file.Path
+        } else {
+            $src = '{0}/{1}?{2}' -f $in_link.Split("?")[0].TrimEnd("/"),$file.Path,$in_link.Split("?")[1]
+        }
+        Invoke-Transfer {
+            param ($src)
+            if ($src -match "^s3") {
+                aws s3 cp "$src" "$dst\"
+            } else {
+                & "$tool_path\tools\azcopy\azcopy.exe" copy "$src" "$dst\"
+            }
+        } $src "in_link"
+    }
+}
+
+# Show-RemoteList - print the files that would be downloaded for each data source, with the total size
+function Show-RemoteList ($data_sources) {
+    $total = [int64]0
+    $count = 0
+    foreach ($dataS in $data_sources) {
+        if ($dataS -eq "") {
+            continue
+        }
+        $script:in_link = $source_links[$dataS]
+        $out_URL, $in_URL = Set-UrlLinks $dataS "list"
+        $files = @(Get-RemoteList $dataS $in_URL)
+        if ($include -ne "" -or $exclude -ne "") {
+            $files = @(Select-RemoteFiles $files $dataS)
+        }
+        $size = ($files | Measure-Object -Property Size -Sum).Sum
+        if ($files.Count -eq 0) {
+            Write-Warning "No files found for $dataS"
+            continue
+        }
+        Write-Host "[+] $dataS, $($files.Count) files, $([math]::Round($size / 1GB, 2)) GB"
+        $files | Format-Table @{Label = "File"; Expression = { $_.Path }}, @{Label = "Size (MB)"; Expression = { [math]::Round($_.Size / 1MB, 2) }; Align = "Right"} | Out-Host
+        $total += $size
+        $count += $files.Count
+    }
+    Write-Host "[+] Total to download: $count files, $([math]::Round($total / 1GB, 2)) GB"
+}
+
+# Get-RemoteSize - src is Cloud url of a file, returns the size in bytes
+function Get-RemoteSize ($src) {
     if ($src -match "^s3") {
-        # if the cloud storage is AWS
-        if ($src -match "[^\\]*\.\w{2,3}$") {
-            Write-Host "[ ] Data is a file"
-            aws s3 cp "$src" "$dst"
+        $bucket, $key = $($src -replace "^s3://","") -split "/",2
+        return [int64]$(aws s3api head-object --bucket $bucket --key $key --query ContentLength --output text)
+    }
+    $client = [System.Net.Http.HttpClient]::new()
+    $response = $client.SendAsync([System.Net.Http.HttpRequestMessage]::new("HEAD", $src)).Result
+    $response.EnsureSuccessStatusCode() | Out-Null
+    return [int64]$response.Content.Headers.ContentLength
+}
+
+# Set-DownloadConcurrency - limit the simultaneous connections of the transfer tools, and retry with
+# backoff when the storage service throttles them
+function Set-DownloadConcurrency ($concurrency) {
+    # azcopy retries throttled (429/503) requests itself, this bounds its connections
+    $env:AZCOPY_CONCURRENCY_VALUE = "$concurrency"
+    # adaptive retry mode backs off the aws cli when throttled
+    $env:AWS_RETRY_MODE = "adaptive"
+    $env:AWS_MAX_ATTEMPTS = "10"
+    # set the s3 concurrency in a copy of the aws config, so the user's config is not changed
+    $aws_config = if ($env:AWS_CONFIG_FILE) { $env:AWS_CONFIG_FILE } else { Join-Path $HOME ".aws/config" }
+    $whipped_config = Join-Path ([System.IO.Path]::GetTempPath()) "whipped_aws_config"
+    if (Test-Path -PathType Leaf $aws_config) {
+        Copy-Item -Force $aws_config $whipped_config
+    } else {
+        New-Item -Force -ItemType File $whipped_config | Out-Null
+    }
+    $env:AWS_CONFIG_FILE = $whipped_config
+    if (Get-Command aws -ErrorAction SilentlyContinue) {
+        aws configure set default.s3.max_concurrent_requests $concurrency
+    }
+    Write-Host "[ ] Download concurrency limited to $concurrency connections"
+}
+
+# Save-Chunk - write the bytes from offset to end (inclusive) of src, a Cloud url, to the part_file
+# at offset, truncating it there first so a retry of the chunk does not append it twice
+function Save-Chunk ($src, $part_file, $offset, $end) {
+    $stream = $null
+    $chunk = $null
+    $client = $null
+    $response = $null
+    try {
+        if ($src -match "^s3") {
+            $bucket, $key = $($src -replace "^s3://","") -split "/",2
+            aws s3api get-object --bucket $bucket --key $key --range "bytes=$offset-$end" "$part_file.chunk" | Out-Null
+            if ($LASTEXITCODE -ne 0) {
+                throw "aws s3api get-object exited with $LASTEXITCODE"
+            }
+            $chunk = [System.IO.File]::OpenRead("$part_file.chunk")
         } else {
-            Write-Host "[ ] Data is a folder"
-            aws s3 cp "$src" "$dst" --recursive
+            $client = [System.Net.Http.HttpClient]::new()
+            $request = [System.Net.Http.HttpRequestMessage]::new("GET", $src)
+            $request.Headers.Range = [System.Net.Http.Headers.RangeHeaderValue]::new($offset, $end)
+            $response = $client.SendAsync($request, [System.Net.Http.HttpCompletionOption]::ResponseHeadersRead).Result
+            if ([int]$response.StatusCode -in 429, 503) {
+                $wait = 0
+                if ($response.Headers.RetryAfter.Delta) {
+                    $wait = [int]$response.Headers.RetryAfter.Delta.TotalSeconds
+                }
+                throw "Throttled by the storage service, retry after $wait seconds"
+            }
+            $response.EnsureSuccessStatusCode() | Out-Null
+            $chunk = $response.Content.ReadAsStreamAsync().Result
+        }
+        # write from the offset, dropping what a failed try of this chunk appended
+        $stream = [System.IO.FileStream]::new($part_file, [System.IO.FileMode]::OpenOrCreate)
+        $stream.SetLength($offset)
+        $stream.Seek($offset, [System.IO.SeekOrigin]::Begin) | Out-Null
+        $chunk.CopyTo($stream)
+    } finally {
+        if ($stream) { $stream.Dispose() }
+        if ($chunk) { $chunk.Dispose() }
+        if ($response) { $response.Dispose() }
+        if ($client) { $client.Dispose() }
+        if (Test-Path -PathType Leaf "$part_file.chunk") {
+            Remove-Item -Force "$part_file.chunk"
         }
-    } elseif ($src -match "^https://[^/]+.core.windows.net") {
-        # if the cloud storage is Azure
-        & "$tool_path\tools\azcopy\azcopy.exe" copy "$src" "$dst" --recursive
     }
 }
 
-# Upload-Cloud - src is local folder, dst is cloud url, folder is local folder and needed for aws
-function Upload-Cloud ($src, $dst, $folder) {
-    Write-Host "[ ] Syncing data from $src to $dst"
+# Download-Resumable - src is Cloud url of a file, dst is local folder
+# Downloads in chunks to a .part file, resuming from its size if it exists, then 
+# verifies the size before renaming it to the file name
+function Download-Resumable ($src, $dst, $link_name = "in_link") {
+    $file_name = [uri]::UnescapeDataString($(Split-Path -Leaf $src.Split("?")[0]))
+    $out_file = Join-Path $dst $file_name
+    $part_file = "$out_file.part"
+    $chunk_size = [int64]$download_chunk_mb * 1MB
+    $total = Get-RemoteSize $src
+
+    $offset = 0
+    if (Test-Path -PathType Leaf $part_file) {
+        $offset = (Get-Item $part_file).Length
+        Write-Host "[ ] Resuming $file_name from byte $offset of $total"
+    }
+    while ($offset -lt $total) {
+        $end = [Math]::Min($offset + $chunk_size, $total) - 1
+        $tries = 0
+        $throttled = 0
+        $refreshed = 0
+        while ($true) {
+            try {
+                Save-Chunk $src $part_file $offset $end
+                break
+            } catch {
+                # back off when throttled, waiting at least as long as the service asks
+                if ("$_" -match "retry after (\d+) seconds" -and $throttled -lt 10) {
+                    $throttled += 1
+                    $wait = [Math]::Max([int]$Matches[1], [Math]::Min([Math]::Pow(2, $throttled), 300))
+                    Write-Warning "Throttled downloading $file_name, waiting $wait seconds"
+                    Start-Sleep -Seconds $wait
+                    continue
+                }
+                # get a new token if it expired, and continue from the same byte
+                if ($token_refresh_command -ne "" -and (Test-AuthError "$_") -and $refreshed -lt 3) {
+                    $refreshed += 1
+                    Write-Warning "Download of $file_name failed as the credentials expired, refreshing them"
+                    Update-Credentials
+                    $src = Update-Sas $src (Get-Variable -Scope Script -Name $link_name -ValueOnly)
+                    continue
+                }
+                $tries += 1
+                if ($tries -ge 3) {
+                    Write-Error "Unable to download $file_name at byte $offset, rerun to resume. Error: $_"
+                    return
+                }
+                Write-Warning "Retrying chunk of $file_name at byte $offset. Error: $_"
+                Start-Sleep -Seconds (5 * $tries)
+            }
+        }
+        $offset = (Get-Item $part_file).Length
+        Write-Progress -Activity "Downloading $file_name" -Status "$offset of $total bytes" -PercentComplete ($offset * 100 / [Math]::Max($total, 1))
+    }
+    Write-Progress -Activity "Downloading $file_name" -Completed
+
+    $size = (Get-Item $part_file).Length
+    if ($size -ne $total) {
+        Write-Error "Downloaded size of $file_name is $size, expected $total. Remove $part_file to download again."
+        return
+    }
+    Move-Item -Force $part_file $out_file
+    Write-Host "[+] Downloaded $file_name, $total bytes"
+}
+
+# Update-Credentials - run the token refresh command, which outputs json with the new in_link and/or 
+# out_link, and optionally env vars to set, i.e. AWS_SESSION_TOKEN
+function Update-Credentials {
+    $new = Invoke-Expression $token_refresh_command | Out-String | ConvertFrom-Json
+    if ($new.in_link) {
+        # the data sources downloading from the same link use the new token too
+        foreach ($dataS in @($source_links.Keys)) {
+            if ($source_links[$dataS] -eq $script:in_link) {
+                $source_links[$dataS] = $new.in_link
+            }
+        }
+        $script:in_link = $new.in_link
+    }
+    if ($new.out_link) {
+        $script:out_link = $new.out_link
+    }
+    if ($new.env) {
+        $new.env.PSObject.Properties | ForEach-Object { Set-Item "env:$($_.Name)" $_.Value }
+    }
+    Write-Host "[ ] Refreshed the credentials with the token refresh command"
+}
+
+# Update-Sas - replace the SAS token of an Azure url with that of the link
+function Update-Sas ($url, $link) {
+    if ($url -match "^https://[^/]+.core.windows.net" -and $link.Contains("?")) {
+        return '{0}?{1}' -f $url.Split("?")[0], $link.Split("?",2)[1]
+    }
+    return $url
+}
+
+# Test-AuthError - check the output of a transfer for a credential that expired or is not valid,
+# by the auth error codes of the services, or a 403 given as the HTTP status, i.e. "Status: 403",
+# "HTTP/1.1 403" or "(403)", rather than any 403 in the output such as in a file name or size
+function Test-AuthError ($output) {
+    $codes = "AuthenticationFailed|AuthorizationFailure|ExpiredToken|TokenExpired|InvalidAccessKeyId|Signature.*(expired|not valid)"
+    $http_status = "(status( code)?\D{0,40}|HTTP/[\d.]+\s+|\()403\b"
+    return "$output" -match "$codes|$http_status"
+}
+
+# Invoke-Transfer - run the transfer scriptblock with the url, and if it fails as the token expired,
+# refresh the credentials with the token refresh command and retry with the new url of the link
+function Invoke-Transfer ($transfer, $url, $link_name) {
+    for ($tries = 0; $tries -le 3; $tries++) {
+        $global:LASTEXITCODE = 0
+        $output = & $transfer $url 2>&1 | ForEach-Object { Write-Host $_; $_ }
+        if ($LASTEXITCODE -eq 0 -or $token_refresh_command -eq "" -or !(Test-AuthError $output)) {
+            return
+        }
+        if ($tries -eq 3) {
+            Write-Error "Transfer failed as the credentials expired, after refreshing them 3 times"
+            return
+        }
+        Write-Warning "Transfer failed as the credentials expired, refreshing them"
+        Update-Credentials
+        $url = Update-Sas $url (Get-Variable -Scope Script -Name $link_name -ValueOnly)
+    }
+}
+
+# Invoke-CustomTransfer - run the download or upload command of the custom storage, replacing ${remote} with the
+# remote path, ${local} with the local path and ${link} with the link, so any transfer tool can be plugged in
+function Invoke-CustomTransfer ($command, $remote, $local, $link) {
+    $command = $command.Replace('${remote}', "`"$remote`"").Replace('${local}', "`"$local`"").Replace('${link}', "`"$link`"")
+    Invoke-Expression "& $command"
+}
+
+# Remove-PartialDownload - remove what was downloaded of a data source when the download is stopped, so the
+# next run downloads it again rather than finding it exists. Only the files of this data source are removed,
+# which is a file with its .part file, or a folder of them. The .part files are kept with -resume_download,
+# to resume from when rerun
+function Remove-PartialDownload ($dataS) {
+    Write-Warning "Download of $dataS was cancelled, removing the partial files from $local_storage"
+    if ($resume_download) {
+        Write-Warning "Keeping the .part files to resume the download from when rerun"
+        Get-ChildItem -File -Recurse "$local_storage\$dataS" -ErrorAction SilentlyContinue | Where-Object { $_.Extension -ne ".part" } | Remove-Item -Force
+    } else {
+        Remove-Item -Force -Recurse "$local_storage\$dataS" -ErrorAction SilentlyContinue
+        Remove-Item -Force "$local_storage\$dataS.part" -ErrorAction SilentlyContinue
+    }
+}
+
+# Download-Cloud - src is Cloud url, dst is local folder, link_name is the link the url is from
+function Download-Cloud ($src, $dst, $link_name = "in_link") {
+    Write-Host "[ ] Copying data from $src to $dst"
+
+    if ($storage_type -eq "custom") {
+        Invoke-Transfer {
+            param ($src)
+            Invoke-CustomTransfer $download_command $src $dst (Get-Variable -Scope Script -Name $link_name -ValueOnly)
+        } $src $link_name
+    } elseif ($resume_download -and $src.Split("?")[0] -match "[^\\/]*\.\w{2,4}$") {
+        Write-Host "[ ] Data is a file, downloading in chunks"
+        Download-Resumable $src $dst $link_name
+    } else {
+        Invoke-Transfer {
+            param ($src)
+            if ($src -match "^s3") {
+                # if the cloud storage is AWS
+                if ($src -match "[^\\]*\.\w{2,3}$") {
+                    Write-Host "[ ] Data is a file"
+                    aws s3 cp "$src" "$dst"
+                } else {
+                    Write-Host "[ ] Data is a folder"
+                    aws s3 cp "$src" "$dst" --recursive
+                }
+            } elseif ($src -match "^https://[^/]+.core.windows.net") {
+                # if the cloud storage is Azure
+                & "$tool_path\tools\azcopy\azcopy.exe" copy "$src" "$dst" --recursive
+            }
+        } $src $link_name
+    }
+}
+
+# Get-RemoteFiles - list the files under the cloud url, returns a hashtable of the path relative to it
+# and its size and MD5 as hex, which is empty if the storage doesn't have it, i.e. an s3 multipart upload
+function Get-RemoteFiles ($dst, $folder) {
+    $remote = @{}
     if ($dst -match "^s3") {
         # if the cloud storage is AWS
-        aws s3 sync "$src" "$dst/$folder"
+        $bucket, $prefix = $("$($dst -replace "/*$",'')/$folder" -replace "^s3://","") -split "/",2
+        $objects = aws s3api list-objects-v2 --bucket $bucket --prefix "$prefix/" --query "Contents[].[Key,Size,ETag]" --output json | ConvertFrom-Json
+        foreach ($obj in $objects) {
+            $etag = "$($obj[2])".Trim('"')
+            $md5 = if ($etag -match "-") { "" } else { $etag.ToLower() }
+            $remote[$obj[0].Substring($prefix.Length + 1)] = @{ Size = [int64]$obj[1]; MD5 = $md5 }
+        }
     } elseif ($dst -match "^https://[^/]+.core.windows.net") {
-        # if the cloud storage is Azure
-        & "$tool_path\tools\azcopy\azcopy.exe" copy "$src" "$dst" --recursive --overwrite=ifSourceNewer
+        # if the cloud storage is Azure, the MD5 is base64
+        $url = '{0}/{1}?{2}' -f $dst.Split("?")[0].TrimEnd("/"),$folder,$dst.Split("?",2)[1]
+        & "$tool_path\tools\azcopy\azcopy.exe" list "$url" --machine-readable --properties "ContentMD5" | ForEach-Object {
+            if ($_ -match "^INFO:\s*(.+?);\s*Content Length:\s*(\d+)(?:;\s*ContentMD5:\s*(\S*))?") {
+                $md5 = if ($Matches[3]) { [System.BitConverter]::ToString([System.Convert]::FromBase64String($Matches[3])).Replace("-","").ToLower() } else { "" }
+                $remote[$Matches[1] -replace "\\","/"] = @{ Size = [int64]$Matches[2]; MD5 = $md5 }
+            }
+        }
+    }
+    return $remote
+}
+
+# Upload-Cloud - src is local folder, dst is cloud url, folder is the folder under dst to upload to. The files
+# already at dst with the same size and MD5 are skipped, so it is safe to re-run and only uploads new or changed files
+function Upload-Cloud ($src, $dst, $folder) {
+    Write-Host "[ ] Syncing data from $src to $dst"
+    if ($storage_type -eq "custom") {
+        # the custom storage can't list the files there, so the whole folder is uploaded
+        Invoke-Transfer {
+            param ($dst)
+            Invoke-CustomTransfer $upload_command "$($dst -replace "/*$",'')/$folder" $src $dst
+        } $dst "out_link"
+        Write-Host "[+] Uploaded $src to $dst with the upload command"
+        return
     }
+    $remote = Get-RemoteFiles $dst $folder
+    $uploaded = 0
+    $skipped = 0
+    Get-ChildItem -Recurse -File "$src" | ForEach-Object {
+        $file = $_.FullName
+        $rel = [System.IO.Path]::GetRelativePath($src, $file) -replace "\\","/"
+        $existing = $remote[$rel]
+        if ($existing -and $existing.Size -eq $_.Length) {
+            # the MD5 is not known for large s3 uploads, so those are matched by size
+            $md5 = (Get-FileHash -Algorithm MD5 $file).Hash.ToLower()
+            if ($existing.MD5 -eq "" -or $existing.MD5 -eq $md5) {
+                $skipped++
+                return
+            }
+        }
+        Invoke-Transfer {
+            param ($dst)
+            if ($dst -match "^s3") {
+                # if the cloud storage is AWS
+                aws s3 cp "$file" "$($dst -replace "/*$",'')/$folder/$rel"
+            } elseif ($dst -match "^https://[^/]+.core.windows.net") {
+                # if the cloud storage is Azure
+                $url = '{0}/{1}/{2}?{3}' -f $dst.Split("?")[0].TrimEnd("/"),$folder,$rel,$dst.Split("?",2)[1]
+                & "$tool_path\tools\azcopy\azcopy.exe" copy "$file" "$url" --put-md5
+            }
+        } $dst "out_link"
+        $uploaded++
+    }
+    Write-Host "[+] Uploaded $uploaded files to $dst, skipped $skipped already there and identical"
 }
 
 function Set-UrlLinks ($dataS, $wiskess_folder) {
+    if ($prefix_run_id -and $run_id -ne "") {
+        # upload the results under a folder of the run id
+        $wiskess_folder = "$run_id/$wiskess_folder"
+    }
+    if ($storage_type -eq "custom") {
+        # the custom storage joins the path to the link, i.e. remote:case/host1.E01
+        $in_URL = '{0}/{1}' -f $($in_link -replace "/*$",""),$dataS
+        $out_URL = '{0}/{1}' -f $($out_link -replace "/*$",""),$wiskess_folder
+        return $out_URL, $in_URL
+    }
     if ($in_link -match "^s3") {
         # if the cloud storage is AWS
         $in_URL = '{0}/{1}' -f $($in_link -replace "/*$",""),$dataS
@@ -273,6 +834,10 @@ function Set-UrlLinks ($dataS, $wiskess_folder) {
 # Set tool_path to correct path
 $tool_path = Resolve-Path -Path "$tool_path/../"
 
+if ($download_concurrency -gt 0) {
+    Set-DownloadConcurrency $download_concurrency
+}
+
 $ds_type = $data_source_list.Split(",").Trim().GetType().Name
 if ($ds_type -match "Object") {
     $split_char = ","
@@ -280,29 +845,77 @@ if ($ds_type -match "Object") {
     $split_char = [Environment]::NewLine
 }
 
-$data_source_list.Split($split_char).Trim() | ForEach-Object {
+# the in_link of each data source, which is its own if set after a '|', else -in_link
+$data_sources = @()
+$source_links = @{}
+foreach ($entry in $data_source_list.Split($split_char).Trim()) {
+    $dataS, $link = $entry -split "\s*\|\s*", 2
+    $data_sources += $dataS
+    $source_links[$dataS] = if ($link) { $link } else { $in_link }
+}
+
+if ($list_remote) {
+    Show-RemoteList $data_sources
+    exit
+}
+
+$data_sources | ForEach-Object {
+    # download from the link of the data source, which may be another storage provider
+    $script:in_link = $source_links[$_]
     if ($image_folder -Match "_files\.zip$") {
         $image_folder = $($_ -Replace "_files\.zip$","")
     } else {
         $image_folder = $($_ -Replace "\.\w+$","")
     }
     $wiskess_folder = "$($image_folder)-Wiskess"
+    if ($out_suffix -ne "") {
+        $wiskess_folder += "-$out_suffix"
+    }
     $out_URL, $in_URL = Set-UrlLinks $_ $wiskess_folder
     
     $uploaded = Find-Uploaded $out_URL
     if (($uploaded -eq $False -or $update -eq $True) -and $_ -ne "") {
         Write-Host "---------------- Get Data ----------------"
-        if ($(Test-Path "$local_storage\$_") -eq $true) {
+        if ($no_download) {
+            # process the data downloaded before, as it was left or once extracted
+            if (!(Test-Path "$local_storage\$_") -and !(Test-Path -PathType Container "$local_storage\$($image_folder)-extracted")) {
+                Write-Error "The data source $_ is not in $local_storage, so it is not processed as -no_download is set"
+                return
+            }
+            Write-Host "[ ] Reusing the data of $_ downloaded to $local_storage"
+        } elseif ($(Test-Path "$local_storage\$_") -eq $true) {
             Write-Warning "File $local_storage\$_ exists, remove it if wanting to download again."
         } elseif ($(Test-Path -Type Container "$local_storage\$($image_folder)-extracted") -eq $true -and $(Get-ChildItem -Recurse -Depth 2 "$local_storage\$($image_folder)-extracted" | Measure-Object -Property Length -sum).sum -gt 1000000000) {
             Write-Warning "Folder $local_storage\$($image_folder)-extracted exists delete it if wanting to extract again."
         } else {
-            if ($_ -match "\.vmdk$") {
-                Get-VMDKDescriptor $_ $in_link
-            } else {
-                # Download the image
-                Write-Host "[+] Downloading $_"
-                Download-Cloud $in_URL "$local_storage\"
+            # remove what was downloaded if it is stopped, i.e. by Ctrl-C, which also stops the transfer tools
+            $dataS = $_
+            $downloaded = $False
+            $failed = $False
+            try {
+                if ($include -ne "" -or $exclude -ne "") {
+                    Download-Filtered $_ $in_URL
+                } elseif ($_ -match "\.vmdk$") {
+                    Get-VMDKDescriptor $_ $in_link
+                } else {
+                    # Download the image
+                    Write-Host "[+] Downloading $_"
+                    Download-Cloud $in_URL "$local_storage\"
+                }
+                $downloaded = $True
+            } catch [System.Management.Automation.PipelineStoppedException] {
+                # stopped, which is cleaned up in finally
+                throw
+            } catch {
+                # a failed download is an error, not a cancel, and what was downloaded is kept to look into
+                $failed = $True
+                throw
+            } finally {
+                # Ctrl-C stops the script without running catch, so a download that did not complete or fail was stopped
+                if (!$downloaded -and !$failed) {
+                    Remove-PartialDownload $dataS
+                    [Environment]::Exit(130)
+                }
             }
             Write-Host "Downloaded files: $(Get-ChildItem -recurse -Depth 3 $local_storage\$_)"
         }
@@ -318,7 +931,9 @@ $data_source_list.Split($split_char).Trim() | ForEach-Object {
             # Download is a zip, check for embedded zips
             $image_archive = $(Get-ChildItem "$local_storage\$_").FullName
         } 
-        if ($(Test-Path -Type Container "$local_storage\$($image_folder)-extracted") -eq $true -and $(Get-ChildItem -Recurse -Depth 2 "$local_storage\$($image_folder)-extracted" | Measure-Object -Property Length -sum).sum -gt 1000000000) {
+        if ($no_download -and $(Test-Path -Type Container "$local_storage\$($image_folder)-extracted")) {
+            Write-Host "[ ] Using the data extracted to $local_storage\$($image_folder)-extracted"
+        } elseif ($(Test-Path -Type Container "$local_storage\$($image_folder)-extracted") -eq $true -and $(Get-ChildItem -Recurse -Depth 2 "$local_storage\$($image_folder)-extracted" | Measure-Object -Property Length -sum).sum -gt 1000000000) {
             Write-Warning "Folder $local_storage\$($image_folder)-extracted exists delete it if wanting to extract again."
         } elseif ($image_archive) {
             # Extracting the zip to folder
@@ -350,7 +965,7 @@ $data_source_list.Split($split_char).Trim() | ForEach-Object {
             Write-Host "---------------- Update Data ----------------"
             if ($uploaded -eq $False) {
                 # Download the wiskess folder
-                Download-Cloud $out_URL "$local_storage\"
+                Download-Cloud $out_URL "$local_storage\" "out_link"
             }
             if ($(Test-Path -Path "$local_storage\$($wiskess_folder)")) {
                 # Remove the Artefacts folder
@@ -365,32 +980,60 @@ $data_source_list.Split($split_char).Trim() | ForEach-Object {
 
         }
         
-        Write-Host "---------------- Process Data ----------------"
-        # Get the name of the disk image based on extension and size being >1GB
-        $image = (Get-ChildItem -Recurse -Depth 3 "$local_storage\$($image_folder)-extracted" | Where-Object {$_.Length -gt 1000000000 -and $_.Name -Match "vmdk|vdi|EX01|vhd|vhdx|E01|raw"}).FullName
-        $surge_collection = (Get-ChildItem -Recurse -Depth 3 "$local_storage\$($image_folder)-extracted" | Where-Object {$_.Name -match "^files$"}).FullName
-        $velo_collection = (Get-ChildItem -Recurse -Depth 3 "$local_storage\$($image_folder)-extracted" | Where-Object {$_.Name -match "^uploads$"}).FullName
-        if ($image) {
-            $add_image = ""
-            ForEach ($i in $image) {
-                Start-ImageProcess -image $i -wiskess_folder "$wiskess_folder$add_image" -start_date $start_date -end_date $end_date -ioc_file $ioc_file -osf_mount $True
-                $add_image += "_1"
+        # upload the output in finally, so the completed outputs are uploaded even if wiskess crashes or the run is stopped
+        try {
+            Write-Host "---------------- Process Data ----------------"
+            # Get the name of the disk image based on extension and size being >1GB
+            $image = (Get-ChildItem -Recurse -Depth 3 "$local_storage\$($image_folder)-extracted" | Where-Object {$_.Length -gt 1000000000 -and $_.Name -Match "vmdk|vdi|EX01|vhd|vhdx|E01|raw"}).FullName
+            $surge_collection = (Get-ChildItem -Recurse -Depth 3 "$local_storage\$($image_folder)-extracted" | Where-Object {$_.Name -match "^files$"}).FullName
+            $velo_collection = (Get-ChildItem -Recurse -Depth 3 "$local_storage\$($image_folder)-extracted" | Where-Object {$_.Name -match "^uploads$"}).FullName
+            if ($image) {
+                $add_image = ""
+                ForEach ($i in $image) {
+                    Start-ImageProcess -image $i -wiskess_folder "$wiskess_folder$add_image" -start_date $start_date -end_date $end_date -ioc_file $ioc_file -osf_mount $True
+                    $add_image += "_1"
+                }
+            } elseif ("$velo_collection") {
+                Start-VeloProcess -velo_collection $velo_collection -wiskess_folder $wiskess_folder -start_date $start_date -end_date $end_date -ioc_file $ioc_file
+            } elseif ("$surge_collection") {
+                Start-SurgeProcess -surge_collection $surge_collection -wiskess_folder $wiskess_folder -start_date $start_date -end_date $end_date -ioc_file $ioc_file
+            } else {
+                Write-Error "Unable to identify the type of data downloaded."
+                Write-Host "Extracted depth 4: $(Get-ChildItem -Recurse -Depth 4 $local_storage\$($image_folder)-extracted)"
+                Write-Host "Image download $(Get-ChildItem -Recurse -Depth 4 $local_storage\$image_folder)"
+            }
+        } finally {
+            if ($(Test-Path -PathType Leaf "$local_storage\$($wiskess_folder)\CRASHED")) {
+                Write-Warning "Wiskess crashed, uploading its partial output: $(Get-Content -Raw "$local_storage\$($wiskess_folder)\CRASHED")"
+            }
+            Write-Host "---------------- Upload Data ----------------"
+            if ($(Test-Path -PathType Container "$local_storage\$($wiskess_folder)")) {
+                $upload_folder = $wiskess_folder
+                if ($prefix_run_id -and $run_id -ne "") {
+                    $upload_folder = "$run_id/$wiskess_folder"
+                }
+                $valid = $True
+                if ($upload_if_valid) {
+                    Write-Host "[ ] Validating the wiskess output before upload..."
+                    & "$tool_path\wiskess_rust.exe" summary --out-path "$local_storage\$($wiskess_folder)" --min-valid $valid_threshold
+                    $valid = $LASTEXITCODE -eq 0
+                }
+                $encrypted = $True
+                if ($encrypt_output -and $valid) {
+                    Write-Host "[ ] Encrypting the wiskess output before upload..."
+                    & "$tool_path\wiskess_rust.exe" encrypt --out-path "$local_storage\$($wiskess_folder)" --key-file "$encrypt_key"
+                    $encrypted = $LASTEXITCODE -eq 0
+                }
+                if (!$valid) {
+                    Write-Warning "The wiskess output is not valid, so it is not uploaded and is left at: $local_storage\$($wiskess_folder)"
+                } elseif ($encrypted) {
+                    Upload-Cloud "$local_storage\$($wiskess_folder)" "$out_link" "$upload_folder"
+                } else {
+                    Write-Error "Unable to encrypt the wiskess output, so it is not uploaded: $local_storage\$($wiskess_folder)"
+                }
             }
-        } elseif ("$velo_collection") {
-            Start-VeloProcess -velo_collection $velo_collection -wiskess_folder $wiskess_folder -start_date $start_date -end_date $end_date -ioc_file $ioc_file
-        } elseif ("$surge_collection") {
-            Start-SurgeProcess -surge_collection $surge_collection -wiskess_folder $wiskess_folder -start_date $start_date -end_date $end_date -ioc_file $ioc_file
-        } else {
-            Write-Error "Unable to identify the type of data downloaded."
-            Write-Host "Extracted depth 4: $(Get-ChildItem -Recurse -Depth 4 $local_storage\$($image_folder)-extracted)"
-            Write-Host "Image download $(Get-ChildItem -Recurse -Depth 4 $local_storage\$image_folder)"
-        }
-
-        Write-Host "---------------- Upload Data ----------------"
-        if ($(Test-Path -PathType Container "$local_storage\$($wiskess_folder)")) {
-            Upload-Cloud "$local_storage\$($wiskess_folder)" "$out_link" "$wiskess_folder"
         }
-        if ($keep_evidence -eq $False) {
+        if ($keep_evidence -eq $False -and !$no_download) {
             Write-Host "[ ] Cleaning up data source files..."
             Remove-Item -Force -Recurse $local_storage\$_
             Get-ChildItem "$local_storage\$($image_folder)-extracted" | Remove-Item -Force -Recurse